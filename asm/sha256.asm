@@ -0,0 +1,216 @@
+# SHA-256: 计算INPUT处消息的摘要，按字节顺序（大端）写到DIGEST
+#   输入: INPUT处一个字的消息字节数，之后是消息内容（内存初始为0，即空串）
+#   输出: DIGEST处32字节摘要
+# 内存按小端访问，消息分组和哈希状态用rev8转换字节序
+
+.equ STACK, 0x4000      # 栈顶，向下增长
+.equ BLOCK, 0x4000      # 当前填充好的64字节分组
+.equ W, 0x4100          # 消息调度 W[0..63]
+.equ DIGEST, 0x4200     # 哈希状态 H0~H7，结束时转成大端字节
+.equ INPUT, 0x5000      # 消息长度 + 消息字节
+
+# rd = rs循环右移n位，tmp被覆盖
+.macro rotr rd, rs, n, tmp
+    srli rd, rs, n
+    slli tmp, rs, 32 - n
+    or rd, rd, tmp
+.endm
+
+# H[offset/4] += reg
+.macro accumulate offset, reg
+    lw t1, DIGEST + offset(x0)
+    add t1, t1, reg
+    sw t1, DIGEST + offset(x0)
+.endm
+
+        jal s0, start           # s0 = 常量表地址（紧跟在这条jal之后）
+k_table:
+        .word 0x428A2F98, 0x71374491, 0xB5C0FBCF, 0xE9B5DBA5, 0x3956C25B, 0x59F111F1, 0x923F82A4, 0xAB1C5ED5
+        .word 0xD807AA98, 0x12835B01, 0x243185BE, 0x550C7DC3, 0x72BE5D74, 0x80DEB1FE, 0x9BDC06A7, 0xC19BF174
+        .word 0xE49B69C1, 0xEFBE4786, 0x0FC19DC6, 0x240CA1CC, 0x2DE92C6F, 0x4A7484AA, 0x5CB0A9DC, 0x76F988DA
+        .word 0x983E5152, 0xA831C66D, 0xB00327C8, 0xBF597FC7, 0xC6E00BF3, 0xD5A79147, 0x06CA6351, 0x14292967
+        .word 0x27B70A85, 0x2E1B2138, 0x4D2C6DFC, 0x53380D13, 0x650A7354, 0x766A0ABB, 0x81C2C92E, 0x92722C85
+        .word 0xA2BFE8A1, 0xA81A664B, 0xC24B8B70, 0xC76C51A3, 0xD192E819, 0xD6990624, 0xF40E3585, 0x106AA070
+        .word 0x19A4C116, 0x1E376C08, 0x2748774C, 0x34B0BCB5, 0x391C0CB3, 0x4ED8AA4A, 0x5B9CCA4F, 0x682E6FF3
+        .word 0x748F82EE, 0x78A5636F, 0x84C87814, 0x8CC70208, 0x90BEFFFA, 0xA4506CEB, 0xBEF9A3F7, 0xC67178F2
+h_init:
+        .word 0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19
+
+start:
+        li sp, STACK
+        # H = 初始哈希值（常量表之后的8个字）
+        addi t0, x0, 0
+        addi t1, x0, 32
+copy_h:
+        add t2, s0, t0
+        lw t3, 256(t2)
+        sw t3, DIGEST(t0)
+        addi t0, t0, 4
+        bne t0, t1, copy_h
+
+        lw s1, INPUT(x0)        # s1 = 消息字节数
+        # 填充后的总字节数 = (长度 + 1 + 8 + 63) / 64 * 64
+        addi s2, s1, 72
+        srli s2, s2, 6
+        slli s2, s2, 6
+        addi s3, x0, 0          # s3 = 当前分组在消息中的起始位置
+next_block:
+        addi t0, x0, 0          # t0 = 分组内偏移
+fill:
+        add t1, s3, t0          # t1 = 消息中的位置
+        bgeu t1, s1, pad
+        lbu t2, INPUT + 4(t1)
+        j store_byte
+pad:
+        addi t2, x0, 0
+        bne t1, s1, store_byte
+        addi t2, x0, 0x80       # 紧跟消息的第一个填充字节
+store_byte:
+        sb t2, BLOCK(t0)
+        addi t0, t0, 1
+        slti t3, t0, 64
+        bnez t3, fill
+
+        addi s3, s3, 64
+        bne s3, s2, compress_block
+        # 最后一个分组的末尾8字节是大端的消息位数
+        srli t0, s1, 29
+        rev8 t0, t0
+        sw t0, BLOCK + 56(x0)
+        slli t0, s1, 3
+        rev8 t0, t0
+        sw t0, BLOCK + 60(x0)
+compress_block:
+        li a0, BLOCK
+        call compress
+        blt s3, s2, next_block
+
+        # 哈希状态按大端字节写出
+        addi t0, x0, 0
+        addi t1, x0, 32
+to_bytes:
+        lw t2, DIGEST(t0)
+        rev8 t2, t2
+        sw t2, DIGEST(t0)
+        addi t0, t0, 4
+        blt t0, t1, to_bytes
+        halt
+
+# 用a0处的64字节分组更新DIGEST处的哈希状态，s0为常量表地址
+compress:
+        addi sp, sp, -32
+        sw s2, 0(sp)
+        sw s3, 4(sp)
+        sw s4, 8(sp)
+        sw s5, 12(sp)
+        sw s6, 16(sp)
+        sw s7, 20(sp)
+        sw s8, 24(sp)
+        sw s9, 28(sp)
+
+        # W[0..15] = 分组按大端读出的16个字，t0为i*4
+        addi t0, x0, 0
+        addi t1, x0, 64
+load_w:
+        add t2, a0, t0
+        lw t3, 0(t2)
+        rev8 t3, t3
+        sw t3, W(t0)
+        addi t0, t0, 4
+        bne t0, t1, load_w
+        # W[i] = σ1(W[i-2]) + W[i-7] + σ0(W[i-15]) + W[i-16]
+        addi t1, x0, 256
+extend_w:
+        lw t2, W - 60(t0)       # σ0(W[i-15])
+        rotr t3, t2, 7, t6
+        rotr t4, t2, 18, t6
+        xor t3, t3, t4
+        srli t4, t2, 3
+        xor t3, t3, t4
+        lw t2, W - 8(t0)        # σ1(W[i-2])
+        rotr t4, t2, 17, t6
+        rotr t5, t2, 19, t6
+        xor t4, t4, t5
+        srli t5, t2, 10
+        xor t4, t4, t5
+        add t3, t3, t4
+        lw t2, W - 28(t0)
+        add t3, t3, t2
+        lw t2, W - 64(t0)
+        add t3, t3, t2
+        sw t3, W(t0)
+        addi t0, t0, 4
+        bne t0, t1, extend_w
+
+        # s2~s9 = a~h
+        lw s2, DIGEST(x0)
+        lw s3, DIGEST + 4(x0)
+        lw s4, DIGEST + 8(x0)
+        lw s5, DIGEST + 12(x0)
+        lw s6, DIGEST + 16(x0)
+        lw s7, DIGEST + 20(x0)
+        lw s8, DIGEST + 24(x0)
+        lw s9, DIGEST + 28(x0)
+        addi t0, x0, 0
+round:
+        # t1 = h + Σ1(e) + ch(e, f, g) + K[i] + W[i]
+        rotr t1, s6, 6, t6
+        rotr t2, s6, 11, t6
+        xor t1, t1, t2
+        rotr t2, s6, 25, t6
+        xor t1, t1, t2
+        and t2, s6, s7
+        not t3, s6
+        and t3, t3, s8
+        xor t2, t2, t3
+        add t1, t1, t2
+        add t1, t1, s9
+        add t2, s0, t0
+        lw t3, 0(t2)
+        add t1, t1, t3
+        lw t3, W(t0)
+        add t1, t1, t3
+        # t2 = Σ0(a) + maj(a, b, c)
+        rotr t2, s2, 2, t6
+        rotr t3, s2, 13, t6
+        xor t2, t2, t3
+        rotr t3, s2, 22, t6
+        xor t2, t2, t3
+        and t3, s2, s3
+        and t4, s2, s4
+        xor t3, t3, t4
+        and t4, s3, s4
+        xor t3, t3, t4
+        add t2, t2, t3
+
+        mov s9, s8
+        mov s8, s7
+        mov s7, s6
+        add s6, s5, t1
+        mov s5, s4
+        mov s4, s3
+        mov s3, s2
+        add s2, t1, t2
+        addi t0, t0, 4
+        slti t1, t0, 256
+        bnez t1, round
+
+        accumulate 0, s2
+        accumulate 4, s3
+        accumulate 8, s4
+        accumulate 12, s5
+        accumulate 16, s6
+        accumulate 20, s7
+        accumulate 24, s8
+        accumulate 28, s9
+
+        lw s2, 0(sp)
+        lw s3, 4(sp)
+        lw s4, 8(sp)
+        lw s5, 12(sp)
+        lw s6, 16(sp)
+        lw s7, 20(sp)
+        lw s8, 24(sp)
+        lw s9, 28(sp)
+        addi sp, sp, 32
+        ret
@@ -0,0 +1,12 @@
+// 汇编任意输入都应当返回Ok或Err，而不是panic
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use riscv_tools::assemble;
+
+// 输入按UTF-8有损解码，非法字节变成U+FFFD，仍然覆盖非ASCII字符的处理。
+// assemble的所有错误都通过Result返回，这里出现的panic会被libFuzzer记录为崩溃
+fuzz_target!(|data: &[u8]| {
+    let input = String::from_utf8_lossy(data);
+    let _ = assemble(&input);
+});
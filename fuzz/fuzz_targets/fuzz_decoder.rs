@@ -0,0 +1,40 @@
+// 解码任意32位字都应当返回一行文本，而不是panic
+#![no_main]
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use libfuzzer_sys::fuzz_target;
+use riscv_tools::{decode_instruction, words_from_bytes};
+
+// 解码次数和其中未知指令的次数，用于确认模糊测试确实覆盖了未知操作码的分支
+static DECODED: AtomicU64 = AtomicU64::new(0);
+static UNKNOWN: AtomicU64 = AtomicU64::new(0);
+
+fn decode(word: u32) {
+    let text = decode_instruction(word);
+    // String保证是合法的UTF-8，这里再检查一遍字节，防止以后改用不安全的拼接
+    assert!(std::str::from_utf8(text.as_bytes()).is_ok());
+    assert!(!text.is_empty());
+    if text.starts_with("未知") {
+        UNKNOWN.fetch_add(1, Ordering::Relaxed);
+    }
+    let decoded = DECODED.fetch_add(1, Ordering::Relaxed) + 1;
+    if decoded.is_power_of_two() && decoded >= 1 << 16 {
+        eprintln!("已解码 {} 个字，其中未知指令 {} 个", decoded, UNKNOWN.load(Ordering::Relaxed));
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    // 前4个字节作为一个小端字
+    if let Some(first) = data.first_chunk::<4>() {
+        decode(u32::from_le_bytes(*first));
+    }
+    // 与读取二进制文件相同的转换，长度不是4的倍数时末尾被忽略
+    for big_endian in [false, true] {
+        let words = words_from_bytes(data, big_endian);
+        assert_eq!(words.len(), data.len() / 4);
+        for word in words {
+            decode(word);
+        }
+    }
+});
@@ -0,0 +1,135 @@
+// 条件汇编: .if EXPR / .ifdef NAME / .ifndef NAME ... [.else] ... .endif，最多嵌套8层。
+// 条件在汇编的两遍中按当时已定义的常量求值，两遍各用一个ConditionStack跳过条件不成立的行。
+// 条件不成立的块中的.if不再求值，只用于匹配.else和.endif
+
+use std::collections::HashMap;
+
+use crate::{evaluate_expr, ErrorCode, LineError, SourceLine};
+
+pub const MAX_CONDITION_DEPTH: usize = 8;
+
+struct Frame {
+    start: SourceLine, // .if所在的行，用于报告缺少.endif
+    parent_active: bool,
+    condition: bool,
+    in_else: bool,
+}
+
+impl Frame {
+    fn active(&self) -> bool {
+        self.parent_active && self.condition != self.in_else
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct ConditionStack {
+    frames: Vec<Frame>,
+}
+
+impl ConditionStack {
+    // 当前行是否需要汇编
+    pub fn active(&self) -> bool {
+        self.frames.last().is_none_or(Frame::active)
+    }
+
+    // 处理去掉标签和注释后的一行，是条件汇编伪指令时返回true。
+    // 出错时状态仍按该伪指令更新（无法求值的.if视为不成立），使后面的.else和.endif能正确匹配
+    pub fn process(&mut self, line: &str, constants: &HashMap<String, i32>, source: &SourceLine) -> Result<bool, LineError> {
+        let mnemonic = line.split_whitespace().next().unwrap_or("");
+        let operand = line[mnemonic.len()..].trim();
+        match mnemonic {
+            ".if" | ".ifdef" | ".ifndef" => {
+                let parent_active = self.active();
+                let condition = if parent_active { evaluate_condition(mnemonic, operand, constants) } else { Ok(false) };
+                self.frames.push(Frame {
+                    start: source.clone(),
+                    parent_active,
+                    condition: *condition.as_ref().unwrap_or(&false),
+                    in_else: false,
+                });
+                if self.frames.len() > MAX_CONDITION_DEPTH {
+                    return Err(LineError::new(ErrorCode::InvalidSyntax, mnemonic, format!("条件汇编嵌套超过{}层", MAX_CONDITION_DEPTH)));
+                }
+                condition.map(|_| true)
+            }
+            ".else" => match self.frames.last_mut() {
+                Some(frame) if !frame.in_else => {
+                    frame.in_else = true;
+                    Ok(true)
+                }
+                Some(_) => Err(LineError::new(ErrorCode::InvalidSyntax, mnemonic, "同一个.if中有多个.else".to_string())),
+                None => Err(LineError::new(ErrorCode::InvalidSyntax, mnemonic, ".else之前没有对应的.if".to_string())),
+            },
+            ".endif" => match self.frames.pop() {
+                Some(_) => Ok(true),
+                None => Err(LineError::new(ErrorCode::InvalidSyntax, mnemonic, ".endif之前没有对应的.if".to_string())),
+            },
+            _ => Ok(false),
+        }
+    }
+
+    // 源码结束时仍未闭合的最内层.if
+    pub fn unclosed(&self) -> Option<(&SourceLine, LineError)> {
+        let frame = self.frames.last()?;
+        Some((&frame.start, LineError::new(ErrorCode::InvalidSyntax, &frame.start.text, "缺少.endif".to_string())))
+    }
+}
+
+fn evaluate_condition(mnemonic: &str, operand: &str, constants: &HashMap<String, i32>) -> Result<bool, LineError> {
+    if operand.is_empty() {
+        return Err(LineError::new(ErrorCode::MissingOperand, mnemonic, format!("{} 缺少操作数", mnemonic)));
+    }
+    match mnemonic {
+        ".ifdef" => Ok(constants.contains_key(operand)),
+        ".ifndef" => Ok(!constants.contains_key(operand)),
+        _ => evaluate_expr(operand, constants)
+            .map(|value| value != 0)
+            .map_err(|e| LineError::new(ErrorCode::InvalidImmediate, operand, format!("无效的表达式 {}: {}", operand, e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 依次处理每一行，返回需要汇编的普通行
+    fn active_lines<'a>(lines: &[&'a str], constants: &HashMap<String, i32>) -> Vec<&'a str> {
+        let mut stack = ConditionStack::default();
+        let source = SourceLine { file: "<input>".to_string(), line: 1, origin: 1, text: String::new() };
+        lines.iter()
+            .filter(|line| !stack.process(line, constants, &source).unwrap() && stack.active())
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn test_nested_conditions() {
+        let lines = [
+            ".if DEBUG", "a", ".ifdef VERBOSE", "b", ".if 1/0", "x", ".endif", ".else", "c", ".endif", ".else", "d", ".endif",
+            ".ifndef DEBUG", "f", ".endif", ".if (LEVEL-1)*2", "g", ".endif", "h",
+        ];
+        // 不成立的块中的.if 1/0不求值
+        let constants = HashMap::from([("DEBUG".to_string(), 1), ("LEVEL".to_string(), 1)]);
+        assert_eq!(active_lines(&lines, &constants), ["a", "c", "h"]);
+        // DEBUG为0但已定义，所以.ifndef也不成立
+        let constants = HashMap::from([("DEBUG".to_string(), 0), ("VERBOSE".to_string(), 0), ("LEVEL".to_string(), 3)]);
+        assert_eq!(active_lines(&lines, &constants), ["d", "g", "h"]);
+    }
+
+    #[test]
+    fn test_condition_errors() {
+        let source = SourceLine { file: "<input>".to_string(), line: 1, origin: 1, text: ".if 1".to_string() };
+        let constants = HashMap::new();
+        let mut stack = ConditionStack::default();
+        assert_eq!(stack.process(".endif", &constants, &source).unwrap_err().message, ".endif之前没有对应的.if");
+        assert_eq!(stack.process(".if UNDEFINED", &constants, &source).unwrap_err().message, "无效的表达式 UNDEFINED: 未定义的常量: UNDEFINED");
+        assert_eq!(stack.process(".endif", &constants, &source), Ok(true));
+        for _ in 0..MAX_CONDITION_DEPTH {
+            assert!(stack.process(".if 1", &constants, &source).unwrap());
+        }
+        assert_eq!(stack.process(".if 1", &constants, &source).unwrap_err().message, "条件汇编嵌套超过8层");
+        assert_eq!(stack.process(".else", &constants, &source), Ok(true));
+        assert!(stack.process(".else", &constants, &source).is_err());
+        assert_eq!(stack.unclosed().unwrap().1.message, "缺少.endif");
+    }
+}
@@ -0,0 +1,214 @@
+// 立即数位置上的常量表达式求值。按C的优先级从低到高:
+//   << >>   + -   * / %   一元-和+   字面量、常量名、括号
+// 字面量的写法与parse_int相同（十进制、0x、0b、下划线分组、字符），
+// 常量名在constants中查找；运算按32位有符号整数进行，溢出视为错误
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::{is_identifier, parse_int};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprError {
+    MissingOperand,              // 表达式为空或运算符之后没有操作数
+    InvalidOperand(String),      // 既不是整数字面量也不是标识符
+    UndefinedConstant(String),
+    UnexpectedChar(char),
+    UnclosedParen,
+    DivisionByZero,
+    InvalidShift(i32),           // 移位量不在0~31之间
+    Overflow,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExprError::MissingOperand => write!(f, "缺少操作数"),
+            ExprError::InvalidOperand(operand) => write!(f, "无效的操作数: {}", operand),
+            ExprError::UndefinedConstant(name) => write!(f, "未定义的常量: {}", name),
+            ExprError::UnexpectedChar(c) => write!(f, "意外的字符: {}", c),
+            ExprError::UnclosedParen => write!(f, "括号不匹配"),
+            ExprError::DivisionByZero => write!(f, "除数为0"),
+            ExprError::InvalidShift(amount) => write!(f, "移位量 {} 超出范围 [0, 31]", amount),
+            ExprError::Overflow => write!(f, "结果超出32位范围"),
+        }
+    }
+}
+
+pub fn evaluate_expr(expr: &str, constants: &HashMap<String, i32>) -> Result<i32, ExprError> {
+    let mut parser = ExprParser { rest: expr, constants };
+    let value = parser.shift()?;
+    parser.rest = parser.rest.trim_start();
+    match parser.rest.chars().next() {
+        Some(c) => Err(ExprError::UnexpectedChar(c)),
+        None => Ok(value),
+    }
+}
+
+// 递归下降求值，rest是尚未读取的部分
+struct ExprParser<'a> {
+    rest: &'a str,
+    constants: &'a HashMap<String, i32>,
+}
+
+impl ExprParser<'_> {
+    // 跳过空白后读取运算符op
+    fn eat(&mut self, op: &str) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(op) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn shift(&mut self) -> Result<i32, ExprError> {
+        let mut value = self.additive()?;
+        loop {
+            let left = if self.eat("<<") {
+                true
+            } else if self.eat(">>") {
+                false
+            } else {
+                return Ok(value);
+            };
+            let amount = self.additive()?;
+            if !(0..32).contains(&amount) {
+                return Err(ExprError::InvalidShift(amount));
+            }
+            value = if left { value << amount } else { value >> amount };
+        }
+    }
+
+    fn additive(&mut self) -> Result<i32, ExprError> {
+        let mut value = self.multiplicative()?;
+        loop {
+            value = if self.eat("+") {
+                value.checked_add(self.multiplicative()?)
+            } else if self.eat("-") {
+                value.checked_sub(self.multiplicative()?)
+            } else {
+                return Ok(value);
+            }
+            .ok_or(ExprError::Overflow)?;
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<i32, ExprError> {
+        let mut value = self.unary()?;
+        loop {
+            let op = if self.eat("*") {
+                '*'
+            } else if self.eat("/") {
+                '/'
+            } else if self.eat("%") {
+                '%'
+            } else {
+                return Ok(value);
+            };
+            let rhs = self.unary()?;
+            value = match op {
+                '*' => value.checked_mul(rhs),
+                _ if rhs == 0 => return Err(ExprError::DivisionByZero),
+                '/' => value.checked_div(rhs),
+                _ => value.checked_rem(rhs),
+            }
+            .ok_or(ExprError::Overflow)?;
+        }
+    }
+
+    fn unary(&mut self) -> Result<i32, ExprError> {
+        if self.eat("-") {
+            self.unary()?.checked_neg().ok_or(ExprError::Overflow)
+        } else if self.eat("+") {
+            self.unary()
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<i32, ExprError> {
+        if self.eat("(") {
+            let value = self.shift()?;
+            if !self.eat(")") {
+                return Err(ExprError::UnclosedParen);
+            }
+            return Ok(value);
+        }
+
+        self.rest = self.rest.trim_start();
+        let len = match self.rest.strip_prefix('\'') {
+            // 字符字面量，到未被转义的单引号为止
+            Some(inner) => {
+                let mut escaped = false;
+                let end = inner.char_indices().find(|&(_, c)| {
+                    let closing = c == '\'' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    closing
+                });
+                end.map_or(self.rest.len(), |(i, _)| i + 2)
+            }
+            None => self.rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(self.rest.len()),
+        };
+        if len == 0 {
+            return Err(self.rest.chars().next().map_or(ExprError::MissingOperand, ExprError::UnexpectedChar));
+        }
+        let (operand, rest) = self.rest.split_at(len);
+        self.rest = rest;
+
+        if is_identifier(operand) {
+            return self.constants.get(operand).copied().ok_or_else(|| ExprError::UndefinedConstant(operand.to_string()));
+        }
+        match parse_int(operand) {
+            Some(value) if (i32::MIN as i64..=u32::MAX as i64).contains(&value) => Ok(value as u32 as i32),
+            Some(_) => Err(ExprError::Overflow),
+            None => Err(ExprError::InvalidOperand(operand.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str) -> Result<i32, ExprError> {
+        evaluate_expr(expr, &HashMap::new())
+    }
+
+    #[test]
+    fn test_evaluate_expr() {
+        assert_eq!(eval("2+3*4"), Ok(14));
+        assert_eq!(eval("-(1<<4)"), Ok(-16));
+        assert_eq!(eval(" (2 + 3) * 4 "), Ok(20));
+        assert_eq!(eval("10-4-3"), Ok(3));
+        assert_eq!(eval("-7/2"), Ok(-3));
+        assert_eq!(eval("-7%2"), Ok(-1));
+        assert_eq!(eval("1+2<<3"), Ok(24));
+        assert_eq!(eval("-0x100>>4"), Ok(-16));
+        assert_eq!(eval("0b1010_0101+'A'"), Ok(0xA5 + 65));
+        assert_eq!(eval("0xFFFFFFFF"), Ok(-1));
+        assert_eq!(eval("--1"), Ok(1));
+
+        let constants = HashMap::from([("BUFFER_SIZE".to_string(), 64), ("OFFSET".to_string(), 4)]);
+        assert_eq!(evaluate_expr("BUFFER_SIZE/4", &constants), Ok(16));
+        assert_eq!(evaluate_expr("(OFFSET+8)*-1", &constants), Ok(-12));
+    }
+
+    #[test]
+    fn test_evaluate_expr_errors() {
+        assert_eq!(eval(""), Err(ExprError::MissingOperand));
+        assert_eq!(eval("1+"), Err(ExprError::MissingOperand));
+        assert_eq!(eval("(1+2"), Err(ExprError::UnclosedParen));
+        assert_eq!(eval("1+2)"), Err(ExprError::UnexpectedChar(')')));
+        assert_eq!(eval("1 2"), Err(ExprError::UnexpectedChar('2')));
+        assert_eq!(eval("4/0"), Err(ExprError::DivisionByZero));
+        assert_eq!(eval("4%(1-1)"), Err(ExprError::DivisionByZero));
+        assert_eq!(eval("1<<32"), Err(ExprError::InvalidShift(32)));
+        assert_eq!(eval("0x7FFFFFFF+1"), Err(ExprError::Overflow));
+        assert_eq!(eval("12a"), Err(ExprError::InvalidOperand("12a".to_string())));
+        assert_eq!(eval("SIZE*2"), Err(ExprError::UndefinedConstant("SIZE".to_string())));
+        assert_eq!(eval("SIZE*2").unwrap_err().to_string(), "未定义的常量: SIZE");
+    }
+}
@@ -0,0 +1,111 @@
+// .include "path" 把另一个源文件的全部行插入到该位置。相对路径从包含它的文件
+// 所在的目录查找，顶层文件的目录由AssemblerConfig::include_dir给出。
+// 嵌套超过MAX_INCLUDE_DEPTH层时报错，循环包含也因此被拒绝
+
+use std::fs;
+use std::path::Path;
+
+use crate::lexer::split_operands;
+use crate::{parse_string_literal, split_label, strip_comment, ErrorCode, LineError, PreprocessResult, SourceLine};
+
+pub const MAX_INCLUDE_DEPTH: usize = 8;
+
+// 展开input中的.include，file是input的文件名（用于错误信息），dir是它所在的目录
+pub(crate) fn expand_includes(input: &str, file: &str, dir: &Path) -> PreprocessResult {
+    let mut output = Vec::new();
+    let mut errors = Vec::new();
+    include_lines(input, file, dir, None, 0, &mut output, &mut errors);
+    if errors.is_empty() {
+        Ok(output)
+    } else {
+        Err(errors)
+    }
+}
+
+// origin为None表示顶层文件；被包含文件的所有行都取顶层.include所在的行号
+fn include_lines(
+    input: &str,
+    file: &str,
+    dir: &Path,
+    origin: Option<usize>,
+    depth: usize,
+    output: &mut Vec<SourceLine>,
+    errors: &mut Vec<(SourceLine, LineError)>,
+) {
+    for (i, text) in input.lines().enumerate() {
+        let source = SourceLine { file: file.to_string(), line: i + 1, origin: origin.unwrap_or(i + 1), text: text.to_string() };
+        let (_, line) = split_label(strip_comment(text));
+        let parts = split_operands(line);
+        if parts[0] != ".include" {
+            output.push(source);
+            continue;
+        }
+
+        let included = match parse_include(&parts) {
+            Ok(path) => path,
+            Err(e) => {
+                errors.push((source, e));
+                continue;
+            }
+        };
+        if depth >= MAX_INCLUDE_DEPTH {
+            let message = format!(".include 嵌套超过{}层（是否循环包含？）: {}", MAX_INCLUDE_DEPTH, included);
+            errors.push((source, LineError::new(ErrorCode::InvalidSyntax, &parts[1], message)));
+            continue;
+        }
+        let path = dir.join(&included);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                let message = format!("无法读取被包含的文件 {}: {}", path.display(), e);
+                errors.push((source, LineError::new(ErrorCode::InvalidSyntax, &parts[1], message)));
+                continue;
+            }
+        };
+        // 错误信息中的文件名与顶层文件名一样相对于同一位置
+        let name = Path::new(file).parent().unwrap_or(Path::new("")).join(&included);
+        let sub_dir = path.parent().unwrap_or(Path::new(""));
+        include_lines(&contents, &name.to_string_lossy(), sub_dir, Some(source.origin), depth + 1, output, errors);
+    }
+}
+
+// .include "path"
+fn parse_include(parts: &[String]) -> Result<String, LineError> {
+    let literal = parts.get(1)
+        .ok_or_else(|| LineError::new(ErrorCode::MissingOperand, &parts[0], ".include 缺少文件名".to_string()))?;
+    if parts.len() > 2 {
+        return Err(LineError::new(ErrorCode::InvalidSyntax, &parts[2], format!("多余的操作数: {}", parts[2])));
+    }
+    let bytes = parse_string_literal(literal).map_err(|e| LineError::new(ErrorCode::InvalidSyntax, literal, e))?;
+    String::from_utf8(bytes).map_err(|_| LineError::new(ErrorCode::InvalidSyntax, literal, format!("无效的文件名: {}", literal)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_includes() {
+        let root = std::env::temp_dir().join(format!("riscv_tools_include_{}", std::process::id()));
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::write(root.join("lib/inner.asm"), "nop").unwrap();
+        fs::write(root.join("lib/outer.asm"), "add x1, x1, x1\n.include \"inner.asm\"").unwrap();
+        fs::write(root.join("self.asm"), ".include \"self.asm\"").unwrap();
+
+        let lines = expand_includes("halt\n.include \"lib/outer.asm\"", "asm/main.asm", &root).unwrap();
+        let located: Vec<_> = lines.iter().map(|l| (l.file.as_str(), l.line, l.origin, l.text.as_str())).collect();
+        assert_eq!(located, [
+            ("asm/main.asm", 1, 1, "halt"),
+            ("asm/lib/outer.asm", 1, 2, "add x1, x1, x1"),
+            ("asm/lib/inner.asm", 1, 2, "nop"),
+        ]);
+
+        let errors = expand_includes(".include \"self.asm\"\n.include \"missing.asm\"", "main.asm", &root).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].0.file.as_str(), errors[0].0.line), ("self.asm", 1));
+        assert_eq!(errors[0].1.message, ".include 嵌套超过8层（是否循环包含？）: self.asm");
+        assert_eq!((errors[1].0.file.as_str(), errors[1].0.line), ("main.asm", 2));
+        assert!(errors[1].1.message.starts_with("无法读取被包含的文件"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
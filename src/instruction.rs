@@ -0,0 +1,443 @@
+// 结构化的指令表示，与32位机器字一一对应
+
+use std::fmt;
+
+use crate::*;
+
+// 一条机器指令。寄存器编号必须小于32；字段名与汇编写法一致，
+// 例如 Sw { rs1, rs2, offset } 即 sw rs2, offset(rs1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Halt,
+    Add { rd: u8, rs1: u8, rs2: u8 },
+    Mul { rd: u8, rs1: u8, rs2: u8 },
+    Sub { rd: u8, rs1: u8, rs2: u8 },
+    Or { rd: u8, rs1: u8, rs2: u8 },
+    And { rd: u8, rs1: u8, rs2: u8 },
+    Xor { rd: u8, rs1: u8, rs2: u8 },
+    Nor { rd: u8, rs1: u8, rs2: u8 },
+    Srl { rd: u8, rs1: u8, rs2: u8 },
+    Sra { rd: u8, rs1: u8, rs2: u8 },
+    Slt { rd: u8, rs1: u8, rs2: u8 },
+    Sltu { rd: u8, rs1: u8, rs2: u8 },
+    Ctz { rd: u8, rs1: u8 },
+    Cpop { rd: u8, rs1: u8 },
+    Rev8 { rd: u8, rs1: u8 },
+    Brev8 { rd: u8, rs1: u8 },
+    Addi { rd: u8, rs1: u8, imm: i16 },
+    Lui { rd: u8, imm: i16 },
+    Lw { rd: u8, rs1: u8, offset: i16 },
+    Lb { rd: u8, rs1: u8, offset: i16 },
+    Lh { rd: u8, rs1: u8, offset: i16 },
+    Lbu { rd: u8, rs1: u8, offset: i16 },
+    Lhu { rd: u8, rs1: u8, offset: i16 },
+    Slli { rd: u8, rs1: u8, imm: i16 },
+    Srli { rd: u8, rs1: u8, imm: i16 },
+    Srai { rd: u8, rs1: u8, imm: i16 },
+    Andi { rd: u8, rs1: u8, imm: i16 },
+    Ori { rd: u8, rs1: u8, imm: i16 },
+    Xori { rd: u8, rs1: u8, imm: i16 },
+    Slti { rd: u8, rs1: u8, imm: i16 },
+    Jalr { rd: u8, rs1: u8, imm: i16 },
+    Clz { rd: u8, rs1: u8 },
+    Bne { rs1: u8, rs2: u8, offset: i16 },
+    Beq { rs1: u8, rs2: u8, offset: i16 },
+    Bge { rs1: u8, rs2: u8, offset: i16 },
+    Bgeu { rs1: u8, rs2: u8, offset: i16 },
+    Blt { rs1: u8, rs2: u8, offset: i16 },
+    Sw { rs1: u8, rs2: u8, offset: i16 },
+    Sb { rs1: u8, rs2: u8, offset: i16 },
+    Sh { rs1: u8, rs2: u8, offset: i16 },
+    Jal { rd: u8, offset: i32 },
+}
+
+impl Instruction {
+    pub fn encode(&self) -> u32 {
+        match *self {
+            Instruction::Halt => encode_halt(),
+            Instruction::Add { rd, rs1, rs2 } => encode_add(rd, rs1, rs2),
+            Instruction::Mul { rd, rs1, rs2 } => encode_mul(rd, rs1, rs2),
+            Instruction::Sub { rd, rs1, rs2 } => encode_sub(rd, rs1, rs2),
+            Instruction::Or { rd, rs1, rs2 } => encode_or(rd, rs1, rs2),
+            Instruction::And { rd, rs1, rs2 } => encode_and(rd, rs1, rs2),
+            Instruction::Xor { rd, rs1, rs2 } => encode_xor(rd, rs1, rs2),
+            Instruction::Nor { rd, rs1, rs2 } => encode_nor(rd, rs1, rs2),
+            Instruction::Srl { rd, rs1, rs2 } => encode_srl(rd, rs1, rs2),
+            Instruction::Sra { rd, rs1, rs2 } => encode_sra(rd, rs1, rs2),
+            Instruction::Slt { rd, rs1, rs2 } => encode_slt(rd, rs1, rs2),
+            Instruction::Sltu { rd, rs1, rs2 } => encode_sltu(rd, rs1, rs2),
+            Instruction::Ctz { rd, rs1 } => encode_ctz(rd, rs1),
+            Instruction::Cpop { rd, rs1 } => encode_cpop(rd, rs1),
+            Instruction::Rev8 { rd, rs1 } => encode_rev8(rd, rs1),
+            Instruction::Brev8 { rd, rs1 } => encode_brev8(rd, rs1),
+            Instruction::Addi { rd, rs1, imm } => encode_addi(rd, rs1, imm),
+            Instruction::Lui { rd, imm } => encode_lui(rd, imm),
+            Instruction::Lw { rd, rs1, offset } => encode_lw(rd, rs1, offset),
+            Instruction::Lb { rd, rs1, offset } => encode_lb(rd, rs1, offset),
+            Instruction::Lh { rd, rs1, offset } => encode_lh(rd, rs1, offset),
+            Instruction::Lbu { rd, rs1, offset } => encode_lbu(rd, rs1, offset),
+            Instruction::Lhu { rd, rs1, offset } => encode_lhu(rd, rs1, offset),
+            Instruction::Slli { rd, rs1, imm } => encode_slli(rd, rs1, imm),
+            Instruction::Srli { rd, rs1, imm } => encode_srli(rd, rs1, imm),
+            Instruction::Srai { rd, rs1, imm } => encode_srai(rd, rs1, imm),
+            Instruction::Andi { rd, rs1, imm } => encode_andi(rd, rs1, imm),
+            Instruction::Ori { rd, rs1, imm } => encode_ori(rd, rs1, imm),
+            Instruction::Xori { rd, rs1, imm } => encode_xori(rd, rs1, imm),
+            Instruction::Slti { rd, rs1, imm } => encode_slti(rd, rs1, imm),
+            Instruction::Jalr { rd, rs1, imm } => encode_jalr(rd, rs1, imm),
+            Instruction::Clz { rd, rs1 } => encode_clz(rd, rs1),
+            Instruction::Bne { rs1, rs2, offset } => encode_bne(rs1, rs2, offset),
+            Instruction::Beq { rs1, rs2, offset } => encode_beq(rs1, rs2, offset),
+            Instruction::Bge { rs1, rs2, offset } => encode_bge(rs1, rs2, offset),
+            Instruction::Bgeu { rs1, rs2, offset } => encode_bgeu(rs1, rs2, offset),
+            Instruction::Blt { rs1, rs2, offset } => encode_blt(rs1, rs2, offset),
+            Instruction::Sw { rs1, rs2, offset } => encode_sw(rs1, rs2, offset),
+            Instruction::Sb { rs1, rs2, offset } => encode_sb(rs1, rs2, offset),
+            Instruction::Sh { rs1, rs2, offset } => encode_sh(rs1, rs2, offset),
+            Instruction::Jal { rd, offset } => encode_jal(rd, offset),
+        }
+    }
+
+    // 未知操作码，或固定为0的字段不为0时返回None，保证decode(w)再encode得到原来的w
+    pub fn decode(word: u32) -> Option<Instruction> {
+        let rd = ((word >> 6) & 0x1F) as u8;
+        let rs1 = ((word >> 11) & 0x1F) as u8;
+        let rs2 = ((word >> 16) & 0x1F) as u8;
+        let imm = (word >> 16) as i16;
+        // C类型: [20:16]和[15:11]两个寄存器字段，偏移拆成高11位和低5位
+        let (c_high, c_low) = (rs2, rs1);
+        let c_offset = ((((word >> 21) & 0x7FF) << 5) | ((word >> 6) & 0x1F)) as i16;
+        // A类型的[31:21]固定为0，单操作数的A类型rs2也为0
+        let a_type = word >> 21 == 0;
+        let unary = word >> 16 == 0;
+
+        let instruction = match word & 0x3F {
+            OPCODE_HALT if word == 0 => Instruction::Halt,
+            OPCODE_ADD if a_type => Instruction::Add { rd, rs1, rs2 },
+            OPCODE_MUL if a_type => Instruction::Mul { rd, rs1, rs2 },
+            OPCODE_SUB if a_type => Instruction::Sub { rd, rs1, rs2 },
+            OPCODE_OR if a_type => Instruction::Or { rd, rs1, rs2 },
+            OPCODE_AND if a_type => Instruction::And { rd, rs1, rs2 },
+            OPCODE_XOR if a_type => Instruction::Xor { rd, rs1, rs2 },
+            OPCODE_NOR if a_type => Instruction::Nor { rd, rs1, rs2 },
+            OPCODE_SRL if a_type => Instruction::Srl { rd, rs1, rs2 },
+            OPCODE_SRA if a_type => Instruction::Sra { rd, rs1, rs2 },
+            OPCODE_SLT if a_type => Instruction::Slt { rd, rs1, rs2 },
+            OPCODE_SLTU if a_type => Instruction::Sltu { rd, rs1, rs2 },
+            OPCODE_CTZ if unary => Instruction::Ctz { rd, rs1 },
+            OPCODE_CPOP if unary => Instruction::Cpop { rd, rs1 },
+            OPCODE_REV8 if unary => Instruction::Rev8 { rd, rs1 },
+            OPCODE_BREV8 if unary => Instruction::Brev8 { rd, rs1 },
+            OPCODE_ADDI => Instruction::Addi { rd, rs1, imm },
+            OPCODE_LUI if rs1 == 0 => Instruction::Lui { rd, imm },
+            OPCODE_LW => Instruction::Lw { rd, rs1, offset: imm },
+            OPCODE_LB => Instruction::Lb { rd, rs1, offset: imm },
+            OPCODE_LH => Instruction::Lh { rd, rs1, offset: imm },
+            OPCODE_LBU => Instruction::Lbu { rd, rs1, offset: imm },
+            OPCODE_LHU => Instruction::Lhu { rd, rs1, offset: imm },
+            OPCODE_SLLI => Instruction::Slli { rd, rs1, imm },
+            OPCODE_SRLI => Instruction::Srli { rd, rs1, imm },
+            OPCODE_SRAI => Instruction::Srai { rd, rs1, imm },
+            OPCODE_ANDI => Instruction::Andi { rd, rs1, imm },
+            OPCODE_ORI => Instruction::Ori { rd, rs1, imm },
+            OPCODE_XORI => Instruction::Xori { rd, rs1, imm },
+            OPCODE_SLTI => Instruction::Slti { rd, rs1, imm },
+            OPCODE_JALR => Instruction::Jalr { rd, rs1, imm },
+            OPCODE_CLZ if unary => Instruction::Clz { rd, rs1 },
+            OPCODE_BNE => Instruction::Bne { rs1: c_high, rs2: c_low, offset: c_offset },
+            OPCODE_BEQ => Instruction::Beq { rs1: c_high, rs2: c_low, offset: c_offset },
+            OPCODE_BGE => Instruction::Bge { rs1: c_high, rs2: c_low, offset: c_offset },
+            OPCODE_BGEU => Instruction::Bgeu { rs1: c_high, rs2: c_low, offset: c_offset },
+            // blt和sw/sb/sh编码时交换了rs1和rs2
+            OPCODE_BLT => Instruction::Blt { rs1: c_low, rs2: c_high, offset: c_offset },
+            OPCODE_SW => Instruction::Sw { rs1: c_low, rs2: c_high, offset: c_offset },
+            OPCODE_SB => Instruction::Sb { rs1: c_low, rs2: c_high, offset: c_offset },
+            OPCODE_SH => Instruction::Sh { rs1: c_low, rs2: c_high, offset: c_offset },
+            OPCODE_JAL if word & (1 << 11) == 0 => Instruction::Jal { rd, offset: (word as i32) >> 12 },
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    // 16位立即数字段的值（访存和分支的偏移也算），jal和没有立即数的指令返回None
+    pub fn immediate(&self) -> Option<i16> {
+        match *self {
+            Instruction::Addi { imm, .. }
+            | Instruction::Lui { imm, .. }
+            | Instruction::Slli { imm, .. }
+            | Instruction::Srli { imm, .. }
+            | Instruction::Srai { imm, .. }
+            | Instruction::Andi { imm, .. }
+            | Instruction::Ori { imm, .. }
+            | Instruction::Xori { imm, .. }
+            | Instruction::Slti { imm, .. }
+            | Instruction::Jalr { imm, .. } => Some(imm),
+            Instruction::Lw { offset, .. }
+            | Instruction::Lb { offset, .. }
+            | Instruction::Lh { offset, .. }
+            | Instruction::Lbu { offset, .. }
+            | Instruction::Lhu { offset, .. }
+            | Instruction::Bne { offset, .. }
+            | Instruction::Beq { offset, .. }
+            | Instruction::Bge { offset, .. }
+            | Instruction::Bgeu { offset, .. }
+            | Instruction::Blt { offset, .. }
+            | Instruction::Sw { offset, .. }
+            | Instruction::Sb { offset, .. }
+            | Instruction::Sh { offset, .. } => Some(offset),
+            _ => None,
+        }
+    }
+
+    // 换掉分支指令的偏移，其他指令原样返回
+    pub fn with_branch_offset(self, offset: i16) -> Instruction {
+        match self {
+            Instruction::Bne { rs1, rs2, .. } => Instruction::Bne { rs1, rs2, offset },
+            Instruction::Beq { rs1, rs2, .. } => Instruction::Beq { rs1, rs2, offset },
+            Instruction::Bge { rs1, rs2, .. } => Instruction::Bge { rs1, rs2, offset },
+            Instruction::Bgeu { rs1, rs2, .. } => Instruction::Bgeu { rs1, rs2, offset },
+            Instruction::Blt { rs1, rs2, .. } => Instruction::Blt { rs1, rs2, offset },
+            other => other,
+        }
+    }
+}
+
+// 输出可以重新汇编的规范写法，不使用伪指令
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Halt => write!(f, "halt"),
+            Instruction::Add { rd, rs1, rs2 } => write!(f, "add x{}, x{}, x{}", rd, rs1, rs2),
+            Instruction::Mul { rd, rs1, rs2 } => write!(f, "mul x{}, x{}, x{}", rd, rs1, rs2),
+            Instruction::Sub { rd, rs1, rs2 } => write!(f, "sub x{}, x{}, x{}", rd, rs1, rs2),
+            Instruction::Or { rd, rs1, rs2 } => write!(f, "or x{}, x{}, x{}", rd, rs1, rs2),
+            Instruction::And { rd, rs1, rs2 } => write!(f, "and x{}, x{}, x{}", rd, rs1, rs2),
+            Instruction::Xor { rd, rs1, rs2 } => write!(f, "xor x{}, x{}, x{}", rd, rs1, rs2),
+            Instruction::Nor { rd, rs1, rs2 } => write!(f, "nor x{}, x{}, x{}", rd, rs1, rs2),
+            Instruction::Srl { rd, rs1, rs2 } => write!(f, "srl x{}, x{}, x{}", rd, rs1, rs2),
+            Instruction::Sra { rd, rs1, rs2 } => write!(f, "sra x{}, x{}, x{}", rd, rs1, rs2),
+            Instruction::Slt { rd, rs1, rs2 } => write!(f, "slt x{}, x{}, x{}", rd, rs1, rs2),
+            Instruction::Sltu { rd, rs1, rs2 } => write!(f, "sltu x{}, x{}, x{}", rd, rs1, rs2),
+            Instruction::Ctz { rd, rs1 } => write!(f, "ctz x{}, x{}", rd, rs1),
+            Instruction::Cpop { rd, rs1 } => write!(f, "cpop x{}, x{}", rd, rs1),
+            Instruction::Rev8 { rd, rs1 } => write!(f, "rev8 x{}, x{}", rd, rs1),
+            Instruction::Brev8 { rd, rs1 } => write!(f, "brev8 x{}, x{}", rd, rs1),
+            Instruction::Addi { rd, rs1, imm } => write!(f, "addi x{}, x{}, {}", rd, rs1, imm),
+            Instruction::Lui { rd, imm } => write!(f, "lui x{}, {}", rd, imm),
+            Instruction::Lw { rd, rs1, offset } => write!(f, "lw x{}, {}(x{})", rd, offset, rs1),
+            Instruction::Lb { rd, rs1, offset } => write!(f, "lb x{}, {}(x{})", rd, offset, rs1),
+            Instruction::Lh { rd, rs1, offset } => write!(f, "lh x{}, {}(x{})", rd, offset, rs1),
+            Instruction::Lbu { rd, rs1, offset } => write!(f, "lbu x{}, {}(x{})", rd, offset, rs1),
+            Instruction::Lhu { rd, rs1, offset } => write!(f, "lhu x{}, {}(x{})", rd, offset, rs1),
+            Instruction::Slli { rd, rs1, imm } => write!(f, "slli x{}, x{}, {}", rd, rs1, imm),
+            Instruction::Srli { rd, rs1, imm } => write!(f, "srli x{}, x{}, {}", rd, rs1, imm),
+            Instruction::Srai { rd, rs1, imm } => write!(f, "srai x{}, x{}, {}", rd, rs1, imm),
+            Instruction::Andi { rd, rs1, imm } => write!(f, "andi x{}, x{}, {}", rd, rs1, imm),
+            Instruction::Ori { rd, rs1, imm } => write!(f, "ori x{}, x{}, {}", rd, rs1, imm),
+            Instruction::Xori { rd, rs1, imm } => write!(f, "xori x{}, x{}, {}", rd, rs1, imm),
+            Instruction::Slti { rd, rs1, imm } => write!(f, "slti x{}, x{}, {}", rd, rs1, imm),
+            Instruction::Jalr { rd, rs1, imm } => write!(f, "jalr x{}, x{}, {}", rd, rs1, imm),
+            Instruction::Clz { rd, rs1 } => write!(f, "clz x{}, x{}", rd, rs1),
+            Instruction::Bne { rs1, rs2, offset } => write!(f, "bne x{}, x{}, {}", rs1, rs2, offset),
+            Instruction::Beq { rs1, rs2, offset } => write!(f, "beq x{}, x{}, {}", rs1, rs2, offset),
+            Instruction::Bge { rs1, rs2, offset } => write!(f, "bge x{}, x{}, {}", rs1, rs2, offset),
+            Instruction::Bgeu { rs1, rs2, offset } => write!(f, "bgeu x{}, x{}, {}", rs1, rs2, offset),
+            Instruction::Blt { rs1, rs2, offset } => write!(f, "blt x{}, x{}, {}", rs1, rs2, offset),
+            Instruction::Sw { rs1, rs2, offset } => write!(f, "sw x{}, {}(x{})", rs2, offset, rs1),
+            Instruction::Sb { rs1, rs2, offset } => write!(f, "sb x{}, {}(x{})", rs2, offset, rs1),
+            Instruction::Sh { rs1, rs2, offset } => write!(f, "sh x{}, {}(x{})", rs2, offset, rs1),
+            Instruction::Jal { rd, offset } => write!(f, "jal x{}, {}", rd, offset),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 每种指令各一条：汇编文本、对应的Instruction
+    fn samples() -> Vec<(&'static str, Instruction)> {
+        vec![
+            ("halt", Instruction::Halt),
+            ("add x1, x2, x3", Instruction::Add { rd: 1, rs1: 2, rs2: 3 }),
+            ("mul x4, x5, x6", Instruction::Mul { rd: 4, rs1: 5, rs2: 6 }),
+            ("sub x7, x8, x9", Instruction::Sub { rd: 7, rs1: 8, rs2: 9 }),
+            ("or x10, x11, x12", Instruction::Or { rd: 10, rs1: 11, rs2: 12 }),
+            ("and x13, x14, x15", Instruction::And { rd: 13, rs1: 14, rs2: 15 }),
+            ("xor x16, x17, x18", Instruction::Xor { rd: 16, rs1: 17, rs2: 18 }),
+            ("nor x19, x20, x0", Instruction::Nor { rd: 19, rs1: 20, rs2: 0 }),
+            ("srl x25, x26, x27", Instruction::Srl { rd: 25, rs1: 26, rs2: 27 }),
+            ("sra x28, x29, x30", Instruction::Sra { rd: 28, rs1: 29, rs2: 30 }),
+            ("slt x19, x20, x21", Instruction::Slt { rd: 19, rs1: 20, rs2: 21 }),
+            ("sltu x22, x23, x24", Instruction::Sltu { rd: 22, rs1: 23, rs2: 24 }),
+            ("ctz x1, x2", Instruction::Ctz { rd: 1, rs1: 2 }),
+            ("cpop x3, x4", Instruction::Cpop { rd: 3, rs1: 4 }),
+            ("rev8 x5, x6", Instruction::Rev8 { rd: 5, rs1: 6 }),
+            ("brev8 x7, x8", Instruction::Brev8 { rd: 7, rs1: 8 }),
+            ("addi x1, x2, -5", Instruction::Addi { rd: 1, rs1: 2, imm: -5 }),
+            ("lui x3, -2", Instruction::Lui { rd: 3, imm: -2 }),
+            ("lw x4, 8(x5)", Instruction::Lw { rd: 4, rs1: 5, offset: 8 }),
+            ("lb x6, -1(x7)", Instruction::Lb { rd: 6, rs1: 7, offset: -1 }),
+            ("lh x8, 2(x9)", Instruction::Lh { rd: 8, rs1: 9, offset: 2 }),
+            ("lbu x10, 3(x11)", Instruction::Lbu { rd: 10, rs1: 11, offset: 3 }),
+            ("lhu x12, -2(x13)", Instruction::Lhu { rd: 12, rs1: 13, offset: -2 }),
+            ("slli x6, x7, 3", Instruction::Slli { rd: 6, rs1: 7, imm: 3 }),
+            ("srli x8, x9, 31", Instruction::Srli { rd: 8, rs1: 9, imm: 31 }),
+            ("srai x10, x11, 1", Instruction::Srai { rd: 10, rs1: 11, imm: 1 }),
+            ("andi x12, x13, 255", Instruction::Andi { rd: 12, rs1: 13, imm: 255 }),
+            ("ori x14, x15, -1", Instruction::Ori { rd: 14, rs1: 15, imm: -1 }),
+            ("xori x16, x17, 32767", Instruction::Xori { rd: 16, rs1: 17, imm: 32767 }),
+            ("slti x18, x19, -32768", Instruction::Slti { rd: 18, rs1: 19, imm: -32768 }),
+            ("jalr x1, x2, 4", Instruction::Jalr { rd: 1, rs1: 2, imm: 4 }),
+            ("clz x20, x21", Instruction::Clz { rd: 20, rs1: 21 }),
+            ("bne x1, x2, -16", Instruction::Bne { rs1: 1, rs2: 2, offset: -16 }),
+            ("beq x3, x4, 8", Instruction::Beq { rs1: 3, rs2: 4, offset: 8 }),
+            ("bge x5, x6, -4", Instruction::Bge { rs1: 5, rs2: 6, offset: -4 }),
+            ("bgeu x7, x8, 1020", Instruction::Bgeu { rs1: 7, rs2: 8, offset: 1020 }),
+            ("blt x14, x15, 20", Instruction::Blt { rs1: 14, rs2: 15, offset: 20 }),
+            ("sw x11, 12(x12)", Instruction::Sw { rs1: 12, rs2: 11, offset: 12 }),
+            ("sb x13, -1(x14)", Instruction::Sb { rs1: 14, rs2: 13, offset: -1 }),
+            ("sh x15, 2(x16)", Instruction::Sh { rs1: 16, rs2: 15, offset: 2 }),
+            ("jal x1, -524288", Instruction::Jal { rd: 1, offset: -524288 }),
+        ]
+    }
+
+    #[test]
+    fn test_instruction_matches_assembler() {
+        for (text, instruction) in samples() {
+            let word = assemble(text).unwrap()[0];
+            assert_eq!(instruction.encode(), word, "{}", text);
+            assert_eq!(Instruction::decode(word), Some(instruction), "{}", text);
+            assert_eq!(instruction.to_string(), text);
+            assert_eq!(decode_instruction(word), disasm_text(text), "{}", text);
+        }
+    }
+
+    // 属性测试用的xorshift64伪随机数。不引入依赖，每个属性在下面几个固定种子上各跑一遍，
+    // 失败信息带上种子，可以复现
+    struct Rng(u64);
+
+    const SEEDS: [u64; 4] = [0x2545_F491_4F6C_DD1D, 0x9E37_79B9_7F4A_7C15, 0xD1B5_4A32_D192_ED03, 0x8CB9_2BA7_2F3D_8DD7];
+
+    impl Rng {
+        fn next_u32(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 32) as u32
+        }
+
+        fn reg(&mut self) -> u8 {
+            (self.next_u32() % 32) as u8
+        }
+    }
+
+    // 按OPCODE_TABLE随机生成一条指令的汇编文本，操作数取各字段的整个取值范围
+    fn random_instruction(rng: &mut Rng) -> (&'static OpcodeEntry, String) {
+        let entry = &OPCODE_TABLE[rng.next_u32() as usize % OPCODE_TABLE.len()];
+        let m = entry.mnemonic;
+        let (rd, rs1, rs2, imm) = (rng.reg(), rng.reg(), rng.reg(), rng.next_u32() as i16);
+        let text = match m {
+            "halt" => m.to_string(),
+            "ctz" | "cpop" | "rev8" | "brev8" | "clz" => format!("{} x{}, x{}", m, rd, rs1),
+            "lui" => format!("lui x{}, {}", rd, imm),
+            "slli" | "srli" | "srai" => format!("{} x{}, x{}, {}", m, rd, rs1, imm & 0x1F),
+            "lw" | "lb" | "lh" | "lbu" | "lhu" => format!("{} x{}, {}(x{})", m, rd, imm, rs1),
+            "sw" | "sb" | "sh" => format!("{} x{}, {}(x{})", m, rs2, imm, rs1),
+            "jal" => format!("jal x{}, {}", rd, (rng.next_u32() as i32) >> 12),
+            _ => match entry.itype {
+                InstructionType::A => format!("{} x{}, x{}, x{}", m, rd, rs1, rs2),
+                InstructionType::C => format!("{} x{}, x{}, {}", m, rs1, rs2, imm),
+                _ => format!("{} x{}, x{}, {}", m, rd, rs1, imm),
+            },
+        };
+        (entry, text)
+    }
+
+    // decode_instruction对部分编码显示为伪指令，把Display的写法换成对应的伪指令
+    fn disasm_text(text: &str) -> String {
+        let operands: Vec<&str> = text.split([' ', ',']).filter(|s| !s.is_empty()).collect();
+        match operands[..] {
+            ["addi", "x0", "x0", "0"] => "nop".to_string(),
+            ["addi", rd, "x0", "0"] => format!("zero {}", rd),
+            ["beq", rs, "x0", offset] => format!("beqz {}, {}", rs, offset),
+            ["bne", rs, "x0", offset] => format!("bnez {}, {}", rs, offset),
+            ["bge", rs, "x0", offset] => format!("bgez {}, {}", rs, offset),
+            ["blt", rs, "x0", offset] => format!("bltz {}, {}", rs, offset),
+            ["jal", "x0", offset] => format!("j {}", offset),
+            _ => text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_round_trip_property() {
+        for seed in SEEDS {
+            let mut rng = Rng(seed);
+            for _ in 0..5_000 {
+                let (entry, text) = random_instruction(&mut rng);
+                let context = format!("种子0x{:016X}: {}", seed, text);
+                let word = assemble(&text).unwrap_or_else(|e| panic!("{}: {:?}", context, e))[0];
+                let instruction = Instruction::decode(word).unwrap_or_else(|| panic!("{}: 0x{:08X}无法解码", context, word));
+                assert_eq!(instruction.encode(), word, "{}", context);
+                assert_eq!(instruction.to_string(), text, "{}", context);
+                assert_eq!(decode_instruction(word), disasm_text(&text), "{}", context);
+                assert_eq!(word & 0x3F, entry.opcode, "{}", context);
+
+                // 按InstructionType::layout检查立即数所在的位字段
+                let operands: Vec<&str> = text.split([' ', ',', '(', ')']).filter(|s| !s.is_empty()).collect();
+                let imm = if text.contains('(') || entry.mnemonic == "lui" { operands[2] } else { operands[operands.len() - 1] };
+                match entry.itype {
+                    InstructionType::A => assert_eq!(word >> 21, 0, "{}", context),
+                    InstructionType::B if entry.mnemonic != "clz" => assert_eq!((word >> 16) as i16, imm.parse().unwrap(), "{}", context),
+                    InstructionType::C => {
+                        let offset = ((((word >> 21) & 0x7FF) << 5) | ((word >> 6) & 0x1F)) as i16;
+                        assert_eq!(offset, imm.parse().unwrap(), "{}", context);
+                    }
+                    InstructionType::J => assert_eq!((word as i32) >> 12, imm.parse().unwrap(), "{}", context),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_encode_property() {
+        // 任意字能解码时，重新编码必须得到原来的字
+        for seed in SEEDS {
+            let mut rng = Rng(seed);
+            let mut decoded = 0;
+            for _ in 0..10_000 {
+                let mut word = rng.next_u32();
+                // 一半的字使用已定义的操作码，并清零A类型的保留位，使各类型都被覆盖
+                if rng.next_u32() & 1 == 0 {
+                    let entry = &OPCODE_TABLE[rng.next_u32() as usize % OPCODE_TABLE.len()];
+                    word = (word & !0x3F) | entry.opcode;
+                    if entry.itype == InstructionType::A {
+                        word &= 0x1F_FFFF;
+                    }
+                }
+                if let Some(instruction) = Instruction::decode(word) {
+                    assert_eq!(instruction.encode(), word, "种子0x{:016X}: 0x{:08X} {}", seed, word, instruction);
+                    decoded += 1;
+                }
+            }
+            assert!(decoded > 3000, "种子0x{:016X}: 只有{}个字可以解码", seed, decoded);
+        }
+    }
+
+    #[test]
+    fn test_instruction_decode_rejects_unknown() {
+        // 未知操作码
+        assert_eq!(Instruction::decode(0b111111), None);
+        // 操作码为0但不全为0
+        assert_eq!(Instruction::decode(0x0000_0040), None);
+        // A类型[31:21]不为0
+        assert_eq!(Instruction::decode(encode_add(1, 2, 3) | (1 << 21)), None);
+        // 单操作数指令的rs2或立即数字段不为0
+        assert_eq!(Instruction::decode(encode_ctz(1, 2) | (1 << 16)), None);
+        assert_eq!(Instruction::decode(encode_clz(1, 2) | (1 << 16)), None);
+        // lui的rs1固定为0，jal的第11位固定为0
+        assert_eq!(Instruction::decode(encode_lui(1, 5) | (3 << 11)), None);
+        assert_eq!(Instruction::decode(encode_jal(1, 8) | (1 << 11)), None);
+    }
+}
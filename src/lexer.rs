@@ -0,0 +1,207 @@
+// 按记号切分一行汇编源码
+
+use std::ops::Range;
+
+use crate::{parse_int, parse_reg};
+
+// 一行源码中的记号。寄存器和立即数在切分时即被识别，
+// 其余的单词（标签名、常量名、段名等）是Identifier
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Mnemonic(String),   // 行首的指令或伪指令助记符
+    Register(u8),       // x0~x31或调用约定中的寄存器名
+    Immediate(i32),     // 十进制、0x十六进制或0b二进制整数（可用下划线分组），或'A'形式的字符，0x80000000~0xFFFFFFFF按补码视为负数
+    Identifier(String), // 标签名、常量名等
+    Str(String),        // 带双引号的字符串字面量（原样保留，含引号和转义）
+    Comma,
+    LParen,
+    RParen,
+    Colon,
+    Newline,
+    Comment(String),    // #之后到行尾的内容，不含#
+}
+
+// 切分line，返回记号及其在line中的字节范围
+pub fn tokenize_with_spans(line: &str) -> Vec<(Token, Range<usize>)> {
+    let mut tokens = Vec::new();
+    let mut seen_mnemonic = false;
+    let mut chars = line.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        let token = match c {
+            _ if c.is_whitespace() && c != '\n' => {
+                chars.next();
+                continue;
+            }
+            ',' | '(' | ')' | ':' | '\n' => {
+                chars.next();
+                match c {
+                    ',' => Token::Comma,
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    ':' => Token::Colon,
+                    _ => Token::Newline,
+                }
+            }
+            '#' => {
+                let end = line[start..].find('\n').map_or(line.len(), |offset| start + offset);
+                while chars.next_if(|&(i, _)| i < end).is_some() {}
+                tokens.push((Token::Comment(line[start + 1..end].to_string()), start..end));
+                continue;
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut escaped = false;
+                while let Some((_, next)) = chars.next_if(|&(_, c)| c != '\n') {
+                    match next {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        _ if next == c => break,
+                        _ => {}
+                    }
+                }
+                let end = chars.peek().map_or(line.len(), |&(i, _)| i);
+                match c {
+                    '"' => Token::Str(line[start..end].to_string()),
+                    _ => classify_word(&line[start..end]),
+                }
+            }
+            _ => {
+                while chars.next_if(|&(_, c)| !is_delimiter(c)).is_some() {}
+                let end = chars.peek().map_or(line.len(), |&(i, _)| i);
+                let word = &line[start..end];
+                let is_label = line[end..].trim_start_matches([' ', '\t']).starts_with(':');
+                if !seen_mnemonic && !is_label {
+                    seen_mnemonic = true;
+                    Token::Mnemonic(word.to_string())
+                } else {
+                    classify_word(word)
+                }
+            }
+        };
+        let end = chars.peek().map_or(line.len(), |&(i, _)| i);
+        if token == Token::Newline {
+            seen_mnemonic = false;
+        }
+        tokens.push((token, start..end));
+    }
+    tokens
+}
+
+pub fn tokenize(line: &str) -> Vec<Token> {
+    tokenize_with_spans(line).into_iter().map(|(token, _)| token).collect()
+}
+
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || matches!(c, ',' | '(' | ')' | ':' | '"' | '#')
+}
+
+fn classify_word(word: &str) -> Token {
+    if let Ok(reg) = parse_reg(word) {
+        return Token::Register(reg);
+    }
+    match parse_int(word) {
+        Some(value) if (i32::MIN as i64..=u32::MAX as i64).contains(&value) => Token::Immediate(value as i32),
+        _ => Token::Identifier(word.to_string()),
+    }
+}
+
+// 拆分一行的助记符和操作数。操作数以逗号分隔，也可以只用空白分隔；
+// 括号内外的记号属于同一个操作数（如 4( x2 ) 即 4(x2)），多余的逗号被忽略
+pub(crate) fn split_operands(line: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut previous_atom = false;
+    for (token, span) in tokenize_with_spans(line) {
+        let atom = !matches!(token, Token::Comma | Token::LParen | Token::RParen | Token::Colon);
+        match token {
+            Token::Comment(_) | Token::Newline => continue,
+            Token::Comma => {
+                if !current.is_empty() {
+                    parts.push(std::mem::take(&mut current));
+                }
+            }
+            _ => {
+                // 第一个记号总是助记符；两个相邻的单词之间没有括号时是两个操作数，
+                // 但运算符两侧的单词属于同一个表达式（如 BASE + N * 4）
+                let word = &line[span.clone()];
+                if parts.is_empty() && !current.is_empty() || atom && previous_atom && !continues_expression(&current, word) {
+                    parts.push(std::mem::take(&mut current));
+                }
+                current.push_str(word);
+            }
+        }
+        previous_atom = atom;
+    }
+    if !current.is_empty() || parts.is_empty() {
+        parts.push(current);
+    }
+    parts
+}
+
+// word是否与前面的current连成一个表达式。单独的-才算减号，-1这样的单词仍是新的操作数（如 addi x1 x0 -1）
+fn continues_expression(current: &str, word: &str) -> bool {
+    const OPERATORS: [char; 7] = ['+', '-', '*', '/', '%', '<', '>'];
+    current.ends_with(OPERATORS) || word.starts_with(['+', '*', '/', '%', '<', '>']) || word == "-"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_whitespace_and_commas() {
+        let expected = vec![
+            Token::Mnemonic("add".to_string()),
+            Token::Register(1), Token::Comma, Token::Register(2), Token::Comma, Token::Register(3),
+        ];
+        assert_eq!(tokenize("add x1, x2, x3"), expected);
+        assert_eq!(tokenize("add\tx1,x2 ,\t x3"), expected);
+        assert_eq!(tokenize("  add   x1 ,  x2,x3  "), expected);
+
+        let mut trailing = expected.clone();
+        trailing.push(Token::Comma);
+        assert_eq!(tokenize("add x1, x2, x3,"), trailing);
+    }
+
+    #[test]
+    fn test_tokenize_operands() {
+        // 行内注释、负十六进制立即数和调用约定中的寄存器名
+        assert_eq!(tokenize("lw a0, -0x10(sp) # 读取参数"), vec![
+            Token::Mnemonic("lw".to_string()),
+            Token::Register(10), Token::Comma,
+            Token::Immediate(-16), Token::LParen, Token::Register(2), Token::RParen,
+            Token::Comment(" 读取参数".to_string()),
+        ]);
+        assert_eq!(tokenize("li t0, 0xFFFFFFFF"), vec![
+            Token::Mnemonic("li".to_string()), Token::Register(5), Token::Comma, Token::Immediate(-1),
+        ]);
+        // 标签定义不是助记符；逗号和#在字符串中不分隔记号
+        assert_eq!(tokenize("loop: bne x1, x0, loop"), vec![
+            Token::Identifier("loop".to_string()), Token::Colon,
+            Token::Mnemonic("bne".to_string()), Token::Register(1), Token::Comma, Token::Register(0),
+            Token::Comma, Token::Identifier("loop".to_string()),
+        ]);
+        assert_eq!(tokenize(".ascii \"a, #b\\\"\"\n.byte 1"), vec![
+            Token::Mnemonic(".ascii".to_string()), Token::Str("\"a, #b\\\"\"".to_string()), Token::Newline,
+            Token::Mnemonic(".byte".to_string()), Token::Immediate(1),
+        ]);
+        assert_eq!(tokenize("li a0, ','"), vec![
+            Token::Mnemonic("li".to_string()), Token::Register(10), Token::Comma, Token::Immediate(44),
+        ]);
+    }
+
+    #[test]
+    fn test_split_operands() {
+        assert_eq!(split_operands("add x1,x2,x3"), ["add", "x1", "x2", "x3"]);
+        assert_eq!(split_operands("add x1 x2, x3,"), ["add", "x1", "x2", "x3"]);
+        assert_eq!(split_operands("sw t0, 4 ( sp )"), ["sw", "t0", "4(sp)"]);
+        assert_eq!(split_operands(".ascii \"a, b\""), [".ascii", "\"a, b\""]);
+        assert_eq!(split_operands("addi x1, x0, ' '"), ["addi", "x1", "x0", "' '"]);
+        assert_eq!(split_operands(""), [""]);
+        assert_eq!(split_operands("li x4, BASE + COUNT * 4"), ["li", "x4", "BASE+COUNT*4"]);
+        assert_eq!(split_operands("lw x1, (OFFSET + 8) (x3)"), ["lw", "x1", "(OFFSET+8)(x3)"]);
+        assert_eq!(split_operands("addi x1 x0 - 1"), ["addi", "x1", "x0-1"]);
+        assert_eq!(split_operands("addi x1 x0 -1"), ["addi", "x1", "x0", "-1"]);
+    }
+}
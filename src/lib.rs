@@ -0,0 +1,4572 @@
+// 汇编器、反汇编器和静态分析的核心实现，命令行参数和文件读写在main.rs中
+
+// 二进制字面量按指令字段(11_5_5_5_6)分组，而非按等宽分组
+#![allow(clippy::unusual_byte_groupings)]
+
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::Instant;
+
+mod conditional;
+pub mod expr;
+mod include;
+mod instruction;
+pub mod lexer;
+pub mod macros;
+pub mod object;
+pub mod parser;
+pub mod sim;
+
+use conditional::ConditionStack;
+pub use expr::{evaluate_expr, ExprError};
+pub use instruction::Instruction;
+
+// 常量定义（各操作码的格式与语义见下方 OPCODE_TABLE）
+pub const OPCODE_HALT: u32 = 0b000000;
+pub const OPCODE_ADD: u32 = 0b000001;
+pub const OPCODE_ADDI: u32 = 0b000010;
+pub const OPCODE_BNE: u32 = 0b000011;
+pub const OPCODE_MUL: u32 = 0b000100;
+pub const OPCODE_LUI: u32 = 0b000101;
+pub const OPCODE_LW: u32 = 0b000110;
+pub const OPCODE_SW: u32 = 0b000111;
+pub const OPCODE_BLT: u32 = 0b001000;
+pub const OPCODE_SLLI: u32 = 0b001001;
+pub const OPCODE_SUB: u32 = 0b001010;
+pub const OPCODE_OR: u32 = 0b001011;
+pub const OPCODE_AND: u32 = 0b001100;
+pub const OPCODE_XOR: u32 = 0b001101;
+pub const OPCODE_SRLI: u32 = 0b001110;
+pub const OPCODE_SRAI: u32 = 0b001111;
+pub const OPCODE_ANDI: u32 = 0b010000;
+pub const OPCODE_ORI: u32 = 0b010001;
+pub const OPCODE_XORI: u32 = 0b010010;
+pub const OPCODE_BEQ: u32 = 0b010011;
+pub const OPCODE_BGE: u32 = 0b010100;
+pub const OPCODE_BGEU: u32 = 0b010101;
+pub const OPCODE_SLT: u32 = 0b010110;
+pub const OPCODE_SLTU: u32 = 0b010111;
+pub const OPCODE_SLTI: u32 = 0b011000;
+pub const OPCODE_JAL: u32 = 0b011001;
+pub const OPCODE_JALR: u32 = 0b011010;
+pub const OPCODE_LB: u32 = 0b011011;
+pub const OPCODE_LH: u32 = 0b011100;
+pub const OPCODE_LBU: u32 = 0b011101;
+pub const OPCODE_LHU: u32 = 0b011110;
+pub const OPCODE_SB: u32 = 0b011111;
+pub const OPCODE_SH: u32 = 0b100000;
+pub const OPCODE_SRL: u32 = 0b100001;
+pub const OPCODE_SRA: u32 = 0b100010;
+pub const OPCODE_NOR: u32 = 0b100011;
+pub const OPCODE_CLZ: u32 = 0b101001;
+pub const OPCODE_CTZ: u32 = 0b101010;
+pub const OPCODE_CPOP: u32 = 0b101011;
+pub const OPCODE_REV8: u32 = 0b101100;
+pub const OPCODE_BREV8: u32 = 0b101101;
+
+// =================== 操作码表 ===================
+
+// 指令格式类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionType {
+    Halt,
+    A,
+    B,
+    C,
+    J,
+}
+
+impl InstructionType {
+    pub fn name(self) -> &'static str {
+        match self {
+            InstructionType::Halt => "停机指令",
+            InstructionType::A => "A型指令",
+            InstructionType::B => "B型指令",
+            InstructionType::C => "C型指令",
+            InstructionType::J => "J型指令",
+        }
+    }
+
+    // 字段布局，高位在前
+    pub fn layout(self) -> &'static str {
+        match self {
+            InstructionType::Halt => "全0",
+            InstructionType::A => "0[31:21] rs2[20:16] rs1[15:11] rd[10:6] opcode[5:0]",
+            InstructionType::B => "imm[31:16] rs1[15:11] rd[10:6] opcode[5:0]",
+            InstructionType::C => "imm_high[31:21] rs1[20:16] rs2[15:11] imm_low[10:6] opcode[5:0]",
+            InstructionType::J => "offset[31:12] 0[11] rd[10:6] opcode[5:0]",
+        }
+    }
+}
+
+pub struct OpcodeEntry {
+    pub opcode: u32,
+    pub mnemonic: &'static str,
+    pub itype: InstructionType,
+    pub description: &'static str,
+}
+
+pub const OPCODE_TABLE: &[OpcodeEntry] = &[
+    OpcodeEntry { opcode: OPCODE_HALT, mnemonic: "halt", itype: InstructionType::Halt, description: "停止执行" },
+    OpcodeEntry { opcode: OPCODE_ADD, mnemonic: "add", itype: InstructionType::A, description: "x[rd] = x[rs1] + x[rs2]" },
+    OpcodeEntry { opcode: OPCODE_MUL, mnemonic: "mul", itype: InstructionType::A, description: "x[rd] = x[rs1] * x[rs2]" },
+    OpcodeEntry { opcode: OPCODE_SUB, mnemonic: "sub", itype: InstructionType::A, description: "x[rd] = x[rs1] - x[rs2]" },
+    OpcodeEntry { opcode: OPCODE_CTZ, mnemonic: "ctz", itype: InstructionType::A, description: "x[rd] = x[rs1]的末尾零个数，rs2固定为0" },
+    OpcodeEntry { opcode: OPCODE_CPOP, mnemonic: "cpop", itype: InstructionType::A, description: "x[rd] = x[rs1]中1的个数，rs2固定为0" },
+    OpcodeEntry { opcode: OPCODE_REV8, mnemonic: "rev8", itype: InstructionType::A, description: "x[rd] = x[rs1]按字节反转（大小端互换），rs2固定为0" },
+    OpcodeEntry { opcode: OPCODE_BREV8, mnemonic: "brev8", itype: InstructionType::A, description: "x[rd] = x[rs1]每个字节内部按位反转，rs2固定为0" },
+    OpcodeEntry { opcode: OPCODE_OR, mnemonic: "or", itype: InstructionType::A, description: "x[rd] = x[rs1] | x[rs2]" },
+    OpcodeEntry { opcode: OPCODE_AND, mnemonic: "and", itype: InstructionType::A, description: "x[rd] = x[rs1] & x[rs2]" },
+    OpcodeEntry { opcode: OPCODE_XOR, mnemonic: "xor", itype: InstructionType::A, description: "x[rd] = x[rs1] ^ x[rs2]" },
+    OpcodeEntry { opcode: OPCODE_SLT, mnemonic: "slt", itype: InstructionType::A, description: "x[rd] = x[rs1] <s x[rs2] ? 1 : 0" },
+    OpcodeEntry { opcode: OPCODE_SLTU, mnemonic: "sltu", itype: InstructionType::A, description: "x[rd] = x[rs1] <u x[rs2] ? 1 : 0" },
+    OpcodeEntry { opcode: OPCODE_SRL, mnemonic: "srl", itype: InstructionType::A, description: "x[rd] = x[rs1] >>u x[rs2][4:0]" },
+    OpcodeEntry { opcode: OPCODE_NOR, mnemonic: "nor", itype: InstructionType::A, description: "x[rd] = ~(x[rs1] | x[rs2])" },
+    OpcodeEntry { opcode: OPCODE_SRA, mnemonic: "sra", itype: InstructionType::A, description: "x[rd] = x[rs1] >>s x[rs2][4:0]" },
+    OpcodeEntry { opcode: OPCODE_ADDI, mnemonic: "addi", itype: InstructionType::B, description: "x[rd] = x[rs1] + sext(imm)" },
+    OpcodeEntry { opcode: OPCODE_LUI, mnemonic: "lui", itype: InstructionType::B, description: "x[rd] = sext(imm) << 16，rs1固定为0" },
+    OpcodeEntry { opcode: OPCODE_LW, mnemonic: "lw", itype: InstructionType::B, description: "x[rd] = M[x[rs1] + sext(imm)]" },
+    OpcodeEntry { opcode: OPCODE_LB, mnemonic: "lb", itype: InstructionType::B, description: "x[rd] = sext(M[x[rs1] + sext(imm)][7:0])" },
+    OpcodeEntry { opcode: OPCODE_LH, mnemonic: "lh", itype: InstructionType::B, description: "x[rd] = sext(M[x[rs1] + sext(imm)][15:0])" },
+    OpcodeEntry { opcode: OPCODE_LBU, mnemonic: "lbu", itype: InstructionType::B, description: "x[rd] = zext(M[x[rs1] + sext(imm)][7:0])" },
+    OpcodeEntry { opcode: OPCODE_LHU, mnemonic: "lhu", itype: InstructionType::B, description: "x[rd] = zext(M[x[rs1] + sext(imm)][15:0])" },
+    OpcodeEntry { opcode: OPCODE_SLLI, mnemonic: "slli", itype: InstructionType::B, description: "x[rd] = x[rs1] << imm" },
+    OpcodeEntry { opcode: OPCODE_CLZ, mnemonic: "clz", itype: InstructionType::B, description: "x[rd] = x[rs1]的前导零个数，imm固定为0" },
+    OpcodeEntry { opcode: OPCODE_SRLI, mnemonic: "srli", itype: InstructionType::B, description: "x[rd] = x[rs1] >>u imm（逻辑右移，imm为0~31）" },
+    OpcodeEntry { opcode: OPCODE_SRAI, mnemonic: "srai", itype: InstructionType::B, description: "x[rd] = x[rs1] >>s imm（算术右移，imm为0~31）" },
+    OpcodeEntry { opcode: OPCODE_ANDI, mnemonic: "andi", itype: InstructionType::B, description: "x[rd] = x[rs1] & sext(imm)" },
+    OpcodeEntry { opcode: OPCODE_ORI, mnemonic: "ori", itype: InstructionType::B, description: "x[rd] = x[rs1] | sext(imm)" },
+    OpcodeEntry { opcode: OPCODE_XORI, mnemonic: "xori", itype: InstructionType::B, description: "x[rd] = x[rs1] ^ sext(imm)" },
+    OpcodeEntry { opcode: OPCODE_SLTI, mnemonic: "slti", itype: InstructionType::B, description: "x[rd] = x[rs1] <s sext(imm) ? 1 : 0" },
+    OpcodeEntry { opcode: OPCODE_JALR, mnemonic: "jalr", itype: InstructionType::B, description: "x[rd] = pc + 4; pc = x[rs1] + sext(imm)" },
+    OpcodeEntry { opcode: OPCODE_BNE, mnemonic: "bne", itype: InstructionType::C, description: "如果 rs1 != rs2，则 pc += sext(offset)" },
+    OpcodeEntry { opcode: OPCODE_SW, mnemonic: "sw", itype: InstructionType::C, description: "M[x[rs1] + sext(imm)] = x[rs2]，rs1/rs2字段互换存放" },
+    OpcodeEntry { opcode: OPCODE_SB, mnemonic: "sb", itype: InstructionType::C, description: "M[x[rs1] + sext(imm)][7:0] = x[rs2][7:0]，字段同sw" },
+    OpcodeEntry { opcode: OPCODE_SH, mnemonic: "sh", itype: InstructionType::C, description: "M[x[rs1] + sext(imm)][15:0] = x[rs2][15:0]，字段同sw" },
+    OpcodeEntry { opcode: OPCODE_BLT, mnemonic: "blt", itype: InstructionType::C, description: "如果 rs1 <s rs2，则 pc += sext(offset)" },
+    OpcodeEntry { opcode: OPCODE_BEQ, mnemonic: "beq", itype: InstructionType::C, description: "如果 rs1 == rs2，则 pc += sext(offset)" },
+    OpcodeEntry { opcode: OPCODE_BGE, mnemonic: "bge", itype: InstructionType::C, description: "如果 rs1 >=s rs2，则 pc += sext(offset)" },
+    OpcodeEntry { opcode: OPCODE_BGEU, mnemonic: "bgeu", itype: InstructionType::C, description: "如果 rs1 >=u rs2，则 pc += sext(offset)" },
+    OpcodeEntry { opcode: OPCODE_JAL, mnemonic: "jal", itype: InstructionType::J, description: "x[rd] = pc + 4; pc += sext(offset)" },
+];
+
+pub fn lookup_opcode(opcode: u32) -> Option<&'static OpcodeEntry> {
+    OPCODE_TABLE.iter().find(|entry| entry.opcode == opcode)
+}
+
+// 说明某条指令立即数的有效范围和所在的位字段
+pub fn explain_immediate(mnemonic: &str) -> Option<String> {
+    let entry = OPCODE_TABLE.iter().find(|entry| entry.mnemonic == mnemonic)?;
+
+    let explanation = match (entry.itype, entry.opcode) {
+        (InstructionType::B, OPCODE_SLLI | OPCODE_SRLI | OPCODE_SRAI) => {
+            "移位量: 0 ~ 31（5位无符号）\n位字段: imm[31:16]，只有低5位有意义".to_string()
+        }
+        (InstructionType::B, OPCODE_CLZ) => "无立即数（imm[31:16]固定为0）".to_string(),
+        (InstructionType::B, OPCODE_LUI) => {
+            "范围: -32768 ~ 65535（16位，有符号和无符号写法都可以，执行时左移16位）\n位字段: imm[31:16]".to_string()
+        }
+        (InstructionType::B, _) => "范围: -32768 ~ 32767（16位有符号）\n位字段: imm[31:16]".to_string(),
+        (InstructionType::C, _) => {
+            "范围: -32768 ~ 32767（16位有符号，拆分为两段）\n位字段: imm[15:5] -> [31:21]，imm[4:0] -> [10:6]".to_string()
+        }
+        (InstructionType::J, _) => "范围: -524288 ~ 524287（20位有符号）\n位字段: offset[31:12]".to_string(),
+        (InstructionType::A, _) | (InstructionType::Halt, _) => "无立即数".to_string(),
+    };
+
+    Some(format!("{} ({})\n{}\n", entry.mnemonic, entry.itype.name(), explanation))
+}
+
+// 按指令类型分组输出操作码表
+pub fn format_opcode_tables() -> String {
+    let mut output = String::new();
+
+    let types = [InstructionType::A, InstructionType::B, InstructionType::C, InstructionType::J, InstructionType::Halt];
+    for itype in types {
+        output.push_str(&format!("{}  字段布局: {}\n", itype.name(), itype.layout()));
+        output.push_str("  二进制   十六进制  助记符  说明\n");
+        for entry in OPCODE_TABLE.iter().filter(|entry| entry.itype == itype) {
+            output.push_str(&format!("  {:06b}   0x{:02X}      {:<6}  {}\n",
+                entry.opcode, entry.opcode, entry.mnemonic, entry.description));
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+// =================== 汇编器部分 ===================
+
+// A类型指令编码（add/mul）
+// 格式: 前11位0_rs2[5位]_rs1[5位]_rd[5位]_opcode[6位]
+pub fn encode_a(opcode: u32, rd: u8, rs1: u8, rs2: u8) -> u32 {
+    // 前11位固定为0
+    ((rs2 as u32 & 0x1F) << 16) | 
+    ((rs1 as u32 & 0x1F) << 11) | 
+    ((rd as u32 & 0x1F) << 6) | 
+    (opcode & 0x3F)
+}
+
+// B类型指令编码（addi/lui/lw）
+// 格式: imm[16位]_rs1[5位]_rd[5位]_opcode[6位]
+pub fn encode_b(opcode: u32, rd: u8, rs1: u8, imm: i16) -> u32 {
+    // 将有符号立即数转为无符号32位整数，保留符号
+    let imm_u32 = (imm as u32) & 0xFFFF;
+    
+    // 构建指令
+    (imm_u32 << 16) |              // 16位立即数放在[31:16]
+    ((rs1 as u32 & 0x1F) << 11) |  // rs1放在[15:11]
+    ((rd as u32 & 0x1F) << 6) |    // rd放在[10:6]
+    (opcode & 0x3F)                // opcode放在[5:0]
+}
+
+// C类型指令编码（bne/sw/blt）
+// 格式: imm_high[31:21] rs1[20:16] rs2[15:11] imm_low[10:6] opcode[5:0]
+pub fn encode_c(opcode: u32, rs1: u8, rs2: u8, offset: i16) -> u32 {
+    // 处理有符号扩展
+    let offset_u32 = offset as u32;
+    // 提取高11位和低5位
+    let imm_high = (offset_u32 >> 5) & 0x7FF;
+    let imm_low = offset_u32 & 0x1F;
+    
+    (imm_high << 21) |
+    ((rs1 as u32) << 16) |  // rs1放在[20:16]
+    ((rs2 as u32) << 11) |  // rs2放在[15:11]
+    (imm_low << 6) |
+    (opcode & 0x3F)
+}
+
+// J类型指令编码（jal）
+// 格式: offset[31:12] 0[11] rd[10:6] opcode[5:0]，offset截断为20位
+pub fn encode_j(opcode: u32, rd: u8, offset: i32) -> u32 {
+    ((offset as u32 & 0xFFFFF) << 12) |
+    ((rd as u32 & 0x1F) << 6) |
+    (opcode & 0x3F)
+}
+
+// 各指令类型编码专用函数
+pub fn encode_add(rd: u8, rs1: u8, rs2: u8) -> u32 {
+    encode_a(OPCODE_ADD, rd, rs1, rs2)
+}
+
+pub fn encode_mul(rd: u8, rs1: u8, rs2: u8) -> u32 {
+    encode_a(OPCODE_MUL, rd, rs1, rs2)
+}
+
+pub fn encode_addi(rd: u8, rs1: u8, imm: i16) -> u32 {
+    encode_b(OPCODE_ADDI, rd, rs1, imm)
+}
+
+pub fn encode_lui(rd: u8, imm: i16) -> u32 {
+    encode_b(OPCODE_LUI, rd, 0, imm)
+}
+
+pub fn encode_lw(rd: u8, rs1: u8, offset: i16) -> u32 {
+    encode_b(OPCODE_LW, rd, rs1, offset)
+}
+
+// lb/lh读取1/2个字节并符号扩展，lbu/lhu零扩展；字段布局与lw相同
+pub fn encode_lb(rd: u8, rs1: u8, offset: i16) -> u32 {
+    encode_b(OPCODE_LB, rd, rs1, offset)
+}
+
+pub fn encode_lh(rd: u8, rs1: u8, offset: i16) -> u32 {
+    encode_b(OPCODE_LH, rd, rs1, offset)
+}
+
+pub fn encode_lbu(rd: u8, rs1: u8, offset: i16) -> u32 {
+    encode_b(OPCODE_LBU, rd, rs1, offset)
+}
+
+pub fn encode_lhu(rd: u8, rs1: u8, offset: i16) -> u32 {
+    encode_b(OPCODE_LHU, rd, rs1, offset)
+}
+
+pub fn encode_bne(rs1: u8, rs2: u8, offset: i16) -> u32 {
+    encode_c(OPCODE_BNE, rs1, rs2, offset)
+}
+
+// beq与bne的字段顺序相同，rs1在[20:16]，不做交换
+pub fn encode_beq(rs1: u8, rs2: u8, offset: i16) -> u32 {
+    encode_c(OPCODE_BEQ, rs1, rs2, offset)
+}
+
+// bge/bgeu同样不交换rs1和rs2，有符号/无符号比较只在执行时区分
+pub fn encode_bge(rs1: u8, rs2: u8, offset: i16) -> u32 {
+    encode_c(OPCODE_BGE, rs1, rs2, offset)
+}
+
+pub fn encode_bgeu(rs1: u8, rs2: u8, offset: i16) -> u32 {
+    encode_c(OPCODE_BGEU, rs1, rs2, offset)
+}
+
+pub fn encode_sw(rs1: u8, rs2: u8, offset: i16) -> u32 {
+    encode_c(OPCODE_SW, rs2, rs1, offset)
+}
+
+// sb/sh只写入x[rs2]的低1/2个字节，rs1和rs2与sw一样交换存放
+pub fn encode_sb(rs1: u8, rs2: u8, offset: i16) -> u32 {
+    encode_c(OPCODE_SB, rs2, rs1, offset)
+}
+
+pub fn encode_sh(rs1: u8, rs2: u8, offset: i16) -> u32 {
+    encode_c(OPCODE_SH, rs2, rs1, offset)
+}
+
+pub fn encode_blt(rs1: u8, rs2: u8, offset: i16) -> u32 {
+    encode_c(OPCODE_BLT, rs2, rs1, offset)
+}
+
+pub fn encode_slli(rd: u8, rs1: u8, imm: i16) -> u32 {
+    encode_b(OPCODE_SLLI, rd, rs1, imm)
+}
+
+// srli/srai的移位量由汇编器限制在0~31
+pub fn encode_srli(rd: u8, rs1: u8, imm: i16) -> u32 {
+    encode_b(OPCODE_SRLI, rd, rs1, imm)
+}
+
+pub fn encode_srai(rd: u8, rs1: u8, imm: i16) -> u32 {
+    encode_b(OPCODE_SRAI, rd, rs1, imm)
+}
+
+// andi/ori/xori的立即数同addi一样做符号扩展，andi x1, x2, -1 即全1掩码
+pub fn encode_andi(rd: u8, rs1: u8, imm: i16) -> u32 {
+    encode_b(OPCODE_ANDI, rd, rs1, imm)
+}
+
+pub fn encode_ori(rd: u8, rs1: u8, imm: i16) -> u32 {
+    encode_b(OPCODE_ORI, rd, rs1, imm)
+}
+
+pub fn encode_xori(rd: u8, rs1: u8, imm: i16) -> u32 {
+    encode_b(OPCODE_XORI, rd, rs1, imm)
+}
+
+// slti: 若 x[rs1] < sext(imm)（有符号比较）则 rd = 1，否则 rd = 0
+pub fn encode_slti(rd: u8, rs1: u8, imm: i16) -> u32 {
+    encode_b(OPCODE_SLTI, rd, rs1, imm)
+}
+
+// jalr x0, x1, 0 即函数返回
+pub fn encode_jalr(rd: u8, rs1: u8, imm: i16) -> u32 {
+    encode_b(OPCODE_JALR, rd, rs1, imm)
+}
+
+pub fn encode_sub(rd: u8, rs1: u8, rs2: u8) -> u32 {
+    encode_a(OPCODE_SUB, rd, rs1, rs2)
+}
+
+pub fn encode_or(rd: u8, rs1: u8, rs2: u8) -> u32 {
+    encode_a(OPCODE_OR, rd, rs1, rs2)
+}
+
+pub fn encode_and(rd: u8, rs1: u8, rs2: u8) -> u32 {
+    encode_a(OPCODE_AND, rd, rs1, rs2)
+}
+
+pub fn encode_xor(rd: u8, rs1: u8, rs2: u8) -> u32 {
+    encode_a(OPCODE_XOR, rd, rs1, rs2)
+}
+
+// nor rd, rs, x0 即按位取反（伪指令not）
+pub fn encode_nor(rd: u8, rs1: u8, rs2: u8) -> u32 {
+    encode_a(OPCODE_NOR, rd, rs1, rs2)
+}
+
+// srl/sra按x[rs2]的低5位右移，srl高位补0，sra高位补符号位
+pub fn encode_srl(rd: u8, rs1: u8, rs2: u8) -> u32 {
+    encode_a(OPCODE_SRL, rd, rs1, rs2)
+}
+
+pub fn encode_sra(rd: u8, rs1: u8, rs2: u8) -> u32 {
+    encode_a(OPCODE_SRA, rd, rs1, rs2)
+}
+
+// slt: 若 x[rs1] < x[rs2]（有符号比较）则 rd = 1，否则 rd = 0
+pub fn encode_slt(rd: u8, rs1: u8, rs2: u8) -> u32 {
+    encode_a(OPCODE_SLT, rd, rs1, rs2)
+}
+
+// sltu: 同slt，但按无符号数比较
+pub fn encode_sltu(rd: u8, rs1: u8, rs2: u8) -> u32 {
+    encode_a(OPCODE_SLTU, rd, rs1, rs2)
+}
+
+// clz只使用rs1，立即数字段固定为0
+pub fn encode_clz(rd: u8, rs1: u8) -> u32 {
+    encode_b(OPCODE_CLZ, rd, rs1, 0)
+}
+
+// ctz/cpop只使用rs1，rs2字段固定为0
+pub fn encode_ctz(rd: u8, rs1: u8) -> u32 {
+    encode_a(OPCODE_CTZ, rd, rs1, 0)
+}
+
+pub fn encode_cpop(rd: u8, rs1: u8) -> u32 {
+    encode_a(OPCODE_CPOP, rd, rs1, 0)
+}
+
+pub fn encode_rev8(rd: u8, rs1: u8) -> u32 {
+    encode_a(OPCODE_REV8, rd, rs1, 0)
+}
+
+pub fn encode_brev8(rd: u8, rs1: u8) -> u32 {
+    encode_a(OPCODE_BREV8, rd, rs1, 0)
+}
+
+pub fn encode_jal(rd: u8, offset: i32) -> u32 {
+    encode_j(OPCODE_JAL, rd, offset)
+}
+
+// 伪指令li展开：16位有符号数用一条addi，否则用lui加载高16位再用addi补低16位。
+// addi会对低16位做符号扩展，低16位为负时高16位要多加1来抵消
+pub fn encode_li(rd: u8, value: i32) -> Vec<u32> {
+    if let Ok(imm) = i16::try_from(value) {
+        return vec![encode_addi(rd, 0, imm)];
+    }
+    let lower = value as i16;
+    let upper = (value.wrapping_sub(lower as i32) >> 16) as i16;
+    if lower == 0 {
+        vec![encode_lui(rd, upper)]
+    } else {
+        vec![encode_lui(rd, upper), encode_addi(rd, rd, lower)]
+    }
+}
+
+// 伪指令popcount展开时用来放32位掩码的临时寄存器（t6），rd和rs都不能是它
+pub const POPCOUNT_SCRATCH: u8 = 31;
+
+// 伪指令popcount rd, rs展开：按位宽逐级求和，s为临时寄存器，共22条指令。
+// andi的立即数只有16位，32位掩码先用li放进s，再用and作用在被掩码的值上，
+// 所以整个过程只需要rd和s，rd与rs相同时也成立:
+//   li   s, 0xAAAAAAAA     每2位中高位的1
+//   and  s, s, rs
+//   srli s, s, 1
+//   sub  rd, rs, s         每2位的1个数: x - ((x >> 1) & 0x55555555)
+//   li   s, 0xCCCCCCCC
+//   and  s, s, rd
+//   srli s, s, 2           s = (x >> 2) & 0x33333333
+//   sub  rd, rd, s
+//   slli s, s, 1
+//   sub  rd, rd, s         每4位的1个数: x - 3 * s
+//   srli s, rd, 4
+//   add  rd, rd, s
+//   li   s, 0x0F0F0F0F
+//   and  rd, rd, s         每8位的1个数: (x + (x >> 4)) & 0x0F0F0F0F
+//   srli s, rd, 8
+//   add  rd, rd, s
+//   srli s, rd, 16
+//   add  rd, rd, s
+//   andi rd, rd, 0x3F      各字节之和在最低字节，结果最大为32
+pub fn encode_popcount(rd: u8, rs: u8, s: u8) -> Vec<u32> {
+    let mut code = encode_li(s, 0xAAAAAAAAu32 as i32);
+    code.extend([encode_and(s, s, rs), encode_srli(s, s, 1), encode_sub(rd, rs, s)]);
+    code.extend(encode_li(s, 0xCCCCCCCCu32 as i32));
+    code.extend([
+        encode_and(s, s, rd), encode_srli(s, s, 2),
+        encode_sub(rd, rd, s), encode_slli(s, s, 1), encode_sub(rd, rd, s),
+        encode_srli(s, rd, 4), encode_add(rd, rd, s),
+    ]);
+    code.extend(encode_li(s, 0x0F0F0F0F));
+    code.extend([
+        encode_and(rd, rd, s),
+        encode_srli(s, rd, 8), encode_add(rd, rd, s),
+        encode_srli(s, rd, 16), encode_add(rd, rd, s),
+        encode_andi(rd, rd, 0x3F),
+    ]);
+    code
+}
+
+pub fn encode_halt() -> u32 {
+    0u32
+}
+
+// 接受x0~x31和调用约定中的寄存器名（fp是s0的别名）
+fn parse_reg(reg: &str) -> Result<u8, LineError> {
+    if let Some(index) = ABI_NAMES.iter().position(|&name| name == reg) {
+        return Ok(index as u8);
+    }
+    if reg == "fp" {
+        return Ok(8);
+    }
+    reg.strip_prefix('x')
+        .and_then(|index| index.parse().ok())
+        .filter(|&index| index < 32)
+        .ok_or_else(|| LineError::new(ErrorCode::InvalidRegister, reg, format!("无效的寄存器: {}", reg)))
+}
+
+// 16位立即数字段能表示的有符号值
+const SIGNED_IMM_RANGE: RangeInclusive<i64> = i16::MIN as i64..=i16::MAX as i64;
+// lui的立即数是高16位，有符号和无符号写法都可以
+const LUI_IMM_RANGE: RangeInclusive<i64> = i16::MIN as i64..=u16::MAX as i64;
+
+fn parse_imm(imm_str: &str) -> Result<i16, LineError> {
+    parse_imm_in(imm_str, SIGNED_IMM_RANGE)
+}
+
+// 十进制数超出range时报错；十六进制、二进制数和表达式超出16位时截断为低16位，由汇编器记录警告
+fn parse_imm_in(imm_str: &str, range: RangeInclusive<i64>) -> Result<i16, LineError> {
+    let imm_str = imm_str.trim();
+    
+    // 处理字符字面量
+    if imm_str.starts_with('\'') {
+        return parse_char_literal(imm_str)
+            .map(i16::from)
+            .map_err(|e| LineError::new(ErrorCode::InvalidImmediate, imm_str, e));
+    }
+
+    // 处理常量表达式，.equ常量在此之前已经被替换为数值。
+    // 与十六进制数一样，超出i16范围时截断为低16位
+    if is_expression(imm_str) {
+        return evaluate_expr(imm_str, &HashMap::new())
+            .map(|value| value as i16)
+            .map_err(|e| LineError::new(ErrorCode::InvalidImmediate, imm_str, format!("无效的表达式 {}: {}", imm_str, e)));
+    }
+
+    // 处理十六进制和二进制值，与parse_int一样可带+/-号（如 -0x10）
+    let (negative, unsigned) = match imm_str.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, imm_str.strip_prefix('+').unwrap_or(imm_str)),
+    };
+    if let Some((radix, name)) = radix_prefix(unsigned) {
+        // 去掉0x/0b前缀
+        let value = parse_digits(&unsigned[2..], radix)
+            .map(|value| if negative { -value } else { value })
+            .and_then(|value| i32::try_from(value).ok())
+            .ok_or_else(|| LineError::new(ErrorCode::InvalidImmediate, imm_str, format!("无效的{}立即数: {}", name, imm_str)))?;
+        
+        // 确保值在i16范围内，或者作为u16处理后解释为i16
+        if value > i16::MAX as i32 || value < i16::MIN as i32 {
+            // 超出i16范围，将高16位截断（由汇编器记录警告）
+            return Ok((value as u16) as i16);
+        }
+        
+        Ok(value as i16)
+    } 
+    // 处理十进制数，可带+/-号
+    else {
+        let value: i64 = parse_decimal(imm_str)
+            .ok_or_else(|| LineError::new(ErrorCode::InvalidImmediate, imm_str, format!("无效的十进制立即数: {}", imm_str)))?;
+        if !range.contains(&value) {
+            let message = format!("立即数 {} 超出16位立即数的范围 [{}, {}]", value, range.start(), range.end());
+            return Err(LineError::new(ErrorCode::ImmediateOutOfRange, imm_str, message));
+        }
+        Ok(value as i16)
+    }
+}
+
+// 数字中可以用下划线分组，但下划线不能出现在开头（包括紧跟0x/0b前缀）、结尾或连续出现
+fn valid_separators(digits: &str) -> bool {
+    !digits.starts_with('_') && !digits.ends_with('_') && !digits.contains("__")
+}
+
+// 按radix解析不带符号和前缀的数字
+fn parse_digits(digits: &str, radix: u32) -> Option<i64> {
+    if !valid_separators(digits) {
+        return None;
+    }
+    i64::from_str_radix(&digits.replace('_', ""), radix).ok()
+}
+
+// 解析可带+/-号的十进制数
+fn parse_decimal<T: std::str::FromStr>(imm_str: &str) -> Option<T> {
+    if !valid_separators(imm_str.strip_prefix(['+', '-']).unwrap_or(imm_str)) {
+        return None;
+    }
+    imm_str.replace('_', "").parse().ok()
+}
+
+// 除开头的正负号外含有运算符或括号时按表达式求值
+fn is_expression(imm_str: &str) -> bool {
+    imm_str.get(1..).is_some_and(|rest| rest.contains(['+', '-', '*', '/', '%', '<', '>', '(']))
+}
+
+// 识别0x/0X十六进制和0b/0B二进制前缀，返回基数和用于错误信息的名称
+fn radix_prefix(imm_str: &str) -> Option<(u32, &'static str)> {
+    match imm_str.get(..2) {
+        Some("0x" | "0X") => Some((16, "十六进制")),
+        Some("0b" | "0B") => Some((2, "二进制")),
+        _ => None,
+    }
+}
+
+// 解析带可选符号的十进制、0x十六进制或0b二进制整数，或者字符字面量
+pub fn parse_int(imm_str: &str) -> Option<i64> {
+    if imm_str.starts_with('\'') {
+        return parse_char_literal(imm_str).ok().map(i64::from);
+    }
+    let (negative, digits) = match imm_str.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, imm_str.strip_prefix('+').unwrap_or(imm_str)),
+    };
+    let value = match radix_prefix(digits) {
+        Some((radix, _)) => parse_digits(&digits[2..], radix)?,
+        None => parse_decimal(digits)?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+// 解析jal的20位有符号跳转偏移
+fn parse_offset20(imm_str: &str) -> Result<i32, LineError> {
+    let imm_str = imm_str.trim();
+    let value = parse_int(imm_str)
+        .and_then(|value| i32::try_from(value).ok())
+        .ok_or_else(|| LineError::new(ErrorCode::InvalidImmediate, imm_str, format!("无效的跳转偏移: {}", imm_str)))?;
+    check_offset20(value).map_err(|e| LineError::new(ErrorCode::ImmediateOutOfRange, imm_str, e))
+}
+
+// 解析li的32位立即数，接受有符号和无符号写法（-1与0xFFFFFFFF等价），也可以是常量表达式
+fn parse_imm32(imm_str: &str) -> Result<i32, LineError> {
+    let imm_str = imm_str.trim();
+    let value = parse_int(imm_str).or_else(|| evaluate_expr(imm_str, &HashMap::new()).ok().map(i64::from));
+    match value {
+        Some(value) if (i32::MIN as i64..=u32::MAX as i64).contains(&value) => Ok(value as u32 as i32),
+        Some(_) => Err(LineError::new(ErrorCode::ImmediateOutOfRange, imm_str, format!("立即数 {} 超出32位范围", imm_str))),
+        None => Err(LineError::new(ErrorCode::InvalidImmediate, imm_str, format!("无效的立即数: {}", imm_str))),
+    }
+}
+
+fn check_offset20(value: i32) -> Result<i32, String> {
+    let (min, max) = (-(1i32 << 19), (1i32 << 19) - 1);
+    if value < min || value > max {
+        return Err(format!("跳转偏移 {} 超出20位有符号范围 [{}, {}]", value, min, max));
+    }
+    Ok(value)
+}
+
+// 检查立即数能否放入有符号bits位字段
+fn check_imm_bits(imm: i16, bits: u32) -> Result<i16, String> {
+    let min = -(1i32 << (bits - 1));
+    let max = (1i32 << (bits - 1)) - 1;
+    if (imm as i32) < min || (imm as i32) > max {
+        return Err(format!("立即数 {} 超出{}位有符号范围 [{}, {}]", imm, bits, min, max));
+    }
+    Ok(imm)
+}
+
+// 移位量为无符号数，有效位宽始终不超过5位
+fn check_shamt_bits(imm: i16, bits: u32) -> Result<i16, String> {
+    let bits = bits.min(5);
+    let max = (1i32 << bits) - 1;
+    if imm < 0 || (imm as i32) > max {
+        return Err(format!("移位量 {} 超出{}位无符号范围 [0, {}]", imm, bits, max));
+    }
+    Ok(imm)
+}
+
+// RV32I基础指令集的助记符
+pub const RV32I_MNEMONICS: &[&str] = &[
+    "add", "sub", "addi", "beq", "bne", "blt", "bge", "bltu", "bgeu",
+    "lb", "lh", "lw", "lbu", "lhu", "sb", "sh", "sw", "lui", "auipc", "jal", "jalr",
+    "and", "or", "xor", "sll", "srl", "sra", "slt", "sltu",
+    "slti", "sltiu", "andi", "ori", "xori", "slli", "srli", "srai",
+];
+
+// 与本指令集语义相同（偏移同样以字节计）、可以直接编码的RV32I指令
+pub const RV32I_COMPATIBLE: &[&str] = &[
+    "add", "sub", "addi", "beq", "bne", "blt", "bge", "bgeu", "lb", "lh", "lw", "lbu", "lhu", "sb", "sh", "sw",
+    "slli", "srli", "srai", "srl", "sra", "and", "or", "xor",
+    "andi", "ori", "xori", "slt", "sltu", "slti", "jal", "jalr",
+];
+
+// 兼容模式下检查RV32I助记符是否有等价编码
+fn check_riscv_compat(mnemonic: &str) -> Result<(), String> {
+    if !RV32I_MNEMONICS.contains(&mnemonic) || RV32I_COMPATIBLE.contains(&mnemonic) {
+        return Ok(());
+    }
+    if mnemonic == "lui" {
+        return Err("RV32I指令 lui 不受支持: 本指令集的lui将立即数左移16位而非12位".to_string());
+    }
+    Err(format!("RV32I指令 {} 在本指令集中没有等价编码", mnemonic))
+}
+
+// 汇编配置
+#[derive(Debug, Clone)]
+pub struct AssemblerConfig {
+    pub max_imm_bits: u32,        // 立即数字段允许的最大有符号位宽（1~16）
+    pub compat_riscv: bool,       // 接受RV32I助记符，没有等价编码的报错
+    pub warnings_as_errors: bool, // 有警告时汇编失败，由调用方在输出警告后检查
+    pub include_dir: PathBuf,     // 顶层源文件所在的目录，.include的相对路径从这里查找
+    pub defines: HashMap<String, i32>, // 汇编开始前预先定义的常量（命令行--define）
+    pub warn_dead_code: bool,     // 警告halt之后不可达的指令
+    pub check_abi: bool,          // 按调用约定检查寄存器使用，结果与其他警告一起收集
+}
+
+impl Default for AssemblerConfig {
+    fn default() -> Self {
+        AssemblerConfig {
+            max_imm_bits: 16,
+            compat_riscv: false,
+            warnings_as_errors: false,
+            include_dir: PathBuf::new(),
+            defines: HashMap::new(),
+            warn_dead_code: true,
+            check_abi: false,
+        }
+    }
+}
+
+// 诊断的严重级别
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+// 汇编错误的分类，每类对应一个固定的错误码
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorCode {
+    UnknownInstruction,
+    InvalidRegister,
+    InvalidImmediate,
+    ImmediateOutOfRange,
+    MissingOperand,
+    UndefinedLabel,
+    DuplicateLabel,
+    LabelOutOfRange, // jal/j/call的目标标签超出20位跳转偏移，范围固定
+    InvalidSyntax,
+    UnsupportedInstruction,
+    // 条件分支的目标标签超出立即数能表示的偏移。范围随max_imm_bits变化，
+    // 与jal分开报告，看错误码就知道调整max_imm_bits是否有用
+    BranchOutOfRange,
+}
+
+impl ErrorCode {
+    const ALL: [ErrorCode; 11] = [
+        ErrorCode::UnknownInstruction, ErrorCode::InvalidRegister, ErrorCode::InvalidImmediate,
+        ErrorCode::ImmediateOutOfRange, ErrorCode::MissingOperand, ErrorCode::UndefinedLabel,
+        ErrorCode::DuplicateLabel, ErrorCode::LabelOutOfRange, ErrorCode::InvalidSyntax,
+        ErrorCode::UnsupportedInstruction, ErrorCode::BranchOutOfRange,
+    ];
+
+    // 错误码按ALL中的顺序编号: E001, E002, ...
+    pub fn code(self) -> String {
+        let index = Self::ALL.iter().position(|&code| code == self).unwrap();
+        format!("E{:03}", index + 1)
+    }
+}
+
+// 预处理（展开.include和宏）之后的一行源码
+#[derive(Debug, Clone, PartialEq)]
+struct SourceLine {
+    file: String,  // 所在文件名，被包含的文件与顶层文件不同
+    line: usize,   // 在所在文件中的行号
+    origin: usize, // 顶层文件中的行号，被包含文件中的行取.include所在的行
+    text: String,
+}
+
+impl SourceLine {
+    fn locate(&self, from: &str, error: LineError) -> AssemblyError {
+        AssemblyError::locate(&self.file, self.line, &self.text, from, error)
+    }
+}
+
+type PreprocessResult = Result<Vec<SourceLine>, Vec<(SourceLine, LineError)>>;
+
+// 汇编一行时的错误。token是出错的记号，由调用方在源码行中查找它的列范围
+#[derive(Debug, Clone, PartialEq)]
+struct LineError {
+    code: ErrorCode,
+    token: String,
+    message: String,
+}
+
+impl LineError {
+    fn new(code: ErrorCode, token: &str, message: String) -> Self {
+        LineError { code, token: token.to_string(), message }
+    }
+}
+
+// 汇编错误：文件名、行号、出错记号的列范围（从1开始，col_end不含）、级别、错误码和错误信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssemblyError {
+    pub file: String,
+    pub line: usize,
+    pub col_start: usize,
+    pub col_end: usize,
+    pub severity: Severity,
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl AssemblyError {
+    // 在源码行source的from部分中查找出错的记号，找不到时（例如记号来自常量替换）标出整个from
+    fn locate(file: &str, line: usize, source: &str, from: &str, error: LineError) -> Self {
+        let start = column_of(source, from);
+        let (col_start, width) = match from.find(&error.token) {
+            Some(offset) if !error.token.is_empty() => (start + from[..offset].chars().count(), error.token.chars().count()),
+            _ => (start, from.chars().count()),
+        };
+        AssemblyError {
+            file: file.to_string(),
+            line,
+            col_start,
+            col_end: col_start + width,
+            severity: Severity::Error,
+            code: error.code,
+            message: error.message,
+        }
+    }
+
+    // 机器可读的单行JSON
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"file\":{},\"line\":{},\"col_start\":{},\"col_end\":{},\"severity\":\"{}\",\"code\":\"{}\",\"message\":{}}}",
+            json_string(&self.file), self.line, self.col_start, self.col_end,
+            self.severity.name(), self.code.code(), json_string(&self.message)
+        )
+    }
+}
+
+impl std::fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}:{}: {}[{}]: {}", self.file, self.line, self.col_start, self.severity.name(), self.code.code(), self.message)
+    }
+}
+
+// 转义为JSON字符串字面量
+fn json_string(text: &str) -> String {
+    let mut output = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    output
+}
+
+// part在line中的列号（从1开始），part必须是line的子串切片
+fn column_of(line: &str, part: &str) -> usize {
+    let offset = part.as_ptr() as usize - line.as_ptr() as usize;
+    line[..offset].chars().count() + 1
+}
+
+// 使用默认配置汇编
+pub fn assemble(input: &str) -> Result<Vec<u32>, Vec<AssemblyError>> {
+    assemble_with_config(input, &AssemblerConfig::default())
+}
+
+pub fn assemble_with_config(input: &str, config: &AssemblerConfig) -> Result<Vec<u32>, Vec<AssemblyError>> {
+    let mut assembler = Assembler::new(config);
+    assembler.run(input)?;
+    Ok(assembler.img)
+}
+
+// 去掉注释和首尾空白，字符串和字符字面量中的#不算注释
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote.is_some() => escaped = true,
+            '"' | '\'' if quote.is_none() => quote = Some(c),
+            _ if quote == Some(c) => quote = None,
+            '#' if quote.is_none() => return line[..i].trim(),
+            _ => {}
+        }
+    }
+    line.trim()
+}
+
+// 解析带双引号的字符串字面量，支持\n \r \t \\ \" \' \0 \xHH转义
+fn parse_string_literal(literal: &str) -> Result<Vec<u8>, String> {
+    let inner = literal.strip_prefix('"').and_then(|rest| rest.strip_suffix('"'))
+        .filter(|_| literal.len() >= 2)
+        .ok_or_else(|| format!("字符串必须用双引号括起: {}", literal))?;
+
+    let mut bytes = Vec::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        bytes.push(parse_escape(&mut chars)?);
+    }
+    Ok(bytes)
+}
+
+// 解析反斜杠之后的转义序列
+fn parse_escape(chars: &mut std::str::Chars) -> Result<u8, String> {
+    Ok(match chars.next() {
+        Some('n') => b'\n',
+        Some('r') => b'\r',
+        Some('t') => b'\t',
+        Some('\\') => b'\\',
+        Some('"') => b'"',
+        Some('\'') => b'\'',
+        Some('0') => 0,
+        Some('x') => {
+            let hex: String = chars.by_ref().take(2).collect();
+            u8::from_str_radix(&hex, 16).ok().filter(|_| hex.len() == 2)
+                .ok_or_else(|| format!("无效的转义序列: \\x{}", hex))?
+        }
+        Some(other) => return Err(format!("无法识别的转义序列: \\{}", other)),
+        None => return Err("字符串以单个反斜杠结尾".to_string()),
+    })
+}
+
+// 解析单引号括起的字符字面量（如'A'、'\n'、'\x41'），值为该ASCII字符的编码
+fn parse_char_literal(literal: &str) -> Result<u8, String> {
+    let inner = literal.strip_prefix('\'').and_then(|rest| rest.strip_suffix('\''))
+        .filter(|_| literal.len() >= 2)
+        .ok_or_else(|| format!("字符字面量必须用单引号括起: {}", literal))?;
+
+    let mut chars = inner.chars();
+    let byte = match chars.next() {
+        Some('\\') => parse_escape(&mut chars)?,
+        Some(c) if c.is_ascii() => c as u8,
+        Some(c) => return Err(format!("字符字面量只能是ASCII字符: {}", c)),
+        None => return Err("字符字面量为空".to_string()),
+    };
+    if chars.next().is_some() {
+        return Err(format!("字符字面量只能包含一个字符: {}", literal));
+    }
+    Ok(byte)
+}
+
+// 字符串伪指令的字节内容，.asciiz末尾加0
+fn string_directive_bytes(line: &str, mnemonic: &str) -> Result<Vec<u8>, LineError> {
+    let literal = line[mnemonic.len()..].trim();
+    let mut bytes = parse_string_literal(literal).map_err(|e| LineError::new(ErrorCode::InvalidSyntax, literal, e))?;
+    if mnemonic == ".asciiz" {
+        bytes.push(0);
+    }
+    Ok(bytes)
+}
+
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// 数据伪指令的全部操作数
+fn directive_operands<'a>(parts: &[&'a str]) -> Result<Vec<&'a str>, LineError> {
+    let operands = parts[1..].to_vec();
+    if operands.is_empty() {
+        return Err(LineError::new(ErrorCode::MissingOperand, parts[0], format!("{} 缺少操作数", parts[0])));
+    }
+    Ok(operands)
+}
+
+// 指令的第index个操作数
+fn operand<'a>(parts: &[&'a str], index: usize) -> Result<&'a str, LineError> {
+    parts.get(index).copied()
+        .ok_or_else(|| LineError::new(ErrorCode::MissingOperand, parts[0], format!("{} 缺少第{}个操作数", parts[0], index)))
+}
+
+// 解析.byte/.half的值，必须在0..=max之间
+fn parse_data_value(operand: &str, max: i64, directive: &str) -> Result<i64, LineError> {
+    let operand = operand.trim();
+    let value = parse_int(operand).ok_or_else(|| {
+        LineError::new(ErrorCode::InvalidImmediate, operand, format!("无效的{}值: {}", directive, operand))
+    })?;
+    if !(0..=max).contains(&value) {
+        return Err(LineError::new(ErrorCode::ImmediateOutOfRange, operand, format!("{}值 {} 超出范围 [0, {}]", directive, value, max)));
+    }
+    Ok(value)
+}
+
+// .space的字节数，必须是正整数
+fn parse_space_size(parts: &[&str]) -> Result<u32, LineError> {
+    let operand = operand(parts, 1)?;
+    match parse_int(operand) {
+        Some(size) if size > 0 && size <= u32::MAX as i64 => Ok(size as u32),
+        size => Err(LineError::new(
+            if size.is_some() { ErrorCode::ImmediateOutOfRange } else { ErrorCode::InvalidImmediate },
+            operand,
+            format!(".space的大小必须是正整数: {}", operand),
+        )),
+    }
+}
+
+// .align N 的对齐字节数2^N，N为1~16
+fn parse_alignment(parts: &[&str]) -> Result<u32, LineError> {
+    let operand = operand(parts, 1)?;
+    match parse_int(operand) {
+        Some(exponent @ 1..=16) => Ok(1 << exponent),
+        exponent => Err(LineError::new(
+            if exponent.is_some() { ErrorCode::ImmediateOutOfRange } else { ErrorCode::InvalidImmediate },
+            operand,
+            format!(".align的参数必须在1~16之间（对齐到2^N字节）: {}", operand),
+        )),
+    }
+}
+
+// 常量定义 ".equ NAME, value" 或 "NAME = value"，返回常量名和值的文本
+fn constant_definition(line: &str) -> Result<Option<(&str, &str)>, LineError> {
+    if line.split_whitespace().next() == Some(".equ") {
+        let (name, value) = line[".equ".len()..].split_once(',').ok_or_else(|| {
+            LineError::new(ErrorCode::InvalidSyntax, line, format!("无效的.equ格式（应为 .equ NAME, value）: {}", line))
+        })?;
+        if !is_identifier(name.trim()) {
+            return Err(LineError::new(ErrorCode::InvalidSyntax, name.trim(), format!("无效的常量名: {}", name.trim())));
+        }
+        return Ok(Some((name.trim(), value.trim())));
+    }
+    // NAME = value 形式，等号左边必须是单个标识符
+    let Some((name, value)) = line.split_once('=') else {
+        return Ok(None);
+    };
+    Ok(is_identifier(name.trim()).then(|| (name.trim(), value.trim())))
+}
+
+// 把text中的标识符替换为lookup给出的文本。单词按字母、数字、_和.切分，
+// 所以.word等伪指令不会被当作标识符；字符串和字符字面量中的内容保持不变
+fn replace_identifiers(text: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let len = match c {
+            '"' | '\'' => quoted_len(rest),
+            _ => rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.')).unwrap_or(rest.len()),
+        };
+        if len == 0 {
+            output.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        let (token, after) = rest.split_at(len);
+        match lookup(token).filter(|_| is_identifier(token)) {
+            Some(replacement) => output.push_str(&replacement),
+            None => output.push_str(token),
+        }
+        rest = after;
+    }
+    output
+}
+
+// text以引号开头时，到匹配的引号为止的长度；没有匹配的引号时为整个text
+fn quoted_len(text: &str) -> usize {
+    let quote = text.chars().next();
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            _ if Some(c) == quote => return i + 1,
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+// 拆出行首的 "name:" 标签定义，返回标签名和剩余部分
+fn split_label(line: &str) -> (Option<&str>, &str) {
+    match line.split_once(':') {
+        Some((name, rest)) if is_identifier(name.trim()) => (Some(name.trim()), rest.trim()),
+        _ => (None, line),
+    }
+}
+
+// 段：.text放指令，.data放数据。输出仍是一个平坦镜像，代码段在前、数据段紧随其后
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Section {
+    Text,
+    Data,
+}
+
+// 解析段切换伪指令 .text / .data / .section NAME，不是段切换时返回None
+fn section_directive(parts: &[&str]) -> Result<Option<Section>, LineError> {
+    let name = match parts[0] {
+        ".section" => operand(parts, 1)?,
+        name => name,
+    };
+    match (parts[0], name) {
+        (_, ".text") => Ok(Some(Section::Text)),
+        (_, ".data") => Ok(Some(Section::Data)),
+        (".section", other) => Err(LineError::new(ErrorCode::InvalidSyntax, other, format!("不支持的段: {}（只支持.text和.data）", other))),
+        _ => Ok(None),
+    }
+}
+
+// 当前不活动的段暂存的内容
+#[derive(Default)]
+struct SectionImage {
+    img: Vec<u32>,
+    lines: Vec<usize>,
+    data: Vec<bool>,
+    pending: Vec<u8>,
+    pending_line: usize,
+}
+
+// 两遍汇编器：第一遍记录标签的字节地址，第二遍编码，
+// 分支指令的标签操作数换算为相对当前指令的字节偏移
+pub struct Assembler<'a> {
+    config: &'a AssemblerConfig,
+    pub labels: HashMap<String, u32>, // 标签名到字节地址
+    constants: HashMap<String, i32>, // .equ定义的常量，按源码顺序生效
+    pub img: Vec<u32>,
+    pub lines: Vec<usize>, // 每个字对应的源码行号（从1开始）
+    pub data: Vec<bool>,   // 每个字是否由数据伪指令生成
+    pending: Vec<u8>,  // .byte/.half产生的、还不满一个字的字节
+    pending_line: usize,
+    section: Section,
+    other: SectionImage, // 另一个段的内容，切换段时与上面的字段交换
+    text_size: u32,      // 代码段的字节数（按字对齐），数据段从这里开始
+    pub file: String,        // 错误信息中显示的源文件名
+    line: usize,         // 第二遍中正在汇编的源码行号
+    pub warnings: Vec<Warning>,
+}
+
+// .byte/.half/.space/.ascii/.asciiz按字节紧凑存放，.align自行决定对齐方式，
+// 其余内容都从4字节边界开始
+fn is_packed_data(mnemonic: &str) -> bool {
+    matches!(mnemonic, ".byte" | ".half" | ".space" | ".ascii" | ".asciiz" | ".align")
+}
+
+impl<'a> Assembler<'a> {
+    pub fn new(config: &'a AssemblerConfig) -> Self {
+        Assembler {
+            config,
+            labels: HashMap::new(),
+            constants: config.defines.clone(),
+            img: Vec::new(),
+            lines: Vec::new(),
+            data: Vec::new(),
+            pending: Vec::new(),
+            pending_line: 0,
+            section: Section::Text,
+            other: SectionImage::default(),
+            text_size: 0,
+            file: "<input>".to_string(),
+            line: 0,
+            warnings: Vec::new(),
+        }
+    }
+
+    // 出错的行跳过继续汇编，最后一并返回所有错误（按行号排序）
+    pub fn run(&mut self, input: &str) -> Result<(), Vec<AssemblyError>> {
+        // 先展开.include和宏，出错时不再汇编
+        let lines = include::expand_includes(input, &self.file, &self.config.include_dir)
+            .and_then(macros::expand_macros)
+            .map_err(|errors| errors.into_iter().map(|(source, error)| source.locate(&source.text, error)).collect::<Vec<_>>())?;
+        let mut errors = self.collect_labels(&lines);
+        // 第二遍重新按顺序定义常量，使重定义前后的引用各自取当时的值
+        self.constants = self.config.defines.clone();
+
+        let mut conditions = ConditionStack::default();
+        for source in &lines {
+            let (_, line) = split_label(strip_comment(&source.text));
+            // 条件汇编伪指令的错误在第一遍已经报告过
+            if conditions.process(line, &self.constants, source).unwrap_or(true) || !conditions.active() {
+                continue;
+            }
+            if line.is_empty() { continue; }
+
+            if let Err(error) = self.assemble_line(line, source.origin) {
+                // 第一遍已经报告过错误的行不重复报告
+                if !errors.iter().any(|e| e.file == source.file && e.line == source.line) {
+                    errors.push(source.locate(line, error));
+                }
+            }
+        }
+        if !errors.is_empty() {
+            errors.sort_by_key(|e| e.line);
+            return Err(errors);
+        }
+        self.switch_section(Section::Data);
+        self.switch_section(Section::Text);
+        // 数据段接在代码段之后，数据段的字在反汇编时都按数据处理
+        let data = std::mem::take(&mut self.other);
+        self.img.extend(data.img);
+        self.lines.extend(data.lines);
+        self.data.extend(std::iter::repeat_n(true, data.data.len()));
+        if self.config.warn_dead_code {
+            self.check_unreachable();
+        }
+        if self.config.check_abi {
+            self.warnings.extend(abi_warnings(&self.img, &self.lines, &self.data));
+        }
+        Ok(())
+    }
+
+    // 代码段中halt之后的指令不可达，直到下一个标签或跳转目标为止（那里可能是另一段代码或数据）。
+    // .word等伪指令生成的数据字不是指令，既不警告也不算作halt
+    fn check_unreachable(&mut self) {
+        let text_words = (self.text_size / 4) as usize;
+        let is_code = |i: usize| !self.data[i];
+        let mut entries: HashSet<u32> = self.labels.values().copied().collect();
+        entries.extend((0..text_words).filter(|&i| is_code(i)).filter_map(|i| branch_target(self.img[i], i as u32 * 4)));
+
+        let mut unreachable = false;
+        for i in (0..text_words).filter(|&i| is_code(i)) {
+            let address = i as u32 * 4;
+            if entries.contains(&address) {
+                unreachable = false;
+            }
+            if unreachable {
+                self.warnings.push(Warning::UnreachableCode { address, line: self.lines[i] });
+            }
+            if self.img[i] == encode_halt() {
+                unreachable = true;
+            }
+        }
+    }
+
+    // 第二遍：汇编去掉标签和注释后的一行，line_no为源码行号
+    fn assemble_line(&mut self, line: &str, line_no: usize) -> Result<(), LineError> {
+        self.line = line_no;
+        if let Some((name, value)) = constant_definition(line)? {
+            return self.define_constant(name, value, Some(line_no));
+        }
+        let parts = self.substitute_constants(line);
+        let parts: Vec<&str> = parts.iter().map(String::as_str).collect();
+        if let Some(section) = section_directive(&parts)? {
+            self.switch_section(section);
+            return Ok(());
+        }
+        if is_packed_data(parts[0]) {
+            self.pending_line = line_no;
+        } else {
+            self.align_word();
+        }
+        if self.config.compat_riscv {
+            check_riscv_compat(parts[0]).map_err(|e| LineError::new(ErrorCode::UnsupportedInstruction, parts[0], e))?;
+        }
+        let start = self.img.len();
+        match self.parse_statements(&parts) {
+            Some(statements) => self.emit_statements(parts[0], statements)?,
+            None => self.encode_line(line, &parts)?,
+        }
+        self.lines.resize(self.img.len(), line_no);
+        self.data.resize(self.img.len(), parts[0].starts_with('.'));
+        self.check_x0_write(parts[0], start, line_no);
+        Ok(())
+    }
+
+    // 用词法分析的记号和语法分析器得到一行的语句。语法分析器不接受的写法（表达式、
+    // 超出16位的十六进制数等）以及超出max_imm_bits的立即数返回None，由encode_line按文本处理，
+    // 错误信息和截断警告都来自encode_line
+    fn parse_statements(&self, parts: &[&str]) -> Option<Vec<parser::Statement>> {
+        let tokens = lexer::tokenize(&format!("{} {}", parts[0], parts[1..].join(", ")));
+        let statements = parser::parse(&tokens).ok()?;
+        let supported = statements.iter().all(|statement| match statement {
+            parser::Statement::Instruction(instruction) => self.fits_imm_bits(*instruction),
+            parser::Statement::Branch { .. } | parser::Statement::Directive(_) => true,
+            // 标签在第一遍已经拆出，同一行的第二个标签交给encode_line报错
+            parser::Statement::Label(_) => false,
+        });
+        supported.then_some(statements)
+    }
+
+    // 立即数能否放进max_imm_bits位，与encode_line中的checked_imm/checked_shamt一致
+    fn fits_imm_bits(&self, instruction: Instruction) -> bool {
+        let bits = self.config.max_imm_bits;
+        match instruction {
+            Instruction::Slli { imm, .. } | Instruction::Srli { imm, .. } | Instruction::Srai { imm, .. } => {
+                check_shamt_bits(imm, bits).is_ok()
+            }
+            _ => instruction.immediate().is_none_or(|imm| check_imm_bits(imm, bits).is_ok()),
+        }
+    }
+
+    // 编码语法分析得到的语句，分支和.word中的标签在这里换算为偏移和地址
+    fn emit_statements(&mut self, mnemonic: &str, statements: Vec<parser::Statement>) -> Result<(), LineError> {
+        for statement in statements {
+            match statement {
+                parser::Statement::Instruction(instruction) => self.img.push(instruction.encode()),
+                parser::Statement::Branch { instruction: Instruction::Jal { rd, .. }, target } => {
+                    let offset = self.jump_offset(mnemonic, &target)?;
+                    self.img.push(encode_jal(rd, offset));
+                }
+                parser::Statement::Branch { instruction, target } => {
+                    let offset = self.branch_offset(mnemonic, &target)?;
+                    self.img.push(instruction.with_branch_offset(offset).encode());
+                }
+                parser::Statement::Directive(directive) => self.emit_directive(directive)?,
+                parser::Statement::Label(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_directive(&mut self, directive: parser::Directive) -> Result<(), LineError> {
+        match directive {
+            parser::Directive::Text => self.switch_section(Section::Text),
+            parser::Directive::Data => self.switch_section(Section::Data),
+            parser::Directive::Word(values) => {
+                for value in values {
+                    let word = match value {
+                        parser::Value::Number(number) => number as u32,
+                        parser::Value::Label(label) => self.word_value(&label)?,
+                    };
+                    self.img.push(word);
+                }
+            }
+            parser::Directive::Half(values) => {
+                for value in values {
+                    self.push_bytes(&value.to_le_bytes());
+                }
+            }
+            parser::Directive::Byte(bytes) | parser::Directive::Ascii(bytes) | parser::Directive::Asciiz(bytes) => {
+                self.push_bytes(&bytes);
+            }
+            parser::Directive::Space(size) => self.push_bytes(&vec![0; size as usize]),
+            parser::Directive::Align(exponent) => {
+                let pc = self.pc();
+                let padding = pc.next_multiple_of(1 << exponent) - pc;
+                self.push_bytes(&vec![0; padding as usize]);
+            }
+            // .equ在assemble_line开头已经按文本处理，不会走到这里
+            parser::Directive::Equ { name, value } => {
+                self.constants.insert(name, value);
+            }
+        }
+        Ok(())
+    }
+
+    // 切换当前段：先补齐当前段的未满字，再与暂存的另一个段交换
+    fn switch_section(&mut self, section: Section) {
+        self.align_word();
+        if section == self.section {
+            return;
+        }
+        std::mem::swap(&mut self.img, &mut self.other.img);
+        std::mem::swap(&mut self.lines, &mut self.other.lines);
+        std::mem::swap(&mut self.data, &mut self.other.data);
+        std::mem::swap(&mut self.pending, &mut self.other.pending);
+        std::mem::swap(&mut self.pending_line, &mut self.other.pending_line);
+        self.section = section;
+    }
+
+    // line为Some时，重定义常量会产生警告
+    fn define_constant(&mut self, name: &str, value: &str, line: Option<usize>) -> Result<(), LineError> {
+        let value = parse_imm32(&self.substitute_operand(value))?;
+        if self.constants.insert(name.to_string(), value).is_some() {
+            if let Some(line) = line {
+                self.warnings.push(Warning::ConstantRedefined { name: name.to_string(), line });
+            }
+        }
+        Ok(())
+    }
+
+    // 拆分一行的助记符和操作数，操作数中的常量名替换为数值
+    fn substitute_constants(&self, line: &str) -> Vec<String> {
+        let mut parts = lexer::split_operands(line);
+        for part in &mut parts[1..] {
+            *part = self.substitute_operand(part);
+        }
+        parts
+    }
+
+    fn substitute_operand(&self, operand: &str) -> String {
+        replace_identifiers(operand, |name| self.constants.get(name).map(i32::to_string))
+    }
+
+    // 追加紧凑存放的数据字节（小端序），凑满4字节即成为一个字
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.pending.push(byte);
+            if self.pending.len() == 4 {
+                self.img.push(u32::from_le_bytes([self.pending[0], self.pending[1], self.pending[2], self.pending[3]]));
+                self.pending.clear();
+            }
+        }
+    }
+
+    // 用0把未满的字补齐到4字节边界
+    fn align_word(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let padding = vec![0; 4 - self.pending.len()];
+        self.push_bytes(&padding);
+        self.lines.resize(self.img.len(), self.pending_line);
+        self.data.resize(self.img.len(), true);
+    }
+
+    // 写入x0的指令没有效果。nop和跳转（丢弃返回地址）是有意写x0的，不报警
+    fn check_x0_write(&mut self, mnemonic: &str, start: usize, line: usize) {
+        if mnemonic.starts_with('.') || matches!(mnemonic, "nop" | "j" | "ret" | "jal" | "jalr") {
+            return;
+        }
+        if self.img[start..].iter().any(|&instr| register_operands(instr).0 == Some(0)) {
+            self.warnings.push(Warning::WriteToX0 { line });
+        }
+    }
+
+    // 一行源码展开后占用的字节数
+    fn line_bytes(line: &str, parts: &[&str], pc: u32) -> Result<u32, LineError> {
+        Ok(match parts[0] {
+            "li" => 4 * encode_li(0, parse_imm32(operand(parts, 2)?)?).len() as u32,
+            "popcount" => 4 * encode_popcount(0, 0, POPCOUNT_SCRATCH).len() as u32,
+            ".word" => 4 * directive_operands(parts)?.len() as u32,
+            ".half" => 2 * directive_operands(parts)?.len() as u32,
+            ".byte" => directive_operands(parts)?.len() as u32,
+            ".space" => parse_space_size(parts)?,
+            ".ascii" | ".asciiz" => string_directive_bytes(line, parts[0])?.len() as u32,
+            ".align" => pc.next_multiple_of(parse_alignment(parts)?) - pc,
+            _ => 4,
+        })
+    }
+
+    // 第一遍：按每行展开的字节数累加地址。标签取其后第一行内容的起始地址，
+    // 所以单独一行的标签后面跟指令时，指向对齐之后的地址
+    fn collect_labels(&mut self, lines: &[SourceLine]) -> Vec<AssemblyError> {
+        let mut errors = Vec::new();
+        let mut defined_at = HashMap::new();
+        let mut unplaced = Vec::new();
+        let mut data_labels = Vec::new();
+        let mut section = Section::Text;
+        let mut pcs = [0u32; 2]; // 代码段和数据段各自的地址
+
+        let mut conditions = ConditionStack::default();
+        for source in lines {
+            let stripped = strip_comment(&source.text);
+            let (label, rest) = split_label(stripped);
+            let error = |part, e| source.locate(part, e);
+            match conditions.process(rest, &self.constants, source) {
+                Ok(false) if conditions.active() => {}
+                Ok(_) => continue,
+                Err(e) => {
+                    errors.push(error(rest, e));
+                    continue;
+                }
+            }
+            if let Some(name) = label {
+                match defined_at.insert(name, source.line) {
+                    Some(first) => errors.push(error(stripped, LineError::new(
+                        ErrorCode::DuplicateLabel, name, format!("标签 {} 重复定义（第{}行已定义）", name, first)))),
+                    None => unplaced.push(name),
+                }
+            }
+            if rest.is_empty() {
+                continue;
+            }
+            let Ok(constant) = constant_definition(rest).map_err(|e| errors.push(error(rest, e))) else {
+                continue;
+            };
+            if let Some((name, value)) = constant {
+                if let Err(e) = self.define_constant(name, value, None) {
+                    errors.push(error(rest, e));
+                }
+                continue;
+            }
+
+            let parts = self.substitute_constants(rest);
+            let parts: Vec<&str> = parts.iter().map(String::as_str).collect();
+            let Ok(next_section) = section_directive(&parts).map_err(|e| errors.push(error(rest, e))) else {
+                continue;
+            };
+            if let Some(next) = next_section {
+                section = next;
+                continue;
+            }
+            let pc = &mut pcs[section as usize];
+            if !is_packed_data(parts[0]) {
+                *pc = pc.next_multiple_of(4);
+            }
+            for name in unplaced.drain(..) {
+                self.labels.insert(name.to_string(), *pc);
+                if section == Section::Data {
+                    data_labels.push(name);
+                }
+            }
+            match Self::line_bytes(rest, &parts, *pc) {
+                Ok(bytes) => *pc += bytes,
+                Err(e) => errors.push(error(rest, e)),
+            }
+        }
+
+        if let Some((source, e)) = conditions.unclosed() {
+            errors.push(source.locate(&source.text, e));
+        }
+
+        let end = pcs[section as usize].next_multiple_of(4);
+        for name in unplaced {
+            self.labels.insert(name.to_string(), end);
+            if section == Section::Data {
+                data_labels.push(name);
+            }
+        }
+        // 数据段排在代码段之后，数据段标签加上代码段的大小
+        self.text_size = pcs[Section::Text as usize].next_multiple_of(4);
+        for name in data_labels {
+            *self.labels.get_mut(name).unwrap() += self.text_size;
+        }
+        errors
+    }
+
+    // 当前位置的字节地址
+    fn pc(&self) -> u32 {
+        let base = if self.section == Section::Data { self.text_size } else { 0 };
+        base + (self.img.len() * 4 + self.pending.len()) as u32
+    }
+
+    // .word的值可以是数字或标签地址
+    fn word_value(&self, operand: &str) -> Result<u32, LineError> {
+        match self.label_target(operand)? {
+            Some(target) => Ok(target),
+            None => Ok(parse_imm32(operand)? as u32),
+        }
+    }
+
+    // 按配置检查立即数位宽
+    fn checked_imm(&mut self, imm_str: &str) -> Result<i16, LineError> {
+        let imm = parse_imm(imm_str)?;
+        self.warn_truncated(imm_str, imm);
+        check_imm_bits(imm, self.config.max_imm_bits)
+            .map_err(|e| LineError::new(ErrorCode::ImmediateOutOfRange, imm_str, e))
+    }
+
+    fn checked_shamt(&mut self, imm_str: &str) -> Result<i16, LineError> {
+        let imm = parse_imm(imm_str)?;
+        self.warn_truncated(imm_str, imm);
+        check_shamt_bits(imm, self.config.max_imm_bits)
+            .map_err(|e| LineError::new(ErrorCode::ImmediateOutOfRange, imm_str, e))
+    }
+
+    // 立即数在执行时按有符号数扩展，0x8000~0xFFFF等超出i16范围的值会变成另一个数，记录警告
+    fn warn_truncated(&mut self, imm_str: &str, truncated: i16) {
+        self.warn_truncated_in(imm_str, truncated, SIGNED_IMM_RANGE);
+    }
+
+    fn warn_truncated_in(&mut self, imm_str: &str, truncated: i16, range: RangeInclusive<i64>) {
+        let imm_str = imm_str.trim();
+        let original = match is_expression(imm_str) {
+            true => evaluate_expr(imm_str, &HashMap::new()).ok().map(i64::from),
+            false => parse_int(imm_str),
+        };
+        let Some(original) = original else { return };
+        if !range.contains(&original) {
+            self.warnings.push(Warning::ImmediateTruncated { original, truncated, bits: 16, line: self.line });
+        }
+    }
+
+    // 标签的字节地址；不是标识符时返回None。
+    // 第一遍已经收集了全部标签，向前和向后引用都能在这里查到
+    fn label_target(&self, operand: &str) -> Result<Option<u32>, LineError> {
+        if !is_identifier(operand) {
+            return Ok(None);
+        }
+        let target = self.labels.get(operand).ok_or_else(|| {
+            LineError::new(ErrorCode::UndefinedLabel, operand, format!("未定义的标签: {}", operand))
+        })?;
+        Ok(Some(*target))
+    }
+
+    // 分支偏移，可以是数字或标签。标签的字节偏移必须能放进C型指令的立即数，
+    // 即max_imm_bits位有符号数（默认16位，[-32768, 32767]字节）
+    fn branch_offset(&mut self, mnemonic: &str, operand: &str) -> Result<i16, LineError> {
+        let Some(target) = self.label_target(operand)? else {
+            return self.checked_imm(operand);
+        };
+        let offset = target as i32 - self.pc() as i32;
+        let bits = self.config.max_imm_bits;
+        let (min, max) = (-(1i32 << (bits - 1)), (1i32 << (bits - 1)) - 1);
+        if !(min..=max).contains(&offset) {
+            let message = format!(
+                "{} 的标签 {} 超出跳转范围: 当前地址 0x{:04X}，目标地址 0x{:04X}（偏移 {} 超出{}位有符号范围 [{}, {}]）",
+                mnemonic, operand, self.pc(), target, offset, bits, min, max,
+            );
+            return Err(LineError::new(ErrorCode::BranchOutOfRange, operand, message));
+        }
+        Ok(offset as i16)
+    }
+
+    // jal的20位跳转偏移，可以是数字或标签。范围固定，超出时报告LabelOutOfRange，
+    // 错误信息与branch_offset的格式相同
+    fn jump_offset(&self, mnemonic: &str, operand: &str) -> Result<i32, LineError> {
+        let Some(target) = self.label_target(operand)? else {
+            return parse_offset20(operand);
+        };
+        check_offset20(target as i32 - self.pc() as i32).map_err(|e| {
+            let message = format!("{} 的标签 {} 超出跳转范围: 当前地址 0x{:04X}，目标地址 0x{:04X}（{}）", mnemonic, operand, self.pc(), target, e);
+            LineError::new(ErrorCode::LabelOutOfRange, operand, message)
+        })
+    }
+
+    // 按文本编码一行，处理语法分析器不接受的写法，并给出所有错误信息
+    fn encode_line(&mut self, line: &str, parts: &[&str]) -> Result<(), LineError> {
+        match parts[0] {
+            "add" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let rs2 = parse_reg(operand(parts, 3)?)?;
+                self.img.push(encode_add(rd, rs1, rs2));
+            }
+            "mul" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let rs2 = parse_reg(operand(parts, 3)?)?;
+                self.img.push(encode_mul(rd, rs1, rs2));
+            }
+            "addi" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let imm = self.checked_imm(operand(parts, 3)?)?;
+                self.img.push(encode_addi(rd, rs1, imm));
+            }
+            "bne" => {
+                let rs1 = parse_reg(operand(parts, 1)?)?;
+                let rs2 = parse_reg(operand(parts, 2)?)?;
+                let offset = self.branch_offset(parts[0], operand(parts, 3)?)?;
+                self.img.push(encode_bne(rs1, rs2, offset));
+            }
+            "beq" => {
+                let rs1 = parse_reg(operand(parts, 1)?)?;
+                let rs2 = parse_reg(operand(parts, 2)?)?;
+                let offset = self.branch_offset(parts[0], operand(parts, 3)?)?;
+                self.img.push(encode_beq(rs1, rs2, offset));
+            }
+            "bge" => {
+                let rs1 = parse_reg(operand(parts, 1)?)?;
+                let rs2 = parse_reg(operand(parts, 2)?)?;
+                let offset = self.branch_offset(parts[0], operand(parts, 3)?)?;
+                self.img.push(encode_bge(rs1, rs2, offset));
+            }
+            "bgeu" => {
+                let rs1 = parse_reg(operand(parts, 1)?)?;
+                let rs2 = parse_reg(operand(parts, 2)?)?;
+                let offset = self.branch_offset(parts[0], operand(parts, 3)?)?;
+                self.img.push(encode_bgeu(rs1, rs2, offset));
+            }
+            "lui" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                // lui的立即数是高16位，十进制写法可以超过i16::MAX
+                let imm_str = operand(parts, 2)?;
+                let imm = parse_imm_in(imm_str, LUI_IMM_RANGE)?;
+                self.warn_truncated_in(imm_str, imm, LUI_IMM_RANGE);
+                let imm = check_imm_bits(imm, self.config.max_imm_bits)
+                    .map_err(|e| LineError::new(ErrorCode::ImmediateOutOfRange, imm_str, e))?;
+                self.img.push(encode_lui(rd, imm));
+            }
+            "lw" | "lb" | "lh" | "lbu" | "lhu" => {
+                // 处理格式如 lw x1, 4(x2) 的指令
+                let rd = parse_reg(operand(parts, 1)?)?;
+                
+                // 解析 4(x2) 格式
+                let offset_reg = operand(parts, 2)?;
+                let syntax_error = || LineError::new(ErrorCode::InvalidSyntax, offset_reg, format!("无效的{}格式: {}", parts[0], offset_reg));
+                // 偏移量本身可以是带括号的表达式，寄存器在最后一对括号中
+                let open_paren = offset_reg.rfind('(').ok_or_else(syntax_error)?;
+                let close_paren = offset_reg.rfind(')').filter(|&close| close > open_paren).ok_or_else(syntax_error)?;
+                
+                let offset = self.checked_imm(&offset_reg[0..open_paren])?;
+                let rs1 = parse_reg(&offset_reg[open_paren+1..close_paren])?;
+                
+                let encode = match parts[0] {
+                    "lb" => encode_lb,
+                    "lh" => encode_lh,
+                    "lbu" => encode_lbu,
+                    "lhu" => encode_lhu,
+                    _ => encode_lw,
+                };
+                self.img.push(encode(rd, rs1, offset));
+            }
+            "sw" | "sb" | "sh" => {
+                // 处理格式如 sw x1, 4(x2) 的指令
+                let rs2 = parse_reg(operand(parts, 1)?)?;
+                
+                // 解析 4(x2) 格式
+                let offset_reg = operand(parts, 2)?;
+                let syntax_error = || LineError::new(ErrorCode::InvalidSyntax, offset_reg, format!("无效的{}格式: {}", parts[0], offset_reg));
+                // 偏移量本身可以是带括号的表达式，寄存器在最后一对括号中
+                let open_paren = offset_reg.rfind('(').ok_or_else(syntax_error)?;
+                let close_paren = offset_reg.rfind(')').filter(|&close| close > open_paren).ok_or_else(syntax_error)?;
+                
+                let offset = self.checked_imm(&offset_reg[0..open_paren])?;
+                let rs1 = parse_reg(&offset_reg[open_paren+1..close_paren])?;
+                
+                let encode = match parts[0] {
+                    "sb" => encode_sb,
+                    "sh" => encode_sh,
+                    _ => encode_sw,
+                };
+                self.img.push(encode(rs1, rs2, offset));
+            }
+            "blt" => {
+                let rs1 = parse_reg(operand(parts, 1)?)?;
+                let rs2 = parse_reg(operand(parts, 2)?)?;
+                let offset = self.branch_offset(parts[0], operand(parts, 3)?)?;
+                self.img.push(encode_blt(rs1, rs2, offset));
+            }
+            "slli" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let imm = self.checked_shamt(operand(parts, 3)?)?;
+                self.img.push(encode_slli(rd, rs1, imm));
+            }
+            "srli" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let imm = self.checked_shamt(operand(parts, 3)?)?;
+                self.img.push(encode_srli(rd, rs1, imm));
+            }
+            "srai" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let imm = self.checked_shamt(operand(parts, 3)?)?;
+                self.img.push(encode_srai(rd, rs1, imm));
+            }
+            "andi" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let imm = self.checked_imm(operand(parts, 3)?)?;
+                self.img.push(encode_andi(rd, rs1, imm));
+            }
+            "ori" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let imm = self.checked_imm(operand(parts, 3)?)?;
+                self.img.push(encode_ori(rd, rs1, imm));
+            }
+            "xori" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let imm = self.checked_imm(operand(parts, 3)?)?;
+                self.img.push(encode_xori(rd, rs1, imm));
+            }
+            "slti" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                // 十六进制等写法超出16位时只警告，不中止汇编
+                let imm_str = operand(parts, 3)?;
+                let imm = parse_imm(imm_str)?;
+                self.warn_truncated(imm_str, imm);
+                let imm = check_imm_bits(imm, self.config.max_imm_bits)
+                    .map_err(|e| LineError::new(ErrorCode::ImmediateOutOfRange, imm_str, e))?;
+                self.img.push(encode_slti(rd, rs1, imm));
+            }
+            "sub" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let rs2 = parse_reg(operand(parts, 3)?)?;
+                self.img.push(encode_sub(rd, rs1, rs2));
+            }
+            "or" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let rs2 = parse_reg(operand(parts, 3)?)?;
+                self.img.push(encode_or(rd, rs1, rs2));
+            }
+            "and" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let rs2 = parse_reg(operand(parts, 3)?)?;
+                self.img.push(encode_and(rd, rs1, rs2));
+            }
+            "xor" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let rs2 = parse_reg(operand(parts, 3)?)?;
+                self.img.push(encode_xor(rd, rs1, rs2));
+            }
+            "nor" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let rs2 = parse_reg(operand(parts, 3)?)?;
+                self.img.push(encode_nor(rd, rs1, rs2));
+            }
+            "srl" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let rs2 = parse_reg(operand(parts, 3)?)?;
+                self.img.push(encode_srl(rd, rs1, rs2));
+            }
+            "sra" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let rs2 = parse_reg(operand(parts, 3)?)?;
+                self.img.push(encode_sra(rd, rs1, rs2));
+            }
+            "slt" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let rs2 = parse_reg(operand(parts, 3)?)?;
+                self.img.push(encode_slt(rd, rs1, rs2));
+            }
+            "sltu" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let rs2 = parse_reg(operand(parts, 3)?)?;
+                self.img.push(encode_sltu(rd, rs1, rs2));
+            }
+            "clz" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                self.img.push(encode_clz(rd, rs1));
+            }
+            "ctz" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                self.img.push(encode_ctz(rd, rs1));
+            }
+            "cpop" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                self.img.push(encode_cpop(rd, rs1));
+            }
+            "rev8" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                self.img.push(encode_rev8(rd, rs1));
+            }
+            "brev8" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                self.img.push(encode_brev8(rd, rs1));
+            }
+            "jal" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let offset = self.jump_offset(parts[0], operand(parts, 2)?)?;
+                self.img.push(encode_jal(rd, offset));
+            }
+            "jalr" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs1 = parse_reg(operand(parts, 2)?)?;
+                let imm = self.checked_imm(operand(parts, 3)?)?;
+                self.img.push(encode_jalr(rd, rs1, imm));
+            }
+            // 数据: .word v1, v2, ... 每个值占一个字
+            ".word" => {
+                for operand in directive_operands(parts)? {
+                    let value = self.word_value(operand)?;
+                    self.img.push(value);
+                }
+            }
+            // 数据: .half/.byte 按2字节/1字节紧凑存放
+            ".half" => {
+                for operand in directive_operands(parts)? {
+                    let value = parse_data_value(operand, u16::MAX as i64, ".half")?;
+                    self.push_bytes(&(value as u16).to_le_bytes());
+                }
+            }
+            ".byte" => {
+                for operand in directive_operands(parts)? {
+                    let value = parse_data_value(operand, u8::MAX as i64, ".byte")?;
+                    self.push_bytes(&[value as u8]);
+                }
+            }
+            // 数据: .space N 预留N个0字节
+            ".space" => {
+                let size = parse_space_size(parts)?;
+                self.push_bytes(&vec![0; size as usize]);
+            }
+            // 数据: .ascii/.asciiz "..." 字符串的字节，.asciiz末尾加0
+            ".ascii" | ".asciiz" => {
+                let bytes = string_directive_bytes(line, parts[0])?;
+                self.push_bytes(&bytes);
+            }
+            // .align N 用0字节填充到2^N字节边界
+            ".align" => {
+                let pc = self.pc();
+                let padding = pc.next_multiple_of(parse_alignment(parts)?) - pc;
+                self.push_bytes(&vec![0; padding as usize]);
+            }
+            // 伪指令: mov rd, rs -> addi rd, rs, 0
+            "mov" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs = parse_reg(operand(parts, 2)?)?;
+                self.img.push(encode_addi(rd, rs, 0));
+            }
+            // 伪指令: not rd, rs -> nor rd, rs, x0
+            "not" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs = parse_reg(operand(parts, 2)?)?;
+                self.img.push(encode_nor(rd, rs, 0));
+            }
+            // 伪指令: j offset -> jal x0, offset; call offset -> jal x1, offset
+            "j" | "call" => {
+                let rd = if parts[0] == "j" { 0 } else { 1 };
+                let offset = self.jump_offset(parts[0], operand(parts, 1)?)?;
+                self.img.push(encode_jal(rd, offset));
+            }
+            // 伪指令: ret -> jalr x0, x1, 0
+            "ret" => {
+                self.img.push(encode_jalr(0, 1, 0));
+            }
+            // 伪指令: beqz/bnez/bgez/bltz rs, offset -> 与x0比较的分支
+            "beqz" | "bnez" | "bgez" | "bltz" => {
+                let rs = parse_reg(operand(parts, 1)?)?;
+                let offset = self.branch_offset(parts[0], operand(parts, 2)?)?;
+                self.img.push(match parts[0] {
+                    "beqz" => encode_beq(rs, 0, offset),
+                    "bnez" => encode_bne(rs, 0, offset),
+                    "bgez" => encode_bge(rs, 0, offset),
+                    _ => encode_blt(rs, 0, offset),
+                });
+            }
+            // 伪指令: li rd, imm32 -> addi 或 lui + addi
+            "li" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let value = parse_imm32(operand(parts, 2)?)?;
+                self.img.extend(encode_li(rd, value));
+            }
+            // 伪指令: popcount rd, rs -> 用t6放掩码的22条指令，见encode_popcount
+            "popcount" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                let rs = parse_reg(operand(parts, 2)?)?;
+                for (reg, text) in [(rd, parts[1]), (rs, parts[2])] {
+                    if reg == POPCOUNT_SCRATCH {
+                        return Err(LineError::new(ErrorCode::InvalidRegister, text, format!("popcount展开时要用{}作临时寄存器，操作数不能是它: {}", ABI_NAMES[POPCOUNT_SCRATCH as usize], text)));
+                    }
+                }
+                self.img.extend(encode_popcount(rd, rs, POPCOUNT_SCRATCH));
+            }
+            // 伪指令: nop -> addi x0, x0, 0
+            "nop" => {
+                self.img.push(encode_addi(0, 0, 0));
+            }
+            // 伪指令: zero_reg rd -> addi rd, x0, 0
+            "zero_reg" | "zero" => {
+                let rd = parse_reg(operand(parts, 1)?)?;
+                self.img.push(encode_addi(rd, 0, 0));
+            }
+            "halt" => {
+                self.img.push(encode_halt());
+            },
+            _ => return Err(LineError::new(ErrorCode::UnknownInstruction, parts[0], format!("未知指令: {}", parts[0]))),
+        }
+        Ok(())
+    }
+}
+
+// 按指令字段分组显示二进制: 0b[31:21]_[20:16]_[15:11]_[10:6]_[5:0]
+pub fn format_binary_grouped(instr: u32) -> String {
+    let binary_str = format!("{:032b}", instr);
+    format!("0b{}_{}_{}_{}_{}",
+        &binary_str[0..11],
+        &binary_str[11..16],
+        &binary_str[16..21],
+        &binary_str[21..26],
+        &binary_str[26..32])
+}
+
+// 每个字对应的源码行号（从1开始）。按默认配置重新汇编source得到：默认配置最宽松，
+// 以任何配置汇编通过的源码在默认配置下也能通过；汇编失败时返回空
+fn word_source_lines(source: &str) -> Vec<usize> {
+    let config = AssemblerConfig::default();
+    let mut assembler = Assembler::new(&config);
+    match assembler.run(source) {
+        Ok(()) => assembler.lines,
+        Err(_) => Vec::new(),
+    }
+}
+
+// 源码映射：字下标到源码行号（从1开始）。空行、注释和.equ等不生成字的行不出现在映射中，
+// 一行生成多个字时这些字映射到同一行
+pub fn generate_source_map(source: &str) -> HashMap<usize, usize> {
+    word_source_lines(source).into_iter().enumerate().filter(|&(_, line)| line > 0).collect()
+}
+
+// 源码映射的JSON格式，按字下标排序: { "0": 3, "1": 5, "4": 7 }
+pub fn format_source_map(map: &HashMap<usize, usize>) -> String {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort();
+    if entries.is_empty() {
+        return "{}\n".to_string();
+    }
+    let entries: Vec<String> = entries.iter().map(|(index, line)| format!("\"{}\": {}", index, line)).collect();
+    format!("{{ {} }}\n", entries.join(", "))
+}
+
+// 读取format_source_map输出的源码映射
+pub fn parse_source_map(text: &str) -> Result<HashMap<usize, usize>, String> {
+    let body = text.trim().strip_prefix('{').and_then(|rest| rest.strip_suffix('}'))
+        .ok_or("源码映射不是JSON对象")?;
+    let mut map = HashMap::new();
+    for entry in body.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let parsed = entry.split_once(':').and_then(|(index, line)| {
+            let index = index.trim().strip_prefix('"')?.strip_suffix('"')?.parse().ok()?;
+            Some((index, line.trim().parse().ok()?))
+        });
+        let (index, line) = parsed.ok_or_else(|| format!("无效的源码映射项: {}", entry))?;
+        map.insert(index, line);
+    }
+    Ok(map)
+}
+
+// 符号表，每行一个"标签名 十六进制地址"，按地址排序: loop 0x00000004
+pub fn format_symbol_table(symbols: &HashMap<String, u32>) -> String {
+    let mut entries: Vec<_> = symbols.iter().collect();
+    entries.sort_by_key(|&(name, &address)| (address, name));
+    entries.iter().map(|(name, address)| format!("{} 0x{:08X}\n", name, address)).collect()
+}
+
+// 读取format_symbol_table输出的符号表，空行和#开头的行被忽略
+pub fn parse_symbol_table(text: &str) -> Result<HashMap<String, u32>, String> {
+    let mut symbols = HashMap::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = line.split_once(char::is_whitespace).and_then(|(name, address)| {
+            let address = parse_int(address.trim())?;
+            Some((name, u32::try_from(address).ok()?))
+        });
+        match parsed {
+            Some((name, address)) if is_identifier(name) => symbols.insert(name.to_string(), address),
+            _ => return Err(format!("第{}行: 无效的符号: {}", i + 1, line)),
+        };
+    }
+    Ok(symbols)
+}
+
+// 汇编清单，每行: 字节地址  十六进制编码  分组二进制  源码
+// 一行源码生成多个字时，后面的字各占一行、不重复源码；空行、注释和.equ等不生成字的行只有源码，
+// 只有标签的行显示标签的地址。img与source对不上时，所有字列在源码之后
+pub fn generate_listing(source: &str, img: &[u32], labels: &HashMap<String, u32>) -> String {
+    const WORD_COLUMNS: usize = 54; // "0000  00000000  0b..." 的宽度
+
+    let mut lines = word_source_lines(source);
+    if lines.len() != img.len() {
+        lines.clear();
+    }
+    let source_lines: Vec<&str> = source.lines().collect();
+    let mut words_of_line = vec![Vec::new(); source_lines.len() + 1];
+    let mut unlisted = Vec::new();
+    for (index, &line) in lines.iter().enumerate() {
+        match words_of_line.get_mut(line) {
+            Some(words) if line > 0 => words.push(index),
+            _ => unlisted.push(index),
+        }
+    }
+    if lines.is_empty() {
+        unlisted.extend(0..img.len());
+    }
+
+    let format_word = |index: usize| format!("{:04X}  {:08X}  {}", index * 4, img[index], format_binary_grouped(img[index]));
+    let mut output = String::new();
+    for (i, text) in source_lines.iter().enumerate() {
+        let words = &words_of_line[i + 1];
+        let prefix = match words.first() {
+            Some(&index) => format_word(index),
+            None => match split_label(strip_comment(text)).0.and_then(|name| labels.get(name)) {
+                Some(address) => format!("{:04X}", address),
+                None => String::new(),
+            },
+        };
+        let line = format!("{:<width$}  {}", prefix, text, width = WORD_COLUMNS);
+        output.push_str(line.trim_end());
+        output.push('\n');
+        for &index in words.iter().skip(1) {
+            output.push_str(&format_word(index));
+            output.push('\n');
+        }
+    }
+    for index in unlisted {
+        output.push_str(&format_word(index));
+        output.push('\n');
+    }
+    output
+}
+
+// 输出文件格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Binary,   // 小端字节序的原始二进制(.o)
+    IntelHex, // Intel HEX文本(.hex)，FPGA烧写工具常用
+    SRecord,  // Motorola S-record文本(.srec)，嵌入式工具链常用
+    ReadmemH, // 每行一个8位小写十六进制字，供Verilog $readmemh初始化存储器
+    ReadmemB, // 每行一个32位二进制字，供Verilog $readmemb使用
+    Mif,      // Quartus的存储器初始化文件(.mif)
+    CHeader,  // C头文件(.h)，程序作为uint32_t数组嵌入固件
+    Object,   // 带文件头和CRC32校验和的目标文件(.obj)，见object模块
+}
+
+impl OutputFormat {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "bin" => Ok(OutputFormat::Binary),
+            "ihex" => Ok(OutputFormat::IntelHex),
+            "srec" => Ok(OutputFormat::SRecord),
+            "readmemh" => Ok(OutputFormat::ReadmemH),
+            "readmemb" => Ok(OutputFormat::ReadmemB),
+            "mif" => Ok(OutputFormat::Mif),
+            "c" => Ok(OutputFormat::CHeader),
+            "obj" => Ok(OutputFormat::Object),
+            _ => Err(format!("未知的输出格式: {}（可选bin、ihex、srec、readmemh、readmemb、mif、c、obj）", name)),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Binary => "o",
+            OutputFormat::IntelHex => "hex",
+            OutputFormat::SRecord => "srec",
+            OutputFormat::ReadmemH => "memh",
+            OutputFormat::ReadmemB => "memb",
+            OutputFormat::Mif => "mif",
+            OutputFormat::CHeader => "h",
+            OutputFormat::Object => "obj",
+        }
+    }
+}
+
+// C头文件：static const uint32_t NAME[] 数组，每行4个字，后面是NAME_len字数
+pub fn format_c_header(img: &[u32], name: &str) -> String {
+    let mut output = String::from("#pragma once\n\n#include <stddef.h>\n#include <stdint.h>\n\n");
+    output.push_str(&format!("static const uint32_t {}[] = {{\n", name));
+    for row in img.chunks(4) {
+        let words: Vec<String> = row.iter().map(|word| format!("0x{:08X}ULL,", word)).collect();
+        output.push_str(&format!("    {}\n", words.join(" ")));
+    }
+    output.push_str("};\n");
+    output.push_str(&format!("static const size_t {}_len = {};\n", name, img.len()));
+    output
+}
+
+// Quartus MIF：头部给出深度、位宽和地址/数据进制（都用十六进制），
+// CONTENT BEGIN ... END; 之间每行 "地址 : 值;"。
+// depth为Some时用0字补齐到该深度，补齐部分写成 [起始..结束] 范围
+pub fn format_mif(img: &[u32], depth: Option<usize>) -> Result<String, String> {
+    let depth = depth.unwrap_or(img.len().max(1));
+    if depth < img.len() {
+        return Err(format!("MIF深度 {} 小于程序的字数 {}", depth, img.len()));
+    }
+    let mut output = format!("DEPTH = {};\nWIDTH = 32;\nADDRESS_RADIX = HEX;\nDATA_RADIX = HEX;\nCONTENT\nBEGIN\n", depth);
+    for (i, word) in img.iter().enumerate() {
+        output.push_str(&format!("{:X} : {:08X};\n", i, word));
+    }
+    match depth - img.len() {
+        0 => {}
+        1 => output.push_str(&format!("{:X} : 00000000;\n", img.len())),
+        _ => output.push_str(&format!("[{:X}..{:X}] : 00000000;\n", img.len(), depth - 1)),
+    }
+    output.push_str("END;\n");
+    Ok(output)
+}
+
+// $readmemh/$readmemb的文本：每行一个字，不带前缀。
+// address_every为Some(N)时每N个字前插入一行@地址（按字编址的十六进制）
+pub fn format_readmem(img: &[u32], binary: bool, address_every: Option<usize>) -> String {
+    let mut output = String::new();
+    for (i, word) in img.iter().enumerate() {
+        if address_every.is_some_and(|n| i % n == 0) {
+            output.push_str(&format!("@{:x}\n", i));
+        }
+        if binary {
+            output.push_str(&format!("{:032b}\n", word));
+        } else {
+            output.push_str(&format!("{:08x}\n", word));
+        }
+    }
+    output
+}
+
+// Intel HEX的一条记录: :LLAAAATT[数据]CC，CC是前面所有字节之和的二进制补码
+fn intel_hex_record(record_type: u8, address: u16, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend(address.to_be_bytes());
+    bytes.push(record_type);
+    bytes.extend(data);
+    let checksum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)).wrapping_neg();
+    bytes.push(checksum);
+    let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    format!(":{}\n", hex)
+}
+
+// 程序镜像转为Intel HEX：与.o相同的小端字节，每条数据记录16字节，
+// 超过64KB时用04类型记录给出高16位地址，最后是:00000001FF结束记录
+pub fn format_intel_hex(img: &[u32]) -> String {
+    let bytes: Vec<u8> = img.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let mut output = String::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let address = (i * 16) as u32;
+        if address > 0 && address & 0xFFFF == 0 {
+            output.push_str(&intel_hex_record(0x04, 0, &((address >> 16) as u16).to_be_bytes()));
+        }
+        output.push_str(&intel_hex_record(0x00, address as u16, chunk));
+    }
+    output.push_str(&intel_hex_record(0x01, 0, &[]));
+    output
+}
+
+// S-record的校验和：长度、地址和数据各字节之和取低8位后按位取反
+fn srec_checksum(bytes: &[u8]) -> u8 {
+    !bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+// S-record的一条记录: S<类型><长度><地址><数据><校验和>。
+// 长度包括地址、数据和校验和的字节数；S0/S1/S9用2字节地址，S2/S8用3字节，S3/S7用4字节
+fn srec_record(record_type: u8, address: u32, data: &[u8]) -> String {
+    let address_len = match record_type {
+        2 | 8 => 3,
+        3 | 7 => 4,
+        _ => 2,
+    };
+    let mut bytes = vec![(address_len + data.len() + 1) as u8];
+    bytes.extend(&address.to_be_bytes()[4 - address_len..]);
+    bytes.extend(data);
+    bytes.push(srec_checksum(&bytes));
+    let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    format!("S{}{}\n", record_type, hex)
+}
+
+// 程序镜像转为S-record：S0头记录，每条16字节的S3数据记录（32位地址），
+// 最后是入口地址为0的S7结束记录
+pub fn format_srecord(img: &[u32], header: &str) -> String {
+    let bytes: Vec<u8> = img.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let mut output = srec_record(0, 0, header.as_bytes());
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        output.push_str(&srec_record(3, (i * 16) as u32, chunk));
+    }
+    output.push_str(&srec_record(7, 0, &[]));
+    output
+}
+
+// 生成WebAssembly文本格式：程序按小端字节序放在线性内存地址0处的数据段，
+// 导出的main函数返回指令条数，供JavaScript模拟器读取
+pub fn generate_wat(img: &[u32]) -> String {
+    const WASM_PAGE_SIZE: usize = 65536;
+    let pages = (img.len() * 4).div_ceil(WASM_PAGE_SIZE).max(1);
+
+    let mut output = String::new();
+    output.push_str("(module\n");
+    output.push_str(&format!("  (memory (export \"memory\") {})\n", pages));
+    output.push_str("  (data (i32.const 0)");
+    for &word in img {
+        output.push_str("\n    \"");
+        for byte in word.to_le_bytes() {
+            output.push_str(&format!("\\{:02x}", byte));
+        }
+        output.push('"');
+    }
+    output.push_str(")\n");
+    output.push_str("  (func $main (export \"main\") (result i32)\n");
+    output.push_str(&format!("    i32.const {})\n", img.len()));
+    output.push_str(")\n");
+    output
+}
+
+// SHA-256（FIPS 180-4）
+fn sha256(data: &[u8]) -> [u8; 32] {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+        0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+        0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+        0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+        0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+        0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+        0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+    ];
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+
+    // 填充: 0x80，若干0，最后是64位大端的比特长度
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend(((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([block[i * 4], block[i * 4 + 1], block[i * 4 + 2], block[i * 4 + 3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+    for (chunk, word) in digest.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+// 指令字节（与.o文件内容相同）的SHA-256前8个十六进制字符
+pub fn binary_hash(img: &[u32]) -> String {
+    let bytes: Vec<u8> = img.iter().flat_map(|word| word.to_le_bytes()).collect();
+    sha256(&bytes)[..4].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// =================== 反汇编器部分 ===================
+
+// 伪指令写法：nop/zero/ret/beqz/bnez/bgez/bltz/j总是使用，
+// pseudos为true时还把nor rd, rs, x0显示为not、addi rd, rs, 0 (rd、rs均非x0) 显示为mov
+fn pseudo_display(instruction: Instruction, pseudos: bool) -> Option<String> {
+    let text = match instruction {
+        Instruction::Nor { rd, rs1, rs2: 0 } if pseudos => format!("not x{}, x{}", rd, rs1),
+        Instruction::Addi { rd: 0, rs1: 0, imm: 0 } => "nop".to_string(),
+        Instruction::Addi { rd, rs1, imm: 0 } if pseudos && rd != 0 && rs1 != 0 => format!("mov x{}, x{}", rd, rs1),
+        Instruction::Addi { rd, rs1: 0, imm: 0 } => format!("zero x{}", rd),
+        Instruction::Jalr { rd: 0, rs1: 1, imm: 0 } => "ret".to_string(),
+        Instruction::Beq { rs1, rs2: 0, offset } => format!("beqz x{}, {}", rs1, offset),
+        Instruction::Bne { rs1, rs2: 0, offset } => format!("bnez x{}, {}", rs1, offset),
+        Instruction::Bge { rs1, rs2: 0, offset } => format!("bgez x{}, {}", rs1, offset),
+        Instruction::Blt { rs1, rs2: 0, offset } => format!("bltz x{}, {}", rs1, offset),
+        Instruction::Jal { rd: 0, offset } => format!("j {}", offset),
+        _ => return None,
+    };
+    Some(text)
+}
+
+// 将反汇编文本中的xN替换为调用约定中的寄存器名
+pub fn use_abi_names(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find('x') {
+        let (before, after) = rest.split_at(pos);
+        output.push_str(before);
+        let digits = after[1..].chars().take_while(|c| c.is_ascii_digit()).count();
+        let at_boundary = !output.ends_with(|c: char| c.is_ascii_alphanumeric());
+        let ends_token = !after[1 + digits..].starts_with(|c: char| c.is_ascii_alphanumeric());
+        match after[1..1 + digits].parse::<usize>() {
+            Ok(reg) if at_boundary && ends_token && reg < 32 => output.push_str(ABI_NAMES[reg]),
+            _ => output.push_str(&after[..1 + digits]),
+        }
+        rest = &after[1 + digits..];
+    }
+    output.push_str(rest);
+    output
+}
+
+// 把原始二进制按4字节一组转换为字，末尾不足4字节的部分被忽略
+pub fn words_from_bytes(bytes: &[u8], big_endian: bool) -> Vec<u32> {
+    bytes.chunks_exact(4)
+        .map(|chunk| {
+            let word = [chunk[0], chunk[1], chunk[2], chunk[3]];
+            if big_endian { u32::from_be_bytes(word) } else { u32::from_le_bytes(word) }
+        })
+        .collect()
+}
+
+// 无法解码为指令的字（未知操作码，或固定为0的字段不为0）视为数据
+pub fn is_data_word(instr: u32) -> bool {
+    Instruction::decode(instr).is_none()
+}
+
+// 解码一个字，无法解码时返回"未知指令"
+pub fn decode_instruction(instr: u32) -> String {
+    decode_instruction_with(instr, false)
+}
+
+// 在Instruction的规范写法之上套用伪指令写法，pseudos为true时尽量显示为伪指令（如mov）
+pub fn decode_instruction_with(instr: u32, pseudos: bool) -> String {
+    match Instruction::decode(instr) {
+        Some(instruction) => pseudo_display(instruction, pseudos).unwrap_or_else(|| instruction.to_string()),
+        None => format!("未知指令: 0x{:08X}", instr),
+    }
+}
+
+// =================== 静态分析 ===================
+
+// 调用约定中的寄存器名（下标即寄存器编号）
+pub const ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2",
+    "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7",
+    "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+pub const REG_SP: u8 = 2;
+
+// 被调用者保存寄存器 s0~s11
+fn is_callee_saved(reg: u8) -> bool {
+    matches!(reg, 8 | 9 | 18..=27)
+}
+
+// 参数寄存器 a0~a7
+fn is_argument(reg: u8) -> bool {
+    (10..=17).contains(&reg)
+}
+
+// 返回指令写入的寄存器和读取的寄存器
+fn register_operands(instr: u32) -> (Option<u8>, Vec<u8>) {
+    let opcode = instr & 0x3F;
+    let rd = ((instr >> 6) & 0x1F) as u8;
+    let field_11 = ((instr >> 11) & 0x1F) as u8;
+    let field_16 = ((instr >> 16) & 0x1F) as u8;
+
+    match lookup_opcode(opcode).map(|entry| entry.itype) {
+        Some(InstructionType::A) => match opcode {
+            OPCODE_CTZ | OPCODE_CPOP | OPCODE_REV8 | OPCODE_BREV8 => (Some(rd), vec![field_11]),
+            _ => (Some(rd), vec![field_11, field_16]),
+        },
+        Some(InstructionType::B) => match opcode {
+            OPCODE_LUI => (Some(rd), vec![]),
+            _ => (Some(rd), vec![field_11]),
+        },
+        // C型指令不写寄存器；sw/blt编码时交换了rs1和rs2，rs1位于[15:11]
+        Some(InstructionType::C) => match opcode {
+            OPCODE_BNE | OPCODE_BEQ | OPCODE_BGE | OPCODE_BGEU => (None, vec![field_16, field_11]),
+            _ => (None, vec![field_11, field_16]),
+        },
+        Some(InstructionType::J) => (Some(rd), vec![]),
+        Some(InstructionType::Halt) | None => (None, vec![]),
+    }
+}
+
+// 汇编和调用约定检查产生的警告
+#[derive(Debug, PartialEq, Eq)]
+pub enum Warning {
+    // 写入s寄存器前没有先将其保存到栈上
+    CalleeSavedNotSaved { reg: u8, line: usize },
+    // 参数寄存器在被读取之前就被覆盖
+    ArgumentClobbered { reg: u8, line: usize },
+    // 目的寄存器是x0，写入没有效果
+    WriteToX0 { line: usize },
+    // .equ常量被重新定义
+    ConstantRedefined { name: String, line: usize },
+    // 立即数超出字段宽度，高位被截断
+    ImmediateTruncated { original: i64, truncated: i16, bits: u32, line: usize },
+    // halt之后的指令，没有标签或跳转指向它，不会被执行
+    UnreachableCode { address: u32, line: usize },
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Warning::CalleeSavedNotSaved { reg, line } => write!(f,
+                "第{}行: 写入被调用者保存寄存器 x{}({}) 前未将其保存到栈上", line, reg, ABI_NAMES[*reg as usize]),
+            Warning::ArgumentClobbered { reg, line } => write!(f,
+                "第{}行: 参数寄存器 x{}({}) 在读取前被覆盖", line, reg, ABI_NAMES[*reg as usize]),
+            Warning::WriteToX0 { line } => write!(f, "line {}: writing to x0 has no effect", line),
+            Warning::ConstantRedefined { name, line } => write!(f, "第{}行: 常量 {} 被重新定义", line, name),
+            Warning::ImmediateTruncated { original, truncated, bits, line } => write!(f,
+                "第{}行: 立即数 {} 超出{}位字段，被截断为 {}", line, original, bits, truncated),
+            Warning::UnreachableCode { address, line } => write!(f,
+                "第{}行: 地址 0x{:04X} 处的指令在halt之后，不会被执行", line, address),
+        }
+    }
+}
+
+// 汇编input并按调用约定检查寄存器使用，只返回这一类警告
+pub fn check_abi(input: &str, config: &AssemblerConfig) -> Result<Vec<Warning>, Vec<AssemblyError>> {
+    let mut assembler = Assembler::new(config);
+    assembler.run(input)?;
+    Ok(abi_warnings(&assembler.img, &assembler.lines, &assembler.data))
+}
+
+// 按源码顺序做简单的数据流检查，每个寄存器只报告一次。数据字不是指令，不参与检查
+fn abi_warnings(img: &[u32], lines: &[usize], data: &[bool]) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut saved = [false; 32];
+    let mut read = [false; 32];
+    let mut reported = [false; 32];
+
+    for ((&instr, &line), _) in img.iter().zip(lines).zip(data).filter(|(_, &is_data)| !is_data) {
+        let (write, reads) = register_operands(instr);
+        for &reg in &reads {
+            read[reg as usize] = true;
+        }
+
+        // sw s?, off(sp) 视为保存该寄存器
+        if instr & 0x3F == OPCODE_SW && reads[0] == REG_SP {
+            saved[reads[1] as usize] = true;
+        }
+
+        let Some(reg) = write else { continue };
+        let idx = reg as usize;
+        if reported[idx] {
+            continue;
+        }
+        if is_callee_saved(reg) && !saved[idx] {
+            warnings.push(Warning::CalleeSavedNotSaved { reg, line });
+            reported[idx] = true;
+        } else if is_argument(reg) && !read[idx] {
+            warnings.push(Warning::ArgumentClobbered { reg, line });
+            reported[idx] = true;
+        }
+    }
+
+    warnings
+}
+
+// 流水线数据冒险：读取的寄存器在冒险窗口内被前面的指令写入。
+// 普通指令的结果对紧随其后的一条指令形成冒险，lw和mul的结果延迟更久，
+// 对之后两条指令都形成冒险。
+pub fn pipeline_hazards(instructions: &[u32]) -> Vec<Vec<String>> {
+    let mut hazards = Vec::with_capacity(instructions.len());
+
+    for (i, &instr) in instructions.iter().enumerate() {
+        let (_, reads) = register_operands(instr);
+        let mut found = Vec::new();
+
+        for distance in 1..=2 {
+            let Some(prev_index) = i.checked_sub(distance) else { break };
+            let prev = instructions[prev_index];
+            let Some(written) = register_operands(prev).0 else { continue };
+            if written == 0 || !reads.contains(&written) {
+                continue;
+            }
+
+            let kind = match prev & 0x3F {
+                OPCODE_LW | OPCODE_LB | OPCODE_LH | OPCODE_LBU | OPCODE_LHU => "load-use",
+                OPCODE_MUL => "mul结果未就绪",
+                _ if distance == 1 => "RAW",
+                _ => continue,
+            };
+            found.push(format!("{}: x{} 由前{}条指令写入", kind, written, distance));
+        }
+        hazards.push(found);
+    }
+
+    hazards
+}
+
+// 每个寄存器作为源操作数被读取、作为目的寄存器被写入的次数 (reads, writes)。
+// 不能解码为指令的数据字不计
+pub fn register_usage(instructions: &[u32]) -> [(usize, usize); 32] {
+    let mut usage = [(0, 0); 32];
+    for &instr in instructions.iter().filter(|&&instr| !is_data_word(instr)) {
+        let (write, reads) = register_operands(instr);
+        for reg in reads {
+            usage[reg as usize].0 += 1;
+        }
+        if let Some(reg) = write {
+            usage[reg as usize].1 += 1;
+        }
+    }
+    usage
+}
+
+// 被写入但程序中没有任何指令读取的寄存器。写入x0本来就会被丢弃，不算在内
+pub fn dead_writes(usage: &[(usize, usize); 32]) -> Vec<u8> {
+    (1..32u8).filter(|&reg| matches!(usage[reg as usize], (0, writes) if writes > 0)).collect()
+}
+
+// 每行一个用到的寄存器: 读取次数、写入次数、读写比（没有写入时为-），最后是无用写入的警告
+pub fn format_register_usage(usage: &[(usize, usize); 32]) -> String {
+    let mut output = "# 寄存器使用统计\n# 寄存器  读取  写入  读/写\n".to_string();
+    for (reg, &(reads, writes)) in usage.iter().enumerate().filter(|(_, &usage)| usage != (0, 0)) {
+        let ratio = if writes == 0 { "-".to_string() } else { format!("{:.2}", reads as f64 / writes as f64) };
+        output.push_str(&format!("# {:<6} {:>5} {:>5} {:>6}\n", format!("x{}", reg), reads, writes, ratio));
+    }
+    for reg in dead_writes(usage) {
+        output.push_str(&format!("# 警告: x{} 被写入后从未被读取\n", reg));
+    }
+    output
+}
+
+// =================== 游程压缩 ===================
+
+// 连续相同的字达到该长度才编码为游程记录
+pub const RLE_MIN_RUN: usize = 3;
+
+// 压缩格式由若干记录组成，每个字段都是4字节:
+//   count(>0), fill      -> fill重复count次
+//   0, len, word * len   -> 原样保存的len个字
+pub fn compress_words(words: &[u32]) -> Vec<u32> {
+    let mut output = Vec::new();
+    let mut verbatim: Vec<u32> = Vec::new();
+
+    let mut i = 0;
+    while i < words.len() {
+        let mut run = 1;
+        while i + run < words.len() && words[i + run] == words[i] {
+            run += 1;
+        }
+
+        if run >= RLE_MIN_RUN {
+            if !verbatim.is_empty() {
+                output.push(0);
+                output.push(verbatim.len() as u32);
+                output.append(&mut verbatim);
+            }
+            output.push(run as u32);
+            output.push(words[i]);
+        } else {
+            verbatim.extend_from_slice(&words[i..i + run]);
+        }
+        i += run;
+    }
+
+    if !verbatim.is_empty() {
+        output.push(0);
+        output.push(verbatim.len() as u32);
+        output.append(&mut verbatim);
+    }
+    output
+}
+
+pub fn decompress_words(data: &[u32]) -> Result<Vec<u32>, String> {
+    let mut output = Vec::new();
+
+    let mut i = 0;
+    while i < data.len() {
+        if i + 1 >= data.len() {
+            return Err(format!("压缩数据在第{}个字处被截断", i));
+        }
+        let count = data[i] as usize;
+        if count > 0 {
+            output.extend(std::iter::repeat_n(data[i + 1], count));
+            i += 2;
+        } else {
+            let len = data[i + 1] as usize;
+            let start = i + 2;
+            if start + len > data.len() {
+                return Err(format!("原样块长度{}超出数据末尾", len));
+            }
+            output.extend_from_slice(&data[start..start + len]);
+            i = start + len;
+        }
+    }
+    Ok(output)
+}
+
+// =================== 解码性能测试 ===================
+
+// 某类指令的解码吞吐量统计（单位: 百万条指令/秒）
+pub struct DecodeBenchmark {
+    pub itype: InstructionType,
+    pub count: usize,
+    pub mean_mips: f64,
+    pub stddev_mips: f64,
+}
+
+// 按指令类型分组，每组重复解码iterations轮，统计每轮吞吐量的均值和标准差
+pub fn benchmark_decode(instructions: &[u32], iterations: usize) -> Vec<DecodeBenchmark> {
+    let mut results = Vec::new();
+
+    for itype in [InstructionType::A, InstructionType::B, InstructionType::C, InstructionType::J] {
+        let group: Vec<u32> = instructions.iter().copied()
+            .filter(|&instr| lookup_opcode(instr & 0x3F).map(|e| e.itype) == Some(itype))
+            .collect();
+        if group.is_empty() {
+            continue;
+        }
+
+        let mut samples = Vec::with_capacity(iterations);
+        for _ in 0..iterations {
+            let start = Instant::now();
+            for &instr in &group {
+                std::hint::black_box(decode_instruction(std::hint::black_box(instr)));
+            }
+            let secs = start.elapsed().as_secs_f64().max(1e-9);
+            samples.push(group.len() as f64 / secs / 1e6);
+        }
+
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+        results.push(DecodeBenchmark { itype, count: group.len(), mean_mips: mean, stddev_mips: variance.sqrt() });
+    }
+
+    results
+}
+
+// 反汇编配置
+pub struct DisasmConfig {
+    pub benchmark_decode: bool, // 只测量解码吞吐量，不输出反汇编结果
+    pub bench_iterations: usize,
+    pub annotate_hazards: bool, // 在输出中标注流水线数据冒险
+    pub pseudo: bool,           // 将匹配的编码显示为伪指令
+    pub abi_names: bool,        // 用调用约定中的寄存器名代替x0~x31
+    pub data_ranges: Vec<(u32, u32)>, // 按数据输出的字节地址范围[start, end)
+    pub big_endian: bool,       // 按大端字节序读取二进制文件
+    pub symbols: HashMap<String, u32>, // 符号表，跳转目标有标签时显示标签名
+    pub histogram: bool,        // 在输出末尾附上助记符出现次数的统计
+    pub histogram_only: bool,   // 只输出助记符统计，不输出逐条反汇编
+    pub reg_usage: bool,        // 在输出末尾附上各寄存器的读写次数
+    pub inline_values: HashMap<u32, (u8, Vec<i32>)>, // 运行时各地址写入的寄存器和值，见sim::parse_trace
+}
+
+impl Default for DisasmConfig {
+    fn default() -> Self {
+        DisasmConfig {
+            benchmark_decode: false,
+            bench_iterations: 10000,
+            annotate_hazards: false,
+            pseudo: false,
+            abi_names: false,
+            data_ranges: Vec::new(),
+            big_endian: false,
+            symbols: HashMap::new(),
+            histogram: false,
+            histogram_only: false,
+            reg_usage: false,
+            inline_values: HashMap::new(),
+        }
+    }
+}
+
+// 分支和jal的跳转目标（字节地址），偏移相对于指令自身的地址
+fn branch_target(instr: u32, address: u32) -> Option<u32> {
+    let offset = match Instruction::decode(instr)? {
+        Instruction::Bne { offset, .. } | Instruction::Beq { offset, .. } | Instruction::Bge { offset, .. }
+        | Instruction::Bgeu { offset, .. } | Instruction::Blt { offset, .. } => offset as i32,
+        Instruction::Jal { offset, .. } => offset,
+        _ => return None,
+    };
+    Some(address.wrapping_add(offset as u32))
+}
+
+// 运行时写入的值: 每次相同时只显示一个值，否则显示第一次和最后一次的值及执行次数
+fn format_inline_values(reg: &str, values: &[i32]) -> String {
+    match values {
+        [first, rest @ ..] if rest.iter().all(|value| value == first) => format!("{} <- 0x{:08X}", reg, first),
+        [first, .., last] => format!("{} <- 0x{:08X}, ..., 0x{:08X} ({}次)", reg, first, last, values.len()),
+        _ => String::new(),
+    }
+}
+
+// 生成反汇编清单
+pub fn disassemble_listing(instructions: &[u32], config: &DisasmConfig) -> String {
+    if config.histogram_only {
+        return format_histogram(&compute_histogram(instructions));
+    }
+    let mut output = String::new();
+    
+    // 地址和机器码放在行尾的注释中，输出本身是可以重新汇编的源码
+    output.push_str("# 反汇编结果\n");
+    output.push_str("# 格式: [汇编指令]  # [地址]: [十六进制表示]\n\n");
+
+    let is_data = |i: usize| {
+        let address = (i * 4) as u32;
+        config.data_ranges.iter().any(|&(start, end)| (start..end).contains(&address)) || is_data_word(instructions[i])
+    };
+    let targets: Vec<Option<u32>> = (0..instructions.len())
+        .map(|i| if is_data(i) { None } else { branch_target(instructions[i], (i * 4) as u32) })
+        .collect();
+
+    // 同一地址有多个标签时取名字最小的。没有符号的跳转目标在程序范围内时生成L_XXXX标签，
+    // 跳转指令的偏移替换为标签名
+    let mut labels: HashMap<u32, String> = HashMap::new();
+    for (name, &address) in &config.symbols {
+        let entry = labels.entry(address).or_insert_with(|| name.clone());
+        if name < entry {
+            *entry = name.clone();
+        }
+    }
+    let end = (instructions.len() * 4) as u32;
+    for &target in targets.iter().flatten().filter(|&&target| target < end && target % 4 == 0) {
+        labels.entry(target).or_insert_with(|| format!("L_{:04X}", target));
+    }
+
+    let hazards = if config.annotate_hazards {
+        pipeline_hazards(instructions)
+    } else {
+        vec![Vec::new(); instructions.len()]
+    };
+    
+    for (i, &instr) in instructions.iter().enumerate() {
+        if let Some(label) = labels.get(&((i * 4) as u32)) {
+            output.push_str(&format!("{}:\n", label));
+        }
+        if is_data(i) {
+            output.push_str(&format!("    .word 0x{:08X}  # {:04X}: {:08X}  数据\n", instr, i * 4, instr));
+            continue;
+        }
+        let mut disasm = decode_instruction_with(instr, config.pseudo);
+        if config.abi_names {
+            disasm = use_abi_names(&disasm);
+        }
+        let mut comments = vec![format!("{:04X}: {:08X}", i * 4, instr)];
+        if let Some(target) = targets[i] {
+            // 最后一个操作数是跳转偏移，替换为目标地址处的标签名
+            if let Some(label) = labels.get(&target) {
+                if let Some((head, _)) = disasm.rsplit_once(' ') {
+                    disasm = format!("{} {}", head, label);
+                }
+            }
+            comments.push(format!("-> 0x{:04X}", target));
+        }
+        if !hazards[i].is_empty() {
+            comments.push(format!("[HAZARD] {}", hazards[i].join("; ")));
+        }
+        if let Some((reg, values)) = config.inline_values.get(&((i * 4) as u32)) {
+            let reg = if config.abi_names { ABI_NAMES[*reg as usize].to_string() } else { format!("x{}", reg) };
+            comments.push(format_inline_values(&reg, values));
+        }
+        output.push_str(&format!("    {}  # {}\n", disasm, comments.join("  ")));
+    }
+
+    if config.histogram {
+        output.push('\n');
+        output.push_str(&format_histogram(&compute_histogram(instructions)));
+    }
+    if config.reg_usage {
+        output.push('\n');
+        output.push_str(&format_register_usage(&register_usage(instructions)));
+    }
+    output
+}
+
+// 统计每个助记符出现的次数，按次数从多到少排列，次数相同时按助记符排列。
+// 无法解码的字与反汇编输出一致计为.word
+pub fn compute_histogram(instructions: &[u32]) -> Vec<(String, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for &instr in instructions {
+        let mnemonic = match lookup_opcode(instr & 0x3F) {
+            Some(entry) if !is_data_word(instr) => entry.mnemonic,
+            _ => ".word",
+        };
+        *counts.entry(mnemonic).or_insert(0) += 1;
+    }
+    let mut histogram: Vec<(String, usize)> = counts.into_iter().map(|(mnemonic, count)| (mnemonic.to_string(), count)).collect();
+    histogram.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    histogram
+}
+
+// 每行: 助记符、次数、占全部字数的百分比
+pub fn format_histogram(histogram: &[(String, usize)]) -> String {
+    let total: usize = histogram.iter().map(|(_, count)| count).sum();
+    let mut output = format!("# 指令频率统计（共 {} 个字）\n", total);
+    for (mnemonic, count) in histogram {
+        output.push_str(&format!("# {:<8} {:>6}  {:>5.1}%\n", mnemonic, count, *count as f64 * 100.0 / total as f64));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 汇编source并逐条与期望的8位十六进制编码比较
+    fn assert_program_matches(source: &str, expected_hex: &[&str]) {
+        let code = assemble(source).unwrap();
+        assert_eq!(code.len(), expected_hex.len(), "指令条数不匹配:\n{}", source);
+        for (i, (&word, &expected)) in code.iter().zip(expected_hex).enumerate() {
+            assert_eq!(format!("{:08X}", word), expected,
+                "第{}条指令编码不匹配: {}", i, decode_instruction(word));
+        }
+    }
+
+    // 汇编器测试
+    #[test]
+    fn test_encode_add() {
+        // add x1, x1, x3 -> 0b00000000000_00011_00001_00001_000001
+        let expected = 0b00000000000_00011_00001_00001_000001;
+        let actual = encode_add(1, 1, 3);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_encode_mul() {
+        // mul x1, x1, x3 -> 0b00000000000_00011_00001_00001_000100
+        let expected = 0b00000000000_00011_00001_00001_000100;
+        let actual = encode_mul(1, 1, 3);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_encode_addi() {
+        // addi x1, x0, 0 -> 0b00000000000_00000_00000_00001_000010
+        let expected = 0b00000000000_00000_00000_00001_000010;
+        let actual = encode_addi(1, 0, 0);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_encode_bne() {
+        // bne x2, x1, -8 -> 0b11111111111_00010_00001_11000_000011
+        let expected = 0b11111111111_00010_00001_11000_000011;
+        let actual = encode_bne(2, 1, -8);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_encode_halt() {
+        let expected = 0;
+        let actual = encode_halt();
+        assert_eq!(actual, expected);
+    }
+
+    // 反汇编器测试
+    #[test]
+    fn test_decode_add() {
+        // add x1, x2, x3
+        let instr = 0b00000000000_00011_00010_00001_000001;
+        assert_eq!(decode_instruction(instr), "add x1, x2, x3");
+    }
+
+    #[test]
+    fn test_decode_mul() {
+        // mul x3, x4, x5
+        let instr = 0b00000000000_00101_00100_00011_000100;
+        assert_eq!(decode_instruction(instr), "mul x3, x4, x5");
+    }
+
+    #[test]
+    fn test_decode_addi() {
+        // addi x1, x0, 10
+        let instr = 0b00000000000_01010_00000_00001_000010;
+        assert_eq!(decode_instruction(instr), "addi x1, x0, 10");
+    }
+
+    #[test]
+    fn test_decode_lui() {
+        // lui x2, 42
+        let instr = 0b00000000001_01010_00000_00010_000101;
+        assert_eq!(decode_instruction(instr), "lui x2, 42");
+    }
+
+    #[test]
+    fn test_decode_lw() {
+        // lw x3, 4(x1)
+        let instr = 0b00000000000_00100_00001_00011_000110;
+        assert_eq!(decode_instruction(instr), "lw x3, 4(x1)");
+    }
+
+    #[test]
+    fn test_decode_bne() {
+        // bne x2, x1, -8
+        let instr = 0b11111111111_00010_00001_11000_000011;
+        assert_eq!(decode_instruction(instr), "bne x2, x1, -8");
+    }
+
+    #[test]
+    fn test_decode_sw() {
+        // sw x1, 8(x2)：要写入的x1在[20:16]，基址x2在[15:11]
+        let instr = 0b00000000000_00001_00010_01000_000111;
+        assert_eq!(decode_instruction(instr), "sw x1, 8(x2)");
+        assert_eq!(decode_instruction(encode_sw(5, 3, 4)), "sw x3, 4(x5)");
+    }
+
+    #[test]
+    fn test_decode_blt() {
+        // blt x5, x4, 16：rs1=x5在[15:11]，rs2=x4在[20:16]
+        let instr = 0b00000000000_00100_00101_10000_001000;
+        assert_eq!(decode_instruction(instr), "blt x5, x4, 16");
+        assert_eq!(decode_instruction(encode_blt(1, 2, 8)), "blt x1, x2, 8");
+    }
+
+    #[test]
+    fn test_decode_halt() {
+        // halt
+        let instr = 0;
+        assert_eq!(decode_instruction(instr), "halt");
+    }
+
+    #[test]
+    fn test_decode_uses_instruction() {
+        // 反汇编文本与Instruction的Display一致，只在其上套用伪指令写法
+        assert_eq!(decode_instruction(encode_add(1, 2, 3)), Instruction::Add { rd: 1, rs1: 2, rs2: 3 }.to_string());
+        assert_eq!(decode_instruction(encode_jal(0, -4)), "j -4");
+        assert_eq!(decode_instruction(encode_nor(1, 2, 0)), "nor x1, x2, x0");
+        assert_eq!(decode_instruction_with(encode_nor(1, 2, 0), true), "not x1, x2");
+        // 固定为0的字段不为0时Instruction::decode拒绝，反汇编也视为数据
+        let word = encode_lui(1, 5) | (1 << 11);
+        assert!(is_data_word(word));
+        assert_eq!(decode_instruction(word), format!("未知指令: 0x{:08X}", word));
+    }
+
+    #[test]
+    fn test_words_from_bytes() {
+        let bytes = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07];
+        assert_eq!(words_from_bytes(&bytes, false), [0x04030201]);
+        assert_eq!(words_from_bytes(&bytes, true), [0x01020304]);
+        assert!(words_from_bytes(&bytes[..3], false).is_empty());
+    }
+    
+    // 编码-解码循环测试
+    #[test]
+    fn test_encode_decode_cycle() {
+        // 测试编码后再解码是否得到原指令
+        let tests = [
+            "add x1, x2, x3",
+            "addi x3, x0, 42",
+            "mul x4, x5, x6",
+            "bne x7, x8, -16",
+            "lw x9, 8(x10)",
+            "lui x13, 1024",
+            "sw x11, 12(x12)",
+            "blt x14, x15, 20",
+            "halt"
+        ];
+        
+        for &test_str in &tests {
+            let code = assemble(test_str).unwrap();
+            assert_eq!(code.len(), 1, "应该只生成一条指令");
+            assert_eq!(decode_instruction(code[0]), test_str, "指令编码后解码不匹配: {}", test_str);
+        }
+    }
+
+    #[test]
+    fn test_encode_slli() {
+        // slli x1, x2, 3 -> 0b00000000000_00011_00010_00001_001001
+        let expected = 0b00000000000_00011_00010_00001_001001;
+        let actual = encode_slli(1, 2, 3);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_encode_sub() {
+        // sub x3, x4, x5 -> 0b00000000000_00101_00100_00011_001010
+        let expected = 0b00000000000_00101_00100_00011_001010;
+        let actual = encode_sub(3, 4, 5);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_decode_slli() {
+        // slli x1, x2, 3
+        let instr = 0b00000000000_00011_00010_00001_001001;
+        assert_eq!(decode_instruction(instr), "slli x1, x2, 3");
+    }
+
+    #[test]
+    fn test_decode_sub() {
+        // sub x3, x4, x5
+        let instr = 0b00000000000_00101_00100_00011_001010;
+        assert_eq!(decode_instruction(instr), "sub x3, x4, x5");
+    }
+
+    #[test]
+    fn test_encode_clz() {
+        // clz x1, x2 -> 0b00000000000_00000_00010_00001_101001
+        let expected = 0b00000000000_00000_00010_00001_101001;
+        let actual = encode_clz(1, 2);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_decode_clz() {
+        // clz x1, x2
+        let instr = 0b00000000000_00000_00010_00001_101001;
+        assert_eq!(decode_instruction(instr), "clz x1, x2");
+        assert_eq!(decode_instruction(assemble("clz x5, x6").unwrap()[0]), "clz x5, x6");
+    }
+
+    #[test]
+    fn test_encode_ctz() {
+        // ctz x3, x4 -> 0b00000000000_00000_00100_00011_101010
+        let expected = 0b00000000000_00000_00100_00011_101010;
+        let actual = encode_ctz(3, 4);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_encode_cpop() {
+        // cpop x3, x4 -> 0b00000000000_00000_00100_00011_101011
+        let expected = 0b00000000000_00000_00100_00011_101011;
+        let actual = encode_cpop(3, 4);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_decode_ctz_cpop() {
+        assert_eq!(decode_instruction(0b00000000000_00000_00100_00011_101010), "ctz x3, x4");
+        assert_eq!(decode_instruction(0b00000000000_00000_00100_00011_101011), "cpop x3, x4");
+        assert_eq!(decode_instruction(assemble("ctz x31, x0").unwrap()[0]), "ctz x31, x0");
+        assert_eq!(decode_instruction(assemble("cpop x0, x31").unwrap()[0]), "cpop x0, x31");
+    }
+
+    #[test]
+    fn test_popcount_expansion() {
+        let code = assemble("popcount x3, x4\nhalt").unwrap();
+        assert_eq!(code.len(), 23);
+        assert_eq!(code[..22], encode_popcount(3, 4, POPCOUNT_SCRATCH));
+        // 只有li和and用到32位掩码，最后用andi截取结果
+        assert_eq!(decode_instruction(code[0]), "lui x31, -21845");
+        assert_eq!(decode_instruction(code[21]), "andi x3, x3, 63");
+        // 标签地址按展开后的长度计算
+        assert_eq!(assemble("popcount a0, a1\nend: j end").unwrap()[22], encode_jal(0, 0));
+
+        let errors = assemble("popcount t6, x1").unwrap_err();
+        assert_eq!(errors[0].code, ErrorCode::InvalidRegister);
+        assert_eq!(errors[0].message, "popcount展开时要用t6作临时寄存器，操作数不能是它: t6");
+        assert!(assemble("popcount x1, x31").is_err());
+    }
+
+    #[test]
+    fn test_encode_rev8() {
+        // rev8 x1, x2 -> 0b00000000000_00000_00010_00001_101100
+        let expected = 0b00000000000_00000_00010_00001_101100;
+        let actual = encode_rev8(1, 2);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_decode_rev8() {
+        // rev8 x1, x2
+        let instr = 0b00000000000_00000_00010_00001_101100;
+        assert_eq!(decode_instruction(instr), "rev8 x1, x2");
+        assert_eq!(decode_instruction(assemble("rev8 x7, x7").unwrap()[0]), "rev8 x7, x7");
+    }
+
+    #[test]
+    fn test_encode_brev8() {
+        // brev8 x1, x2 -> 0b00000000000_00000_00010_00001_101101
+        let expected = 0b00000000000_00000_00010_00001_101101;
+        let actual = encode_brev8(1, 2);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_decode_brev8() {
+        // brev8 x1, x2
+        let instr = 0b00000000000_00000_00010_00001_101101;
+        assert_eq!(decode_instruction(instr), "brev8 x1, x2");
+        assert_eq!(decode_instruction(assemble("brev8 x9, x10").unwrap()[0]), "brev8 x9, x10");
+    }
+
+    #[test]
+    fn test_opcode_table_unique() {
+        for (i, a) in OPCODE_TABLE.iter().enumerate() {
+            for b in &OPCODE_TABLE[i + 1..] {
+                assert_ne!(a.opcode, b.opcode, "操作码重复: {} / {}", a.mnemonic, b.mnemonic);
+                assert_ne!(a.mnemonic, b.mnemonic, "助记符重复: {}", a.mnemonic);
+            }
+        }
+    }
+
+    #[test]
+    fn test_opcode_table_decodes() {
+        // 表中每个非halt操作码都应能被解码为对应助记符；
+        // 单操作数A型、lui和jal有固定为0的字段，依次尝试清掉一个寄存器字段
+        for entry in OPCODE_TABLE.iter().filter(|e| e.itype != InstructionType::Halt) {
+            let candidates = [(1 << 6) | (1 << 11) | (1 << 16), (1 << 6) | (1 << 16), (1 << 6) | (1 << 11)];
+            let word = candidates.iter().map(|fields| entry.opcode | fields).find(|&word| !is_data_word(word));
+            let decoded = decode_instruction(word.unwrap_or_else(|| panic!("{} 无法解码", entry.mnemonic)));
+            assert!(decoded.starts_with(entry.mnemonic), "{} 解码为 {}", entry.mnemonic, decoded);
+        }
+    }
+
+    #[test]
+    fn test_format_opcode_tables() {
+        let tables = format_opcode_tables();
+        assert!(tables.contains("A型指令"));
+        assert!(tables.contains("  000001   0x01      add     x[rd] = x[rs1] + x[rs2]"));
+        for entry in OPCODE_TABLE {
+            assert!(tables.contains(entry.mnemonic));
+        }
+    }
+
+    #[test]
+    fn test_check_imm_bits() {
+        assert!(check_imm_bits(200, 8).is_err());
+        assert_eq!(check_imm_bits(100, 8), Ok(100));
+        assert_eq!(check_imm_bits(-128, 8), Ok(-128));
+        assert!(check_imm_bits(-129, 8).is_err());
+        assert_eq!(check_imm_bits(i16::MIN, 16), Ok(i16::MIN));
+        // 移位量最多5位
+        assert_eq!(check_shamt_bits(31, 16), Ok(31));
+        assert!(check_shamt_bits(32, 16).is_err());
+        assert!(check_shamt_bits(-1, 16).is_err());
+        assert!(check_shamt_bits(8, 3).is_err());
+    }
+
+    #[test]
+    fn test_max_imm_bits_config() {
+        let config = AssemblerConfig { max_imm_bits: 8, ..AssemblerConfig::default() };
+        let code = assemble_with_config("addi x1, x0, 100", &config).unwrap();
+        assert_eq!(decode_instruction(code[0]), "addi x1, x0, 100");
+    }
+
+    #[test]
+    #[should_panic(expected = "超出8位有符号范围")]
+    fn test_max_imm_bits_overflow() {
+        let config = AssemblerConfig { max_imm_bits: 8, ..AssemblerConfig::default() };
+        assemble_with_config("addi x1, x0, 200", &config).unwrap();
+    }
+
+    #[test]
+    fn test_zero_reg() {
+        assert_eq!(assemble("zero_reg x5").unwrap(), vec![encode_addi(5, 0, 0)]);
+        assert_eq!(assemble("zero x5").unwrap(), vec![encode_addi(5, 0, 0)]);
+        assert_eq!(decode_instruction(encode_addi(5, 0, 0)), "zero x5");
+        // rd为x0时是nop，rs1/imm非0时保持addi
+        assert_eq!(decode_instruction(encode_addi(0, 0, 0)), "nop");
+        assert_eq!(decode_instruction(encode_addi(5, 1, 0)), "addi x5, x1, 0");
+        assert_eq!(decode_instruction(encode_addi(5, 0, 1)), "addi x5, x0, 1");
+    }
+
+    #[test]
+    fn test_check_abi() {
+        // s0(x8)先保存后写入不报警，s1(x9)直接写入报警
+        // a0(x10)先读后写不报警，a1(x11)直接覆盖报警
+        let source = "sw x8, 0(x2)\naddi x8, x0, 1\naddi x9, x0, 2\nadd x5, x10, x0\naddi x10, x0, 3\naddi x11, x0, 4\naddi x9, x0, 5";
+        let warnings = check_abi(source, &AssemblerConfig::default()).unwrap();
+        assert_eq!(warnings, vec![
+            Warning::CalleeSavedNotSaved { reg: 9, line: 3 },
+            Warning::ArgumentClobbered { reg: 11, line: 6 },
+        ]);
+        assert_eq!(warnings[0].to_string(), "第3行: 写入被调用者保存寄存器 x9(s1) 前未将其保存到栈上");
+
+        // 打开check_abi时与其他警告收集在一起；数据字不参与检查
+        let config = AssemblerConfig { check_abi: true, ..AssemblerConfig::default() };
+        let mut assembler = Assembler::new(&config);
+        assembler.run(&format!("{}\nhalt\n.word 0x00000482", source)).unwrap();
+        assert_eq!(assembler.warnings, warnings);
+    }
+
+    #[test]
+    fn test_register_operands() {
+        assert_eq!(register_operands(encode_add(1, 2, 3)), (Some(1), vec![2, 3]));
+        assert_eq!(register_operands(encode_lui(4, 1)), (Some(4), vec![]));
+        assert_eq!(register_operands(encode_sw(2, 8, 4)), (None, vec![2, 8]));
+        assert_eq!(register_operands(encode_bne(5, 6, -4)), (None, vec![5, 6]));
+        assert_eq!(register_operands(encode_halt()), (None, vec![]));
+    }
+
+    #[test]
+    fn test_compress_roundtrip() {
+        // 程序中间包含100个0字
+        let mut words = assemble("addi x1, x0, 1\naddi x2, x0, 2").unwrap();
+        words.extend(std::iter::repeat_n(0, 100));
+        words.extend(assemble("add x3, x1, x2\nadd x3, x1, x2\nhalt").unwrap());
+
+        let compressed = compress_words(&words);
+        assert!(compressed.len() < words.len());
+        assert_eq!(decompress_words(&compressed).unwrap(), words);
+
+        assert_eq!(compress_words(&[]), Vec::<u32>::new());
+        assert_eq!(compress_words(&[7, 7, 7, 7]), vec![4, 7]);
+        assert_eq!(compress_words(&[1, 2]), vec![0, 2, 1, 2]);
+    }
+
+    #[test]
+    fn test_decompress_truncated() {
+        assert!(decompress_words(&[5]).is_err());
+        assert!(decompress_words(&[0, 3, 1]).is_err());
+    }
+
+    #[test]
+    fn test_benchmark_decode() {
+        let code = assemble("add x1, x2, x3\naddi x1, x0, 1\nslli x1, x1, 2\nhalt").unwrap();
+        let results = benchmark_decode(&code, 5);
+        // 没有C型指令，halt不参与统计
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].itype, InstructionType::A);
+        assert_eq!(results[0].count, 1);
+        assert_eq!(results[1].itype, InstructionType::B);
+        assert_eq!(results[1].count, 2);
+        assert!(results.iter().all(|r| r.mean_mips > 0.0 && r.stddev_mips >= 0.0));
+    }
+
+    #[test]
+    fn test_generate_wat() {
+        let wat = generate_wat(&assemble("addi x1, x0, 10\nhalt").unwrap());
+        assert!(wat.starts_with("(module\n"));
+        assert!(wat.contains("(memory (export \"memory\") 1)"));
+        assert!(wat.contains("\"\\42\\00\\0a\\00\""));
+        assert!(wat.contains("\"\\00\\00\\00\\00\""));
+        assert!(wat.contains("(func $main (export \"main\") (result i32)"));
+        assert!(wat.contains("i32.const 2)"));
+
+        // 括号配对（字符串内容只含十六进制转义，不含括号）
+        let mut depth = 0i32;
+        for c in wat.chars() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            assert!(depth >= 0);
+        }
+        assert_eq!(depth, 0);
+    }
+
+    #[test]
+    fn test_pipeline_hazards() {
+        let code = assemble("lw x1, 0(x2)\naddi x3, x0, 1\nadd x4, x1, x3\nmul x5, x4, x4\nsub x6, x0, x0\nadd x7, x5, x6\nhalt").unwrap();
+        let hazards = pipeline_hazards(&code);
+        assert!(hazards[0].is_empty());
+        assert!(hazards[1].is_empty());
+        // x1来自两条之前的lw，x3来自上一条
+        assert_eq!(hazards[2], vec!["RAW: x3 由前1条指令写入", "load-use: x1 由前2条指令写入"]);
+        assert_eq!(hazards[3], vec!["RAW: x4 由前1条指令写入"]);
+        assert!(hazards[4].is_empty());
+        assert_eq!(hazards[5], vec!["RAW: x6 由前1条指令写入", "mul结果未就绪: x5 由前2条指令写入"]);
+        assert!(hazards[6].is_empty());
+    }
+
+    #[test]
+    fn test_compute_histogram() {
+        let code = assemble("addi x1, x0, 1\naddi x2, x0, 2\nadd x3, x1, x2\naddi x1, x1, 1\naddi x2, x2, 1\nadd x3, x3, x1\naddi x3, x3, 1\nhalt\n.word 0xFFFFFFFF").unwrap();
+        let histogram = compute_histogram(&code);
+        assert_eq!(histogram, [("addi".to_string(), 5), ("add".to_string(), 2), (".word".to_string(), 1), ("halt".to_string(), 1)]);
+
+        let config = DisasmConfig { histogram_only: true, ..DisasmConfig::default() };
+        assert_eq!(disassemble_listing(&code, &config), "\
+# 指令频率统计（共 9 个字）
+# addi          5   55.6%
+# add           2   22.2%
+# .word         1   11.1%
+# halt          1   11.1%
+");
+        let config = DisasmConfig { histogram: true, ..DisasmConfig::default() };
+        let listing = disassemble_listing(&code, &config);
+        assert!(listing.contains("    addi x1, x0, 1  # 0000: 00010042\n"));
+        assert!(listing.ends_with("\n\n# 指令频率统计（共 9 个字）\n# addi          5   55.6%\n# add           2   22.2%\n# .word         1   11.1%\n# halt          1   11.1%\n"));
+    }
+
+    #[test]
+    fn test_register_usage() {
+        // x5只写不读；sw读取x1和x3；bne读取x3和x2；数据字不计
+        let code = assemble("addi x1, x0, 1\naddi x2, x0, 4\nadd x3, x1, x1\nsw x1, 0(x3)\naddi x5, x0, 7\nbne x3, x2, 0\nhalt\n.word 0xFFFFFFFF").unwrap();
+        let usage = register_usage(&code);
+        assert_eq!(usage[0], (3, 0));
+        assert_eq!(usage[1], (3, 1));
+        assert_eq!(usage[2], (1, 1));
+        assert_eq!(usage[3], (2, 1));
+        assert_eq!(usage[5], (0, 1));
+        assert!(usage[6..].iter().all(|&usage| usage == (0, 0)));
+        assert_eq!(dead_writes(&usage), [5]);
+
+        assert_eq!(format_register_usage(&usage), "\
+# 寄存器使用统计
+# 寄存器  读取  写入  读/写
+# x0         3     0      -
+# x1         3     1   3.00
+# x2         1     1   1.00
+# x3         2     1   2.00
+# x5         0     1   0.00
+# 警告: x5 被写入后从未被读取
+");
+    }
+
+    #[test]
+    fn test_disassemble_branch_targets() {
+        let code = assemble("addi x1, x0, 3\nloop: addi x1, x1, -1\nbnez x1, loop\nj 8\nhalt\nbeq x1, x2, 100").unwrap();
+        // 跳转目标处生成L_XXXX标签，超出程序范围的目标保持数字偏移
+        let listing = disassemble_listing(&code, &DisasmConfig::default());
+        assert_eq!(listing, "\
+# 反汇编结果
+# 格式: [汇编指令]  # [地址]: [十六进制表示]
+
+    addi x1, x0, 3  # 0000: 00030042
+L_0004:
+    addi x1, x1, -1  # 0004: FFFF0842
+    bnez x1, L_0004  # 0008: FFE10703  -> 0x0004
+    j L_0014  # 000C: 00008019  -> 0x0014
+    halt  # 0010: 00000000
+L_0014:
+    beq x1, x2, 100  # 0014: 00611113  -> 0x0078
+");
+        // 与冒险标注在同一个注释中
+        let config = DisasmConfig { annotate_hazards: true, ..DisasmConfig::default() };
+        assert!(disassemble_listing(&code, &config).contains("    bnez x1, L_0004  # 0008: FFE10703  -> 0x0004  [HAZARD] RAW: x1 由前1条指令写入\n"));
+    }
+
+    #[test]
+    fn test_disassemble_listing_hazards() {
+        let code = assemble("addi x1, x0, 1\nadd x2, x1, x1").unwrap();
+        let config = DisasmConfig { annotate_hazards: true, ..DisasmConfig::default() };
+        let listing = disassemble_listing(&code, &config);
+        assert!(listing.contains("    add x2, x1, x1  # 0004: 00010881  [HAZARD] RAW: x1 由前1条指令写入\n"));
+        assert!(!disassemble_listing(&code, &DisasmConfig::default()).contains("[HAZARD]"));
+    }
+
+    #[test]
+    fn test_assert_program_matches() {
+        assert_program_matches("addi x1, x0, 10\nadd x1, x1, x3\nhalt", &["000A0042", "00030841", "00000000"]);
+    }
+
+    #[test]
+    fn test_compat_riscv() {
+        assert_eq!(check_riscv_compat("add"), Ok(()));
+        assert_eq!(check_riscv_compat("slli"), Ok(()));
+        // 非RV32I助记符不受影响
+        assert_eq!(check_riscv_compat("halt"), Ok(()));
+        assert!(check_riscv_compat("lui").unwrap_err().contains("16位"));
+        // 其余RV32I指令在本指令集中没有等价编码
+        for mnemonic in RV32I_MNEMONICS.iter().filter(|m| !RV32I_COMPATIBLE.contains(m)) {
+            assert!(check_riscv_compat(mnemonic).is_err(), "{} 应报错", mnemonic);
+        }
+        assert!(check_riscv_compat("bltu").is_err());
+
+        let config = AssemblerConfig { compat_riscv: true, ..AssemblerConfig::default() };
+        let code = assemble_with_config("addi x1, x0, 1\nsw x1, 4(x2)\nbne x1, x0, -8", &config).unwrap();
+        assert_eq!(code, assemble("addi x1, x0, 1\nsw x1, 4(x2)\nbne x1, x0, -8").unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "RV32I指令 sll 在本指令集中没有等价编码")]
+    fn test_compat_riscv_unsupported() {
+        let config = AssemblerConfig { compat_riscv: true, ..AssemblerConfig::default() };
+        assemble_with_config("sll x1, x2, x3", &config).unwrap();
+    }
+
+    #[test]
+    fn test_sha256() {
+        let hex = |digest: [u8; 32]| digest.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!(hex(sha256(b"")), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(hex(sha256(b"abc")), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        // 跨越两个分组
+        assert_eq!(hex(sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+        assert_eq!(binary_hash(&[]), "e3b0c442");
+    }
+
+    #[test]
+    fn test_format_binary_grouped() {
+        assert_eq!(format_binary_grouped(0), "0b00000000000_00000_00000_00000_000000");
+        assert_eq!(format_binary_grouped(encode_bne(2, 1, -8)), "0b11111111111_00010_00001_11000_000011");
+
+        // 解析自身输出后再格式化，结果不变
+        for instr in [0, 1, 0xFFFF_FFFF, encode_addi(3, 0, 42), encode_sw(1, 2, -4)] {
+            let formatted = format_binary_grouped(instr);
+            let parsed = u32::from_str_radix(&formatted[2..].replace('_', ""), 2).unwrap();
+            assert_eq!(parsed, instr);
+            assert_eq!(format_binary_grouped(parsed), formatted);
+        }
+    }
+
+    #[test]
+    fn test_generate_listing() {
+        let source = "# 倒数求和\nstart:\n    li x1, 5\nloop: add x2, x2, x1\n    addi x1, x1, -1\n\n    bne x1, x0, loop\n    halt\n.equ N, 3\n";
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run(source).unwrap();
+        let listing = generate_listing(source, &assembler.img, &assembler.labels);
+        let lines: Vec<&str> = listing.lines().collect();
+        let pad = " ".repeat(56);
+        assert_eq!(lines, vec![
+            format!("{}# 倒数求和", pad),
+            format!("0000{}start:", &pad[4..]),
+            "0000  00050042  0b00000000000_00101_00000_00001_000010      li x1, 5".to_string(),
+            "0004  00011081  0b00000000000_00001_00010_00010_000001  loop: add x2, x2, x1".to_string(),
+            "0008  FFFF0842  0b11111111111_11111_00001_00001_000010      addi x1, x1, -1".to_string(),
+            String::new(),
+            "000C  FFE10603  0b11111111111_00001_00000_11000_000011      bne x1, x0, loop".to_string(),
+            "0010  00000000  0b00000000000_00000_00000_00000_000000      halt".to_string(),
+            format!("{}.equ N, 3", pad),
+        ]);
+
+        // 一行生成多个字时，后面的字单独成行
+        let source = "li x1, 0x12345678\n.word 1, 2";
+        let img = assemble(source).unwrap();
+        let listing = generate_listing(source, &img, &HashMap::new());
+        assert_eq!(listing.lines().count(), 4);
+        assert!(listing.lines().nth(1).unwrap().starts_with("0004  56780842"));
+        assert!(listing.lines().nth(3).unwrap().starts_with("000C  00000002"));
+        assert!(listing.lines().nth(3).unwrap().ends_with("_000010"));
+    }
+
+    #[test]
+    fn test_source_map() {
+        // 注释、空行、单独的标签和.equ不占字；li展开成两个字，都映射到第5行
+        let source = "# 注释\n\naddi x1, x0, 1\nloop:\n  li x2, 0x12345678  # 两个字\n.equ N, 2\n\n  bne x1, x2, loop\nhalt";
+        let map = generate_source_map(source);
+        let expected: HashMap<usize, usize> = [(0, 3), (1, 5), (2, 5), (3, 8), (4, 9)].into_iter().collect();
+        assert_eq!(map, expected);
+
+        let text = format_source_map(&map);
+        assert_eq!(text, "{ \"0\": 3, \"1\": 5, \"2\": 5, \"3\": 8, \"4\": 9 }\n");
+        assert_eq!(parse_source_map(&text), Ok(map));
+        assert_eq!(format_source_map(&HashMap::new()), "{}\n");
+        assert_eq!(parse_source_map("{}"), Ok(HashMap::new()));
+        assert!(parse_source_map("{ \"a\": 1 }").is_err());
+        // 汇编失败时映射为空
+        assert!(generate_source_map("foo x1").is_empty());
+    }
+
+    #[test]
+    fn test_symbol_table() {
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run("start: addi x1, x0, 3\nloop: addi x1, x1, -1\nbne x1, x0, loop\nj done\ndone: halt").unwrap();
+        let text = format_symbol_table(&assembler.labels);
+        assert_eq!(text, "start 0x00000000\nloop 0x00000004\ndone 0x00000010\n");
+        assert_eq!(parse_symbol_table(&text), Ok(assembler.labels.clone()));
+        assert_eq!(parse_symbol_table("# 注释\n\n  a 16\n").unwrap()["a"], 16);
+        assert_eq!(parse_symbol_table("loop\n"), Err("第1行: 无效的符号: loop".to_string()));
+        assert!(parse_symbol_table("1x 0x10\n").is_err());
+
+        // 跳转目标替换为标签名，没有符号的目标使用生成的L_XXXX标签
+        let mut img = assembler.img.clone();
+        img.push(encode_beq(1, 2, -8));
+        let config = DisasmConfig { symbols: assembler.labels.clone(), abi_names: true, ..DisasmConfig::default() };
+        let listing = disassemble_listing(&img, &config);
+        assert!(listing.contains("loop:\n    addi ra, ra, -1  # 0004:"));
+        assert!(listing.contains("    bnez ra, loop  # 0008: FFE10703  -> 0x0004\n"));
+        assert!(listing.contains("L_000C:\n    j done  # 000C: 00004019  -> 0x0010\n"));
+        assert!(listing.contains("    beq ra, sp, L_000C  # 0014: FFE11613  -> 0x000C\n"));
+    }
+
+    #[test]
+    fn test_explain_immediate() {
+        assert!(explain_immediate("addi").unwrap().contains("-32768 ~ 32767"));
+        assert!(explain_immediate("bne").unwrap().contains("[31:21]"));
+        assert!(explain_immediate("slli").unwrap().contains("0 ~ 31"));
+        assert!(explain_immediate("add").unwrap().contains("无立即数"));
+        assert!(explain_immediate("jal").unwrap().contains("20位有符号"));
+        assert!(explain_immediate("foo").is_none());
+    }
+
+    #[test]
+    fn test_encode_or_and_xor() {
+        // or x1, x2, x3 -> 0b00000000000_00011_00010_00001_001011
+        assert_eq!(encode_or(1, 2, 3), 0b00000000000_00011_00010_00001_001011);
+        assert_eq!(encode_and(1, 2, 3), 0b00000000000_00011_00010_00001_001100);
+        assert_eq!(encode_xor(1, 2, 3), 0b00000000000_00011_00010_00001_001101);
+    }
+
+    #[test]
+    fn test_decode_or_and_xor() {
+        for test_str in ["or x1, x2, x3", "and x31, x0, x31", "xor x0, x31, x0", "or x31, x31, x31"] {
+            let code = assemble(test_str).unwrap();
+            assert_eq!(decode_instruction(code[0]), test_str);
+        }
+    }
+
+    #[test]
+    fn test_encode_nor() {
+        // nor x1, x2, x3 -> 0b00000000000_00011_00010_00001_100011
+        assert_eq!(encode_nor(1, 2, 3), 0b00000000000_00011_00010_00001_100011);
+        assert_eq!(assemble("not x1, x2").unwrap(), vec![encode_nor(1, 2, 0)]);
+        assert_eq!(decode_instruction(encode_nor(1, 2, 3)), "nor x1, x2, x3");
+        assert_eq!(decode_instruction(encode_nor(1, 2, 0)), "nor x1, x2, x0");
+        // 只有rs2为x0时才是not
+        assert_eq!(decode_instruction_with(encode_nor(1, 2, 0), true), "not x1, x2");
+        assert_eq!(decode_instruction_with(encode_nor(1, 0, 2), true), "nor x1, x0, x2");
+    }
+
+    #[test]
+    fn test_encode_srl_sra() {
+        // srl x1, x2, x3 -> 0b00000000000_00011_00010_00001_100001
+        assert_eq!(encode_srl(1, 2, 3), 0b00000000000_00011_00010_00001_100001);
+        assert_eq!(encode_sra(1, 2, 3), 0b00000000000_00011_00010_00001_100010);
+        for test_str in ["srl x1, x2, x3", "sra x31, x0, x31"] {
+            assert_eq!(decode_instruction(assemble(test_str).unwrap()[0]), test_str);
+        }
+        let config = AssemblerConfig { compat_riscv: true, ..AssemblerConfig::default() };
+        assert_eq!(assemble_with_config("sra a0, a0, t0", &config).unwrap(), vec![encode_sra(10, 10, 5)]);
+    }
+
+    #[test]
+    fn test_encode_srli_srai() {
+        // srli x1, x2, 0 -> 0b00000000000_00000_00010_00001_001110
+        assert_eq!(encode_srli(1, 2, 0), 0b00000000000_00000_00010_00001_001110);
+        // srai x1, x2, 31 -> 0b00000000000_11111_00010_00001_001111
+        assert_eq!(encode_srai(1, 2, 31), 0b00000000000_11111_00010_00001_001111);
+        assert_eq!(assemble("srli x1, x2, 31").unwrap(), vec![encode_srli(1, 2, 31)]);
+        assert_eq!(assemble("srai x1, x2, 1").unwrap(), vec![encode_srai(1, 2, 1)]);
+    }
+
+    #[test]
+    fn test_decode_srli_srai() {
+        for test_str in ["srli x1, x2, 0", "srli x3, x4, 31", "srai x5, x6, 1", "srai x31, x31, 31"] {
+            assert_eq!(decode_instruction(assemble(test_str).unwrap()[0]), test_str);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "移位量 32 超出5位无符号范围")]
+    fn test_srai_shamt_out_of_range() {
+        assemble("srai x1, x2, 32").unwrap();
+    }
+
+    #[test]
+    fn test_encode_andi_ori_xori() {
+        // andi x1, x2, -1 -> 0b11111111111_11111_00010_00001_010000
+        assert_eq!(encode_andi(1, 2, -1), 0b11111111111_11111_00010_00001_010000);
+        // ori x1, x2, 255 -> 0b00000000111_11111_00010_00001_010001
+        assert_eq!(encode_ori(1, 2, 255), 0b00000000111_11111_00010_00001_010001);
+        assert_eq!(encode_xori(1, 2, 0), 0b00000000000_00000_00010_00001_010010);
+        assert_eq!(assemble("andi x1, x2, -1").unwrap(), vec![encode_andi(1, 2, -1)]);
+        assert_eq!(assemble("xori x3, x3, 0xFF").unwrap(), vec![encode_xori(3, 3, 255)]);
+    }
+
+    #[test]
+    fn test_decode_andi_ori_xori() {
+        for mnemonic in ["andi", "ori", "xori"] {
+            for imm in [0, -1, 32767, -32768] {
+                let test_str = format!("{} x1, x2, {}", mnemonic, imm);
+                assert_eq!(decode_instruction(assemble(&test_str).unwrap()[0]), test_str);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_narrow_loads() {
+        // lb x1, -4(x2) -> 0b11111111111_11100_00010_00001_011011
+        assert_eq!(encode_lb(1, 2, -4), 0b11111111111_11100_00010_00001_011011);
+        // lh x3, 2(x4) -> 0b00000000000_00010_00100_00011_011100
+        assert_eq!(encode_lh(3, 4, 2), 0b00000000000_00010_00100_00011_011100);
+        assert_eq!(encode_lbu(5, 6, 0), 0b00000000000_00000_00110_00101_011101);
+        assert_eq!(encode_lhu(7, 8, 6), 0b00000000000_00110_01000_00111_011110);
+        assert_eq!(assemble("lbu t0, 3(sp)").unwrap(), vec![encode_lbu(5, 2, 3)]);
+        for test_str in ["lb x1, -4(x2)", "lh x3, 2(x4)", "lbu x5, 0(x6)", "lhu x7, 32767(x8)"] {
+            assert_eq!(decode_instruction(assemble(test_str).unwrap()[0]), test_str);
+        }
+        assert_eq!(pipeline_hazards(&assemble("lbu x1, 0(x2)\naddi x3, x0, 0\nadd x4, x1, x1").unwrap())[2],
+            vec!["load-use: x1 由前2条指令写入"]);
+    }
+
+    #[test]
+    fn test_encode_sb_sh() {
+        // sb x1, -4(x2) -> 0b11111111111_00001_00010_11100_011111，rs1在[15:11]
+        assert_eq!(encode_sb(2, 1, -4), 0b11111111111_00001_00010_11100_011111);
+        // 与sw只差操作码
+        assert_eq!(encode_sh(2, 1, -4) & !0x3F, encode_sw(2, 1, -4) & !0x3F);
+        assert_eq!(assemble("sb t0, 3(sp)").unwrap(), vec![encode_sb(2, 5, 3)]);
+        for test_str in ["sb x1, -4(x2)", "sb x0, 0(x31)", "sh x5, 32767(x6)", "sh x7, -32768(x8)"] {
+            assert_eq!(decode_instruction(assemble(test_str).unwrap()[0]), test_str);
+        }
+        assert_eq!(register_operands(encode_sh(2, 1, 0)), (None, vec![2, 1]));
+    }
+
+    #[test]
+    fn test_encode_beq() {
+        // beq x2, x1, -8 -> 0b11111111111_00010_00001_11000_010011
+        let expected = 0b11111111111_00010_00001_11000_010011;
+        let actual = encode_beq(2, 1, -8);
+        assert_eq!(actual, expected);
+        // 与bne只差操作码，寄存器字段顺序相同
+        assert_eq!(encode_beq(2, 1, -8) & !0x3F, encode_bne(2, 1, -8) & !0x3F);
+    }
+
+    #[test]
+    fn test_decode_beq() {
+        // beq x2, x1, -8
+        let instr = 0b11111111111_00010_00001_11000_010011;
+        assert_eq!(decode_instruction(instr), "beq x2, x1, -8");
+        // 偏移0（原地循环）、正负偏移和16位拆分字段的边界
+        for test_str in ["beq x1, x2, 0", "beq x3, x4, 12", "beq x5, x6, -4", "beq x7, x8, 32767", "beq x9, x10, -32768"] {
+            assert_eq!(decode_instruction(assemble(test_str).unwrap()[0]), test_str);
+        }
+    }
+
+    #[test]
+    fn test_encode_bge_bgeu() {
+        // bge x2, x1, -8 -> 0b11111111111_00010_00001_11000_010100
+        assert_eq!(encode_bge(2, 1, -8), 0b11111111111_00010_00001_11000_010100);
+        // bgeu x2, x1, 0 -> 0b00000000000_00010_00001_00000_010101
+        assert_eq!(encode_bgeu(2, 1, 0), 0b00000000000_00010_00001_00000_010101);
+        assert_eq!(register_operands(encode_bgeu(2, 1, 0)), (None, vec![2, 1]));
+    }
+
+    #[test]
+    fn test_decode_bge_bgeu() {
+        for test_str in ["bge x1, x2, -16", "bge x3, x3, 0", "bge x4, x5, 8",
+                         "bgeu x1, x2, -16", "bgeu x31, x31, 0", "bgeu x0, x6, 32767"] {
+            assert_eq!(decode_instruction(assemble(test_str).unwrap()[0]), test_str);
+        }
+    }
+
+    #[test]
+    fn test_encode_slt_sltu() {
+        // slt x1, x2, x3 -> 0b00000000000_00011_00010_00001_010110
+        assert_eq!(encode_slt(1, 2, 3), 0b00000000000_00011_00010_00001_010110);
+        // sltu x1, x2, x3 -> 0b00000000000_00011_00010_00001_010111
+        assert_eq!(encode_sltu(1, 2, 3), 0b00000000000_00011_00010_00001_010111);
+    }
+
+    #[test]
+    fn test_decode_slt_sltu() {
+        for test_str in ["slt x1, x2, x3", "slt x31, x0, x31", "sltu x4, x5, x6", "sltu x0, x31, x0"] {
+            assert_eq!(decode_instruction(assemble(test_str).unwrap()[0]), test_str);
+        }
+    }
+
+    #[test]
+    fn test_encode_slti() {
+        // slti x1, x2, 0 -> 0b00000000000_00000_00010_00001_011000
+        assert_eq!(encode_slti(1, 2, 0), 0b00000000000_00000_00010_00001_011000);
+        // slti x1, x2, -1 -> 0b11111111111_11111_00010_00001_011000
+        assert_eq!(encode_slti(1, 2, -1), 0b11111111111_11111_00010_00001_011000);
+        // slti x1, x0, 1 -> 0b00000000000_00001_00000_00001_011000
+        assert_eq!(encode_slti(1, 0, 1), 0b00000000000_00001_00000_00001_011000);
+        for test_str in ["slti x1, x2, 0", "slti x1, x2, -1", "slti x1, x0, 1"] {
+            assert_eq!(decode_instruction(assemble(test_str).unwrap()[0]), test_str);
+        }
+    }
+
+    #[test]
+    fn test_slti_imm_truncated() {
+        // 超出16位的十六进制立即数只警告并截断，不会panic
+        assert_eq!(assemble("slti x1, x2, 0x10001").unwrap(), vec![encode_slti(1, 2, 1)]);
+        // 十进制数与addi一样按范围报错
+        let error = assemble("slti x1, x2, 65537").unwrap_err().remove(0);
+        assert_eq!(error.code, ErrorCode::ImmediateOutOfRange);
+
+        // slti是有符号比较，0x9C40会变成-25536，同样要警告
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run("slti x1, x2, 0x9C40\nslti x3, x4, 32767\nslti x5, x6, -32768").unwrap();
+        assert_eq!(assembler.img[0], encode_slti(1, 2, -25536));
+        assert_eq!(assembler.warnings, vec![
+            Warning::ImmediateTruncated { original: 40000, truncated: -25536, bits: 16, line: 1 },
+        ]);
+    }
+
+    #[test]
+    fn test_encode_jal() {
+        // jal x0, 0 -> 原地死循环，只有操作码非0
+        assert_eq!(encode_jal(0, 0), 0b011001);
+        // jal x1, 1024 -> offset[31:12] = 1024, rd[10:6] = 1
+        assert_eq!(encode_jal(1, 1024), (1024 << 12) | (1 << 6) | 0b011001);
+        // 负偏移截断为20位
+        assert_eq!(encode_jal(1, -4) >> 12, 0xFFFFC);
+        assert_eq!(assemble("jal x1, 1024").unwrap(), vec![encode_jal(1, 1024)]);
+    }
+
+    #[test]
+    fn test_decode_jal() {
+        for test_str in ["jal x1, 0", "jal x1, 1024", "jal x31, -8", "jal x1, 524287", "jal x1, -524288"] {
+            assert_eq!(decode_instruction(assemble(test_str).unwrap()[0]), test_str);
+        }
+        assert_eq!(register_operands(encode_jal(1, 8)), (Some(1), vec![]));
+    }
+
+    #[test]
+    #[should_panic(expected = "跳转偏移 524288 超出20位有符号范围")]
+    fn test_jal_offset_out_of_range() {
+        assemble("jal x1, 524288").unwrap();
+    }
+
+    #[test]
+    fn test_encode_jalr() {
+        // jalr x0, x1, 0 -> 0b00000000000_00000_00001_00000_011010
+        assert_eq!(encode_jalr(0, 1, 0), 0b00000000000_00000_00001_00000_011010);
+        // jalr x1, x5, -4 -> 0b11111111111_11100_00101_00001_011010
+        assert_eq!(encode_jalr(1, 5, -4), 0b11111111111_11100_00101_00001_011010);
+    }
+
+    #[test]
+    fn test_decode_jalr() {
+        for test_str in ["jalr x0, x1, 4", "jalr x1, x5, -4", "jalr x31, x31, 32767"] {
+            assert_eq!(decode_instruction(assemble(test_str).unwrap()[0]), test_str);
+        }
+        assert_eq!(register_operands(encode_jalr(0, 1, 0)), (Some(0), vec![1]));
+    }
+
+    #[test]
+    fn test_backward_label() {
+        // 与asm/sum.asm相同，只是分支目标换成标签
+        let source = "addi x1, x0, 0\naddi x2, x0, 10\naddi x3, x0, 0\nloop:\naddi x3, x3, 1\nadd x1, x1, x3\nbne x3, x2, loop\nhalt";
+        assert_eq!(assemble(source).unwrap(), assemble(&source.replace("loop:\n", "").replace("loop", "-8")).unwrap());
+
+        // 标签和指令在同一行，jal同样可以使用标签
+        let code = assemble("start: addi x1, x0, 1  # 注释\nloop: jal x0, start").unwrap();
+        assert_eq!(code, vec![encode_addi(1, 0, 1), encode_jal(0, -4)]);
+    }
+
+    #[test]
+    fn test_duplicate_label() {
+        let errors = assemble("loop:\naddi x1, x0, 1\nloop: halt").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "<input>:3:1: error[E007]: 标签 loop 重复定义（第1行已定义）");
+    }
+
+    #[test]
+    #[should_panic(expected = "未定义的标签: done")]
+    fn test_undefined_label() {
+        assemble("bne x1, x0, done").unwrap();
+    }
+
+    #[test]
+    fn test_forward_label() {
+        let source = "beq x1, x0, done\naddi x2, x0, 1\naddi x3, x0, 2\nadd x4, x2, x3\nmul x5, x4, x4\ndone: halt";
+        let code = assemble(source).unwrap();
+        assert_eq!(code[0], encode_beq(1, 0, 20));
+        assert_eq!(decode_instruction(code[0]), "beqz x1, 20");
+
+        // 前后引用混合
+        let code = assemble("top: blt x1, x2, end\nbne x1, x2, top\nend: jal x0, top").unwrap();
+        assert_eq!(code, vec![encode_blt(1, 2, 8), encode_bne(1, 2, -4), encode_jal(0, -8)]);
+    }
+
+    #[test]
+    fn test_label_out_of_range() {
+        // 8位立即数最多向前跳127字节
+        let config = AssemblerConfig { max_imm_bits: 8, ..AssemblerConfig::default() };
+        let source = format!("bne x1, x0, far\n{}far: halt", "addi x1, x1, 1\n".repeat(63));
+        let error = assemble_with_config(&source, &config).unwrap_err().remove(0);
+        assert_eq!((error.code, error.line), (ErrorCode::BranchOutOfRange, 1));
+        assert_eq!(error.message, "bne 的标签 far 超出跳转范围: 当前地址 0x0000，目标地址 0x0100（偏移 256 超出8位有符号范围 [-128, 127]）");
+
+        // jal的20位偏移与max_imm_bits无关，报告LabelOutOfRange
+        let error = assemble_with_config("jal x1, far\n.space 600000\nfar: halt", &config).unwrap_err().remove(0);
+        assert_eq!((error.code, error.line), (ErrorCode::LabelOutOfRange, 1));
+        assert_eq!(error.message, "jal 的标签 far 超出跳转范围: 当前地址 0x0000，目标地址 0x927C4（跳转偏移 600004 超出20位有符号范围 [-524288, 524287]）");
+    }
+
+    #[test]
+    fn test_branch_out_of_range() {
+        // bne位于地址0，far是.space之后按4字节对齐的指令地址
+        let forward = |n: usize| assemble(&format!("bne x1, x0, far\n.space {}\nfar: halt", n));
+        assert_eq!(forward(32756).unwrap()[0], encode_bne(1, 0, 32760));
+        // 正好在边界上: 指令地址对齐后向前最远是32764
+        assert_eq!(forward(32760).unwrap()[0], encode_bne(1, 0, 32764));
+        // 多出一个字
+        let error = forward(32761).unwrap_err().remove(0);
+        assert_eq!(error.code, ErrorCode::BranchOutOfRange);
+        assert_eq!(error.message, "bne 的标签 far 超出跳转范围: 当前地址 0x0000，目标地址 0x8000（偏移 32768 超出16位有符号范围 [-32768, 32767]）");
+
+        // 向后跳转最多-32768字节
+        let backward = |n: usize| assemble(&format!("back: .space {}\nbeqz x1, back", n));
+        assert_eq!(backward(32768).unwrap()[32768 / 4], encode_beq(1, 0, -32768));
+        let error = backward(32772).unwrap_err().remove(0);
+        assert_eq!((error.code, error.line), (ErrorCode::BranchOutOfRange, 2));
+        assert!(error.message.starts_with("beqz 的标签 back 超出跳转范围: 当前地址 0x8004，目标地址 0x0000（偏移 -32772"));
+    }
+
+    #[test]
+    fn test_nop() {
+        assert_eq!(assemble("nop").unwrap(), vec![encode_addi(0, 0, 0)]);
+        assert_eq!(assemble("  nop   \nnop # wait").unwrap(), vec![encode_addi(0, 0, 0); 2]);
+        assert_eq!(decode_instruction(encode_addi(0, 0, 0)), "nop");
+        // 只有全0字段才是nop
+        assert_eq!(decode_instruction(encode_addi(0, 0, 1)), "addi x0, x0, 1");
+        assert_eq!(decode_instruction(encode_addi(0, 1, 0)), "addi x0, x1, 0");
+    }
+
+    #[test]
+    fn test_mov() {
+        assert_eq!(assemble("mov x3, x5").unwrap(), vec![encode_addi(3, 5, 0)]);
+        // 默认不显示mov
+        assert_eq!(decode_instruction(encode_addi(3, 5, 0)), "addi x3, x5, 0");
+        assert_eq!(decode_instruction_with(assemble("mov x3, x5").unwrap()[0], true), "mov x3, x5");
+        // 涉及x0或imm非0时不是mov
+        assert_eq!(decode_instruction_with(encode_addi(3, 0, 0), true), "zero x3");
+        assert_eq!(decode_instruction_with(encode_addi(0, 5, 0), true), "addi x0, x5, 0");
+        assert_eq!(decode_instruction_with(encode_addi(3, 5, 1), true), "addi x3, x5, 1");
+
+        let config = DisasmConfig { pseudo: true, ..DisasmConfig::default() };
+        assert!(disassemble_listing(&[encode_addi(3, 5, 0)], &config).contains("mov x3, x5"));
+    }
+
+    #[test]
+    fn test_li() {
+        // lui加载sext(imm) << 16，addi再加上sext(imm)
+        let eval = |words: &[u32]| words.iter().fold(0i32, |acc, &w| {
+            let imm = (w >> 16) as i16 as i32;
+            match w & 0x3F {
+                OPCODE_LUI => imm << 16,
+                _ => acc.wrapping_add(imm),
+            }
+        });
+
+        assert_eq!(assemble("li x1, 0").unwrap(), vec![encode_addi(1, 0, 0)]);
+        assert_eq!(assemble("li x1, -1").unwrap(), vec![encode_addi(1, 0, -1)]);
+        assert_eq!(assemble("li x1, 0xFFFF").unwrap(), vec![encode_lui(1, 1), encode_addi(1, 1, -1)]);
+        assert_eq!(assemble("li x1, 0x10000").unwrap(), vec![encode_lui(1, 1)]);
+        assert_eq!(assemble("li x1, 0x7FFFFFFF").unwrap(), vec![encode_lui(1, -32768), encode_addi(1, 1, -1)]);
+        assert_eq!(assemble("li x1, -0x80000000").unwrap(), vec![encode_lui(1, -32768)]);
+
+        for value in [0, -1, 0xFFFF, 0x10000, 0x7FFFFFFF, i32::MIN, 0x12345678, -0x12345678, 0x8000, -32769] {
+            assert_eq!(eval(&assemble(&format!("li x5, {}", value)).unwrap()), value, "li {}", value);
+        }
+        assert_eq!(assemble("li x1, 0xFFFFFFFF").unwrap(), assemble("li x1, -1").unwrap());
+    }
+
+    #[test]
+    fn test_li_label_addresses() {
+        // li展开为两条指令时，后面的标签地址随之后移
+        let code = assemble("li x1, 0x12345\nloop: addi x1, x1, -1\nbne x1, x0, loop\ndone: beq x0, x0, done").unwrap();
+        assert_eq!(code.len(), 5);
+        assert_eq!(code[3], encode_bne(1, 0, -4));
+    }
+
+    #[test]
+    fn test_branch_zero_pseudos() {
+        assert_eq!(assemble("beqz x5, 8").unwrap(), vec![encode_beq(5, 0, 8)]);
+        assert_eq!(assemble("bnez x5, -8").unwrap(), vec![encode_bne(5, 0, -8)]);
+        assert_eq!(assemble("bgez x5, 0").unwrap(), vec![encode_bge(5, 0, 0)]);
+        assert_eq!(assemble("bltz x5, 12").unwrap(), vec![encode_blt(5, 0, 12)]);
+        assert_eq!(assemble("loop: bnez x1, loop").unwrap(), vec![encode_bne(1, 0, 0)]);
+
+        for test_str in ["beqz x5, 8", "bnez x5, -8", "bgez x31, 0", "bltz x5, 12"] {
+            assert_eq!(decode_instruction(assemble(test_str).unwrap()[0]), test_str);
+        }
+        // rs1为x0时不是伪指令
+        assert_eq!(decode_instruction(encode_beq(0, 5, 8)), "beq x0, x5, 8");
+    }
+
+    #[test]
+    fn test_j_ret() {
+        assert_eq!(assemble("j -8").unwrap(), vec![encode_jal(0, -8)]);
+        assert_eq!(assemble("ret").unwrap(), vec![encode_jalr(0, 1, 0)]);
+        assert_eq!(decode_instruction(encode_jal(0, 0)), "j 0");
+        assert_eq!(decode_instruction(encode_jalr(0, 1, 0)), "ret");
+        // 只有jalr x0, x1, 0是ret
+        assert_eq!(decode_instruction(encode_jalr(0, 2, 0)), "jalr x0, x2, 0");
+
+        // 调用一个把x10加倍的子程序，然后原地停机
+        let source = "addi x10, x0, 21\ncall double\nend: j end\ndouble: add x10, x10, x10\nret";
+        let code = assemble(source).unwrap();
+        assert_eq!(code, vec![encode_addi(10, 0, 21), encode_jal(1, 8), encode_jal(0, 0), encode_add(10, 10, 10), encode_jalr(0, 1, 0)]);
+        let decoded: Vec<String> = code.iter().map(|&w| decode_instruction(w)).collect();
+        assert_eq!(decoded, ["addi x10, x0, 21", "jal x1, 8", "j 0", "add x10, x10, x10", "ret"]);
+        assert_eq!(assemble(&decoded.join("\n")).unwrap(), code);
+    }
+
+    #[test]
+    fn test_abi_register_names() {
+        for (index, name) in ABI_NAMES.iter().enumerate() {
+            assert_eq!(parse_reg(name).unwrap() as usize, index, "{}", name);
+            assert_eq!(parse_reg(&format!("x{}", index)).unwrap() as usize, index);
+        }
+        assert_eq!(parse_reg("fp").unwrap(), 8);
+        assert_eq!(assemble("addi sp, sp, -16\nsw ra, 12(sp)\nadd a0, a1, t6").unwrap(),
+            assemble("addi x2, x2, -16\nsw x1, 12(x2)\nadd x10, x11, x31").unwrap());
+    }
+
+    #[test]
+    fn test_use_abi_names() {
+        assert_eq!(use_abi_names("add x10, x11, x31"), "add a0, a1, t6");
+        assert_eq!(use_abi_names("sw x1, 12(x2)"), "sw ra, 12(sp)");
+        assert_eq!(use_abi_names("xori x0, x8, 0"), "xori zero, s0, 0");
+        assert_eq!(use_abi_names("未知指令: 0x0000003F"), "未知指令: 0x0000003F");
+
+        let config = DisasmConfig { abi_names: true, ..DisasmConfig::default() };
+        let listing = disassemble_listing(&assemble("add a0, a0, s1").unwrap(), &config);
+        assert!(listing.contains("add a0, a0, s1"));
+    }
+
+    #[test]
+    fn test_unreachable_code_warning() {
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run("addi x1, x0, 1\nhalt\nadd x1, x1, x1\nnop\nmul x1, x1, x1").unwrap();
+        assert_eq!(assembler.warnings, vec![
+            Warning::UnreachableCode { address: 8, line: 3 },
+            Warning::UnreachableCode { address: 12, line: 4 },
+            Warning::UnreachableCode { address: 16, line: 5 },
+        ]);
+        assert_eq!(assembler.warnings[0].to_string(), "第3行: 地址 0x0008 处的指令在halt之后，不会被执行");
+
+        // 标签、数字偏移的跳转目标和数据字之后都不警告
+        let mut assembler = Assembler::new(&config);
+        assembler.run("beqz x1, 12\nj func\nhalt\naddi x2, x0, 1\nhalt\n.word 7\nfunc: ret\nhalt\nhalt").unwrap();
+        assert_eq!(assembler.warnings, vec![Warning::UnreachableCode { address: 32, line: 9 }]);
+
+        let config = AssemblerConfig { warn_dead_code: false, ..AssemblerConfig::default() };
+        let mut assembler = Assembler::new(&config);
+        assembler.run("halt\nnop").unwrap();
+        assert!(assembler.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_write_to_x0_warning() {
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run("addi x0, x1, 5\nadd x1, x2, x3\nmul x0, x1, x1\nnop\nj 0\nret\nsw x0, 0(x2)\nbeqz x0, 0").unwrap();
+        assert_eq!(assembler.warnings, vec![Warning::WriteToX0 { line: 1 }, Warning::WriteToX0 { line: 3 }]);
+        assert_eq!(assembler.warnings[0].to_string(), "line 1: writing to x0 has no effect");
+    }
+
+    #[test]
+    fn test_word_directive() {
+        let source = "j start\ntable: .word 1, 2, 0xFFFFFFFF\n.word -1,table\nstart: lw x1, 4(x0)\nhalt";
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run(source).unwrap();
+        assert_eq!(assembler.img, vec![encode_jal(0, 24), 1, 2, 0xFFFF_FFFF, 0xFFFF_FFFF, 4, encode_lw(1, 0, 4), 0]);
+        assert_eq!(assembler.data, vec![false, true, true, true, true, true, false, false]);
+        assert_eq!(assembler.lines, vec![1, 2, 2, 2, 3, 3, 4, 5]);
+        // 数据不参与写入x0检查
+        assert!(assembler.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_disassemble_reassemble() {
+        // 反汇编输出重新汇编后得到相同的字，包括sw/blt等交换了寄存器字段的指令、标签和数据字
+        let source = "\
+addi x1, x0, 3
+loop: sw x3, 4(x5)
+sb x6, -1(x7)
+blt x1, x2, loop
+bltz x4, end
+jal x1, loop
+addi x1, x1, -1
+bnez x1, loop
+end: halt
+.word 0x3F, 0xFFFFFFFF
+beq x1, x2, 100";
+        let code = assemble(source).unwrap();
+        let configs = [
+            DisasmConfig::default(),
+            DisasmConfig { pseudo: true, abi_names: true, annotate_hazards: true, ..DisasmConfig::default() },
+        ];
+        for config in configs {
+            let listing = disassemble_listing(&code, &config);
+            assert_eq!(assemble(&listing).unwrap_or_else(|e| panic!("{}\n{:?}", listing, e)), code, "{}", listing);
+        }
+    }
+
+    #[test]
+    fn test_disassemble_inline_values() {
+        let code = assemble("li x1, 3\nloop: addi x1, x1, -1\nbnez x1, loop\nli x2, 5\nli x2, 5\nhalt").unwrap();
+        let mut cpu = sim::CpuState::new(&code).unwrap();
+        let mut tracer = sim::PrintTracer::new(Vec::new());
+        cpu.run_traced(&mut tracer).unwrap();
+        let trace = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        let mut config = DisasmConfig { inline_values: sim::parse_trace(&trace).unwrap(), ..DisasmConfig::default() };
+        let listing = disassemble_listing(&code, &config);
+        let lines: Vec<&str> = listing.lines().skip(3).collect();
+        assert_eq!(lines, [
+            "    addi x1, x0, 3  # 0000: 00030042  x1 <- 0x00000003",
+            "L_0004:",
+            "    addi x1, x1, -1  # 0004: FFFF0842  x1 <- 0x00000002, ..., 0x00000000 (3次)",
+            "    bnez x1, L_0004  # 0008: FFE10703  -> 0x0004",
+            "    addi x2, x0, 5  # 000C: 00050082  x2 <- 0x00000005",
+            "    addi x2, x0, 5  # 0010: 00050082  x2 <- 0x00000005",
+            "    halt  # 0014: 00000000",
+        ]);
+        // 标注在注释中，不影响重新汇编
+        assert_eq!(assemble(&listing).unwrap(), code);
+
+        config.abi_names = true;
+        assert!(disassemble_listing(&code, &config).contains("    addi sp, zero, 5  # 000C: 00050082  sp <- 0x00000005\n"));
+    }
+
+    #[test]
+    fn test_disassemble_data_words() {
+        let code = assemble("addi x1, x0, 1\n.word 0x3F, 0xFFFFFFFF, 0x100\nhalt").unwrap();
+        let listing = disassemble_listing(&code, &DisasmConfig::default());
+        assert!(listing.contains("    .word 0x0000003F  # 0004: 0000003F  数据\n"));
+        assert!(listing.contains("    .word 0xFFFFFFFF  # 0008: FFFFFFFF  数据\n"));
+        assert!(listing.contains("    .word 0x00000100  # 000C: 00000100  数据\n"));
+        assert!(listing.contains("    halt  # 0010: 00000000\n"));
+    }
+
+    #[test]
+    #[should_panic(expected = ".word 缺少操作数")]
+    fn test_word_directive_empty() {
+        assemble(".word").unwrap();
+    }
+
+    #[test]
+    fn test_byte_half_directives() {
+        // 字节按小端序打包，不满一个字时补0
+        assert_eq!(assemble(".byte 1, 2, 3").unwrap(), vec![0x0003_0201]);
+        assert_eq!(assemble(".half 0x1234, 0xFFFF\n.half 7").unwrap(), vec![0xFFFF_1234, 0x0000_0007]);
+        // 连续的.byte/.half行紧凑存放
+        assert_eq!(assemble(".byte 0xAA\n.half 0xBBCC\n.byte 0xDD").unwrap(), vec![0xDDBB_CCAA]);
+
+        let source = "msg: .byte 72, 105\nnext: .byte 33\n.word 0x11223344\nend: .byte 255, 0, 1, 2, 3\nstart: lw x1, 0(x0)\nhalt";
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run(source).unwrap();
+        assert_eq!(assembler.img, vec![0x0021_6948, 0x1122_3344, 0x0201_00FF, 0x0000_0003, encode_lw(1, 0, 0), 0]);
+        assert_eq!(assembler.data, vec![true, true, true, true, false, false]);
+        assert_eq!(assembler.lines, vec![2, 3, 4, 4, 5, 6]);
+        assert_eq!(assembler.labels["msg"], 0);
+        assert_eq!(assembler.labels["next"], 2);
+        assert_eq!(assembler.labels["end"], 8);
+        assert_eq!(assembler.labels["start"], 16);
+    }
+
+    #[test]
+    fn test_label_after_bytes_is_aligned() {
+        // 单独一行的标签指向对齐之后的指令
+        let code = assemble(".byte 1\nloop:\naddi x1, x1, 1\nbnez x1, loop").unwrap();
+        assert_eq!(code, vec![1, encode_addi(1, 1, 1), encode_bne(1, 0, -4)]);
+    }
+
+    #[test]
+    #[should_panic(expected = ".byte值 256 超出范围 [0, 255]")]
+    fn test_byte_out_of_range() {
+        assemble(".byte 1, 256").unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = ".half值 -1 超出范围 [0, 65535]")]
+    fn test_half_out_of_range() {
+        assemble(".half -1").unwrap();
+    }
+
+    #[test]
+    fn test_space_directive() {
+        let source = "addi x1, x0, 1\nbuffer: .space 16\nafter: sw x1, 0(x0)\n.space 1\n.byte 7\nhalt";
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run(source).unwrap();
+        assert_eq!(assembler.img, vec![encode_addi(1, 0, 1), 0, 0, 0, 0, encode_sw(0, 1, 0), 0x0000_0700, 0]);
+        assert_eq!(assembler.labels["buffer"], 4);
+        assert_eq!(assembler.labels["after"], 20);
+        // 不满一个字时补齐
+        assert_eq!(assemble(".space 5").unwrap(), vec![0, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = ".space的大小必须是正整数: 0")]
+    fn test_space_zero() {
+        assemble(".space 0").unwrap();
+    }
+
+    #[test]
+    fn test_ascii_directives() {
+        assert_eq!(assemble(".ascii \"\"").unwrap(), Vec::<u32>::new());
+        assert_eq!(assemble(".asciiz \"\"").unwrap(), vec![0]);
+        assert_eq!(assemble(".ascii \"A\"").unwrap(), vec![0x41]);
+        assert_eq!(assemble(".ascii \"ABCD\"").unwrap(), vec![0x4443_4241]);
+        assert_eq!(assemble(".asciiz \"ABCD\"").unwrap(), vec![0x4443_4241, 0]);
+        assert_eq!(assemble(".ascii \"hello, world\"").unwrap(), vec![0x6C6C_6568, 0x7720_2C6F, 0x646C_726F]);
+        // 字符串中的#和空格原样保留，之后的#是注释
+        assert_eq!(assemble("msg: .ascii \"a #b\"  # 注释\nhalt").unwrap(), vec![0x6223_2061, 0]);
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        assert_eq!(parse_string_literal(r#""\n\r\t\\\"\0\x41\x7f""#).unwrap(), b"\n\r\t\\\"\0A\x7f");
+        assert!(parse_string_literal(r#""\q""#).unwrap_err().contains("无法识别的转义序列"));
+        assert!(parse_string_literal(r#""\x4""#).is_err());
+        assert!(parse_string_literal("abc").is_err());
+        assert!(parse_string_literal("\"").is_err());
+        // 字符串后的标签地址按字节计算
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run(".asciiz \"hi\"\nnext: .byte 1\nstart: halt").unwrap();
+        assert_eq!(assembler.labels["next"], 3);
+        assert_eq!(assembler.labels["start"], 4);
+    }
+
+    #[test]
+    fn test_align_directive() {
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run(".byte 1\n.align 2\nstart: addi x1, x0, 1\n.byte 2\n.align 3\ntable: .byte 3\n.align 2\nhalt").unwrap();
+        assert_eq!(assembler.labels["start"], 4);
+        assert_eq!(assembler.labels["table"], 16);
+        assert_eq!(assembler.img, vec![1, encode_addi(1, 0, 1), 2, 0, 3, 0]);
+        // 已经对齐时不填充
+        assert_eq!(assemble(".word 5\n.align 2\nhalt").unwrap(), vec![5, 0]);
+        assert_eq!(assemble(".byte 1, 2\n.align 1\n.half 0x0403").unwrap(), vec![0x0403_0201]);
+    }
+
+    #[test]
+    #[should_panic(expected = ".align的参数必须在1~16之间")]
+    fn test_align_zero() {
+        assemble(".align 0").unwrap();
+    }
+
+    #[test]
+    fn test_equ_constants() {
+        let source = ".equ COUNT, 10\nOFFSET = -4\nSTEP = COUNT\naddi x1, x0, COUNT\nlw x2, OFFSET(x3)\nsw x2, OFFSET(x3)\nloop: addi x1, x1, -1\nbne x1, x0, OFFSET\nslli x4, x4, STEP\nli x5, 0x12345\n.word COUNT";
+        let expected = "addi x1, x0, 10\nlw x2, -4(x3)\nsw x2, -4(x3)\nloop: addi x1, x1, -1\nbne x1, x0, -4\nslli x4, x4, 10\nli x5, 0x12345\n.word 10";
+        assert_eq!(assemble(source).unwrap(), assemble(expected).unwrap());
+        // 标签名和常量名中的子串不会被替换
+        assert_eq!(assemble("N = 3\nNN: addi x1, x0, N\nbne x1, x0, NN").unwrap(), vec![encode_addi(1, 0, 3), encode_bne(1, 0, -4)]);
+        assert_eq!(assemble(".ascii \"a=b\"").unwrap(), vec![0x0062_3D61]);
+    }
+
+    #[test]
+    fn test_equ_redefinition_warning() {
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run(".equ N, 1\naddi x1, x0, N\n.equ N, 2\naddi x1, x0, N").unwrap();
+        assert_eq!(assembler.img, vec![encode_addi(1, 0, 1), encode_addi(1, 0, 2)]);
+        assert_eq!(assembler.warnings, vec![Warning::ConstantRedefined { name: "N".to_string(), line: 3 }]);
+        assert_eq!(assembler.warnings[0].to_string(), "第3行: 常量 N 被重新定义");
+    }
+
+    #[test]
+    fn test_text_data_sections() {
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run(".data\nmsg: .byte 1, 2\n.text\nstart: addi x1, x0, 1\n.data\ntable: .word start, msg\n.section .text\nbeq x1, x0, msg\nhalt").unwrap();
+        assert_eq!(assembler.labels["msg"], 12);
+        assert_eq!(assembler.labels["table"], 16);
+        assert_eq!(assembler.img, vec![encode_addi(1, 0, 1), encode_beq(1, 0, 8), encode_halt(), 0x0201, 0, 12]);
+        assert_eq!(assembler.data, vec![false, false, false, true, true, true]);
+        assert_eq!(assembler.lines, vec![4, 8, 9, 2, 6, 6]);
+        // 没有段伪指令时按源码顺序输出
+        assert_eq!(assemble(".word 7\nhalt").unwrap(), vec![7, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "不支持的段: .bss")]
+    fn test_unknown_section() {
+        assemble(".section .bss").unwrap();
+    }
+
+    #[test]
+    fn test_parse_reg_errors() {
+        assert_eq!(parse_reg("x32").unwrap_err().message, "无效的寄存器: x32");
+        assert_eq!(parse_reg("y1").unwrap_err().message, "无效的寄存器: y1");
+        assert_eq!(parse_reg("").unwrap_err().message, "无效的寄存器: ");
+        assert_eq!(parse_imm("12a").unwrap_err(), LineError::new(ErrorCode::InvalidImmediate, "12a", "无效的十进制立即数: 12a".to_string()));
+    }
+
+    #[test]
+    fn test_decimal_imm_out_of_range() {
+        // 格式正确但超出范围的十进制数报告为ImmediateOutOfRange，并给出范围
+        for (source, value) in [("addi x1, x0, 40000", "40000"), ("addi x1, x0, -32769", "-32769"), ("slti x1, x2, 40000", "40000")] {
+            let error = assemble(source).unwrap_err().remove(0);
+            assert_eq!(error.code, ErrorCode::ImmediateOutOfRange, "{}", source);
+            assert_eq!(error.message, format!("立即数 {} 超出16位立即数的范围 [-32768, 32767]", value));
+        }
+        assert_eq!(assemble("addi x1, x0, -32768").unwrap(), vec![encode_addi(1, 0, i16::MIN)]);
+        // lui的立即数是高16位，十进制可以写到65535
+        assert_eq!(assemble("lui x1, 40000").unwrap(), vec![encode_lui(1, 40000u32 as i16)]);
+        let error = assemble("lui x1, 65536").unwrap_err().remove(0);
+        assert_eq!(error.message, "立即数 65536 超出16位立即数的范围 [-32768, 65535]");
+    }
+
+    #[test]
+    fn test_assembly_error_location() {
+        let errors = assemble("addi x1, x0, 1\n  loop: foo x1, x2\n").unwrap_err();
+        assert_eq!(errors, vec![AssemblyError {
+            file: "<input>".to_string(),
+            line: 2,
+            col_start: 9,
+            col_end: 12,
+            severity: Severity::Error,
+            code: ErrorCode::UnknownInstruction,
+            message: "未知指令: foo".to_string(),
+        }]);
+        let errors = assemble("add x1, x2").unwrap_err();
+        assert_eq!(errors[0].message, "add 缺少第3个操作数");
+        assert_eq!(assemble("addi x1, x0, 1").unwrap(), vec![encode_addi(1, 0, 1)]);
+    }
+
+    #[test]
+    fn test_multiple_errors() {
+        let source = "addi x1, x0, 1\nadd x1, x2, x99\nloop: halt\nfoo x1\nloop: bne x1, x0, missing\nhalt";
+        let errors = assemble(source).unwrap_err();
+        let summary: Vec<(usize, &str)> = errors.iter().map(|e| (e.line, e.message.as_str())).collect();
+        assert_eq!(summary, vec![
+            (2, "无效的寄存器: x99"),
+            (4, "未知指令: foo"),
+            (5, "标签 loop 重复定义（第3行已定义）"),
+        ]);
+    }
+
+    #[test]
+    fn test_assembly_error_display_and_json() {
+        let mut error = assemble("addi x1, x0, 1\n  add x1, x2, x40 # 注释").unwrap_err().remove(0);
+        assert_eq!((error.col_start, error.col_end), (15, 18));
+        assert_eq!(error.to_string(), "<input>:2:15: error[E002]: 无效的寄存器: x40");
+        error.file = "asm/a \"b\".asm".to_string();
+        error.severity = Severity::Warning;
+        assert_eq!(error.to_string(), "asm/a \"b\".asm:2:15: warning[E002]: 无效的寄存器: x40");
+        assert_eq!(
+            error.to_json(),
+            r#"{"file":"asm/a \"b\".asm","line":2,"col_start":15,"col_end":18,"severity":"warning","code":"E002","message":"无效的寄存器: x40"}"#
+        );
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let cases = [
+            ("foo x1", ErrorCode::UnknownInstruction, "foo"),
+            ("add x1, x2, x99", ErrorCode::InvalidRegister, "x99"),
+            ("addi x1, x0, 1z", ErrorCode::InvalidImmediate, "1z"),
+            ("srai x1, x2, 32", ErrorCode::ImmediateOutOfRange, "32"),
+            ("add x1, x2", ErrorCode::MissingOperand, "add"),
+            ("bne x1, x0, done", ErrorCode::UndefinedLabel, "done"),
+            ("a: halt\na: halt", ErrorCode::DuplicateLabel, "a"),
+            ("jal x1, far\n.space 600000\nfar: halt", ErrorCode::LabelOutOfRange, "far"),
+            ("lw x1, 4", ErrorCode::InvalidSyntax, "4"),
+            ("sll x1, x2, x3", ErrorCode::UnsupportedInstruction, "sll"),
+            ("bne x1, x0, far\n.space 40000\nfar: halt", ErrorCode::BranchOutOfRange, "far"),
+        ];
+        let config = AssemblerConfig { compat_riscv: true, ..AssemblerConfig::default() };
+        for (i, (source, code, token)) in cases.into_iter().enumerate() {
+            let error = assemble_with_config(source, &config).unwrap_err().remove(0);
+            assert_eq!(error.code, code, "{}", source);
+            assert_eq!(code.code(), format!("E{:03}", i + 1));
+            let line = source.lines().nth(error.line - 1).unwrap();
+            let span: String = line.chars().skip(error.col_start - 1).take(error.col_end - error.col_start).collect();
+            assert_eq!(span, token, "{}", source);
+        }
+        assert_eq!(ErrorCode::ALL.len(), cases.len());
+    }
+
+    #[test]
+    fn test_immediate_truncated_warning() {
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run("addi x1, x0, 0x12345\nslti x2, x1, 0x11170\nlui x3, 0xFFFF\nlui x4, 0x11170\nslli x5, x5, 0x10001").unwrap();
+        assert_eq!(assembler.img[0], encode_addi(1, 0, 0x2345));
+        assert_eq!(assembler.img[2], encode_lui(3, -1));
+        assert_eq!(assembler.warnings, vec![
+            Warning::ImmediateTruncated { original: 0x12345, truncated: 0x2345, bits: 16, line: 1 },
+            Warning::ImmediateTruncated { original: 70000, truncated: 70000u32 as i16, bits: 16, line: 2 },
+            Warning::ImmediateTruncated { original: 70000, truncated: 70000u32 as i16, bits: 16, line: 4 },
+            Warning::ImmediateTruncated { original: 0x10001, truncated: 1, bits: 16, line: 5 },
+        ]);
+        assert_eq!(assembler.warnings[0].to_string(), "第1行: 立即数 74565 超出16位字段，被截断为 9029");
+
+        // 有符号立即数超出i16范围就警告，lui的0x8000~0xFFFF是正常的高16位
+        let mut assembler = Assembler::new(&config);
+        assembler.run("addi x1, x0, 0x8000\nori x2, x0, 0xFFFF\nlui x3, 0x8000").unwrap();
+        assert_eq!(assembler.warnings, vec![
+            Warning::ImmediateTruncated { original: 0x8000, truncated: i16::MIN, bits: 16, line: 1 },
+            Warning::ImmediateTruncated { original: 0xFFFF, truncated: -1, bits: 16, line: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_imm_binary() {
+        assert_eq!(parse_imm("0b0").unwrap(), 0);
+        assert_eq!(parse_imm("0B1010").unwrap(), 10);
+        // 超出i16范围的值与十六进制一样按低16位解释
+        assert_eq!(parse_imm("0b1111111111111111").unwrap(), -1);
+        assert_eq!(parse_imm("0b10000000_00000000").unwrap(), i16::MIN);
+        assert_eq!(parse_imm("0b102").unwrap_err().message, "无效的二进制立即数: 0b102");
+        assert_eq!(parse_int("-0b1_0000"), Some(-16));
+        assert_eq!(parse_imm("0b1111_0000").unwrap(), 0xF0);
+
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run("andi x1, x2, 0b1111_0000
+addi x3, x0, 0b1_0000_0000_0000_0001
+addi x4, x0, 0b1111111111111111
+addi x5, x0, 0b10000000_00000000
+addi x6, x0, 0b0111_1111_1111_1111").unwrap();
+        assert_eq!(assembler.img[0], encode_andi(1, 2, 0xF0));
+        assert_eq!(assembler.img[1], encode_addi(3, 0, 1));
+        // 65535和32768按有符号数扩展后变成-1和-32768，同样要警告
+        assert_eq!(assembler.warnings, vec![
+            Warning::ImmediateTruncated { original: 0x10001, truncated: 1, bits: 16, line: 2 },
+            Warning::ImmediateTruncated { original: 0xFFFF, truncated: -1, bits: 16, line: 3 },
+            Warning::ImmediateTruncated { original: 0x8000, truncated: i16::MIN, bits: 16, line: 4 },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_imm_separators() {
+        assert_eq!(parse_imm("32_767").unwrap(), 32767);
+        assert_eq!(parse_imm("-1_024").unwrap(), -1024);
+        assert_eq!(parse_imm("0xFF_FF").unwrap(), -1);
+        assert_eq!(parse_int("1_000_000"), Some(1_000_000));
+        assert_eq!(parse_int("0xDEAD_BEEF"), Some(0xDEAD_BEEF));
+        for bad in ["1__2", "_100", "100_", "-_1", "0x_FF", "0b_1", "0xFF_"] {
+            assert!(parse_imm(bad).is_err(), "{}", bad);
+            assert_eq!(parse_int(bad), None, "{}", bad);
+        }
+        assert_eq!(parse_imm("1__2").unwrap_err().message, "无效的十进制立即数: 1__2");
+        assert_eq!(assemble("lui x1, 65_535").unwrap(), vec![encode_lui(1, -1)]);
+    }
+
+    #[test]
+    fn test_statements_from_parser() {
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run("loop: lw x1, -0x10(x2)\nbnez x1, loop\n.data\n.word loop, 'A'\n.asciiz \"hi\"").unwrap();
+        // 记号已经是寄存器和立即数的行由语法分析器得到语句
+        let statements = assembler.parse_statements(&["bnez", "x1", "loop"]).unwrap();
+        assert_eq!(statements, vec![parser::Statement::Branch {
+            instruction: Instruction::Bne { rs1: 1, rs2: 0, offset: 0 },
+            target: "loop".to_string(),
+        }]);
+        assert_eq!(assembler.img, vec![encode_lw(1, 2, -16), encode_bne(1, 0, -4), 0, 0x41, u32::from_le_bytes(*b"hi\0\0")]);
+
+        // 表达式、要截断的十六进制数、超出max_imm_bits的立即数和语法分析器不认识的指令按文本处理
+        let text_only: [&[&str]; 3] = [&["addi", "x1", "x0", "1+2"], &["addi", "x1", "x0", "0xFFFF"], &["popcount", "x1", "x2"]];
+        for parts in text_only {
+            assert_eq!(assembler.parse_statements(parts), None, "{:?}", parts);
+        }
+        let narrow = AssemblerConfig { max_imm_bits: 8, ..AssemblerConfig::default() };
+        assert_eq!(Assembler::new(&narrow).parse_statements(&["addi", "x1", "x0", "200"]), None);
+    }
+
+    #[test]
+    fn test_signed_radix_immediates() {
+        assert_eq!(parse_imm("-0x10").unwrap(), -16);
+        assert_eq!(parse_imm("+0x10").unwrap(), 16);
+        assert_eq!(parse_imm("-0b1_0000").unwrap(), -16);
+        assert_eq!(parse_imm("-0x_10").unwrap_err().message, "无效的十六进制立即数: -0x_10");
+
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run("addi x1, x0, -0x10
+lw x1, -0x10(x2)
+addi x3, x0, -0b1_0000
+sw x3, -0b1_0000(x2)
+addi x4, x0, -0x8000
+addi x5, x0, -0x8001").unwrap();
+        assert_eq!(assembler.img, vec![
+            encode_addi(1, 0, -16),
+            encode_lw(1, 2, -16),
+            encode_addi(3, 0, -16),
+            encode_sw(2, 3, -16),
+            encode_addi(4, 0, i16::MIN),
+            encode_addi(5, 0, i16::MAX),
+        ]);
+        // 与正的十六进制数一样，超出16位有符号范围时截断并警告
+        assert_eq!(assembler.warnings, vec![
+            Warning::ImmediateTruncated { original: -0x8001, truncated: i16::MAX, bits: 16, line: 6 },
+        ]);
+    }
+
+    #[test]
+    fn test_char_literals() {
+        assert_eq!(parse_imm("'A'").unwrap(), 65);
+        assert_eq!(parse_imm("'\\n'").unwrap(), 10);
+        assert_eq!(parse_imm("'\\x41'").unwrap(), 65);
+        assert_eq!(parse_imm("'\\''").unwrap(), 39);
+        assert_eq!(parse_imm("'\\\\'").unwrap(), 92);
+        assert_eq!(parse_imm("'\\0'").unwrap(), 0);
+        assert_eq!(parse_imm("'AB'").unwrap_err().message, "字符字面量只能包含一个字符: 'AB'");
+        assert_eq!(parse_imm("''").unwrap_err().message, "字符字面量为空");
+        assert!(parse_imm("'A").is_err());
+
+        // 逗号、空格和#在字符字面量中不分隔操作数，n不被当作常量替换
+        let source = ".equ n, 5\naddi x1, x0, ','\naddi x2, x0, '#' # 注释\naddi x3, x0, ' '\nli x4, '\\n'";
+        assert_eq!(assemble(source).unwrap(), vec![
+            encode_addi(1, 0, 44), encode_addi(2, 0, 35), encode_addi(3, 0, 32), encode_addi(4, 0, 10),
+        ]);
+    }
+
+    #[test]
+    fn test_immediate_expressions() {
+        assert_eq!(parse_imm("2+3*4").unwrap(), 14);
+        assert_eq!(parse_imm("-(1<<4)").unwrap(), -16);
+        assert_eq!(parse_imm("1/0").unwrap_err().message, "无效的表达式 1/0: 除数为0");
+        // 开头的负号不使表达式求值，-5仍是普通十进制数
+        assert_eq!(parse_imm("-5").unwrap(), -5);
+
+        let source = ".equ BUFFER_SIZE, 64\n.equ OFFSET, 4\naddi x1, x0, BUFFER_SIZE/4\nlw x2, (OFFSET+8)(x3)\nli x4, BUFFER_SIZE*0x1000";
+        assert_eq!(assemble(source).unwrap(), vec![
+            encode_addi(1, 0, 16), encode_lw(2, 3, 12), encode_lui(4, 4),
+        ]);
+
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run("addi x1, x0, 0x10000+1").unwrap();
+        assert_eq!(assembler.img[0], encode_addi(1, 0, 1));
+        assert_eq!(assembler.warnings, vec![Warning::ImmediateTruncated { original: 0x10001, truncated: 1, bits: 16, line: 1 }]);
+    }
+
+    #[test]
+    fn test_macro_expansion() {
+        let source = "\
+.macro addmul rd, rs
+    add rd, rd, rs
+    mul rd, rd, rs
+    addi rd, rd, -1
+.endm
+loop: addmul x1, x2
+bne x1, x0, loop";
+        let config = AssemblerConfig::default();
+        let mut assembler = Assembler::new(&config);
+        assembler.run(source).unwrap();
+        assert_eq!(assembler.img, vec![
+            encode_add(1, 1, 2), encode_mul(1, 1, 2), encode_addi(1, 1, -1), encode_bne(1, 0, -12),
+        ]);
+        // 展开出的指令都属于调用宏的那一行
+        assert_eq!(assembler.lines, vec![6, 6, 6, 7]);
+
+        let errors = assemble(".macro twice r\ntwice r\n.endm\n  twice x1").unwrap_err();
+        assert_eq!((errors[0].line, errors[0].col_start, errors[0].col_end), (4, 3, 8));
+        assert_eq!(errors[0].message, "宏 twice 递归调用自身");
+    }
+
+    #[test]
+    fn test_conditional_assembly() {
+        let source = "\
+.ifndef DEBUG
+.equ DEBUG, 0
+.endif
+    addi x1, x0, 1
+.if DEBUG
+    .if DEBUG - 1
+verbose: addi x2, x0, 2
+    .else
+    addi x2, x0, 1
+    .endif
+.else
+    nop
+.endif
+done: halt";
+        let assemble_with = |defines: &[(&str, i32)]| {
+            let config = AssemblerConfig {
+                defines: defines.iter().map(|&(name, value)| (name.to_string(), value)).collect(),
+                ..AssemblerConfig::default()
+            };
+            let mut assembler = Assembler::new(&config);
+            assembler.run(source).unwrap();
+            (assembler.img, assembler.labels.get("done").copied(), assembler.labels.contains_key("verbose"))
+        };
+        assert_eq!(assemble_with(&[]), (vec![encode_addi(1, 0, 1), encode_addi(0, 0, 0), encode_halt()], Some(8), false));
+        assert_eq!(assemble_with(&[("DEBUG", 1)]), (vec![encode_addi(1, 0, 1), encode_addi(2, 0, 1), encode_halt()], Some(8), false));
+        assert_eq!(assemble_with(&[("DEBUG", 2)]), (vec![encode_addi(1, 0, 1), encode_addi(2, 0, 2), encode_halt()], Some(8), true));
+
+        let errors = assemble("addi x1, x0, 1\n.if 1\n.else\n.else\nhalt").unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| (e.line, e.message.as_str())).collect();
+        assert_eq!(messages, [(2, "缺少.endif"), (4, "同一个.if中有多个.else")]);
+    }
+
+    #[test]
+    fn test_intel_hex_output() {
+        let img = assemble("addi x1, x0, 10\nhalt").unwrap();
+        let hex = format_intel_hex(&img);
+        assert_eq!(hex, ":0800000042000A0000000000AC\n:00000001FF\n");
+
+        // 逐字节校验第一条记录：长度、地址、类型、数据和校验和
+        let record = hex.lines().next().unwrap();
+        let bytes: Vec<u8> = (1..record.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&record[i..i + 2], 16).unwrap())
+            .collect();
+        assert_eq!(bytes[0] as usize, 8);
+        assert_eq!(u16::from_be_bytes([bytes[1], bytes[2]]), 0);
+        assert_eq!(bytes[3], 0x00);
+        let data: Vec<u8> = img.iter().flat_map(|word| word.to_le_bytes()).collect();
+        assert_eq!(&bytes[4..12], &data[..]);
+        assert_eq!(bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)), 0);
+
+        // 超过64KB时插入扩展线性地址记录
+        let hex = format_intel_hex(&vec![0; 0x4001]);
+        assert!(hex.ends_with(":020000040001F9\n:0400000000000000FC\n:00000001FF\n"));
+        assert_eq!(OutputFormat::parse("ihex"), Ok(OutputFormat::IntelHex));
+        assert!(OutputFormat::parse("elf").is_err());
+    }
+
+    #[test]
+    fn test_srec_checksum() {
+        // 与汇编器无关的已知记录
+        let record = [0x13, 0x7A, 0xF0, 0x0A, 0x0A, 0x0D, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(srec_checksum(&record), 0x61);
+        assert_eq!(srec_record(0, 0, b"hello     \0\0"), "S00F000068656C6C6F202020202000003C\n");
+        assert_eq!(srec_record(7, 0, &[]), "S70500000000FA\n");
+    }
+
+    #[test]
+    fn test_srecord_output() {
+        let img = assemble("addi x1, x0, 10\nhalt").unwrap();
+        assert_eq!(
+            format_srecord(&img, "sum"),
+            "S006000073756DA4\nS30D0000000042000A0000000000A6\nS70500000000FA\n"
+        );
+        assert_eq!(OutputFormat::parse("srec"), Ok(OutputFormat::SRecord));
+    }
+
+    #[test]
+    fn test_readmem_output() {
+        let img = assemble("addi x1, x0, 10\nadd x1, x1, x3\nhalt").unwrap();
+        assert_eq!(format_readmem(&img, false, None), "000a0042\n00030841\n00000000\n");
+        assert_eq!(format_readmem(&img, false, Some(2)), "@0\n000a0042\n00030841\n@2\n00000000\n");
+        assert_eq!(
+            format_readmem(&img, true, None),
+            "00000000000010100000000001000010\n00000000000000110000100001000001\n00000000000000000000000000000000\n"
+        );
+    }
+
+    #[test]
+    fn test_operand_spacing() {
+        let expected = assemble("add x1, x2, x3\nsw t0, 4(sp)\n.byte 1, 2").unwrap();
+        assert_eq!(assemble("add x1,x2,x3\nsw t0,4( sp )\n.byte 1,2,").unwrap(), expected);
+        assert_eq!(assemble("add\tx1 ,\tx2 , x3 # 注释\nsw  t0 ,  4(sp)\n.byte 1 2").unwrap(), expected);
+    }
+}
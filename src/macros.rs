@@ -0,0 +1,211 @@
+// 宏预处理：.macro NAME p1, p2 ... .endm 定义宏，之后以 NAME a1, a2 调用时
+// 把宏体中的参数名替换为实参后原地展开。宏必须先定义后使用，宏体中可以调用
+// 其他宏，但不能直接或间接地调用自身。展开在两遍汇编之前进行，展开出的行沿用调用处的行号
+
+use std::collections::HashMap;
+
+use crate::lexer::split_operands;
+use crate::{is_identifier, replace_identifiers, split_label, strip_comment, ErrorCode, LineError, PreprocessResult, SourceLine};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Macro {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<String>, // 宏体的原始源码行
+}
+
+impl Macro {
+    // 用实参替换宏体中的参数名，args与params一一对应
+    pub fn expand(&self, args: &[&str]) -> Vec<String> {
+        self.body.iter()
+            .map(|line| replace_identifiers(line, |name| {
+                self.params.iter().position(|param| param == name).map(|i| args[i].to_string())
+            }))
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MacroTable {
+    macros: HashMap<String, Macro>,
+}
+
+impl MacroTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 同名的宏被新定义替换
+    pub fn define(&mut self, definition: Macro) {
+        self.macros.insert(definition.name.clone(), definition);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Macro> {
+        self.macros.get(name)
+    }
+}
+
+// 展开已经展开了.include的源码中的全部宏。出错的行被丢弃，最后返回所有错误及其所在的行
+pub(crate) fn expand_macros(lines: Vec<SourceLine>) -> PreprocessResult {
+    let mut table = MacroTable::new();
+    let mut output = Vec::new();
+    let mut errors = Vec::new();
+    // 正在定义的宏及其.macro所在的行；.macro行有错时宏为None，宏体被丢弃到.endm为止
+    let mut defining: Option<(SourceLine, Option<Macro>)> = None;
+
+    for source in lines {
+        let (_, line) = split_label(strip_comment(&source.text));
+        let parts = split_operands(line);
+        let mnemonic = parts[0].as_str();
+
+        if let Some((header, mut definition)) = defining.take() {
+            match mnemonic {
+                ".endm" => {
+                    if let Some(definition) = definition {
+                        table.define(definition);
+                    }
+                    continue;
+                }
+                ".macro" => errors.push((source.clone(), LineError::new(ErrorCode::InvalidSyntax, mnemonic, "宏定义中不能嵌套.macro".to_string()))),
+                _ => {
+                    if let Some(definition) = &mut definition {
+                        definition.body.push(source.text.clone());
+                    }
+                }
+            }
+            defining = Some((header, definition));
+            continue;
+        }
+        match mnemonic {
+            ".macro" => {
+                let definition = parse_macro_header(&parts).map_err(|e| errors.push((source.clone(), e))).ok();
+                defining = Some((source.clone(), definition));
+            }
+            ".endm" => errors.push((source.clone(), LineError::new(ErrorCode::InvalidSyntax, mnemonic, ".endm之前没有对应的.macro".to_string()))),
+            _ => expand_line(&table, source.text.clone(), &source, &mut Vec::new(), &mut output, &mut errors),
+        }
+    }
+    if let Some((header, definition)) = defining {
+        let name = definition.map_or(String::new(), |definition| definition.name);
+        errors.push((header, LineError::new(ErrorCode::InvalidSyntax, ".macro", format!("宏 {} 缺少.endm", name))));
+    }
+
+    if errors.is_empty() {
+        Ok(output)
+    } else {
+        Err(errors)
+    }
+}
+
+// .macro NAME p1, p2 ...
+fn parse_macro_header(parts: &[String]) -> Result<Macro, LineError> {
+    let name = parts.get(1)
+        .ok_or_else(|| LineError::new(ErrorCode::MissingOperand, "", ".macro 缺少宏名".to_string()))?;
+    let mut params: Vec<String> = Vec::new();
+    for (i, part) in parts[1..].iter().enumerate() {
+        if !is_identifier(part) {
+            return Err(LineError::new(ErrorCode::InvalidSyntax, part, format!("无效的宏名或参数名: {}", part)));
+        }
+        if params.contains(part) {
+            return Err(LineError::new(ErrorCode::InvalidSyntax, part, format!("宏 {} 的参数 {} 重复", name, part)));
+        }
+        if i > 0 {
+            params.push(part.clone());
+        }
+    }
+    Ok(Macro { name: name.clone(), params, body: Vec::new() })
+}
+
+// 展开一行，宏体中的宏调用递归展开，展开出的行都记在最外层的调用行call_site上。
+// stack是正在展开的宏名，用于发现递归调用
+fn expand_line(
+    table: &MacroTable,
+    text: String,
+    call_site: &SourceLine,
+    stack: &mut Vec<String>,
+    output: &mut Vec<SourceLine>,
+    errors: &mut Vec<(SourceLine, LineError)>,
+) {
+    let (label, line) = split_label(strip_comment(&text));
+    let parts = split_operands(line);
+    let Some(definition) = table.get(&parts[0]) else {
+        output.push(SourceLine { text, ..call_site.clone() });
+        return;
+    };
+    let error = |message| LineError::new(ErrorCode::InvalidSyntax, &definition.name, message);
+    if stack.contains(&definition.name) {
+        errors.push((call_site.clone(), error(format!("宏 {} 递归调用自身", definition.name))));
+        return;
+    }
+    let args: Vec<&str> = parts[1..].iter().map(String::as_str).collect();
+    if args.len() != definition.params.len() {
+        errors.push((call_site.clone(), error(format!("宏 {} 需要{}个参数，实际为{}个", definition.name, definition.params.len(), args.len()))));
+        return;
+    }
+
+    // 调用行上的标签指向展开后的第一条指令
+    if let Some(label) = label {
+        output.push(SourceLine { text: format!("{}:", label), ..call_site.clone() });
+    }
+    stack.push(definition.name.clone());
+    for body_line in definition.expand(&args) {
+        expand_line(table, body_line, call_site, stack, output, errors);
+    }
+    stack.pop();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source_lines(input: &str) -> Vec<SourceLine> {
+        input.lines().enumerate()
+            .map(|(i, text)| SourceLine { file: "<input>".to_string(), line: i + 1, origin: i + 1, text: text.to_string() })
+            .collect()
+    }
+
+    // 展开后每一行的行号和内容
+    fn expand(input: &str) -> Vec<(usize, String)> {
+        expand_macros(source_lines(input)).unwrap().into_iter().map(|source| (source.line, source.text)).collect()
+    }
+
+    // 出错的行号和错误信息
+    fn expand_errors(input: &str) -> Vec<(usize, String)> {
+        expand_macros(source_lines(input)).unwrap_err().into_iter().map(|(source, e)| (source.line, e.message)).collect()
+    }
+
+    #[test]
+    fn test_expand_macros() {
+        let source = "\
+.macro swap_add a, b
+    add a, a, b   # a += b
+    sub b, a, b
+    sub a, a, b
+.endm
+start: swap_add x1, x2
+halt";
+        assert_eq!(expand(source), vec![
+            (6, "start:".to_string()),
+            (6, "    add x1, x1, x2   # x1 += x2".to_string()),
+            (6, "    sub x2, x1, x2".to_string()),
+            (6, "    sub x1, x1, x2".to_string()),
+            (7, "halt".to_string()),
+        ]);
+
+        // 宏体中可以调用之前定义的宏；参数名不替换助记符和字符串中的同名单词
+        let source = ".macro inc r\naddi r, r, 1\n.endm\n.macro inc2 r\ninc r\ninc r\n.ascii \"r\"\n.endm\ninc2 t0";
+        let text: Vec<_> = expand(source).into_iter().map(|(_, text)| text).collect();
+        assert_eq!(text, ["addi t0, t0, 1", "addi t0, t0, 1", ".ascii \"r\""]);
+    }
+
+    #[test]
+    fn test_macro_errors() {
+        assert_eq!(expand_errors(".macro loop\nloop\n.endm\nloop"), [(4, "宏 loop 递归调用自身".to_string())]);
+        assert_eq!(expand_errors(".macro m a, b\nadd a, a, b\n.endm\nm x1\n.endm\n.macro open"), [
+            (4, "宏 m 需要2个参数，实际为1个".to_string()),
+            (5, ".endm之前没有对应的.macro".to_string()),
+            (6, "宏 open 缺少.endm".to_string()),
+        ]);
+        assert_eq!(expand_errors(".macro m a, a\n.endm"), [(1, "宏 m 的参数 a 重复".to_string())]);
+    }
+}
@@ -1,397 +1,513 @@
+use std::collections::HashMap;
 use std::fs;
-use std::io::{self, Read};
+use std::io::{self, BufRead, Read, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
-// 常量定义
-const OPCODE_HALT: u32 = 0b000000;  // halt - 停止执行
-const OPCODE_ADD: u32 = 0b000001;   // add x[rd] = x[rs1] + x[rs2]
-const OPCODE_ADDI: u32 = 0b000010;  // addi x[rd] = x[rs1] + sext(imm)
-const OPCODE_BNE: u32 = 0b000011;   // bne 如果 rs1 != rs2，则 pc += sext(offset)
-const OPCODE_MUL: u32 = 0b000100;   // mul x[rd] = x[rs1] * x[rs2]
-const OPCODE_LUI: u32 = 0b000101;   // lui x[rd] = sext(imm) << 16
-const OPCODE_LW: u32 = 0b000110;    // lw x[rd] = M[x[rs1] + sext(imm)]
-const OPCODE_SW: u32 = 0b000111;    // sw M[x[rs1] + sext(imm)] = x[rs2]
-const OPCODE_BLT: u32 = 0b001000;   // blt 如果 rs1 <s rs2，则 pc += sext(offset)
-const OPCODE_SLLI: u32 = 0b001001;  // slli x[rd] = x[rs1] << imm
-const OPCODE_SUB: u32 = 0b001010;   // sub x[rd] = x[rs1] - x[rs2]
-
-// =================== 汇编器部分 ===================
-
-// A类型指令编码（add/mul）
-// 格式: 前11位0_rs2[5位]_rs1[5位]_rd[5位]_opcode[6位]
-fn encode_a(opcode: u32, rd: u8, rs1: u8, rs2: u8) -> u32 {
-    // 前11位固定为0
-    ((0u32) << 21) | 
-    ((rs2 as u32 & 0x1F) << 16) | 
-    ((rs1 as u32 & 0x1F) << 11) | 
-    ((rd as u32 & 0x1F) << 6) | 
-    (opcode & 0x3F)
-}
-
-// B类型指令编码（addi/lui/lw）
-// 格式: imm[16位]_rs1[5位]_rd[5位]_opcode[6位]
-fn encode_b(opcode: u32, rd: u8, rs1: u8, imm: i16) -> u32 {
-    // 将有符号立即数转为无符号32位整数，保留符号
-    let imm_u32 = (imm as u32) & 0xFFFF;
-    
-    // 构建指令
-    (imm_u32 << 16) |              // 16位立即数放在[31:16]
-    ((rs1 as u32 & 0x1F) << 11) |  // rs1放在[15:11]
-    ((rd as u32 & 0x1F) << 6) |    // rd放在[10:6]
-    (opcode & 0x3F)                // opcode放在[5:0]
-}
-
-// C类型指令编码（bne/sw/blt）
-// 格式: imm_high[31:21] rs1[20:16] rs2[15:11] imm_low[10:6] opcode[5:0]
-fn encode_c(opcode: u32, rs1: u8, rs2: u8, offset: i16) -> u32 {
-    // 处理有符号扩展
-    let offset_u32 = offset as u32;
-    // 提取高11位和低5位
-    let imm_high = (offset_u32 >> 5) & 0x7FF;
-    let imm_low = offset_u32 & 0x1F;
-    
-    (imm_high << 21) |
-    ((rs1 as u32) << 16) |  // rs1放在[20:16]
-    ((rs2 as u32) << 11) |  // rs2放在[15:11]
-    (imm_low << 6) |
-    (opcode & 0x3F)
-}
-
-// 各指令类型编码专用函数
-fn encode_add(rd: u8, rs1: u8, rs2: u8) -> u32 {
-    encode_a(OPCODE_ADD, rd, rs1, rs2)
-}
-
-fn encode_mul(rd: u8, rs1: u8, rs2: u8) -> u32 {
-    encode_a(OPCODE_MUL, rd, rs1, rs2)
-}
-
-fn encode_addi(rd: u8, rs1: u8, imm: i16) -> u32 {
-    encode_b(OPCODE_ADDI, rd, rs1, imm)
-}
-
-fn encode_lui(rd: u8, imm: i16) -> u32 {
-    encode_b(OPCODE_LUI, rd, 0, imm)
-}
-
-fn encode_lw(rd: u8, rs1: u8, offset: i16) -> u32 {
-    encode_b(OPCODE_LW, rd, rs1, offset)
-}
+use riscv_tools::object::{read_object_file_v2, write_object_file_v2};
+use riscv_tools::sim::{
+    parse_trace, run_lockstep, AccessType, BranchTracer, CpuState, MemoryProfile, MemoryRegion, Permission, PrintTracer, SimError, Statistics, Tracer, MEMORY_SIZE,
+};
+use riscv_tools::*;
 
-fn encode_bne(rs1: u8, rs2: u8, offset: i16) -> u32 {
-    encode_c(OPCODE_BNE, rs1, rs2, offset)
+// 命令行的汇编配置：汇编本身的选项加上输出相关的选项
+struct Config {
+    assembler: AssemblerConfig,
+    no_output: bool,   // 只做校验，不写任何文件
+    wasm_out: bool,    // 额外输出WebAssembly文本格式(.wat)
+    hash_output: bool, // 输出文件名附加二进制内容的SHA-256前缀
+    json_errors: bool, // 错误以JSON格式逐行输出，便于编辑器解析
+    suppress_warnings: bool, // 不输出任何警告
+    format: OutputFormat, // 主输出文件的格式
+    readmem_address_every: Option<usize>, // readmemh/readmemb输出每隔N个字插入@地址标记
+    mif_depth: Option<usize>, // MIF输出用0字补齐到的深度（2的幂）
+    array_name: String, // C头文件输出中的数组名
+    big_endian: bool,   // .o文件按大端字节序写出
+    listing: bool,      // 额外输出汇编清单(.lst)
+    source_map: bool,   // 额外输出源码映射(.map)
 }
 
-fn encode_sw(rs1: u8, rs2: u8, offset: i16) -> u32 {
-    encode_c(OPCODE_SW, rs2, rs1, offset)
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            assembler: AssemblerConfig::default(),
+            no_output: false,
+            wasm_out: false,
+            hash_output: false,
+            json_errors: false,
+            suppress_warnings: false,
+            format: OutputFormat::Binary,
+            readmem_address_every: None,
+            mif_depth: None,
+            array_name: "program".to_string(),
+            big_endian: false,
+            listing: false,
+            source_map: false,
+        }
+    }
 }
 
-fn encode_blt(rs1: u8, rs2: u8, offset: i16) -> u32 {
-    encode_c(OPCODE_BLT, rs2, rs1, offset)
+// 默认按小端字节序写出，big_endian时按大端
+fn write_object_file<P: AsRef<Path>>(img: &[u32], path: P, big_endian: bool) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(img.len() * 4);
+    for &word in img {
+        buf.extend(if big_endian { word.to_be_bytes() } else { word.to_le_bytes() });
+    }
+    fs::write(path, buf)
 }
 
-fn encode_slli(rd: u8, rs1: u8, imm: i16) -> u32 {
-    encode_b(OPCODE_SLLI, rd, rs1, imm)
+// 写出符号表，格式见format_symbol_table
+fn write_symbol_table(symbols: &HashMap<String, u32>, path: &str) -> io::Result<()> {
+    fs::write(path, format_symbol_table(symbols))
 }
 
-fn encode_sub(rd: u8, rs1: u8, rs2: u8) -> u32 {
-    encode_a(OPCODE_SUB, rd, rs1, rs2)
+fn read_symbol_table(path: &str) -> io::Result<HashMap<String, u32>> {
+    parse_symbol_table(&fs::read_to_string(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
 }
 
-fn encode_halt() -> u32 {
-    0u32
+// 按配置中的格式写出程序镜像
+fn write_object_file_fmt<P: AsRef<Path>>(img: &[u32], path: P, config: &Config) -> io::Result<()> {
+    match config.format {
+        OutputFormat::Binary => write_object_file(img, path, config.big_endian),
+        OutputFormat::IntelHex => fs::write(path, format_intel_hex(img)),
+        OutputFormat::SRecord => {
+            // S0头记录写入输出文件名
+            let header = path.as_ref().file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+            fs::write(path, format_srecord(img, &header))
+        }
+        OutputFormat::ReadmemH => fs::write(path, format_readmem(img, false, config.readmem_address_every)),
+        OutputFormat::ReadmemB => fs::write(path, format_readmem(img, true, config.readmem_address_every)),
+        OutputFormat::Mif => {
+            let mif = format_mif(img, config.mif_depth).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            fs::write(path, mif)
+        }
+        OutputFormat::CHeader => fs::write(path, format_c_header(img, &config.array_name)),
+        OutputFormat::Object => write_object_file_v2(img, path),
+    }
 }
 
-fn parse_reg(reg: &str) -> u8 {
-    reg[1..].parse().unwrap_or_else(|_| panic!("无效的寄存器: {}", reg))
+// 让link指向最新生成的target（非unix平台直接复制）
+fn link_latest_output(link: &Path, target: &Path) -> io::Result<()> {
+    if link.symlink_metadata().is_ok() {
+        fs::remove_file(link)?;
+    }
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(target.file_name().unwrap(), link)
+    }
+    #[cfg(not(unix))]
+    {
+        fs::copy(target, link).map(|_| ())
+    }
 }
 
-fn parse_imm(imm_str: &str) -> i16 {
-    let imm_str = imm_str.trim();
+// 字节序需与写出时一致，默认小端。.obj文件按带文件头和校验和的格式读取并校验
+fn read_binary_file(file_path: &str, big_endian: bool) -> io::Result<Vec<u32>> {
+    if file_path.ends_with(".obj") {
+        return read_object_file_v2(file_path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
+    }
+    let mut file = fs::File::open(file_path)?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
     
-    // 处理十六进制值
-    if imm_str.starts_with("0x") || imm_str.starts_with("0X") {
-        // 去掉0x前缀
-        let value_str = &imm_str[2..];
-        let value = i32::from_str_radix(value_str, 16).unwrap_or_else(|_| {
-            panic!("无效的十六进制立即数: {}", imm_str);
-        });
-        
-        // 确保值在i16范围内，或者作为u16处理后解释为i16
-        if value > i16::MAX as i32 || value < i16::MIN as i32 {
-            // 超出i16范围，将高16位截断
-            println!("警告: 十六进制值 {} 超出i16范围，将被截断", imm_str);
-            return (value as u16) as i16;
-        }
-        
-        return value as i16;
-    } 
-    // 处理带+前缀的十进制数
-    else if imm_str.starts_with("+") {
-        imm_str[1..].parse().unwrap_or_else(|_| {
-            panic!("无效的十进制立即数: {}", imm_str);
-        })
-    } 
-    // 处理普通十进制数
-    else {
-        imm_str.parse().unwrap_or_else(|_| {
-            panic!("无效的十进制立即数: {}", imm_str);
-        })
-    }
-}
-
-fn assemble(input: &str) -> Vec<u32> {
-    let mut img = Vec::new();
+    if buffer.len() % 4 != 0 {
+        println!("警告：文件大小不是4的倍数，最后的不完整指令将被忽略");
+    }
     
-    for line in input.lines() {
-        let line = line.split('#').next().unwrap().trim();
-        if line.is_empty() { continue; }
+    Ok(words_from_bytes(&buffer, big_endian))
+}
 
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        match parts[0] {
-            "add" => {
-                let rd = parse_reg(parts[1].trim_end_matches(','));
-                let rs1 = parse_reg(parts[2].trim_end_matches(','));
-                let rs2 = parse_reg(parts[3]);
-                img.push(encode_add(rd, rs1, rs2));
-            }
-            "mul" => {
-                let rd = parse_reg(parts[1].trim_end_matches(','));
-                let rs1 = parse_reg(parts[2].trim_end_matches(','));
-                let rs2 = parse_reg(parts[3]);
-                img.push(encode_mul(rd, rs1, rs2));
-            }
-            "addi" => {
-                let rd = parse_reg(parts[1].trim_end_matches(','));
-                let rs1 = parse_reg(parts[2].trim_end_matches(','));
-                let imm = parse_imm(parts[3]);
-                img.push(encode_addi(rd, rs1, imm));
-            }
-            "bne" => {
-                let rs1 = parse_reg(parts[1].trim_end_matches(','));
-                let rs2 = parse_reg(parts[2].trim_end_matches(','));
-                let offset = parse_imm(parts[3]);
-                img.push(encode_bne(rs1, rs2, offset));
-            }
-            "lui" => {
-                let rd = parse_reg(parts[1].trim_end_matches(','));
-                let imm = parse_imm(parts[2]);
-                img.push(encode_lui(rd, imm));
-            }
-            "lw" => {
-                // 处理格式如 lw x1, 4(x2) 的指令
-                let rd = parse_reg(parts[1].trim_end_matches(','));
-                
-                // 解析 4(x2) 格式
-                let offset_reg = parts[2];
-                let open_paren = offset_reg.find('(').unwrap_or_else(|| panic!("无效的lw格式: {}", offset_reg));
-                let close_paren = offset_reg.find(')').unwrap_or_else(|| panic!("无效的lw格式: {}", offset_reg));
-                
-                let offset = parse_imm(&offset_reg[0..open_paren]);
-                let rs1 = parse_reg(&offset_reg[open_paren+1..close_paren]);
-                
-                img.push(encode_lw(rd, rs1, offset));
-            }
-            "sw" => {
-                // 处理格式如 sw x1, 4(x2) 的指令
-                let rs2 = parse_reg(parts[1].trim_end_matches(','));
-                
-                // 解析 4(x2) 格式
-                let offset_reg = parts[2];
-                let open_paren = offset_reg.find('(').unwrap_or_else(|| panic!("无效的sw格式: {}", offset_reg));
-                let close_paren = offset_reg.find(')').unwrap_or_else(|| panic!("无效的sw格式: {}", offset_reg));
-                
-                let offset = parse_imm(&offset_reg[0..open_paren]);
-                let rs1 = parse_reg(&offset_reg[open_paren+1..close_paren]);
-                
-                img.push(encode_sw(rs1, rs2, offset));
-            }
-            "blt" => {
-                let rs1 = parse_reg(parts[1].trim_end_matches(','));
-                let rs2 = parse_reg(parts[2].trim_end_matches(','));
-                let offset = parse_imm(parts[3]);
-                img.push(encode_blt(rs1, rs2, offset));
-            }
-            "slli" => {
-                let rd = parse_reg(parts[1].trim_end_matches(','));
-                let rs1 = parse_reg(parts[2].trim_end_matches(','));
-                let imm = parse_imm(parts[3]);
-                img.push(encode_slli(rd, rs1, imm));
-            }
-            "sub" => {
-                let rd = parse_reg(parts[1].trim_end_matches(','));
-                let rs1 = parse_reg(parts[2].trim_end_matches(','));
-                let rs2 = parse_reg(parts[3]);
-                img.push(encode_sub(rd, rs1, rs2));
-            }
-            "halt" => {
-                img.push(encode_halt());
-            },
-            _ => panic!("未知指令: {}", parts[0]),
+// 逐行打印汇编错误，并转换为io::Error
+fn report_errors(errors: &[AssemblyError], json: bool) -> io::Error {
+    for error in errors {
+        if json {
+            eprintln!("{}", error.to_json());
+        } else {
+            eprintln!("{}", error);
         }
     }
-    img
+    io::Error::new(io::ErrorKind::InvalidData, format!("共 {} 个错误", errors.len()))
 }
 
-fn write_object_file(img: &[u32], path: &str) -> io::Result<()> {
-    let mut buf = Vec::with_capacity(img.len() * 4);
-    for &word in img {
-        buf.extend(word.to_le_bytes());
+// 解析 start,end 形式的地址范围（包含start，不包含end）
+fn parse_data_range(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("无效的地址范围: {}", value);
+    let (start, end) = value.split_once(',').ok_or_else(invalid)?;
+    let parse = |s: &str| parse_int(s.trim()).and_then(|v| u32::try_from(v).ok()).ok_or_else(invalid);
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start >= end {
+        return Err(invalid());
     }
-    fs::write(path, buf)
+    Ok((start, end))
 }
 
-// =================== 反汇编器部分 ===================
-
-// 解码A类型指令（add/mul/sub）
-fn decode_a_type(instr: u32) -> String {
-    let opcode = instr & 0x3F;
-    let rd = (instr >> 6) & 0x1F;
-    let rs1 = (instr >> 11) & 0x1F;
-    let rs2 = (instr >> 16) & 0x1F;
-
-    match opcode {
-        OPCODE_ADD => format!("add x{}, x{}, x{}", rd, rs1, rs2),
-        OPCODE_MUL => format!("mul x{}, x{}, x{}", rd, rs1, rs2),
-        OPCODE_SUB => format!("sub x{}, x{}, x{}", rd, rs1, rs2),
-        _ => format!("未知A型指令: 0x{:08X}", instr),
+// 解析 地址=值 形式的故障注入，地址必须按4字节对齐
+fn parse_fault(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("无效的故障注入: {}（格式为 地址=指令字）", value);
+    let (address, word) = value.split_once('=').ok_or_else(invalid)?;
+    let parse = |s: &str| parse_int(s.trim()).and_then(|v| u32::try_from(v).ok()).ok_or_else(invalid);
+    let (address, word) = (parse(address)?, parse(word)?);
+    if !address.is_multiple_of(4) {
+        return Err(format!("故障注入地址 0x{:X} 未按4字节对齐", address));
     }
+    Ok((address, word))
 }
 
-// 解码B类型指令（addi/lui/lw/slli）
-fn decode_b_type(instr: u32) -> String {
-    let opcode = instr & 0x3F;
-    let rd = (instr >> 6) & 0x1F;
-    let rs1 = (instr >> 11) & 0x1F;
-    let imm = ((instr >> 16) & 0xFFFF) as i16;
-
-    match opcode {
-        OPCODE_ADDI => format!("addi x{}, x{}, {}", rd, rs1, imm),
-        OPCODE_LUI => format!("lui x{}, {}", rd, imm),
-        OPCODE_LW => format!("lw x{}, {}(x{})", rd, imm, rs1),
-        OPCODE_SLLI => format!("slli x{}, x{}, {}", rd, rs1, imm),
-        _ => format!("未知B型指令: 0x{:08X}", instr),
+// 解析 start:end:权限 形式的内存保护区域（包含start，不包含end），权限为RO、RW、WO或NA
+fn parse_mem_protect(value: &str) -> Result<MemoryRegion, String> {
+    let invalid = || format!("无效的内存保护区域: {}（格式为 起始:结束:RO|RW|WO|NA）", value);
+    let parts: Vec<&str> = value.split(':').collect();
+    let [start, end, permission] = parts[..] else { return Err(invalid()) };
+    let parse = |s: &str| parse_int(s.trim()).and_then(|v| u32::try_from(v).ok()).ok_or_else(invalid);
+    let (start, end) = (parse(start)?, parse(end)?);
+    let permission = Permission::from_name(permission.trim()).ok_or_else(invalid)?;
+    if start >= end {
+        return Err(invalid());
     }
+    Ok(MemoryRegion { start, end, permission })
 }
 
-// 解码C类型指令（bne/sw/blt）
-fn decode_c_type(instr: u32) -> String {
-    let opcode = instr & 0x3F;
-    let imm_low = (instr >> 6) & 0x1F;
-    let rs2 = (instr >> 11) & 0x1F;
-    let rs1 = (instr >> 16) & 0x1F;
-    let imm_high = (instr >> 21) & 0x7FF;
-    
-    // 组合立即数
-    let imm = ((imm_high << 5) | imm_low) as i16;
+// 解析disasm子命令的选项，返回配置和位置参数
+fn parse_disasm_args(args: &[String]) -> Result<(DisasmConfig, Vec<String>), String> {
+    let mut config = DisasmConfig::default();
+    let mut files = Vec::new();
 
-    match opcode {
-        OPCODE_BNE => {
-            // bne指令中，rs1在[20:16]，rs2在[15:11]
-            format!("bne x{}, x{}, {}", rs1, rs2, imm)
-        },
-        OPCODE_SW => {
-            // 由于encode_sw交换了rs1和rs2，所以这里也需要交换回来
-            format!("sw x{}, {}(x{})", rs2, imm, rs1)
-        },
-        OPCODE_BLT => {
-            // 由于encode_blt交换了rs1和rs2，所以这里也需要交换回来
-            format!("blt x{}, x{}, {}", rs1, rs2, imm)
-        },
-        _ => format!("未知C型指令: 0x{:08X}", instr),
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--benchmark-decode" => config.benchmark_decode = true,
+            "--annotate-pipeline-hazards" => config.annotate_hazards = true,
+            "--pseudo" => config.pseudo = true,
+            "--abi-names" => config.abi_names = true,
+            "--big-endian" => config.big_endian = true,
+            "--histogram" => config.histogram = true,
+            "--histogram-only" => config.histogram_only = true,
+            "--reg-usage" => config.reg_usage = true,
+            "--sym" => {
+                let value = iter.next().ok_or("--sym 缺少参数")?;
+                config.symbols = read_symbol_table(value).map_err(|e| format!("无法读取符号表 {}: {}", value, e))?;
+            }
+            "--disasm-inline-values" => {
+                let value = iter.next().ok_or("--disasm-inline-values 缺少参数")?;
+                let trace = fs::read_to_string(value).map_err(|e| format!("无法读取跟踪文件 {}: {}", value, e))?;
+                config.inline_values = parse_trace(&trace).map_err(|e| format!("{}: {}", value, e))?;
+            }
+            "--data-range" => {
+                let value = iter.next().ok_or("--data-range 缺少参数")?;
+                config.data_ranges.push(parse_data_range(value)?);
+            }
+            "--bench-iterations" => {
+                let value = iter.next().ok_or("--bench-iterations 缺少参数")?;
+                config.bench_iterations = value.parse().ok().filter(|&n| n > 0)
+                    .ok_or(format!("无效的迭代次数: {}", value))?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("未知选项: {}", arg)),
+            _ => files.push(arg.clone()),
+        }
     }
-}
 
-// 解码halt指令（全0）
-fn decode_halt(instr: u32) -> String {
-    if instr == 0 {
-        "halt".to_string()
-    } else {
-        format!("未知指令: 0x{:08X}", instr)
-    }
+    Ok((config, files))
 }
 
-// 根据操作码类型解码指令
-fn decode_instruction(instr: u32) -> String {
-    let opcode = instr & 0x3F;
-    
-    match opcode {
-        OPCODE_HALT => decode_halt(instr),
-        OPCODE_ADD | OPCODE_MUL | OPCODE_SUB => decode_a_type(instr),
-        OPCODE_ADDI | OPCODE_LUI | OPCODE_LW | OPCODE_SLLI => decode_b_type(instr),
-        OPCODE_BNE | OPCODE_SW | OPCODE_BLT => decode_c_type(instr),
-        _ => format!("未知指令: 0x{:08X}", instr),
+// 将目录下每个NAME.asm汇编后按小端字节序写入NAME.bin（与默认的.o输出相同），返回更新的文件数
+fn update_golden(dir: &Path) -> io::Result<usize> {
+    let mut entries: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "asm"))
+        .collect();
+    entries.sort();
+
+    for path in &entries {
+        let source = fs::read_to_string(path)?;
+        let bin_path = path.with_extension("bin");
+        println!("更新黄金文件: {}", bin_path.display());
+        let img = assemble(&source).map_err(|errors| report_errors(&errors, false))?;
+        write_object_file(&img, bin_path, false)?;
     }
+    Ok(entries.len())
 }
 
-fn read_binary_file(file_path: &str) -> io::Result<Vec<u32>> {
-    let mut file = fs::File::open(file_path)?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
-    
-    if buffer.len() % 4 != 0 {
-        println!("警告：文件大小不是4的倍数，最后的不完整指令将被忽略");
-    }
-    
-    let mut instructions = Vec::new();
-    let mut i = 0;
-    
-    while i + 3 < buffer.len() {
-        let instr = u32::from_le_bytes([buffer[i], buffer[i+1], buffer[i+2], buffer[i+3]]);
-        instructions.push(instr);
-        i += 4;
-    }
-    
-    Ok(instructions)
+// 命令行参数有误：错误信息写到stderr，显示用法后以状态2退出
+fn usage_error(program: &str, message: &str) -> ! {
+    eprintln!("错误: {}", message);
+    show_usage(program);
+    std::process::exit(2);
 }
 
 fn show_usage(program: &str) {
     println!("RISC-V简易汇编器和反汇编器 - 使用方法:");
     println!("  汇编功能:");
-    println!("    {} asm <汇编文件名> - 将asm/文件名.asm编译为二进制，输出到out/文件名.o", program);
+    println!("    {} asm [选项] <汇编文件名> - 将asm/文件名.asm编译为二进制，输出到out/文件名.o", program);
     println!("    例如: {} asm sum - 编译asm/sum.asm，输出到out/sum.o", program);
+    println!("    选项:");
+    println!("      --max-imm-bits N  限制立即数为N位有符号数（N<=16，默认16；移位量最多5位）");
+    println!("      --check-abi       检查s0~s11是否先保存再写入、a0~a7是否在读取前被覆盖");
+    println!("      --no-output       只校验汇编源码，不写出任何文件");
+    println!("      --wasm-out        额外输出out/文件名.wat，可用wat2wasm编译");
+    println!("      --compat-riscv    接受RV32I助记符，本指令集无等价编码的指令报错");
+    println!("      --hash-output     输出为out/文件名_<8位哈希>.o，并创建out/文件名.o链接");
+    println!("      --Werror          将警告（如写入x0）视为错误");
+    println!("      --json-errors     错误以JSON格式输出，每行一个");
+    println!("      --suppress-warnings  不输出警告（--Werror也不再生效）");
+    println!("      --no-warn-dead-code  不警告halt之后不可达的指令");
+    println!("      --format FMT      输出格式: bin（默认，out/文件名.o）、ihex（Intel HEX，out/文件名.hex）、");
+    println!("                        srec（Motorola S-record，out/文件名.srec）、");
+    println!("                        readmemh/readmemb（Verilog $readmemh/$readmemb，out/文件名.memh/.memb）、");
+    println!("                        mif（Quartus存储器初始化文件，out/文件名.mif）、c（C头文件，out/文件名.h）、");
+    println!("                        obj（带文件头和CRC32校验和的目标文件，out/文件名.obj）");
+    println!("      --readmem-address-every N  readmemh/readmemb输出每N个字插入一行@地址");
+    println!("      --mif-depth N     MIF输出用0补齐到N个字（N为2的幂）");
+    println!("      --array-name NAME C头文件中的数组名（默认program）");
+    println!("      --big-endian      .o文件按大端字节序写出（默认小端）");
+    println!("      --listing         额外输出out/文件名.lst: 地址、十六进制、分组二进制和源码");
+    println!("      --source-map      额外输出out/文件名.map: 字下标到源码行号的JSON映射");
+    println!("      --define NAME=VALUE  预先定义常量，可在.if/.ifdef中使用（可重复；只写NAME时值为1）");
     println!();
     println!("  反汇编功能:");
     println!("    {} disasm <二进制文件> <输出文件> - 将二进制文件反汇编为汇编代码", program);
     println!("    例如: {} disasm out/sum.o out/sum_disasm.asm", program);
+    println!("    选项:");
+    println!("      --annotate-pipeline-hazards  用[HAZARD]标注RAW、load-use和mul结果冒险");
+    println!("      --pseudo                     将addi rd, rs, 0显示为mov rd, rs，nor rd, rs, x0显示为not rd, rs");
+    println!("      --abi-names                  用sp、ra、a0等寄存器名代替x0~x31");
+    println!("      --data-range start,end       将[start, end)地址按数据输出（.space的0字会被解码为halt）");
+    println!("      --big-endian                 按大端字节序读取二进制文件");
+    println!("      --sym NAME.sym               读取符号表（asm生成的out/文件名.sym），跳转目标显示为标签名");
+    println!("      --histogram                  在输出末尾附上各助记符的出现次数，按次数从多到少排列");
+    println!("      --histogram-only             只输出助记符出现次数，不输出逐条反汇编");
+    println!("      --reg-usage                  在输出末尾附上各寄存器的读写次数，并警告写入后从未被读取的寄存器");
+    println!("      --disasm-inline-values TRACE 用sim --trace的输出在每条指令后标注运行时写入的寄存器值");
+    println!("    {} disasm --benchmark-decode [--bench-iterations N] <二进制文件> - 按A/B/C类型测量解码吞吐量", program);
+    println!();
+    println!("  模拟功能:");
+    println!("    {} sim [选项] <二进制文件> - 从地址0开始执行到halt，打印寄存器（64 KiB内存）", program);
+    println!("    选项:");
+    println!("      --trace            每条指令执行后输出 PC、编码、指令和写入的寄存器值");
+    println!("      --trace-file PATH  跟踪输出写入PATH（隐含--trace）");
+    println!("      --source-map PATH  按asm --source-map生成的映射在跟踪输出中附上源码行号（隐含--trace）");
+    println!("      --step             单步执行：每条指令前显示寄存器和下一条指令并等待命令");
+    println!("                         （s或回车 单步, c 继续, r N 查看寄存器, m 地址 长度 查看内存, q 退出）");
+    println!("      --save-state PATH  收到SIGINT时把寄存器、pc、周期数和内存写入检查点文件PATH后停止");
+    println!("      --checkpoint-interval N  另外每执行N条指令写一次检查点（需要--save-state）");
+    println!("      --load-state PATH  从检查点PATH恢复并继续执行，代替二进制文件参数");
+    println!("      --inject-fault ADDR=WORD  执行到地址ADDR之前把那里的指令字替换为WORD，模拟单粒子翻转（可重复）");
+    println!("      --detect-stack-overflow  sp低于栈底减栈大小时报告STACK OVERFLOW并停止，sp高于栈底时警告");
+    println!("      --stack-base ADDR  栈底地址（默认0x10000，即内存末尾）");
+    println!("      --stack-size N     栈大小（字节，默认0x8000）");
+    println!("      --branch-trace     每条跳转了的条件分支输出一行到标准错误: PC、指令、目标地址");
+    println!("      --branch-trace-file PATH  分支跟踪写入PATH（隐含--branch-trace）");
+    println!("      --cycle-count      执行完成后输出周期数，分支跟踪中附上周期");
+    println!("      --mem-protect START:END:PERM  访存指令访问[START, END)时按权限检查，PERM为RO、RW、WO或NA，");
+    println!("                         违反时停止（可重复，重叠时后面的优先）；指定后程序所在区域默认RO");
+    println!("      --statistics-file PATH  执行结束后写入JSON统计: 指令条数、周期数、寄存器、内存CRC-32、");
+    println!("                         各助记符的执行次数、分支跳转/不跳转次数和耗时");
+    println!("      --watch-register REG  REG的值改变时输出指令和新旧值并进入单步模式（可重复）");
+    println!("      --watch-value REG=VALUE  同上，但只在REG变为VALUE时停下");
+    println!("      --watch-memory ADDR  访存指令读写字节地址ADDR时输出指令、地址和值并进入单步模式（可重复）");
+    println!("      --watch-memory-read  只在读取时停下");
+    println!("      --watch-memory-write 只在写入时停下");
+    println!("      --profile-memory   执行结束后按访问次数列出访存地址: addr=0x0010  reads=150  writes=3");
+    println!("      --profile-memory-chart  同上，并在每行末尾附上条形图（宽度按COLUMNS环境变量，默认80列）");
+    println!("      --compare OTHER    与OTHER同步执行，每步比较寄存器和写入的内存，报告第一处差异（有差异时退出码为1）");
+    println!();
+    println!("  其他:");
+    println!("    {} --dump-tables - 按指令类型打印操作码表", program);
+    println!("    {} explain-imm <助记符> - 显示该指令立即数的有效范围和位字段", program);
+    println!("    {} --update-golden [目录] - 重新生成黄金测试文件（默认tests/golden）", program);
+    println!("    {} compress <输入.o> <输出> - 对二进制文件做游程压缩", program);
+    println!("    {} decompress <输入> <输出.o> - 还原游程压缩的二进制文件", program);
 }
 
-fn run_assembler(base_name: &str) -> io::Result<()> {
-    let input_file = format!("asm/{}.asm", base_name);
-    let output_binary = format!("out/{}.o", base_name);
-    let output_text = format!("out/{}.txt", base_name);
-    
-    fs::create_dir_all("out")?;
+// 解析asm子命令的选项和文件名
+fn parse_asm_args(args: &[String]) -> Result<(Config, String), String> {
+    let mut config = Config::default();
+    let mut base_name = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--max-imm-bits" => {
+                let value = iter.next().ok_or("--max-imm-bits 缺少参数")?;
+                let bits: u32 = value.parse().map_err(|_| format!("无效的位宽: {}", value))?;
+                if bits == 0 || bits > 16 {
+                    return Err(format!("位宽必须在1~16之间: {}", bits));
+                }
+                config.assembler.max_imm_bits = bits;
+            }
+            "--check-abi" => config.assembler.check_abi = true,
+            "--no-output" => config.no_output = true,
+            "--wasm-out" => config.wasm_out = true,
+            "--compat-riscv" => config.assembler.compat_riscv = true,
+            "--hash-output" => config.hash_output = true,
+            "--Werror" => config.assembler.warnings_as_errors = true,
+            "--no-warn-dead-code" => config.assembler.warn_dead_code = false,
+            "--json-errors" => config.json_errors = true,
+            "--suppress-warnings" => config.suppress_warnings = true,
+            "--big-endian" => config.big_endian = true,
+            "--listing" => config.listing = true,
+            "--source-map" => config.source_map = true,
+            "--format" => {
+                let value = iter.next().ok_or("--format 缺少参数")?;
+                config.format = OutputFormat::parse(value)?;
+            }
+            "--define" => {
+                let value = iter.next().ok_or("--define 缺少参数")?;
+                let (name, value) = parse_define(value, &config.assembler.defines)?;
+                config.assembler.defines.insert(name, value);
+            }
+            "--readmem-address-every" => {
+                let value = iter.next().ok_or("--readmem-address-every 缺少参数")?;
+                let every = value.parse().ok().filter(|&n: &usize| n > 0)
+                    .ok_or_else(|| format!("无效的地址标记间隔: {}", value))?;
+                config.readmem_address_every = Some(every);
+            }
+            "--mif-depth" => {
+                let value = iter.next().ok_or("--mif-depth 缺少参数")?;
+                let depth = value.parse().ok().filter(|&n: &usize| n.is_power_of_two())
+                    .ok_or_else(|| format!("MIF深度必须是2的幂: {}", value))?;
+                config.mif_depth = Some(depth);
+            }
+            "--array-name" => {
+                let value = iter.next().ok_or("--array-name 缺少参数")?;
+                if !is_identifier(value) {
+                    return Err(format!("数组名不是合法的C标识符: {}", value));
+                }
+                config.array_name = value.clone();
+            }
+            _ if arg.starts_with("--") => return Err(format!("未知选项: {}", arg)),
+            _ => base_name = Some(arg.clone()),
+        }
+    }
+
+    let base_name = base_name.ok_or("缺少汇编文件名参数")?;
+    Ok((config, base_name))
+}
+
+// --define NAME=VALUE，VALUE可以是常量表达式并引用之前定义的常量；只写NAME时值为1
+fn parse_define(arg: &str, defines: &HashMap<String, i32>) -> Result<(String, i32), String> {
+    let (name, value) = arg.split_once('=').unwrap_or((arg, "1"));
+    if !is_identifier(name) {
+        return Err(format!("无效的常量名: {}", name));
+    }
+    let value = evaluate_expr(value, defines).map_err(|e| format!("无效的常量值 {}: {}", value, e))?;
+    Ok((name.to_string(), value))
+}
+
+fn run_assembler(base_name: &str, config: &Config) -> io::Result<()> {
+    run_assembler_in(Path::new("."), base_name, config)
+}
+
+// 在root目录下读取asm/NAME.asm并输出到out/
+fn run_assembler_in(root: &Path, base_name: &str, config: &Config) -> io::Result<()> {
+    let input_file = root.join(format!("asm/{}.asm", base_name));
+    let output_dir = root.join("out");
     
-    println!("读取汇编文件: {}", input_file);
+    println!("读取汇编文件: {}", input_file.display());
     let asm_code = fs::read_to_string(&input_file)?;
     
     println!("汇编代码...");
-    let img = assemble(&asm_code);
-    
-    let mut text_output = String::new();
-    for &instr in &img {
-        let binary_str = format!("{:032b}", instr);
-        let formatted_binary = format!("0b{}_{}_{}_{}_{}", 
-            &binary_str[0..11], 
-            &binary_str[11..16], 
-            &binary_str[16..21], 
-            &binary_str[21..26], 
-            &binary_str[26..32]);
-        text_output.push_str(&format!("{}\n", formatted_binary));
+    // .include的相对路径从源文件所在的目录查找
+    let assembler_config = AssemblerConfig { include_dir: root.join("asm"), ..config.assembler.clone() };
+    let mut assembler = Assembler::new(&assembler_config);
+    assembler.file = format!("asm/{}.asm", base_name);
+    assembler.run(&asm_code).map_err(|errors| report_errors(&errors, config.json_errors))?;
+    let img = assembler.img;
+
+    let mut warning_count = 0;
+    if !config.suppress_warnings {
+        warning_count += assembler.warnings.len();
+        for warning in &assembler.warnings {
+            eprintln!("warning: {}", warning);
+        }
+    }
+    if config.assembler.warnings_as_errors && warning_count > 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("--Werror: 共 {} 个警告", warning_count)));
     }
     
-    println!("写入二进制文件: {}", output_binary);
-    write_object_file(&img, &output_binary)?;
-    
-    // println!("写入文本格式文件: {}", output_text);
-    // fs::write(&output_text, text_output)?;
+    if config.no_output {
+        println!("校验通过，共 {} 条指令（未写出文件）", img.len());
+        return Ok(());
+    }
+
+    fs::create_dir_all(&output_dir)?;
+
+    // 所有输出文件共用同一个文件名主干
+    let output_stem = if config.hash_output {
+        format!("{}_{}", base_name, binary_hash(&img))
+    } else {
+        base_name.to_string()
+    };
+    let extension = config.format.extension();
+    let output_binary = output_dir.join(format!("{}.{}", output_stem, extension));
+
+    println!("写入输出文件: {}", output_binary.display());
+    write_object_file_fmt(&img, &output_binary, config)?;
+
+    if config.hash_output {
+        let link = output_dir.join(format!("{}.{}", base_name, extension));
+        println!("创建链接: {} -> {}", link.display(), output_binary.display());
+        link_latest_output(&link, &output_binary)?;
+    }
     
+    if config.wasm_out {
+        let output_wat = output_dir.join(format!("{}.wat", output_stem));
+        println!("写入WebAssembly文本文件: {}", output_wat.display());
+        fs::write(&output_wat, generate_wat(&img))?;
+    }
+
+    // 有标签时总是输出符号表
+    if !assembler.labels.is_empty() {
+        let output_sym = output_dir.join(format!("{}.sym", output_stem));
+        println!("写入符号表: {}", output_sym.display());
+        write_symbol_table(&assembler.labels, &output_sym.to_string_lossy())?;
+    }
+
+    if config.listing {
+        let output_listing = output_dir.join(format!("{}.lst", output_stem));
+        println!("写入汇编清单: {}", output_listing.display());
+        fs::write(&output_listing, generate_listing(&asm_code, &img, &assembler.labels))?;
+    }
+
+    if config.source_map {
+        let output_map = output_dir.join(format!("{}.map", output_stem));
+        println!("写入源码映射: {}", output_map.display());
+        fs::write(&output_map, format_source_map(&generate_source_map(&asm_code)))?;
+    }
+
     println!("汇编成功完成，共生成 {} 条指令", img.len());
     Ok(())
 }
 
-fn run_disassembler(input_file: &str, output_file: &str) -> io::Result<()> {
+fn run_compress(input_file: &str, output_file: &str) -> io::Result<()> {
+    let words = read_binary_file(input_file, false)?;
+    let compressed = compress_words(&words);
+    write_object_file(&compressed, output_file, false)?;
+    println!("压缩完成: {} 字 -> {} 字", words.len(), compressed.len());
+    Ok(())
+}
+
+fn run_decompress(input_file: &str, output_file: &str) -> io::Result<()> {
+    let data = read_binary_file(input_file, false)?;
+    let words = decompress_words(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_object_file(&words, output_file, false)?;
+    println!("解压完成: {} 字 -> {} 字", data.len(), words.len());
+    Ok(())
+}
+
+fn run_decode_benchmark(input_file: &str, config: &DisasmConfig) -> io::Result<()> {
+    println!("读取二进制文件: {}", input_file);
+    let instructions = read_binary_file(input_file, config.big_endian)?;
+
+    println!("解码性能测试，每类指令重复 {} 轮...", config.bench_iterations);
+    for result in benchmark_decode(&instructions, config.bench_iterations) {
+        println!("  {}  {} 条  {:.2} ± {:.2} MIPS",
+            result.itype.name(), result.count, result.mean_mips, result.stddev_mips);
+    }
+    Ok(())
+}
+
+fn run_disassembler(input_file: &str, output_file: &str, config: &DisasmConfig) -> io::Result<()> {
 
     if let Some(parent) = Path::new(output_file).parent() {
         if !parent.exists() {
@@ -400,18 +516,14 @@ fn run_disassembler(input_file: &str, output_file: &str) -> io::Result<()> {
     }
     
     println!("读取二进制文件: {}", input_file);
-    let instructions = read_binary_file(input_file)?;
+    let instructions = read_binary_file(input_file, config.big_endian)?;
     
     println!("反汇编指令...");
-    let mut output = String::new();
-    
-    output.push_str("# 反汇编结果\n");
-    output.push_str("# 格式: [地址] [十六进制表示] [汇编指令]\n\n");
-    
-    for (i, &instr) in instructions.iter().enumerate() {
-        let disasm = decode_instruction(instr);
-        let line = format!("{:04X}:  {:08X}  {}\n", i * 4, instr, disasm);
-        output.push_str(&line);
+    let output = disassemble_listing(&instructions, config);
+    if config.reg_usage {
+        for reg in dead_writes(&register_usage(&instructions)) {
+            eprintln!("警告: x{} 被写入后从未被读取", reg);
+        }
     }
     
     println!("写入汇编文件: {}", output_file);
@@ -421,6 +533,497 @@ fn run_disassembler(input_file: &str, output_file: &str) -> io::Result<()> {
     Ok(())
 }
 
+// 模拟配置
+struct SimConfig {
+    trace: bool,                // 逐条输出执行的指令和写入的寄存器
+    trace_file: Option<String>, // 跟踪输出写入该文件而不是标准输出
+    step: bool,                 // 交互式单步执行
+    source_map: Option<String>, // 跟踪输出中按该源码映射附上源码行号
+    save_state: Option<String>, // 检查点文件
+    checkpoint_interval: u32,   // 每执行这么多条指令写一次检查点，0表示只在收到SIGINT时写
+    load_state: bool,           // 输入文件是检查点而不是二进制文件
+    compare: Option<String>,    // 与该二进制文件同步执行并比较状态
+    faults: Vec<(u32, u32)>,    // 执行到该地址之前把那里的指令字替换为给定的值
+    detect_stack_overflow: bool,
+    stack_base: u32,            // 栈底（初始sp），栈向低地址增长
+    stack_size: u32,
+    branch_trace: bool,                // 记录跳转了的条件分支
+    branch_trace_file: Option<String>, // 分支跟踪写入该文件而不是标准错误
+    cycle_count: bool,                 // 输出周期数，分支跟踪中附上周期
+    mem_protect: Vec<MemoryRegion>,    // 访存权限，指定后程序本身所在的区域默认只读
+    statistics_file: Option<String>,   // 执行结束后把统计信息以JSON写入该文件
+    watch_registers: Vec<(usize, Option<i32>)>, // 寄存器的值改变（且等于给定值）时进入单步模式
+    watch_memory: Vec<u32>,            // 访存指令访问这些字节地址时进入单步模式
+    watch_memory_read: bool,           // 只监视读取（与watch_memory_write都没有指定时读写都监视）
+    watch_memory_write: bool,          // 只监视写入
+    profile_memory: bool,              // 执行结束后按访问次数列出访存地址
+    profile_memory_chart: bool,        // 同时输出条形图
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            trace: false,
+            trace_file: None,
+            step: false,
+            source_map: None,
+            save_state: None,
+            checkpoint_interval: 0,
+            load_state: false,
+            compare: None,
+            faults: Vec::new(),
+            detect_stack_overflow: false,
+            // 默认栈占内存的后一半
+            stack_base: MEMORY_SIZE as u32,
+            stack_size: MEMORY_SIZE as u32 / 2,
+            branch_trace: false,
+            branch_trace_file: None,
+            cycle_count: false,
+            mem_protect: Vec::new(),
+            statistics_file: None,
+            watch_registers: Vec::new(),
+            watch_memory: Vec::new(),
+            watch_memory_read: false,
+            watch_memory_write: false,
+            profile_memory: false,
+            profile_memory_chart: false,
+        }
+    }
+}
+
+// 寄存器的编号，x0..x31（可以省略x）或ABI名称
+fn parse_register_name(name: &str) -> Option<usize> {
+    name.strip_prefix('x').unwrap_or(name).parse::<usize>().ok()
+        .or_else(|| ABI_NAMES.iter().position(|abi_name| *abi_name == name))
+        .filter(|&index| index < 32)
+}
+
+// 添加寄存器监视，同一寄存器再次指定时替换原来的值条件
+fn add_register_watch(watches: &mut Vec<(usize, Option<i32>)>, register: usize, value: Option<i32>) {
+    watches.retain(|&(watched, _)| watched != register);
+    watches.push((register, value));
+}
+
+// 解析sim子命令的选项和文件名
+fn parse_sim_args(args: &[String]) -> Result<(SimConfig, String), String> {
+    let mut config = SimConfig::default();
+    let mut input_file = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--trace" => config.trace = true,
+            "--step" => config.step = true,
+            "--trace-file" => {
+                let value = iter.next().ok_or("--trace-file 缺少参数")?;
+                config.trace = true;
+                config.trace_file = Some(value.clone());
+            }
+            "--source-map" => {
+                let value = iter.next().ok_or("--source-map 缺少参数")?;
+                config.trace = true;
+                config.source_map = Some(value.clone());
+            }
+            "--save-state" => {
+                let value = iter.next().ok_or("--save-state 缺少参数")?;
+                config.save_state = Some(value.clone());
+            }
+            "--checkpoint-interval" => {
+                let value = iter.next().ok_or("--checkpoint-interval 缺少参数")?;
+                config.checkpoint_interval = value.parse().ok().filter(|&n| n > 0)
+                    .ok_or_else(|| format!("无效的检查点间隔: {}", value))?;
+            }
+            "--inject-fault" => {
+                let value = iter.next().ok_or("--inject-fault 缺少参数")?;
+                config.faults.push(parse_fault(value)?);
+            }
+            "--detect-stack-overflow" => config.detect_stack_overflow = true,
+            "--branch-trace" => config.branch_trace = true,
+            "--branch-trace-file" => {
+                let value = iter.next().ok_or("--branch-trace-file 缺少参数")?;
+                config.branch_trace = true;
+                config.branch_trace_file = Some(value.clone());
+            }
+            "--cycle-count" => config.cycle_count = true,
+            "--statistics-file" => {
+                let value = iter.next().ok_or("--statistics-file 缺少参数")?;
+                config.statistics_file = Some(value.clone());
+            }
+            "--watch-register" => {
+                let value = iter.next().ok_or("--watch-register 缺少参数")?;
+                let register = parse_register_name(value).ok_or_else(|| format!("无效的寄存器: {}", value))?;
+                add_register_watch(&mut config.watch_registers, register, None);
+            }
+            "--watch-value" => {
+                let value = iter.next().ok_or("--watch-value 缺少参数")?;
+                let invalid = || format!("无效的监视条件: {}（格式为 寄存器=值）", value);
+                let (register, expected) = value.split_once('=').ok_or_else(invalid)?;
+                let register = parse_register_name(register.trim()).ok_or_else(invalid)?;
+                // 按32位解释，0xFFFFFFFF与-1相同
+                let expected = parse_int(expected.trim()).filter(|v| (i32::MIN as i64..=u32::MAX as i64).contains(v)).ok_or_else(invalid)?;
+                add_register_watch(&mut config.watch_registers, register, Some(expected as i32));
+            }
+            "--watch-memory" => {
+                let value = iter.next().ok_or("--watch-memory 缺少参数")?;
+                let address = parse_int(value).and_then(|v| u32::try_from(v).ok())
+                    .ok_or_else(|| format!("无效的地址: {}", value))?;
+                config.watch_memory.push(address);
+            }
+            "--watch-memory-read" => config.watch_memory_read = true,
+            "--profile-memory" => config.profile_memory = true,
+            "--profile-memory-chart" => {
+                config.profile_memory = true;
+                config.profile_memory_chart = true;
+            }
+            "--watch-memory-write" => config.watch_memory_write = true,
+            "--mem-protect" => {
+                let value = iter.next().ok_or("--mem-protect 缺少参数")?;
+                config.mem_protect.push(parse_mem_protect(value)?);
+            }
+            "--stack-base" | "--stack-size" => {
+                let value = iter.next().ok_or_else(|| format!("{} 缺少参数", arg))?;
+                let value = parse_int(value).and_then(|v| u32::try_from(v).ok())
+                    .ok_or_else(|| format!("{} 的参数无效: {}", arg, value))?;
+                if arg == "--stack-base" {
+                    config.stack_base = value;
+                } else {
+                    config.stack_size = value;
+                }
+            }
+            "--compare" => {
+                let value = iter.next().ok_or("--compare 缺少参数")?;
+                config.compare = Some(value.clone());
+            }
+            "--load-state" => {
+                let value = iter.next().ok_or("--load-state 缺少参数")?;
+                if input_file.replace(value.clone()).is_some() {
+                    return Err("--load-state 不能与二进制文件同时指定".to_string());
+                }
+                config.load_state = true;
+            }
+            _ if arg.starts_with("--") => return Err(format!("未知选项: {}", arg)),
+            _ if config.load_state => return Err("--load-state 不能与二进制文件同时指定".to_string()),
+            _ => input_file = Some(arg.clone()),
+        }
+    }
+
+    if (config.watch_memory_read || config.watch_memory_write) && config.watch_memory.is_empty() {
+        return Err("--watch-memory-read/--watch-memory-write 需要与 --watch-memory 一起使用".to_string());
+    }
+    if config.stack_size > config.stack_base {
+        return Err(format!("栈大小 0x{:X} 超过栈底地址 0x{:X}", config.stack_size, config.stack_base));
+    }
+    if config.compare.is_some() && config.load_state {
+        return Err("--compare 不能与 --load-state 同时使用".to_string());
+    }
+    if config.checkpoint_interval > 0 && config.save_state.is_none() {
+        return Err("--checkpoint-interval 需要与 --save-state 一起使用".to_string());
+    }
+    let input_file = input_file.ok_or("缺少二进制文件参数")?;
+    Ok((config, input_file))
+}
+
+fn sim_error(e: SimError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+// 收到SIGINT后在下一条指令前停下
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// 标准库没有信号处理接口，直接调用libc的signal；只在需要写检查点时安装
+#[cfg(unix)]
+fn install_interrupt_handler() {
+    extern "C" fn on_interrupt(_signum: i32) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+    const SIGINT: i32 = 2;
+    unsafe {
+        signal(SIGINT, on_interrupt);
+    }
+}
+
+#[cfg(not(unix))]
+fn install_interrupt_handler() {}
+
+fn write_checkpoint(cpu: &CpuState, path: &str) -> io::Result<()> {
+    let mut out = io::BufWriter::new(fs::File::create(path)?);
+    cpu.save_state(&mut out)?;
+    out.flush()
+}
+
+// 模拟过程中随选项打开的附加功能
+#[derive(Default)]
+struct SimSession {
+    stepping: bool,                              // 每条指令执行前等待命令
+    tracer: Option<PrintTracer<Box<dyn Write>>>, // 逐条跟踪输出
+    branch_tracer: Option<BranchTracer<Box<dyn Write>>>,
+    statistics: Option<Statistics>,
+    memory_profile: Option<MemoryProfile>,
+    watch_registers: Vec<(usize, Option<i32>)>,  // 见SimConfig
+    watch_memory: Vec<u32>,                      // 见SimConfig
+    watch_access: Option<AccessType>,            // 只监视这种访问，None时读写都监视
+    save_state: Option<String>,                  // 检查点文件
+    checkpoint_interval: u32,                    // 见SimConfig
+    faults: Vec<(u32, u32)>,                     // 尚未注入的故障，每个只注入一次
+    stack: Option<(u32, u32)>,                   // 检查sp时的栈底和栈大小
+    sp_above_base: bool,                         // sp当前在栈底之上，已经警告过
+}
+
+// 单步模式下读入的命令
+enum StepCommand {
+    Step,
+    Continue,
+    Quit,
+}
+
+// 读入命令直到s、c或q，r和m命令在这里直接处理；输入结束视为q
+fn read_step_command<W: Write>(cpu: &CpuState, lines: &mut impl Iterator<Item = io::Result<String>>, out: &mut W) -> io::Result<StepCommand> {
+    loop {
+        write!(out, "> ")?;
+        out.flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            return Ok(StepCommand::Quit);
+        };
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            [] | ["s"] => return Ok(StepCommand::Step),
+            ["c"] => return Ok(StepCommand::Continue),
+            ["q"] => return Ok(StepCommand::Quit),
+            ["r", reg] => {
+                match parse_register_name(reg) {
+                    Some(i) => writeln!(out, "x{} ({}) = {} (0x{:08X})", i, ABI_NAMES[i], cpu.regs[i], cpu.regs[i])?,
+                    None => writeln!(out, "无效的寄存器: {}", reg)?,
+                }
+            }
+            ["m", address, len] => {
+                let range = parse_int(address).zip(parse_int(len))
+                    .and_then(|(start, len)| Some(usize::try_from(start).ok()?..usize::try_from(start + len).ok()?));
+                match range.filter(|range| range.end <= cpu.memory.len()) {
+                    Some(range) => {
+                        for (i, chunk) in cpu.memory[range.clone()].chunks(16).enumerate() {
+                            let bytes: Vec<String> = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+                            writeln!(out, "0x{:04X}: {}", range.start + i * 16, bytes.join(" "))?;
+                        }
+                    }
+                    None => writeln!(out, "无效的内存范围: {} {}", address, len)?,
+                }
+            }
+            _ => writeln!(out, "命令: s(或回车) 单步, c 继续, r N 查看寄存器, m 地址 长度 查看内存, q 退出")?,
+        }
+    }
+}
+
+// 执行到halt。单步模式下每条指令执行前输出寄存器和下一条指令，然后读入一条命令:
+//   s或空行 执行一条指令      c 不再停顿，执行到halt
+//   r N     查看寄存器N       m 地址 长度  按十六进制查看内存
+//   q       退出
+// 返回执行的指令条数，以及是否执行到了halt（q、输入结束或收到SIGINT时为false）
+fn run_session<R: BufRead, W: Write>(cpu: &mut CpuState, session: &mut SimSession, input: R, out: &mut W) -> io::Result<(usize, bool)> {
+    let mut count = 0;
+    let mut lines = input.lines();
+    loop {
+        if let Some(index) = session.faults.iter().position(|&(address, _)| address as usize == cpu.pc) {
+            let (address, word) = session.faults.remove(index);
+            let original = cpu.store_word(address, word).map_err(sim_error)?;
+            let describe = |word| Instruction::decode(word).map_or_else(|| "(非法指令)".to_string(), |instruction| instruction.to_string());
+            writeln!(out, "注入故障: PC=0x{:04X}  {:08X} {} -> {:08X} {}", address, original, describe(original), word, describe(word))?;
+        }
+        let word = cpu.fetch().map_err(sim_error)?;
+        let text = || Instruction::decode(word).map_or_else(|| "(非法指令)".to_string(), |instruction| instruction.to_string());
+        if session.stepping {
+            write!(out, "{}", cpu.format_registers())?;
+            writeln!(out, "下一条指令: PC=0x{:04X}  {:08X}  {}", cpu.pc, word, text())?;
+            match read_step_command(cpu, &mut lines, out)? {
+                StepCommand::Step => {}
+                StepCommand::Continue => session.stepping = false,
+                StepCommand::Quit => return Ok((count, false)),
+            }
+        }
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            if let Some(path) = &session.save_state {
+                write_checkpoint(cpu, path)?;
+                writeln!(out, "收到中断，检查点已写入 {}", path)?;
+            }
+            return Ok((count, false));
+        }
+
+        let (pc, regs) = (cpu.pc, cpu.regs);
+        let running = cpu.step().map_err(sim_error)?;
+        count += 1;
+        let sp = regs[2];
+        if let Some((base, size)) = session.stack.filter(|_| cpu.regs[2] != sp) {
+            let sp = cpu.regs[2] as u32;
+            if sp < base - size {
+                return Err(sim_error(SimError::StackOverflow { pc, sp }));
+            }
+            // 向上越过栈底只警告一次，回到栈内后再次越过时重新警告
+            if sp > base && !session.sp_above_base {
+                writeln!(out, "警告: PC=0x{:04X}: sp=0x{:08X} 高于栈底 0x{:08X}", pc, sp, base)?;
+            }
+            session.sp_above_base = sp > base;
+        }
+        if let Some(tracer) = &mut session.tracer {
+            tracer.on_execute(pc as u32, word, cpu);
+        }
+        if let Some(tracer) = &mut session.branch_tracer {
+            tracer.on_execute(pc as u32, word, cpu);
+        }
+        if let Some(statistics) = &mut session.statistics {
+            statistics.on_execute(pc as u32, word, cpu);
+        }
+        if let Some(profile) = &mut session.memory_profile {
+            profile.on_execute(pc as u32, word, cpu);
+        }
+        if session.stepping {
+            writeln!(out, "执行: PC=0x{:04X}  {:08X}  {}", pc, word, text())?;
+        }
+        for &(register, expected) in &session.watch_registers {
+            let (old, new) = (regs[register], cpu.regs[register]);
+            if old != new && expected.is_none_or(|value| value == new) {
+                writeln!(out, "监视: PC=0x{:04X}  {}  x{} ({}): {} (0x{:08X}) -> {} (0x{:08X})",
+                    pc, text(), register, ABI_NAMES[register], old, old, new, new)?;
+                session.stepping = true;
+            }
+        }
+        if let Some(access) = cpu.last_access {
+            let watched = session.watch_access.is_none_or(|access_type| access_type == access.access_type)
+                && session.watch_memory.iter().any(|&address| address.wrapping_sub(access.address) < access.len as u32);
+            if watched {
+                writeln!(out, "监视: PC=0x{:04X}  {}  {} 0x{:04X} ({}字节) = 0x{:0width$X}",
+                    pc, text(), access.access_type.name(), access.address, access.len, access.value, width = access.len * 2)?;
+                session.stepping = true;
+            }
+        }
+        if let Some(path) = &session.save_state {
+            if session.checkpoint_interval > 0 && cpu.cycles.is_multiple_of(session.checkpoint_interval) {
+                write_checkpoint(cpu, path)?;
+            }
+        }
+        if !running {
+            return Ok((count, true));
+        }
+    }
+}
+
+// 执行二进制文件（或从检查点继续）直到halt，打印最终的寄存器状态
+fn run_simulator(input_file: &str, config: &SimConfig) -> io::Result<()> {
+    let mut cpu = if config.load_state {
+        println!("读取检查点: {}", input_file);
+        CpuState::load_state(&mut io::BufReader::new(fs::File::open(input_file)?))?
+    } else {
+        println!("读取二进制文件: {}", input_file);
+        let program = read_binary_file(input_file, false)?;
+        let mut cpu = CpuState::new(&program).map_err(sim_error)?;
+        // 打开内存保护时程序本身（指令ROM）默认只读，命令行指定的区域可以覆盖
+        if !config.mem_protect.is_empty() && !program.is_empty() {
+            cpu.regions.push(MemoryRegion { start: 0, end: program.len() as u32 * 4, permission: Permission::ReadOnly });
+        }
+        cpu
+    };
+    cpu.regions.extend(config.mem_protect.iter().copied());
+
+    let mut session = SimSession {
+        stepping: config.step,
+        save_state: config.save_state.clone(),
+        checkpoint_interval: config.checkpoint_interval,
+        faults: config.faults.clone(),
+        stack: config.detect_stack_overflow.then_some((config.stack_base, config.stack_size)),
+        watch_registers: config.watch_registers.clone(),
+        watch_memory: config.watch_memory.clone(),
+        watch_access: match (config.watch_memory_read, config.watch_memory_write) {
+            (true, false) => Some(AccessType::Read),
+            (false, true) => Some(AccessType::Write),
+            _ => None,
+        },
+        ..SimSession::default()
+    };
+    if config.trace {
+        let out: Box<dyn Write> = match &config.trace_file {
+            Some(path) => Box::new(io::BufWriter::new(fs::File::create(path)?)),
+            None => Box::new(io::stdout()),
+        };
+        let mut tracer = PrintTracer::new(out);
+        if let Some(path) = &config.source_map {
+            let map = parse_source_map(&fs::read_to_string(path)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            tracer = tracer.with_source_map(map);
+        }
+        session.tracer = Some(tracer);
+    }
+    if config.branch_trace {
+        let out: Box<dyn Write> = match &config.branch_trace_file {
+            Some(path) => Box::new(io::BufWriter::new(fs::File::create(path)?)),
+            None => Box::new(io::stderr()),
+        };
+        let tracer = BranchTracer::new(out);
+        session.branch_tracer = Some(if config.cycle_count { tracer.with_cycles() } else { tracer });
+    }
+    if config.statistics_file.is_some() {
+        session.statistics = Some(Statistics::default());
+    }
+    if config.profile_memory {
+        session.memory_profile = Some(MemoryProfile::default());
+    }
+    if session.save_state.is_some() {
+        install_interrupt_handler();
+    }
+
+    let start = Instant::now();
+    let result = run_session(&mut cpu, &mut session, io::stdin().lock(), &mut io::stdout());
+    let elapsed = start.elapsed();
+    if let Some(tracer) = session.tracer.take() {
+        tracer.finish()?;
+    }
+    if let Some(tracer) = session.branch_tracer.take() {
+        tracer.finish()?;
+    }
+    let (count, finished) = result?;
+    if let (Some(path), Some(statistics)) = (&config.statistics_file, &session.statistics) {
+        fs::write(path, statistics.to_json(&cpu, elapsed))?;
+    }
+    if !finished {
+        println!("已退出，共执行 {} 条指令，停在 pc=0x{:04X}", count, cpu.pc);
+        return Ok(());
+    }
+    println!("执行完成，共 {} 条指令，停在 pc=0x{:04X}", count, cpu.pc);
+    if config.cycle_count {
+        // 从检查点恢复时周期数包含检查点之前执行的指令
+        println!("周期数: {}", cpu.cycles);
+    }
+    print!("{}", cpu.format_registers());
+    if let Some(profile) = &session.memory_profile {
+        println!("内存访问统计（按访问次数排列）:");
+        if profile.counts.is_empty() {
+            println!("没有执行访存指令");
+        }
+        // 条形图放在地址和次数之后，占满终端剩下的宽度
+        let columns = std::env::var("COLUMNS").ok().and_then(|value| value.parse::<usize>().ok()).unwrap_or(80);
+        let chart_width = config.profile_memory_chart.then_some(columns.saturating_sub(40).max(10));
+        print!("{}", profile.format_report(chart_width));
+    }
+    Ok(())
+}
+
+// 同步执行两个二进制文件，报告第一处寄存器或内存的差异；状态始终相同时返回true
+fn run_compare<W: Write>(first_file: &str, second_file: &str, out: &mut W) -> io::Result<bool> {
+    writeln!(out, "比较: {} <-> {}", first_file, second_file)?;
+    let mut first = CpuState::new(&read_binary_file(first_file, false)?).map_err(sim_error)?;
+    let mut second = CpuState::new(&read_binary_file(second_file, false)?).map_err(sim_error)?;
+    let Some(divergence) = run_lockstep(&mut first, &mut second).map_err(sim_error)? else {
+        writeln!(out, "两个程序的状态始终相同，分别执行 {} / {} 条指令", first.cycles, second.cycles)?;
+        return Ok(true);
+    };
+    let width = first_file.chars().count().max(second_file.chars().count());
+    writeln!(out, "第 {} 步后出现差异:", divergence.cycle)?;
+    writeln!(out, "  {:<width$}  PC=0x{:04X}", first_file, divergence.pcs[0], width = width)?;
+    writeln!(out, "  {:<width$}  PC=0x{:04X}", second_file, divergence.pcs[1], width = width)?;
+    writeln!(out, "  {}", divergence.difference)?;
+    Ok(false)
+}
+
 fn main() -> io::Result<()> {
     let args: Vec<String> = std::env::args().collect();
     
@@ -430,35 +1033,95 @@ fn main() -> io::Result<()> {
     }
     
     match args[1].as_str() {
+        "--dump-tables" => {
+            print!("{}", format_opcode_tables());
+        },
+        "explain-imm" => {
+            let Some(mnemonic) = args.get(2) else { usage_error(&args[0], "缺少助记符参数") };
+            match explain_immediate(mnemonic) {
+                Some(explanation) => print!("{}", explanation),
+                None => {
+                    eprintln!("未知指令: {}", mnemonic);
+                    std::process::exit(1);
+                }
+            }
+        },
+        "--update-golden" => {
+            let dir = args.get(2).map(String::as_str).unwrap_or("tests/golden");
+            match update_golden(Path::new(dir)) {
+                Ok(count) => println!("共更新 {} 个黄金文件", count),
+                Err(e) => {
+                    eprintln!("更新黄金文件失败: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        },
         "asm" => {
             if args.len() < 3 {
-                println!("错误: 缺少汇编文件名参数");
-                show_usage(&args[0]);
-                return Ok(());
+                usage_error(&args[0], "缺少汇编文件名参数");
             }
             
-            let base_name = &args[2];
-            if let Err(e) = run_assembler(base_name) {
+            let (config, base_name) = match parse_asm_args(&args[2..]) {
+                Ok(parsed) => parsed,
+                Err(e) => usage_error(&args[0], &e),
+            };
+            if let Err(e) = run_assembler(&base_name, &config) {
                 eprintln!("汇编失败: {}", e);
+                std::process::exit(1);
             }
         },
         "disasm" => {
+            let (config, files) = match parse_disasm_args(&args[2..]) {
+                Ok(parsed) => parsed,
+                Err(e) => usage_error(&args[0], &e),
+            };
+
+            let result = match (config.benchmark_decode, files.as_slice()) {
+                (true, [input_file]) => run_decode_benchmark(input_file, &config),
+                (false, [input_file, output_file]) => run_disassembler(input_file, output_file, &config),
+                _ => usage_error(&args[0], "缺少输入或输出文件参数"),
+            };
+            if let Err(e) = result {
+                eprintln!("反汇编失败: {}", e);
+                std::process::exit(1);
+            }
+        },
+        "sim" => {
+            let (config, input_file) = match parse_sim_args(&args[2..]) {
+                Ok(parsed) => parsed,
+                Err(e) => usage_error(&args[0], &e),
+            };
+            let result = match &config.compare {
+                // 被模拟的程序在前，--compare指定的程序在后
+                Some(other) => run_compare(&input_file, other, &mut io::stdout()),
+                None => run_simulator(&input_file, &config).map(|_| true),
+            };
+            match result {
+                Ok(true) => {}
+                // 比较模式下两个程序的状态不同
+                Ok(false) => std::process::exit(1),
+                Err(e) => {
+                    eprintln!("模拟失败: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        },
+        "compress" | "decompress" => {
             if args.len() < 4 {
-                println!("错误: 缺少输入或输出文件参数");
-                show_usage(&args[0]);
-                return Ok(());
+                usage_error(&args[0], "缺少输入或输出文件参数");
             }
-            
-            let input_file = &args[2];
-            let output_file = &args[3];
-            if let Err(e) = run_disassembler(input_file, output_file) {
-                eprintln!("反汇编失败: {}", e);
+
+            let result = if args[1] == "compress" {
+                run_compress(&args[2], &args[3])
+            } else {
+                run_decompress(&args[2], &args[3])
+            };
+            if let Err(e) = result {
+                eprintln!("{}失败: {}", if args[1] == "compress" { "压缩" } else { "解压" }, e);
+                std::process::exit(1);
             }
         },
-        _ => {
-            println!("未知命令: {}", args[1]);
-            show_usage(&args[0]);
-        }
+        _ => usage_error(&args[0], &format!("未知命令: {}", args[1])),
     }
     
     Ok(())
@@ -468,188 +1131,550 @@ fn main() -> io::Result<()> {
 mod tests {
     use super::*;
 
-    // 汇编器测试
     #[test]
-    fn test_encode_add() {
-        // add x1, x1, x3 -> 0b00000000000_00011_00001_00001_000001
-        let expected = 0b00000000000_00011_00001_00001_000001;
-        let actual = encode_add(1, 1, 3);
-        assert_eq!(actual, expected);
+    fn test_parse_asm_args() {
+        let args: Vec<String> = ["--max-imm-bits", "8", "sum"].iter().map(|s| s.to_string()).collect();
+        let (config, base_name) = parse_asm_args(&args).unwrap();
+        assert_eq!(config.assembler.max_imm_bits, 8);
+        assert!(config.assembler.warn_dead_code);
+        assert_eq!(base_name, "sum");
+
+        let args: Vec<String> = ["--no-warn-dead-code", "sum"].iter().map(|s| s.to_string()).collect();
+        assert!(!parse_asm_args(&args).unwrap().0.assembler.warn_dead_code);
+
+        let args: Vec<String> = ["--max-imm-bits", "17", "sum"].iter().map(|s| s.to_string()).collect();
+        assert!(parse_asm_args(&args).is_err());
+
+        let args: Vec<String> = ["--define", "DEBUG", "--define", "LEVEL=0x10", "--define", "SIZE=LEVEL*4", "sum"]
+            .iter().map(|s| s.to_string()).collect();
+        let (config, _) = parse_asm_args(&args).unwrap();
+        let expected = HashMap::from([("DEBUG".to_string(), 1), ("LEVEL".to_string(), 16), ("SIZE".to_string(), 64)]);
+        assert_eq!(config.assembler.defines, expected);
+        for bad in ["1X=2", "X=", "X=Y"] {
+            let args: Vec<String> = ["--define", bad, "sum"].iter().map(|s| s.to_string()).collect();
+            assert!(parse_asm_args(&args).is_err(), "{}", bad);
+        }
     }
 
     #[test]
-    fn test_encode_mul() {
-        // mul x1, x1, x3 -> 0b00000000000_00011_00001_00001_000100
-        let expected = 0b00000000000_00011_00001_00001_000100;
-        let actual = encode_mul(1, 1, 3);
-        assert_eq!(actual, expected);
+    fn test_no_output() {
+        let root = std::env::temp_dir().join(format!("riscv_tools_no_output_{}", std::process::id()));
+        fs::create_dir_all(root.join("asm")).unwrap();
+        fs::write(root.join("asm/prog.asm"), "addi x1, x0, 1\nhalt\n").unwrap();
+
+        let config = Config { no_output: true, ..Config::default() };
+        run_assembler_in(&root, "prog", &config).unwrap();
+        assert!(!root.join("out").exists());
+
+        run_assembler_in(&root, "prog", &Config::default()).unwrap();
+        assert!(root.join("out/prog.o").exists());
+        assert!(!root.join("out/prog.lst").exists());
+
+        let config = Config { listing: true, source_map: true, ..Config::default() };
+        run_assembler_in(&root, "prog", &config).unwrap();
+        assert!(fs::read_to_string(root.join("out/prog.lst")).unwrap().starts_with("0000  00010042  "));
+        assert_eq!(fs::read_to_string(root.join("out/prog.map")).unwrap(), "{ \"0\": 1, \"1\": 2 }\n");
+        fs::remove_dir_all(&root).unwrap();
     }
 
     #[test]
-    fn test_encode_addi() {
-        // addi x1, x0, 0 -> 0b00000000000_00000_00000_00001_000010
-        let expected = 0b00000000000_00000_00000_00001_000010;
-        let actual = encode_addi(1, 0, 0);
-        assert_eq!(actual, expected);
+    fn test_include_files() {
+        let root = std::env::temp_dir().join(format!("riscv_tools_include_files_{}", std::process::id()));
+        fs::create_dir_all(root.join("asm/lib")).unwrap();
+        fs::write(root.join("asm/defs.inc"), ".equ COUNT, 5\n.equ STEP, 2\n").unwrap();
+        fs::write(root.join("asm/lib/math.asm"), "# x1 += x2 * x3\nmuladd:\n    mul x4, x2, x3\n    add x1, x1, x4\n    jalr x0, x5, 0\n").unwrap();
+        fs::write(root.join("asm/main.asm"), "\
+.include \"defs.inc\"
+    addi x2, x0, COUNT
+    addi x3, x0, STEP
+    jal x5, muladd
+    halt
+.include \"lib/math.asm\"
+").unwrap();
+
+        run_assembler_in(&root, "main", &Config::default()).unwrap();
+        let expected = assemble("addi x2, x0, 5\naddi x3, x0, 2\njal x5, muladd\nhalt\nmuladd: mul x4, x2, x3\nadd x1, x1, x4\njalr x0, x5, 0").unwrap();
+        let bytes: Vec<u8> = expected.iter().flat_map(|word| word.to_le_bytes()).collect();
+        assert_eq!(fs::read(root.join("out/main.o")).unwrap(), bytes);
+        assert_eq!(fs::read_to_string(root.join("out/main.sym")).unwrap(), "muladd 0x00000010\n");
+
+        // 被包含文件中的错误报告该文件的文件名和行号
+        fs::write(root.join("asm/lib/math.asm"), "muladd:\n    mul x4, x2, x99\n").unwrap();
+        let config = AssemblerConfig { include_dir: root.join("asm"), ..AssemblerConfig::default() };
+        let mut assembler = Assembler::new(&config);
+        assembler.file = "asm/main.asm".to_string();
+        let errors = assembler.run(&fs::read_to_string(root.join("asm/main.asm")).unwrap()).unwrap_err();
+        assert_eq!((errors[0].file.as_str(), errors[0].line, errors[0].col_start), ("asm/lib/math.asm", 2, 17));
+        assert_eq!(errors[0].message, "无效的寄存器: x99");
+        fs::remove_dir_all(&root).unwrap();
     }
 
     #[test]
-    fn test_encode_bne() {
-        // bne x2, x1, -8 -> 0b11111111111_00010_00001_11000_000011
-        let expected = 0b11111111111_00010_00001_11000_000011;
-        let actual = encode_bne(2, 1, -8);
-        assert_eq!(actual, expected);
+    fn test_parse_disasm_args() {
+        let args: Vec<String> = ["--benchmark-decode", "--bench-iterations", "50", "a.o"].iter().map(|s| s.to_string()).collect();
+        let (config, files) = parse_disasm_args(&args).unwrap();
+        assert!(config.benchmark_decode);
+        assert_eq!(config.bench_iterations, 50);
+        assert_eq!(files, vec!["a.o"]);
+
+        let args: Vec<String> = ["--bench-iterations", "0"].iter().map(|s| s.to_string()).collect();
+        assert!(parse_disasm_args(&args).is_err());
+
+        let args: Vec<String> = ["--histogram", "--histogram-only", "--reg-usage", "a.o", "b.asm"].iter().map(|s| s.to_string()).collect();
+        let (config, _) = parse_disasm_args(&args).unwrap();
+        assert!(config.histogram && config.histogram_only && config.reg_usage);
     }
 
     #[test]
-    fn test_encode_halt() {
-        let expected = 0;
-        let actual = encode_halt();
-        assert_eq!(actual, expected);
+    fn test_hash_output() {
+        let root = std::env::temp_dir().join(format!("riscv_tools_hash_output_{}", std::process::id()));
+        fs::create_dir_all(root.join("asm")).unwrap();
+        fs::write(root.join("asm/prog.asm"), "addi x1, x0, 1\nhalt\n").unwrap();
+
+        let config = Config { hash_output: true, ..Config::default() };
+        run_assembler_in(&root, "prog", &config).unwrap();
+        let hash = binary_hash(&assemble("addi x1, x0, 1\nhalt").unwrap());
+        let hashed = root.join(format!("out/prog_{}.o", hash));
+        assert!(hashed.exists());
+        assert_eq!(fs::read(root.join("out/prog.o")).unwrap(), fs::read(&hashed).unwrap());
+
+        // 再次生成时替换旧链接
+        run_assembler_in(&root, "prog", &config).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_werror() {
+        let root = std::env::temp_dir().join(format!("riscv_tools_werror_{}", std::process::id()));
+        fs::create_dir_all(root.join("asm")).unwrap();
+        fs::write(root.join("asm/prog.asm"), "add x0, x1, x2\nhalt\n").unwrap();
+
+        let config = Config { no_output: true, ..Config::default() };
+        run_assembler_in(&root, "prog", &config).unwrap();
+        let config = Config { no_output: true, assembler: AssemblerConfig { warnings_as_errors: true, ..AssemblerConfig::default() }, ..Config::default() };
+        assert!(run_assembler_in(&root, "prog", &config).is_err());
+        let config = Config { no_output: true, suppress_warnings: true, assembler: AssemblerConfig { warnings_as_errors: true, ..AssemblerConfig::default() }, ..Config::default() };
+        run_assembler_in(&root, "prog", &config).unwrap();
+
+        // --check-abi的警告同样受--Werror和--suppress-warnings控制
+        fs::write(root.join("asm/prog.asm"), "addi s1, x0, 1\nhalt\n").unwrap();
+        let args: Vec<String> = ["--no-output", "--check-abi", "--Werror", "prog"].iter().map(|s| s.to_string()).collect();
+        let (mut config, _) = parse_asm_args(&args).unwrap();
+        assert!(run_assembler_in(&root, "prog", &config).is_err());
+        config.suppress_warnings = true;
+        run_assembler_in(&root, "prog", &config).unwrap();
+        fs::remove_dir_all(&root).unwrap();
     }
 
-    // 反汇编器测试
     #[test]
-    fn test_decode_add() {
-        // add x1, x2, x3
-        let instr = 0b00000000000_00011_00010_00001_000001;
-        assert_eq!(decode_instruction(instr), "add x1, x2, x3");
+    fn test_disasm_data_range() {
+        let code = assemble("addi x1, x0, 1\n.space 8\nhalt").unwrap();
+        // 默认0字被解码为halt
+        assert!(disassemble_listing(&code, &DisasmConfig::default()).contains("    halt  # 0004: 00000000\n"));
+
+        let args: Vec<String> = ["--data-range", "0x4,12", "a.o", "b.asm"].iter().map(|s| s.to_string()).collect();
+        let (config, _) = parse_disasm_args(&args).unwrap();
+        assert_eq!(config.data_ranges, vec![(4, 12)]);
+        let listing = disassemble_listing(&code, &config);
+        assert!(listing.contains("    .word 0x00000000  # 0004: 00000000  数据\n"));
+        assert!(listing.contains("    .word 0x00000000  # 0008: 00000000  数据\n"));
+        assert!(listing.contains("    halt  # 000C: 00000000\n"));
+
+        assert!(parse_data_range("8,4").is_err());
+        assert!(parse_data_range("8").is_err());
     }
 
     #[test]
-    fn test_decode_mul() {
-        // mul x3, x4, x5
-        let instr = 0b00000000000_00101_00100_00011_000100;
-        assert_eq!(decode_instruction(instr), "mul x3, x4, x5");
+    fn test_mif_output() {
+        let img = assemble("addi x1, x0, 10\nadd x1, x1, x3\nhalt").unwrap();
+        let header = "WIDTH = 32;\nADDRESS_RADIX = HEX;\nDATA_RADIX = HEX;\nCONTENT\nBEGIN\n";
+        let body = "0 : 000A0042;\n1 : 00030841;\n2 : 00000000;\n";
+        assert_eq!(format_mif(&img, None).unwrap(), format!("DEPTH = 3;\n{}{}END;\n", header, body));
+        assert_eq!(format_mif(&img, Some(4)).unwrap(), format!("DEPTH = 4;\n{}{}3 : 00000000;\nEND;\n", header, body));
+        assert_eq!(format_mif(&img, Some(16)).unwrap(), format!("DEPTH = 16;\n{}{}[3..F] : 00000000;\nEND;\n", header, body));
+        assert!(format_mif(&img, Some(2)).is_err());
+
+        let args: Vec<String> = ["--format", "mif", "--mif-depth", "6", "prog"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(parse_asm_args(&args).err(), Some("MIF深度必须是2的幂: 6".to_string()));
+    }
+
+    #[test]
+    fn test_c_header_output() {
+        let img = assemble("addi x1, x0, 10\nadd x1, x1, x3\nnop\nmov x2, x1\nhalt").unwrap();
+        let header = format_c_header(&img, "boot_rom");
+        assert!(header.starts_with("#pragma once\n"));
+        assert!(header.contains("static const uint32_t boot_rom[] = {\n    0x000A0042ULL, 0x00030841ULL,"));
+        assert!(header.contains("\n    0x00000000ULL,\n};\n"));
+        assert!(header.ends_with("static const size_t boot_rom_len = 5;\n"));
+        // 括号配对，声明以分号结束
+        assert_eq!(header.matches('{').count(), header.matches('}').count());
+        assert_eq!(header.matches('[').count(), header.matches(']').count());
+        for line in header.lines().filter(|line| line.starts_with("static") || line.starts_with('}')) {
+            assert!(line.ends_with(';') || line.ends_with('{'), "{}", line);
+        }
+
+        let args: Vec<String> = ["--format", "c", "--array-name", "1x", "prog"].iter().map(|s| s.to_string()).collect();
+        assert!(parse_asm_args(&args).is_err());
+    }
+
+    #[test]
+    fn test_big_endian_round_trip() {
+        let img = assemble("addi x1, x0, 10\nadd x1, x1, x3\nhalt").unwrap();
+        let path = std::env::temp_dir().join(format!("riscv_tools_be_{}.o", std::process::id()));
+        let path_str = path.to_str().unwrap();
+
+        write_object_file(&img, &path, true).unwrap();
+        assert_eq!(&fs::read(&path).unwrap()[..4], &[0x00, 0x0A, 0x00, 0x42]);
+        assert_eq!(read_binary_file(path_str, true).unwrap(), img);
+        let swapped: Vec<u32> = img.iter().map(|word| word.swap_bytes()).collect();
+        assert_eq!(read_binary_file(path_str, false).unwrap(), swapped);
+
+        write_object_file(&img, &path, false).unwrap();
+        assert_eq!(read_binary_file(path_str, false).unwrap(), img);
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn test_decode_addi() {
-        // addi x1, x0, 10
-        let instr = 0b00000000000_01010_00000_00001_000010;
-        assert_eq!(decode_instruction(instr), "addi x1, x0, 10");
+    fn test_run_simulator() {
+        let path = std::env::temp_dir().join(format!("riscv_tools_sim_{}.o", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        write_object_file(&assemble("addi x1, x0, 3\nhalt").unwrap(), &path, false).unwrap();
+        run_simulator(path_str, &SimConfig::default()).unwrap();
+
+        // 跟踪写入文件
+        let trace_path = path.with_extension("trace");
+        let args: Vec<String> = ["--trace-file", trace_path.to_str().unwrap(), path_str].iter().map(|s| s.to_string()).collect();
+        let (config, input_file) = parse_sim_args(&args).unwrap();
+        run_simulator(&input_file, &config).unwrap();
+        assert_eq!(fs::read_to_string(&trace_path).unwrap(),
+            "PC=0x0000  00030042  addi x1, x0, 3   x1=3\nPC=0x0004  00000000  halt\n");
+        fs::remove_file(&trace_path).unwrap();
+
+        // 分支跟踪写入文件，附上周期
+        write_object_file(&assemble("li x1, 2\nloop: addi x1, x1, -1\nbnez x1, loop\nhalt").unwrap(), &path, false).unwrap();
+        let args: Vec<String> = ["--branch-trace-file", trace_path.to_str().unwrap(), "--cycle-count", path_str]
+            .iter().map(|s| s.to_string()).collect();
+        let (config, input_file) = parse_sim_args(&args).unwrap();
+        run_simulator(&input_file, &config).unwrap();
+        assert_eq!(fs::read_to_string(&trace_path).unwrap(), "PC=0x0008  BNE x1,x0  taken  target=0x0004  cycle=3\n");
+        fs::remove_file(&trace_path).unwrap();
+
+        // 内存访问统计只输出到标准输出，这里只检查选项
+        let (config, _) = parse_sim_args(&["--profile-memory-chart".to_string(), path_str.to_string()]).unwrap();
+        assert!(config.profile_memory && config.profile_memory_chart);
+        run_simulator(path_str, &config).unwrap();
+
+        // 统计文件
+        let args: Vec<String> = ["--statistics-file", trace_path.to_str().unwrap(), path_str].iter().map(|s| s.to_string()).collect();
+        let (config, input_file) = parse_sim_args(&args).unwrap();
+        run_simulator(&input_file, &config).unwrap();
+        let json = fs::read_to_string(&trace_path).unwrap();
+        assert!(json.starts_with("{\n  \"instructions\": 6,\n  \"cycles\": 6,\n"));
+        assert!(json.contains("\"histogram\": {\"addi\": 3, \"bne\": 2, \"halt\": 1},\n  \"branches\": {\"taken\": 1, \"not_taken\": 1},\n"));
+        fs::remove_file(&trace_path).unwrap();
+
+        // 没有halt，执行到0地址之前的非法区域
+        write_object_file(&assemble("addi x1, x0, 3\nj -8").unwrap(), &path, false).unwrap();
+        let error = run_simulator(path_str, &SimConfig::default()).unwrap_err();
+        assert_eq!(error.to_string(), "pc=0xFFFFFFFC 超出内存范围或未按4字节对齐");
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn test_decode_lui() {
-        // lui x2, 42
-        let instr = 0b00000000001_01010_00000_00010_000101;
-        assert_eq!(decode_instruction(instr), "lui x2, 42");
+    fn test_sim_checkpoint() {
+        let path = std::env::temp_dir().join(format!("riscv_tools_ckpt_{}.o", std::process::id()));
+        let checkpoint = path.with_extension("state");
+        let (path_str, checkpoint_str) = (path.to_str().unwrap(), checkpoint.to_str().unwrap());
+        write_object_file(&assemble("addi x1, x0, 3\naddi x1, x1, 4\nlui x2, 1\nhalt").unwrap(), &path, false).unwrap();
+
+        // 每2条指令写一次检查点，最后一次在第4条（halt）之后
+        let args: Vec<String> = ["--save-state", checkpoint_str, "--checkpoint-interval", "2", path_str]
+            .iter().map(|s| s.to_string()).collect();
+        let (config, input_file) = parse_sim_args(&args).unwrap();
+        run_simulator(&input_file, &config).unwrap();
+        let cpu = CpuState::load_state(&mut fs::read(&checkpoint).unwrap().as_slice()).unwrap();
+        assert_eq!((cpu.pc, cpu.cycles, cpu.regs[1], cpu.regs[2]), (12, 4, 7, 0x10000));
+
+        // 从第2条指令之后的检查点继续
+        let mut cpu = CpuState::new(&read_binary_file(path_str, false).unwrap()).unwrap();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        write_checkpoint(&cpu, checkpoint_str).unwrap();
+        let (config, input_file) = parse_sim_args(&["--load-state".to_string(), checkpoint_str.to_string()]).unwrap();
+        assert!(config.load_state);
+        run_simulator(&input_file, &config).unwrap();
+
+        let parse = |args: &[&str]| parse_sim_args(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>()).map(|_| ()).unwrap_err();
+        assert_eq!(parse(&["--load-state", checkpoint_str, path_str]), "--load-state 不能与二进制文件同时指定");
+        assert_eq!(parse(&["--checkpoint-interval", "10", path_str]), "--checkpoint-interval 需要与 --save-state 一起使用");
+        assert_eq!(parse(&["--save-state", checkpoint_str, "--checkpoint-interval", "0", path_str]), "无效的检查点间隔: 0");
+
+        // 检查点文件不对时报告错误
+        let error = run_simulator(path_str, &SimConfig { load_state: true, ..SimConfig::default() }).unwrap_err();
+        assert!(error.to_string().starts_with("检查点魔数错误"));
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&checkpoint).unwrap();
     }
 
     #[test]
-    fn test_decode_lw() {
-        // lw x3, 4(x1)
-        let instr = 0b00000000000_00100_00001_00011_000110;
-        assert_eq!(decode_instruction(instr), "lw x3, 4(x1)");
+    fn test_run_compare() {
+        let dir = std::env::temp_dir();
+        let first = dir.join(format!("riscv_tools_cmp1_{}.o", std::process::id()));
+        let second = dir.join(format!("riscv_tools_cmp2_{}.o", std::process::id()));
+        let (first_str, second_str) = (first.to_str().unwrap(), second.to_str().unwrap());
+        write_object_file(&assemble("li x1, 6\nslli x1, x1, 1\nhalt").unwrap(), &first, false).unwrap();
+        write_object_file(&assemble("li x1, 6\nadd x1, x1, x1\nhalt").unwrap(), &second, false).unwrap();
+        let args: Vec<String> = ["--compare", second_str, first_str].iter().map(|s| s.to_string()).collect();
+        let (config, input_file) = parse_sim_args(&args).unwrap();
+        assert!(run_compare(&input_file, config.compare.as_deref().unwrap(), &mut Vec::new()).unwrap());
+
+        // sim --compare SECOND FIRST: 被模拟的FIRST排在前面，差异中的第一个值也来自FIRST
+        write_object_file(&assemble("li x1, 6\nmul x1, x1, x1\nhalt").unwrap(), &second, false).unwrap();
+        let mut out = Vec::new();
+        assert!(!run_compare(&input_file, config.compare.as_deref().unwrap(), &mut out).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], format!("比较: {} <-> {}", first_str, second_str));
+        assert!(lines[2].trim_start().starts_with(first_str) && lines[2].ends_with("PC=0x0004"), "{}", out);
+        assert!(lines[3].trim_start().starts_with(second_str), "{}", out);
+        assert_eq!(lines[4], "  x1 (ra): 12 (0x0000000C) != 36 (0x00000024)");
+        fs::remove_file(&first).unwrap();
+        fs::remove_file(&second).unwrap();
     }
 
     #[test]
-    fn test_decode_bne() {
-        // bne x2, x1, -8
-        let instr = 0b11111111111_00010_00001_11000_000011;
-        assert_eq!(decode_instruction(instr), "bne x2, x1, -8");
+    fn test_inject_fault() {
+        assert_eq!(parse_fault("0x0010=0xDEADBEEF"), Ok((0x10, 0xDEADBEEF)));
+        assert_eq!(parse_fault("8 = 0"), Ok((8, 0)));
+        assert_eq!(parse_fault("6=0").unwrap_err(), "故障注入地址 0x6 未按4字节对齐");
+        assert_eq!(parse_fault("0x10").unwrap_err(), "无效的故障注入: 0x10（格式为 地址=指令字）");
+
+        // 循环体中的指令被替换后一直保持替换后的值
+        let program = assemble("li x1, 3\nloop: addi x2, x2, 1\naddi x1, x1, -1\nbnez x1, loop\nhalt").unwrap();
+        let mut cpu = CpuState::new(&program).unwrap();
+        let mut session = SimSession { faults: vec![(4, encode_addi(2, 2, 10)), (0x40, 0)], ..SimSession::default() };
+        let mut out = Vec::new();
+        assert_eq!(run_session(&mut cpu, &mut session, "".as_bytes(), &mut out).unwrap(), (11, true));
+        assert_eq!(cpu.regs[2], 30);
+        assert_eq!(String::from_utf8(out).unwrap(),
+            "注入故障: PC=0x0004  00011082 addi x2, x2, 1 -> 000A1082 addi x2, x2, 10\n");
+        // 没有执行到的地址不注入
+        assert_eq!(session.faults, vec![(0x40, 0)]);
+
+        // 注入非法指令字
+        let mut cpu = CpuState::new(&program).unwrap();
+        let mut session = SimSession { faults: vec![(4, 0xFFFFFFFF)], ..SimSession::default() };
+        let error = run_session(&mut cpu, &mut session, "".as_bytes(), &mut Vec::new()).unwrap_err();
+        assert_eq!(error.to_string(), "pc=0x00000004: 非法指令 0xFFFFFFFF");
     }
 
     #[test]
-    fn test_decode_sw() {
-        // sw x2, 8(x1)
-        let instr = 0b00000000000_00001_00010_01000_000111;
-        assert_eq!(decode_instruction(instr), "sw x2, 8(x1)");
+    fn test_detect_stack_overflow() {
+        let args: Vec<String> = ["--detect-stack-overflow", "--stack-base", "0x1000", "--stack-size", "64", "a.o"]
+            .iter().map(|s| s.to_string()).collect();
+        let (config, _) = parse_sim_args(&args).unwrap();
+        let run = |source: &str| {
+            let mut cpu = CpuState::new(&assemble(source).unwrap()).unwrap();
+            let mut session = SimSession {
+                stack: config.detect_stack_overflow.then_some((config.stack_base, config.stack_size)),
+                ..SimSession::default()
+            };
+            let mut out = Vec::new();
+            let result = run_session(&mut cpu, &mut session, "".as_bytes(), &mut out).map(|(count, _)| count);
+            (result.map_err(|e| e.to_string()), String::from_utf8(out).unwrap())
+        };
+
+        // 递归时每层压栈16字节，第5层越过0x1000 - 64
+        let recursive = "li sp, 0x1000\nli a0, 10\njal ra, f\nhalt\n\
+                         f: addi sp, sp, -16\nsw ra, 0(sp)\naddi a0, a0, -1\nbeqz a0, done\njal ra, f\n\
+                         done: lw ra, 0(sp)\naddi sp, sp, 16\nret";
+        assert_eq!(run(recursive).0.unwrap_err(), "STACK OVERFLOW at PC=0x0010, sp=0x00000FB0");
+        assert_eq!(run(&recursive.replace("li a0, 10", "li a0, 4")), (Ok(35), String::new()));
+
+        // 高于栈底只警告
+        let (result, out) = run("li sp, 0x1000\naddi sp, sp, 4\naddi sp, sp, 4\naddi sp, sp, -8\naddi sp, sp, 4\nhalt");
+        assert_eq!(result, Ok(6));
+        assert_eq!(out, "警告: PC=0x0004: sp=0x00001004 高于栈底 0x00001000\n警告: PC=0x0010: sp=0x00001004 高于栈底 0x00001000\n");
+
+        let parse = |args: &[&str]| parse_sim_args(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>()).map(|_| ()).unwrap_err();
+        assert_eq!(parse(&["--stack-base", "16", "a.o"]), "栈大小 0x8000 超过栈底地址 0x10");
+        assert_eq!(parse(&["--stack-size", "x", "a.o"]), "--stack-size 的参数无效: x");
     }
 
     #[test]
-    fn test_decode_blt() {
-        // blt x4, x5, 16
-        let instr = 0b00000000000_00100_00101_10000_001000;
-        assert_eq!(decode_instruction(instr), "blt x4, x5, 16");
+    fn test_mem_protect() {
+        assert_eq!(parse_mem_protect("0x100:0x200:WO"), Ok(MemoryRegion { start: 0x100, end: 0x200, permission: Permission::WriteOnly }));
+        assert_eq!(parse_mem_protect("0x100:0x200:XX").unwrap_err(), "无效的内存保护区域: 0x100:0x200:XX（格式为 起始:结束:RO|RW|WO|NA）");
+        assert!(parse_mem_protect("0x200:0x100:RW").is_err());
+        assert!(parse_mem_protect("0x100:0x200").is_err());
+
+        // 程序写入自己的代码时出错，除非把那里改为可写
+        let path = std::env::temp_dir().join(format!("riscv_tools_mpu_{}.o", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        write_object_file(&assemble("sw x0, 8(x0)\nlw x1, 0x104(x0)\nhalt").unwrap(), &path, false).unwrap();
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().chain([&path_str]).map(|s| s.to_string()).collect();
+            let (config, input_file) = parse_sim_args(&args).unwrap();
+            run_simulator(&input_file, &config).map_err(|e| e.to_string())
+        };
+        assert_eq!(run(&[]), Ok(()));
+        assert_eq!(run(&["--mem-protect", "0x100:0x200:RW"]).unwrap_err(), "pc=0x00000000: 写入地址 0x00000008 违反内存保护（RO）");
+        assert_eq!(run(&["--mem-protect", "0x100:0x200:NA", "--mem-protect", "0:12:RW"]).unwrap_err(),
+            "pc=0x00000004: 读取地址 0x00000104 违反内存保护（NA）");
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn test_decode_halt() {
-        // halt
-        let instr = 0;
-        assert_eq!(decode_instruction(instr), "halt");
+    fn test_watch_register() {
+        let args: Vec<String> = ["--watch-register", "t0", "--watch-register", "x6", "--watch-value", "x6=0x2", "a.o"]
+            .iter().map(|s| s.to_string()).collect();
+        let (config, _) = parse_sim_args(&args).unwrap();
+        assert_eq!(config.watch_registers, vec![(5, None), (6, Some(2))]);
+
+        // 写入相同的值不算改变；x6只在变为2时停下，之后c继续执行到halt
+        let program = assemble("li x5, 1\nli x5, 1\nli x6, 1\nli x6, 2\nli x6, 3\nhalt").unwrap();
+        let mut cpu = CpuState::new(&program).unwrap();
+        let mut session = SimSession { watch_registers: config.watch_registers, ..SimSession::default() };
+        let mut out = Vec::new();
+        assert_eq!(run_session(&mut cpu, &mut session, "c\nq\n".as_bytes(), &mut out).unwrap(), (4, false));
+        assert_eq!(cpu.pc, 16);
+        let out = String::from_utf8(out).unwrap();
+        // 输入不回显，监视信息紧跟在提示符后面
+        let watch_lines: Vec<&str> = out.lines().map(|line| line.trim_start_matches("> ")).filter(|line| line.starts_with("监视")).collect();
+        assert_eq!(watch_lines, [
+            "监视: PC=0x0000  addi x5, x0, 1  x5 (t0): 0 (0x00000000) -> 1 (0x00000001)",
+            "监视: PC=0x000C  addi x6, x0, 2  x6 (t1): 1 (0x00000001) -> 2 (0x00000002)",
+        ]);
+        assert!(out.contains("下一条指令: PC=0x0004  00010142  addi x5, x0, 1\n> "));
+
+        let parse = |args: &[&str]| parse_sim_args(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>()).map(|_| ()).unwrap_err();
+        assert_eq!(parse(&["--watch-register", "x32", "a.o"]), "无效的寄存器: x32");
+        assert_eq!(parse(&["--watch-value", "x5", "a.o"]), "无效的监视条件: x5（格式为 寄存器=值）");
+        assert_eq!(parse_register_name("fp"), None);
+        assert_eq!(parse_register_name("s0"), Some(8));
     }
-    
-    // 编码-解码循环测试
+
     #[test]
-    fn test_encode_decode_cycle() {
-        // 测试编码后再解码是否得到原指令
-        let tests = [
-            "add x1, x2, x3",
-            "addi x3, x0, 42",
-            "mul x4, x5, x6",
-            "bne x7, x8, -16",
-            "lw x9, 8(x10)",
-            "lui x13, 1024",
-            "halt"
-        ];
-        
-        // 单独测试sw和blt指令，因为它们的编码-解码顺序有特殊处理
-        let sw_test = "sw x11, 12(x12)";
-        let blt_test = "blt x14, x15, 20";
-        
-        // 测试普通指令
-        for &test_str in &tests {
-            let code = assemble(test_str);
-            assert_eq!(code.len(), 1, "应该只生成一条指令");
-            
-            let decoded = decode_instruction(code[0]);
-            // 对于lui指令，解码可能会使用不同的数字表示形式，所以进行特殊处理
-            if test_str.starts_with("lui") {
-                assert!(decoded.starts_with("lui"), "lui指令解码错误");
-            } else {
-                assert_eq!(decoded, test_str, "指令编码后解码不匹配: {}", test_str);
-            }
-        }
-        
-        // 特殊处理sw指令
-        {
-            let code = assemble(sw_test);
-            assert_eq!(code.len(), 1, "sw指令应该只生成一条指令");
-            let decoded = decode_instruction(code[0]);
-            assert!(decoded.starts_with("sw"), "sw指令解码错误");
-            // 不检查确切格式，只确保它是sw指令
-        }
-        
-        // 特殊处理blt指令
-        {
-            let code = assemble(blt_test);
-            assert_eq!(code.len(), 1, "blt指令应该只生成一条指令");
-            let decoded = decode_instruction(code[0]);
-            assert!(decoded.starts_with("blt"), "blt指令解码错误");
-            // 不检查确切格式，只确保它是blt指令
-        }
+    fn test_watch_memory() {
+        let program = assemble("li x1, 0x1234\nsw x1, 0x100(x0)\nlbu x2, 0x101(x0)\nlbu x3, 0x102(x0)\nsb x1, 0x103(x0)\nhalt").unwrap();
+        let run = |args: &[&str]| {
+            let args: Vec<String> = args.iter().chain(&["a.o"]).map(|s| s.to_string()).collect();
+            let (config, _) = parse_sim_args(&args).unwrap();
+            let mut session = SimSession {
+                watch_memory: config.watch_memory,
+                watch_access: if config.watch_memory_read { Some(AccessType::Read) } else { None },
+                ..SimSession::default()
+            };
+            let mut cpu = CpuState::new(&program).unwrap();
+            let mut out = Vec::new();
+            run_session(&mut cpu, &mut session, "c\nc\nc\n".as_bytes(), &mut out).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            out.lines().map(|line| line.trim_start_matches("> ").to_string()).filter(|line| line.starts_with("监视")).collect::<Vec<_>>()
+        };
+
+        // 字访问覆盖了0x101；0x102只被lbu x3和sw访问
+        assert_eq!(run(&["--watch-memory", "0x101"]), [
+            "监视: PC=0x0004  sw x1, 256(x0)  写入 0x0100 (4字节) = 0x00001234",
+            "监视: PC=0x0008  lbu x2, 257(x0)  读取 0x0101 (1字节) = 0x12",
+        ]);
+        assert_eq!(run(&["--watch-memory", "0x103", "--watch-memory", "0x102", "--watch-memory-read"]), [
+            "监视: PC=0x000C  lbu x3, 258(x0)  读取 0x0102 (1字节) = 0x00",
+        ]);
+
+        let parse = |args: &[&str]| parse_sim_args(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>()).map(|_| ()).unwrap_err();
+        assert_eq!(parse(&["--watch-memory-write", "a.o"]), "--watch-memory-read/--watch-memory-write 需要与 --watch-memory 一起使用");
+        assert_eq!(parse(&["--watch-memory", "-1", "a.o"]), "无效的地址: -1");
+    }
+
+    fn run_step_loop(cpu: &mut CpuState, input: &str, out: &mut Vec<u8>) -> io::Result<(usize, bool)> {
+        let mut session = SimSession { stepping: true, ..SimSession::default() };
+        run_session(cpu, &mut session, input.as_bytes(), out)
     }
 
     #[test]
-    fn test_encode_slli() {
-        // slli x1, x2, 3 -> 0b00000000000_00011_00010_00001_001001
-        let expected = 0b00000000000_00011_00010_00001_001001;
-        let actual = encode_slli(1, 2, 3);
-        assert_eq!(actual, expected);
+    fn test_run_step_loop() {
+        let program = assemble("addi x1, x0, 3\naddi x2, x1, 4\nhalt").unwrap();
+        let mut cpu = CpuState::new(&program).unwrap();
+        let mut out = Vec::new();
+        let (count, finished) = run_step_loop(&mut cpu, "\nr 1\nm 0 6\ns\n\n", &mut out).unwrap();
+        assert_eq!((count, finished), (3, true));
+        assert_eq!(cpu.regs[2], 7);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("执行: PC=0x0000  00030042  addi x1, x0, 3\n"));
+        assert!(out.contains("执行: PC=0x0004  00040882  addi x2, x1, 4\n"));
+        assert!(out.contains("执行: PC=0x0008  00000000  halt\n"));
+        assert!(out.contains("> x1 (ra) = 3 (0x00000003)\n"));
+        assert!(out.contains("> 0x0000: 42 00 03 00 82 08\n"));
+
+        // q和输入结束都停在当前指令；c执行到halt
+        let mut cpu = CpuState::new(&program).unwrap();
+        assert_eq!(run_step_loop(&mut cpu, "\nq\n", &mut Vec::new()).unwrap(), (1, false));
+        assert_eq!(cpu.pc, 4);
+        assert_eq!(run_step_loop(&mut cpu, "", &mut Vec::new()).unwrap(), (0, false));
+        assert_eq!(run_step_loop(&mut cpu, "c\n", &mut Vec::new()).unwrap(), (2, true));
+        assert_eq!(cpu.regs[2], 7);
     }
 
     #[test]
-    fn test_encode_sub() {
-        // sub x3, x4, x5 -> 0b00000000000_00101_00100_00011_001010
-        let expected = 0b00000000000_00101_00100_00011_001010;
-        let actual = encode_sub(3, 4, 5);
-        assert_eq!(actual, expected);
+    fn test_disasm_inline_values() {
+        let root = std::env::temp_dir().join(format!("riscv_tools_inline_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let trace_path = root.join("prog.trace");
+        let trace_path = trace_path.to_str().unwrap();
+        fs::write(trace_path, "读取二进制文件: prog.o\nPC=0x0000  00030042  addi x1, x0, 3   x1=3\nPC=0x0004  00000000  halt\n").unwrap();
+
+        let args: Vec<String> = ["--disasm-inline-values", trace_path, "prog.o"].iter().map(|s| s.to_string()).collect();
+        let (config, files) = parse_disasm_args(&args).unwrap();
+        assert_eq!(files, vec!["prog.o"]);
+        assert!(disassemble_listing(&[0x0003_0042, 0], &config).contains("    addi x1, x0, 3  # 0000: 00030042  x1 <- 0x00000003\n"));
+
+        fs::write(trace_path, "").unwrap();
+        assert!(parse_disasm_args(&args).is_err_and(|e| e.contains("跟踪文件中没有指令记录")));
+        fs::remove_dir_all(&root).unwrap();
+        assert!(parse_disasm_args(&args).is_err_and(|e| e.starts_with("无法读取跟踪文件")));
     }
 
     #[test]
-    fn test_decode_slli() {
-        // slli x1, x2, 3
-        let instr = 0b00000000000_00011_00010_00001_001001;
-        assert_eq!(decode_instruction(instr), "slli x1, x2, 3");
+    fn test_symbol_table_files() {
+        let root = std::env::temp_dir().join(format!("riscv_tools_sym_{}", std::process::id()));
+        fs::create_dir_all(root.join("asm")).unwrap();
+        fs::write(root.join("asm/prog.asm"), "addi x1, x0, 2\nloop: addi x1, x1, -1\nbnez x1, loop\nhalt\n").unwrap();
+        run_assembler_in(&root, "prog", &Config::default()).unwrap();
+
+        let sym_path = root.join("out/prog.sym");
+        let sym_path = sym_path.to_str().unwrap();
+        let symbols = read_symbol_table(sym_path).unwrap();
+        assert_eq!(symbols, HashMap::from([("loop".to_string(), 4)]));
+        write_symbol_table(&symbols, sym_path).unwrap();
+        assert_eq!(fs::read_to_string(sym_path).unwrap(), "loop 0x00000004\n");
+
+        let args: Vec<String> = ["--sym", sym_path, "out/prog.o"].iter().map(|s| s.to_string()).collect();
+        let (config, _) = parse_disasm_args(&args).unwrap();
+        let img = read_binary_file(root.join("out/prog.o").to_str().unwrap(), false).unwrap();
+        assert!(disassemble_listing(&img, &config).contains("bnez x1, loop"));
+
+        fs::write(sym_path, "loop\n").unwrap();
+        assert!(read_symbol_table(sym_path).is_err());
+        assert!(parse_disasm_args(&args).is_err());
+        fs::remove_dir_all(&root).unwrap();
     }
 
     #[test]
-    fn test_decode_sub() {
-        // sub x3, x4, x5
-        let instr = 0b00000000000_00101_00100_00011_001010;
-        assert_eq!(decode_instruction(instr), "sub x3, x4, x5");
+    fn test_object_output() {
+        let root = std::env::temp_dir().join(format!("riscv_tools_obj_out_{}", std::process::id()));
+        fs::create_dir_all(root.join("asm")).unwrap();
+        fs::write(root.join("asm/prog.asm"), "addi x1, x0, 7\nhalt\n").unwrap();
+        let args: Vec<String> = ["--format", "obj", "prog"].iter().map(|s| s.to_string()).collect();
+        let (config, base_name) = parse_asm_args(&args).unwrap();
+        run_assembler_in(&root, &base_name, &config).unwrap();
+
+        let path = root.join("out/prog.obj");
+        let path_str = path.to_str().unwrap();
+        assert_eq!(read_binary_file(path_str, false).unwrap(), assemble("addi x1, x0, 7\nhalt").unwrap());
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[12] ^= 0xFF;
+        fs::write(&path, bytes).unwrap();
+        assert!(read_binary_file(path_str, false).unwrap_err().to_string().starts_with("校验和不匹配"));
+        fs::remove_dir_all(&root).unwrap();
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,207 @@
+// 带文件头和校验和的目标文件格式(.obj)，所有字段与指令字一样按小端字节序存放:
+//   文件头[12]  指令字[4 * instruction_count]  CRC32[4]
+// CRC32（IEEE 802.3，即zlib/PNG所用的算法）覆盖它之前的所有字节
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+// 目标文件的魔数，按小端写出后文件以 DE C0 5E A5 开头
+pub const OBJECT_MAGIC: u32 = 0xA55E_C0DE;
+pub const OBJECT_VERSION: u16 = 1;
+
+// 文件头共12字节: magic[4] version[2] flags[2] instruction_count[4]
+// flags目前没有定义任何位，写出时为0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectFileHeader {
+    pub magic: u32,
+    pub version: u16,
+    pub flags: u16,
+    pub instruction_count: u32,
+}
+
+impl ObjectFileHeader {
+    pub const SIZE: usize = 12;
+
+    pub fn new(instruction_count: u32) -> Self {
+        ObjectFileHeader { magic: OBJECT_MAGIC, version: OBJECT_VERSION, flags: 0, instruction_count }
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0..4].copy_from_slice(&self.magic.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.version.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.flags.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.instruction_count.to_le_bytes());
+        out.write_all(&bytes)
+    }
+
+    // 只读取字段，不检查魔数和版本
+    pub fn read<R: Read>(input: &mut R) -> io::Result<Self> {
+        let mut bytes = [0; Self::SIZE];
+        input.read_exact(&mut bytes)?;
+        Ok(ObjectFileHeader {
+            magic: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            version: u16::from_le_bytes(bytes[4..6].try_into().unwrap()),
+            flags: u16::from_le_bytes(bytes[6..8].try_into().unwrap()),
+            instruction_count: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+        })
+    }
+}
+
+// 读取目标文件时的错误
+#[derive(Debug)]
+pub enum ObjectFileError {
+    Io(io::Error),
+    Truncated { size: usize },                          // 文件短于文件头加校验和
+    BadMagic { found: u32 },
+    UnsupportedVersion { found: u16 },
+    CountMismatch { header: u32, actual: usize },       // 文件头中的字数与文件大小不符
+    ChecksumMismatch { stored: u32, computed: u32 },
+}
+
+impl fmt::Display for ObjectFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjectFileError::Io(e) => write!(f, "{}", e),
+            ObjectFileError::Truncated { size } => write!(f, "文件只有 {} 字节，不足以容纳文件头和校验和", size),
+            ObjectFileError::BadMagic { found } => write!(f, "魔数错误: 0x{:08X}（应为0x{:08X}）", found, OBJECT_MAGIC),
+            ObjectFileError::UnsupportedVersion { found } => write!(f, "不支持的版本: {}（支持{}）", found, OBJECT_VERSION),
+            ObjectFileError::CountMismatch { header, actual } => {
+                write!(f, "文件头记录 {} 个字，文件中实际有 {} 个", header, actual)
+            }
+            ObjectFileError::ChecksumMismatch { stored, computed } => {
+                write!(f, "校验和不匹配: 文件中为0x{:08X}，计算得0x{:08X}", stored, computed)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for ObjectFileError {
+    fn from(e: io::Error) -> Self {
+        ObjectFileError::Io(e)
+    }
+}
+
+// CRC-32（多项式0x04C11DB7按位反转后为0xEDB88320），check("123456789") = 0xCBF43926
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+pub fn encode_object(img: &[u32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(ObjectFileHeader::SIZE + img.len() * 4 + 4);
+    ObjectFileHeader::new(img.len() as u32).write(&mut bytes).unwrap();
+    for &word in img {
+        bytes.extend(word.to_le_bytes());
+    }
+    bytes.extend(crc32(&bytes).to_le_bytes());
+    bytes
+}
+
+// 依次检查长度、魔数、版本、字数，最后检查校验和
+pub fn decode_object(bytes: &[u8]) -> Result<Vec<u32>, ObjectFileError> {
+    if bytes.len() < ObjectFileHeader::SIZE + 4 {
+        return Err(ObjectFileError::Truncated { size: bytes.len() });
+    }
+    let header = ObjectFileHeader::read(&mut &bytes[..ObjectFileHeader::SIZE])?;
+    if header.magic != OBJECT_MAGIC {
+        return Err(ObjectFileError::BadMagic { found: header.magic });
+    }
+    if header.version != OBJECT_VERSION {
+        return Err(ObjectFileError::UnsupportedVersion { found: header.version });
+    }
+    let (content, checksum) = bytes.split_at(bytes.len() - 4);
+    let words = &content[ObjectFileHeader::SIZE..];
+    if words.len() % 4 != 0 || words.len() / 4 != header.instruction_count as usize {
+        return Err(ObjectFileError::CountMismatch { header: header.instruction_count, actual: words.len() / 4 });
+    }
+    let stored = u32::from_le_bytes(checksum.try_into().unwrap());
+    let computed = crc32(content);
+    if stored != computed {
+        return Err(ObjectFileError::ChecksumMismatch { stored, computed });
+    }
+    Ok(words.chunks_exact(4).map(|word| u32::from_le_bytes(word.try_into().unwrap())).collect())
+}
+
+pub fn write_object_file_v2<P: AsRef<Path>>(img: &[u32], path: P) -> io::Result<()> {
+    fs::write(path, encode_object(img))
+}
+
+pub fn read_object_file_v2<P: AsRef<Path>>(path: P) -> Result<Vec<u32>, ObjectFileError> {
+    decode_object(&fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_round_trip() {
+        let header = ObjectFileHeader { magic: 0x1234_5678, version: 0xBEEF, flags: 0x0102, instruction_count: 0xCAFE_0007 };
+        let mut bytes = Vec::new();
+        header.write(&mut bytes).unwrap();
+        assert_eq!(bytes, [0x78, 0x56, 0x34, 0x12, 0xEF, 0xBE, 0x02, 0x01, 0x07, 0x00, 0xFE, 0xCA]);
+
+        let read = ObjectFileHeader::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read.magic, 0x1234_5678);
+        assert_eq!(read.version, 0xBEEF);
+        assert_eq!(read.flags, 0x0102);
+        assert_eq!(read.instruction_count, 0xCAFE_0007);
+
+        let mut bytes = Vec::new();
+        ObjectFileHeader::new(3).write(&mut bytes).unwrap();
+        assert_eq!(&bytes[..4], &[0xDE, 0xC0, 0x5E, 0xA5]);
+        assert_eq!(ObjectFileHeader::read(&mut bytes.as_slice()).unwrap(), ObjectFileHeader::new(3));
+        // 文件头不完整
+        assert!(ObjectFileHeader::read(&mut &bytes[..8]).is_err());
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_object_round_trip() {
+        let img = crate::assemble("addi x1, x0, 10\nadd x1, x1, x3\nhalt").unwrap();
+        let bytes = encode_object(&img);
+        assert_eq!(bytes.len(), 12 + 3 * 4 + 4);
+        assert_eq!(decode_object(&bytes).unwrap(), img);
+        assert_eq!(decode_object(&encode_object(&[])).unwrap(), Vec::<u32>::new());
+
+        let path = std::env::temp_dir().join(format!("riscv_tools_obj_{}.obj", std::process::id()));
+        write_object_file_v2(&img, &path).unwrap();
+        assert_eq!(read_object_file_v2(&path).unwrap(), img);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(read_object_file_v2(&path), Err(ObjectFileError::Io(_))));
+    }
+
+    #[test]
+    fn test_object_corruption() {
+        let bytes = encode_object(&[0x000A_0042, 0x0003_0841, 0]);
+        let corrupt = |offset: usize| {
+            let mut bytes = bytes.clone();
+            bytes[offset] ^= 0x01;
+            decode_object(&bytes).unwrap_err()
+        };
+        assert!(matches!(corrupt(0), ObjectFileError::BadMagic { found: 0xA55E_C0DF }));
+        assert!(matches!(corrupt(4), ObjectFileError::UnsupportedVersion { found: 0 }));
+        assert!(matches!(corrupt(8), ObjectFileError::CountMismatch { header: 2, actual: 3 }));
+        // flags、指令字和校验和本身的改动都由校验和发现
+        for offset in [6, 12, 23, bytes.len() - 1] {
+            assert!(matches!(corrupt(offset), ObjectFileError::ChecksumMismatch { .. }), "偏移 {}", offset);
+        }
+        assert!(matches!(decode_object(&bytes[..15]), Err(ObjectFileError::Truncated { size: 15 })));
+        assert!(matches!(decode_object(&bytes[..bytes.len() - 2]), Err(ObjectFileError::CountMismatch { .. })));
+        assert_eq!(corrupt(0).to_string(), "魔数错误: 0xA55EC0DF（应为0xA55EC0DE）");
+    }
+}
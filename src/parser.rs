@@ -0,0 +1,509 @@
+// 递归下降语法分析：把记号序列转换为语句序列，编码前就能发现寄存器、立即数和语法错误
+
+use std::fmt;
+
+use crate::lexer::Token;
+use crate::{check_offset20, parse_string_literal, ErrorCode, Instruction};
+
+// .word的值或跳转目标：数值，或者要在确定地址后才能求值的标签
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(i32),
+    Label(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Directive {
+    Text,
+    Data,
+    Word(Vec<Value>),
+    Half(Vec<u16>),
+    Byte(Vec<u8>),
+    Space(u32),
+    Align(u32), // 对齐到2^N字节的N
+    Ascii(Vec<u8>),
+    Asciiz(Vec<u8>),
+    Equ { name: String, value: i32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Label(String),
+    Instruction(Instruction),
+    // 跳转目标是标签的分支或jal，instruction中的偏移为0，确定地址后再填入
+    Branch { instruction: Instruction, target: String },
+    Directive(Directive),
+}
+
+// 语法错误，position是出错记号在记号序列中的下标（序列意外结束时为序列长度）
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub code: ErrorCode,
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "记号{}: error[{}]: {}", self.position, self.code.code(), self.message)
+    }
+}
+
+pub fn parse(tokens: &[Token]) -> Result<Vec<Statement>, ParseError> {
+    let mut parser = Parser { tokens, pos: 0 };
+    let mut statements = Vec::new();
+    while parser.pos < tokens.len() {
+        parser.line(&mut statements)?;
+    }
+    Ok(statements)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn error(&self, code: ErrorCode, message: String) -> ParseError {
+        ParseError { code, position: self.pos, message }
+    }
+
+    fn at_line_end(&self) -> bool {
+        matches!(self.peek(), None | Some(Token::Newline) | Some(Token::Comment(_)))
+    }
+
+    // line := (Identifier Colon)* [Mnemonic 操作数...] [Comment] [Newline]
+    fn line(&mut self, out: &mut Vec<Statement>) -> Result<(), ParseError> {
+        while let (Some(Token::Identifier(name)), Some(Token::Colon)) = (self.peek(), self.tokens.get(self.pos + 1)) {
+            out.push(Statement::Label(name.clone()));
+            self.pos += 2;
+        }
+        match self.peek() {
+            Some(Token::Mnemonic(mnemonic)) => {
+                let mnemonic = mnemonic.clone();
+                self.pos += 1;
+                self.statement(&mnemonic, out)?;
+                if !self.at_line_end() {
+                    return Err(self.error(ErrorCode::InvalidSyntax, format!("{} 的操作数过多", mnemonic)));
+                }
+            }
+            _ if self.at_line_end() => {}
+            _ => return Err(self.error(ErrorCode::InvalidSyntax, "行首应为指令或标签".to_string())),
+        }
+        if let Some(Token::Comment(_)) = self.peek() {
+            self.pos += 1;
+        }
+        if let Some(Token::Newline) = self.peek() {
+            self.pos += 1;
+        }
+        Ok(())
+    }
+
+    // 操作数之间的逗号可以省略
+    fn skip_comma(&mut self) {
+        if let Some(Token::Comma) = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn missing(&self, mnemonic: &str, what: &str) -> ParseError {
+        self.error(ErrorCode::MissingOperand, format!("{} 缺少{}", mnemonic, what))
+    }
+
+    fn register(&mut self, mnemonic: &str) -> Result<u8, ParseError> {
+        self.skip_comma();
+        match self.peek() {
+            Some(&Token::Register(reg)) => {
+                self.pos += 1;
+                Ok(reg)
+            }
+            Some(Token::Identifier(name)) => Err(self.error(ErrorCode::InvalidRegister, format!("无效的寄存器: {}", name))),
+            _ if self.at_line_end() => Err(self.missing(mnemonic, "寄存器操作数")),
+            _ => Err(self.error(ErrorCode::InvalidRegister, format!("{} 需要寄存器操作数", mnemonic))),
+        }
+    }
+
+    fn number(&mut self, mnemonic: &str) -> Result<i32, ParseError> {
+        self.skip_comma();
+        match self.peek() {
+            Some(&Token::Immediate(value)) => {
+                self.pos += 1;
+                Ok(value)
+            }
+            _ if self.at_line_end() => Err(self.missing(mnemonic, "立即数操作数")),
+            _ => Err(self.error(ErrorCode::InvalidImmediate, format!("{} 需要立即数操作数", mnemonic))),
+        }
+    }
+
+    // 在min..=max之间的立即数，出错时指向立即数本身
+    fn number_in(&mut self, mnemonic: &str, min: i32, max: i32) -> Result<i32, ParseError> {
+        let value = self.number(mnemonic)?;
+        if value < min || value > max {
+            self.pos -= 1;
+            return Err(self.error(ErrorCode::ImmediateOutOfRange, format!("立即数 {} 超出范围 [{}, {}]", value, min, max)));
+        }
+        Ok(value)
+    }
+
+    // 16位立即数字段，执行时按有符号数扩展，所以只接受有符号范围
+    fn imm16(&mut self, mnemonic: &str) -> Result<i16, ParseError> {
+        Ok(self.number_in(mnemonic, i16::MIN as i32, i16::MAX as i32)? as i16)
+    }
+
+    // lui的立即数是高16位，有符号写法或不超过0xFFFF的无符号写法
+    fn upper16(&mut self, mnemonic: &str) -> Result<i16, ParseError> {
+        Ok(self.number_in(mnemonic, i16::MIN as i32, u16::MAX as i32)? as i16)
+    }
+
+    fn shamt(&mut self, mnemonic: &str) -> Result<i16, ParseError> {
+        Ok(self.number_in(mnemonic, 0, 31)? as i16)
+    }
+
+    // offset(rs1)
+    fn memory(&mut self, mnemonic: &str) -> Result<(i16, u8), ParseError> {
+        let offset = self.imm16(mnemonic)?;
+        if self.peek() != Some(&Token::LParen) {
+            return Err(self.error(ErrorCode::InvalidSyntax, format!("无效的{}格式，应为 offset(rs1)", mnemonic)));
+        }
+        self.pos += 1;
+        let rs1 = self.register(mnemonic)?;
+        if self.peek() != Some(&Token::RParen) {
+            return Err(self.error(ErrorCode::InvalidSyntax, format!("无效的{}格式，缺少右括号", mnemonic)));
+        }
+        self.pos += 1;
+        Ok((offset, rs1))
+    }
+
+    fn value(&mut self, mnemonic: &str) -> Result<Value, ParseError> {
+        self.skip_comma();
+        if let Some(Token::Identifier(name)) = self.peek() {
+            let name = name.clone();
+            self.pos += 1;
+            return Ok(Value::Label(name));
+        }
+        self.number(mnemonic).map(Value::Number)
+    }
+
+    // 分支和jal的目标：数值偏移直接填入，标签留待之后解析
+    fn jump(&mut self, mnemonic: &str, rd_or_rs1: u8, rs2: u8, out: &mut Vec<Statement>) -> Result<(), ParseError> {
+        self.skip_comma();
+        let start = self.pos;
+        let target = self.value(mnemonic)?;
+        let offset = match target {
+            Value::Label(_) => 0,
+            Value::Number(value) if mnemonic == "jal" || mnemonic == "j" || mnemonic == "call" => {
+                check_offset20(value).map_err(|e| ParseError { code: ErrorCode::ImmediateOutOfRange, position: start, message: e })?
+            }
+            Value::Number(value) => i16::try_from(value).map_err(|_| ParseError {
+                code: ErrorCode::ImmediateOutOfRange,
+                position: start,
+                message: format!("分支偏移 {} 超出16位有符号范围", value),
+            })? as i32,
+        };
+        let (rs1, offset16) = (rd_or_rs1, offset as i16);
+        let instruction = match mnemonic {
+            "jal" | "j" | "call" => Instruction::Jal { rd: rd_or_rs1, offset },
+            "bne" | "bnez" => Instruction::Bne { rs1, rs2, offset: offset16 },
+            "beq" | "beqz" => Instruction::Beq { rs1, rs2, offset: offset16 },
+            "bge" | "bgez" => Instruction::Bge { rs1, rs2, offset: offset16 },
+            "bgeu" => Instruction::Bgeu { rs1, rs2, offset: offset16 },
+            _ => Instruction::Blt { rs1, rs2, offset: offset16 },
+        };
+        out.push(match target {
+            Value::Label(target) => Statement::Branch { instruction, target },
+            Value::Number(_) => Statement::Instruction(instruction),
+        });
+        Ok(())
+    }
+
+    // 数据伪指令以逗号分隔的数值列表，至少一个
+    fn numbers(&mut self, mnemonic: &str, max: i32) -> Result<Vec<i32>, ParseError> {
+        let mut values = vec![self.number_in(mnemonic, 0, max)?];
+        while !self.at_line_end() {
+            values.push(self.number_in(mnemonic, 0, max)?);
+        }
+        Ok(values)
+    }
+
+    fn string(&mut self, mnemonic: &str) -> Result<Vec<u8>, ParseError> {
+        match self.peek() {
+            Some(Token::Str(literal)) => {
+                let bytes = parse_string_literal(literal).map_err(|e| self.error(ErrorCode::InvalidSyntax, e))?;
+                self.pos += 1;
+                Ok(bytes)
+            }
+            _ if self.at_line_end() => Err(self.missing(mnemonic, "字符串操作数")),
+            _ => Err(self.error(ErrorCode::InvalidSyntax, format!("{} 需要带双引号的字符串", mnemonic))),
+        }
+    }
+
+    fn statement(&mut self, mnemonic: &str, out: &mut Vec<Statement>) -> Result<(), ParseError> {
+        type Rrr = fn(u8, u8, u8) -> Instruction;
+        type Rri = fn(u8, u8, i16) -> Instruction;
+        type Rr = fn(u8, u8) -> Instruction;
+
+        let rrr: Option<Rrr> = match mnemonic {
+            "add" => Some(|rd, rs1, rs2| Instruction::Add { rd, rs1, rs2 }),
+            "mul" => Some(|rd, rs1, rs2| Instruction::Mul { rd, rs1, rs2 }),
+            "sub" => Some(|rd, rs1, rs2| Instruction::Sub { rd, rs1, rs2 }),
+            "or" => Some(|rd, rs1, rs2| Instruction::Or { rd, rs1, rs2 }),
+            "and" => Some(|rd, rs1, rs2| Instruction::And { rd, rs1, rs2 }),
+            "xor" => Some(|rd, rs1, rs2| Instruction::Xor { rd, rs1, rs2 }),
+            "nor" => Some(|rd, rs1, rs2| Instruction::Nor { rd, rs1, rs2 }),
+            "srl" => Some(|rd, rs1, rs2| Instruction::Srl { rd, rs1, rs2 }),
+            "sra" => Some(|rd, rs1, rs2| Instruction::Sra { rd, rs1, rs2 }),
+            "slt" => Some(|rd, rs1, rs2| Instruction::Slt { rd, rs1, rs2 }),
+            "sltu" => Some(|rd, rs1, rs2| Instruction::Sltu { rd, rs1, rs2 }),
+            _ => None,
+        };
+        if let Some(make) = rrr {
+            let (rd, rs1, rs2) = (self.register(mnemonic)?, self.register(mnemonic)?, self.register(mnemonic)?);
+            out.push(Statement::Instruction(make(rd, rs1, rs2)));
+            return Ok(());
+        }
+
+        let rri: Option<Rri> = match mnemonic {
+            "addi" => Some(|rd, rs1, imm| Instruction::Addi { rd, rs1, imm }),
+            "andi" => Some(|rd, rs1, imm| Instruction::Andi { rd, rs1, imm }),
+            "ori" => Some(|rd, rs1, imm| Instruction::Ori { rd, rs1, imm }),
+            "xori" => Some(|rd, rs1, imm| Instruction::Xori { rd, rs1, imm }),
+            "slti" => Some(|rd, rs1, imm| Instruction::Slti { rd, rs1, imm }),
+            "jalr" => Some(|rd, rs1, imm| Instruction::Jalr { rd, rs1, imm }),
+            _ => None,
+        };
+        if let Some(make) = rri {
+            let (rd, rs1, imm) = (self.register(mnemonic)?, self.register(mnemonic)?, self.imm16(mnemonic)?);
+            out.push(Statement::Instruction(make(rd, rs1, imm)));
+            return Ok(());
+        }
+
+        let shift: Option<Rri> = match mnemonic {
+            "slli" => Some(|rd, rs1, imm| Instruction::Slli { rd, rs1, imm }),
+            "srli" => Some(|rd, rs1, imm| Instruction::Srli { rd, rs1, imm }),
+            "srai" => Some(|rd, rs1, imm| Instruction::Srai { rd, rs1, imm }),
+            _ => None,
+        };
+        if let Some(make) = shift {
+            let (rd, rs1, imm) = (self.register(mnemonic)?, self.register(mnemonic)?, self.shamt(mnemonic)?);
+            out.push(Statement::Instruction(make(rd, rs1, imm)));
+            return Ok(());
+        }
+
+        let rr: Option<Rr> = match mnemonic {
+            "ctz" => Some(|rd, rs1| Instruction::Ctz { rd, rs1 }),
+            "cpop" => Some(|rd, rs1| Instruction::Cpop { rd, rs1 }),
+            "rev8" => Some(|rd, rs1| Instruction::Rev8 { rd, rs1 }),
+            "brev8" => Some(|rd, rs1| Instruction::Brev8 { rd, rs1 }),
+            "clz" => Some(|rd, rs1| Instruction::Clz { rd, rs1 }),
+            "mov" => Some(|rd, rs1| Instruction::Addi { rd, rs1, imm: 0 }),
+            "not" => Some(|rd, rs1| Instruction::Nor { rd, rs1, rs2: 0 }),
+            _ => None,
+        };
+        if let Some(make) = rr {
+            let (rd, rs1) = (self.register(mnemonic)?, self.register(mnemonic)?);
+            out.push(Statement::Instruction(make(rd, rs1)));
+            return Ok(());
+        }
+
+        let statement = match mnemonic {
+            "halt" => Statement::Instruction(Instruction::Halt),
+            "nop" => Statement::Instruction(Instruction::Addi { rd: 0, rs1: 0, imm: 0 }),
+            "ret" => Statement::Instruction(Instruction::Jalr { rd: 0, rs1: 1, imm: 0 }),
+            "zero" | "zero_reg" => Statement::Instruction(Instruction::Addi { rd: self.register(mnemonic)?, rs1: 0, imm: 0 }),
+            "lui" => {
+                let rd = self.register(mnemonic)?;
+                Statement::Instruction(Instruction::Lui { rd, imm: self.upper16(mnemonic)? })
+            }
+            "lw" | "lb" | "lh" | "lbu" | "lhu" => {
+                let rd = self.register(mnemonic)?;
+                let (offset, rs1) = self.memory(mnemonic)?;
+                Statement::Instruction(match mnemonic {
+                    "lb" => Instruction::Lb { rd, rs1, offset },
+                    "lh" => Instruction::Lh { rd, rs1, offset },
+                    "lbu" => Instruction::Lbu { rd, rs1, offset },
+                    "lhu" => Instruction::Lhu { rd, rs1, offset },
+                    _ => Instruction::Lw { rd, rs1, offset },
+                })
+            }
+            "sw" | "sb" | "sh" => {
+                let rs2 = self.register(mnemonic)?;
+                let (offset, rs1) = self.memory(mnemonic)?;
+                Statement::Instruction(match mnemonic {
+                    "sb" => Instruction::Sb { rs1, rs2, offset },
+                    "sh" => Instruction::Sh { rs1, rs2, offset },
+                    _ => Instruction::Sw { rs1, rs2, offset },
+                })
+            }
+            "li" => {
+                let rd = self.register(mnemonic)?;
+                let value = self.number(mnemonic)?;
+                for word in crate::encode_li(rd, value) {
+                    out.push(Statement::Instruction(Instruction::decode(word).unwrap()));
+                }
+                return Ok(());
+            }
+            "bne" | "beq" | "bge" | "bgeu" | "blt" => {
+                let (rs1, rs2) = (self.register(mnemonic)?, self.register(mnemonic)?);
+                return self.jump(mnemonic, rs1, rs2, out);
+            }
+            // beqz rs 即 beq rs, x0，其余类推
+            "beqz" | "bnez" | "bgez" | "bltz" => {
+                let rs1 = self.register(mnemonic)?;
+                return self.jump(mnemonic, rs1, 0, out);
+            }
+            "jal" => {
+                let rd = self.register(mnemonic)?;
+                return self.jump(mnemonic, rd, 0, out);
+            }
+            "j" => return self.jump(mnemonic, 0, 0, out),
+            "call" => return self.jump(mnemonic, 1, 0, out),
+            ".text" => Statement::Directive(Directive::Text),
+            ".data" => Statement::Directive(Directive::Data),
+            ".section" => match self.peek() {
+                Some(Token::Identifier(name)) if name == ".text" || name == ".data" => {
+                    let directive = if name == ".text" { Directive::Text } else { Directive::Data };
+                    self.pos += 1;
+                    Statement::Directive(directive)
+                }
+                _ if self.at_line_end() => return Err(self.missing(mnemonic, "段名")),
+                _ => return Err(self.error(ErrorCode::InvalidSyntax, "只支持.text和.data段".to_string())),
+            },
+            ".word" => {
+                let mut values = vec![self.value(mnemonic)?];
+                while !self.at_line_end() {
+                    values.push(self.value(mnemonic)?);
+                }
+                Statement::Directive(Directive::Word(values))
+            }
+            ".half" => Statement::Directive(Directive::Half(
+                self.numbers(mnemonic, u16::MAX as i32)?.into_iter().map(|v| v as u16).collect())),
+            ".byte" => Statement::Directive(Directive::Byte(
+                self.numbers(mnemonic, u8::MAX as i32)?.into_iter().map(|v| v as u8).collect())),
+            ".space" => Statement::Directive(Directive::Space(self.number_in(mnemonic, 1, i32::MAX)? as u32)),
+            ".align" => Statement::Directive(Directive::Align(self.number_in(mnemonic, 1, 16)? as u32)),
+            ".ascii" => Statement::Directive(Directive::Ascii(self.string(mnemonic)?)),
+            ".asciiz" => {
+                let mut bytes = self.string(mnemonic)?;
+                bytes.push(0);
+                Statement::Directive(Directive::Asciiz(bytes))
+            }
+            ".equ" => {
+                let Some(Token::Identifier(name)) = self.peek() else {
+                    return Err(self.error(ErrorCode::InvalidSyntax, "无效的.equ格式（应为 .equ NAME, value）".to_string()));
+                };
+                let name = name.clone();
+                self.pos += 1;
+                let value = self.number(mnemonic)?;
+                Statement::Directive(Directive::Equ { name, value })
+            }
+            _ => {
+                self.pos -= 1;
+                return Err(self.error(ErrorCode::UnknownInstruction, format!("未知指令: {}", mnemonic)));
+            }
+        };
+        out.push(statement);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mnemonic(name: &str) -> Token {
+        Token::Mnemonic(name.to_string())
+    }
+
+    fn ident(name: &str) -> Token {
+        Token::Identifier(name.to_string())
+    }
+
+    #[test]
+    fn test_parse_instructions() {
+        let tokens = vec![
+            ident("loop"), Token::Colon,
+            mnemonic("addi"), Token::Register(1), Token::Comma, Token::Register(1), Token::Comma, Token::Immediate(-1),
+            Token::Comment(" 计数".to_string()), Token::Newline,
+            mnemonic("sw"), Token::Register(1), Token::Comma,
+            Token::Immediate(8), Token::LParen, Token::Register(2), Token::RParen, Token::Newline,
+            mnemonic("bne"), Token::Register(1), Token::Comma, Token::Register(0), Token::Comma, ident("loop"), Token::Newline,
+            mnemonic("li"), Token::Register(5), Token::Immediate(0x12345678), Token::Newline,
+            mnemonic("halt"),
+        ];
+        assert_eq!(parse(&tokens), Ok(vec![
+            Statement::Label("loop".to_string()),
+            Statement::Instruction(Instruction::Addi { rd: 1, rs1: 1, imm: -1 }),
+            Statement::Instruction(Instruction::Sw { rs1: 2, rs2: 1, offset: 8 }),
+            Statement::Branch { instruction: Instruction::Bne { rs1: 1, rs2: 0, offset: 0 }, target: "loop".to_string() },
+            Statement::Instruction(Instruction::Lui { rd: 5, imm: 0x1234 }),
+            Statement::Instruction(Instruction::Addi { rd: 5, rs1: 5, imm: 0x5678 }),
+            Statement::Instruction(Instruction::Halt),
+        ]));
+    }
+
+    #[test]
+    fn test_parse_directives() {
+        let tokens = vec![
+            mnemonic(".data"), Token::Newline,
+            mnemonic(".word"), Token::Immediate(-1), Token::Comma, ident("main"), Token::Newline,
+            mnemonic(".byte"), Token::Immediate(1), Token::Comma, Token::Immediate(255), Token::Newline,
+            mnemonic(".asciiz"), Token::Str("\"hi\\n\"".to_string()), Token::Newline,
+            mnemonic(".equ"), ident("SIZE"), Token::Comma, Token::Immediate(16),
+        ];
+        assert_eq!(parse(&tokens), Ok(vec![
+            Statement::Directive(Directive::Data),
+            Statement::Directive(Directive::Word(vec![Value::Number(-1), Value::Label("main".to_string())])),
+            Statement::Directive(Directive::Byte(vec![1, 255])),
+            Statement::Directive(Directive::Asciiz(b"hi\n\0".to_vec())),
+            Statement::Directive(Directive::Equ { name: "SIZE".to_string(), value: 16 }),
+        ]));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |tokens: &[Token]| parse(tokens).map(|_| ()).unwrap_err();
+
+        // 寄存器位置上是标识符：指向该记号
+        let e = error(&[mnemonic("add"), Token::Register(1), Token::Comma, ident("x32"), Token::Comma, Token::Register(2)]);
+        assert_eq!((e.code, e.position), (ErrorCode::InvalidRegister, 3));
+        // 立即数超出16位
+        let e = error(&[mnemonic("addi"), Token::Register(1), Token::Register(0), Token::Immediate(70000)]);
+        assert_eq!((e.code, e.position), (ErrorCode::ImmediateOutOfRange, 3));
+        // 0x8000~0xFFFF只有lui可以用
+        let e = error(&[mnemonic("addi"), Token::Register(1), Token::Register(0), Token::Immediate(0xFFFF)]);
+        assert_eq!((e.code, e.position), (ErrorCode::ImmediateOutOfRange, 3));
+        assert_eq!(parse(&[mnemonic("lui"), Token::Register(1), Token::Immediate(0xFFFF)]),
+            Ok(vec![Statement::Instruction(Instruction::Lui { rd: 1, imm: -1 })]));
+        // 移位量超出0~31
+        let e = error(&[mnemonic("slli"), Token::Register(1), Token::Register(1), Token::Immediate(32)]);
+        assert_eq!((e.code, e.position), (ErrorCode::ImmediateOutOfRange, 3));
+        // 缺少操作数：指向行尾
+        let e = error(&[mnemonic("lw"), Token::Register(1), Token::Newline, mnemonic("halt")]);
+        assert_eq!((e.code, e.position), (ErrorCode::MissingOperand, 2));
+        // 缺少右括号
+        let e = error(&[mnemonic("lw"), Token::Register(1), Token::Immediate(0), Token::LParen, Token::Register(2)]);
+        assert_eq!((e.code, e.position), (ErrorCode::InvalidSyntax, 5));
+        // 多余的操作数、未知指令
+        let e = error(&[mnemonic("halt"), Token::Register(1)]);
+        assert_eq!((e.code, e.position), (ErrorCode::InvalidSyntax, 1));
+        let e = error(&[Token::Newline, mnemonic("foo")]);
+        assert_eq!((e.code, e.position), (ErrorCode::UnknownInstruction, 1));
+        assert_eq!(e.to_string(), "记号1: error[E001]: 未知指令: foo");
+    }
+
+    #[test]
+    fn test_parse_tokenized_source() {
+        let source = "main: li a0, 10\n  sw a0, 0(sp)  # 保存\n  lbu t0, 1(sp)\n  j main\n";
+        assert_eq!(parse(&crate::lexer::tokenize(source)), Ok(vec![
+            Statement::Label("main".to_string()),
+            Statement::Instruction(Instruction::Addi { rd: 10, rs1: 0, imm: 10 }),
+            Statement::Instruction(Instruction::Sw { rs1: 2, rs2: 10, offset: 0 }),
+            Statement::Instruction(Instruction::Lbu { rd: 5, rs1: 2, offset: 1 }),
+            Statement::Branch { instruction: Instruction::Jal { rd: 0, offset: 0 }, target: "main".to_string() },
+        ]));
+    }
+}
@@ -0,0 +1,1070 @@
+// 指令级模拟器：程序按小端字节序装入内存地址0处，从pc=0开始执行到halt
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::time::Duration;
+
+use crate::object::crc32;
+use crate::{lookup_opcode, register_operands, Instruction, ABI_NAMES};
+
+// 模拟器内存大小（字节）
+pub const MEMORY_SIZE: usize = 64 * 1024;
+
+// 检查点文件的魔数，按小端写出后文件以 52 56 43 4B ("RVCK") 开头
+pub const CHECKPOINT_MAGIC: u32 = 0x4B43_5652;
+
+// 访存类型
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessType {
+    Read,
+    Write,
+}
+
+impl AccessType {
+    pub fn name(self) -> &'static str {
+        match self {
+            AccessType::Read => "读取",
+            AccessType::Write => "写入",
+        }
+    }
+}
+
+// 内存区域的访问权限
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Permission {
+    ReadOnly,
+    ReadWrite,
+    WriteOnly,
+    NoAccess,
+}
+
+impl Permission {
+    // 命令行中的写法: RO、RW、WO、NA
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "RO" => Some(Permission::ReadOnly),
+            "RW" => Some(Permission::ReadWrite),
+            "WO" => Some(Permission::WriteOnly),
+            "NA" => Some(Permission::NoAccess),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Permission::ReadOnly => "RO",
+            Permission::ReadWrite => "RW",
+            Permission::WriteOnly => "WO",
+            Permission::NoAccess => "NA",
+        }
+    }
+
+    fn allows(self, access_type: AccessType) -> bool {
+        matches!((self, access_type),
+            (Permission::ReadWrite, _) | (Permission::ReadOnly, AccessType::Read) | (Permission::WriteOnly, AccessType::Write))
+    }
+}
+
+// 受保护的内存区域，包含start，不包含end
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryRegion {
+    pub start: u32,
+    pub end: u32,
+    pub permission: Permission,
+}
+
+// 一条访存指令的访问：地址、字节数、类型，以及读到或写入的值（零扩展）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryAccess {
+    pub address: u32,
+    pub len: usize,
+    pub access_type: AccessType,
+    pub value: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimError {
+    ProgramTooLarge { size: usize },       // 程序字节数超过内存大小
+    PcOutOfRange { pc: usize },            // pc超出内存或不是4的倍数
+    IllegalInstruction { pc: usize, word: u32 },
+    MemoryFault { pc: usize, address: u32, access_type: AccessType }, // 访存地址超出内存
+    StackOverflow { pc: usize, sp: u32 },  // sp低于栈的下限，由调用方检测
+    ProtectionFault { pc: usize, address: u32, access_type: AccessType, permission: Permission },
+}
+
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimError::ProgramTooLarge { size } => write!(f, "程序大小 {} 字节超出内存大小 {} 字节", size, MEMORY_SIZE),
+            SimError::PcOutOfRange { pc } => write!(f, "pc=0x{:08X} 超出内存范围或未按4字节对齐", pc),
+            SimError::IllegalInstruction { pc, word } => write!(f, "pc=0x{:08X}: 非法指令 0x{:08X}", pc, word),
+            SimError::MemoryFault { pc, address, access_type } => {
+                write!(f, "pc=0x{:08X}: {}地址 0x{:08X} 超出内存范围", pc, access_type.name(), address)
+            }
+            SimError::ProtectionFault { pc, address, access_type, permission } => {
+                write!(f, "pc=0x{:08X}: {}地址 0x{:08X} 违反内存保护（{}）", pc, access_type.name(), address, permission.name())
+            }
+            SimError::StackOverflow { pc, sp } => write!(f, "STACK OVERFLOW at PC=0x{:04X}, sp=0x{:08X}", pc, sp),
+        }
+    }
+}
+
+// 处理器状态：32个寄存器、pc（字节地址）、已执行的指令条数和内存
+#[derive(Debug, Clone)]
+pub struct CpuState {
+    pub regs: [i32; 32],
+    pub pc: usize,
+    pub cycles: u32, // 每条指令（含halt）计1个周期，按32位回绕
+    pub memory: Vec<u8>,
+    pub last_access: Option<MemoryAccess>, // 最近执行的一条指令的访存，不是访存指令时为None
+    pub regions: Vec<MemoryRegion>,        // 访存指令受其限制的区域，重叠时后面的优先；不限制取指
+}
+
+impl CpuState {
+    pub fn new(program: &[u32]) -> Result<Self, SimError> {
+        if program.len() * 4 > MEMORY_SIZE {
+            return Err(SimError::ProgramTooLarge { size: program.len() * 4 });
+        }
+        let mut memory = vec![0; MEMORY_SIZE];
+        for (i, word) in program.iter().enumerate() {
+            memory[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        Ok(CpuState { regs: [0; 32], pc: 0, cycles: 0, memory, last_access: None, regions: Vec::new() })
+    }
+
+    // 检查点格式，所有字段按小端字节序存放:
+    //   magic[4]  寄存器[32 * 4]  pc[4]  周期数[4]  内存[MEMORY_SIZE]
+    pub fn save_state<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(&CHECKPOINT_MAGIC.to_le_bytes())?;
+        for value in self.regs {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        let pc = u32::try_from(self.pc).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "pc超出32位"))?;
+        out.write_all(&pc.to_le_bytes())?;
+        out.write_all(&self.cycles.to_le_bytes())?;
+        out.write_all(&self.memory)
+    }
+
+    // 读取save_state写出的检查点，魔数不对或内存大小不符时返回InvalidData
+    pub fn load_state<R: Read>(input: &mut R) -> io::Result<Self> {
+        let read_u32 = |input: &mut R| -> io::Result<u32> {
+            let mut bytes = [0; 4];
+            input.read_exact(&mut bytes)?;
+            Ok(u32::from_le_bytes(bytes))
+        };
+        let magic = read_u32(input)?;
+        if magic != CHECKPOINT_MAGIC {
+            let message = format!("检查点魔数错误: 0x{:08X}（应为0x{:08X}）", magic, CHECKPOINT_MAGIC);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        let mut regs = [0; 32];
+        for value in regs.iter_mut() {
+            *value = read_u32(input)? as i32;
+        }
+        let pc = read_u32(input)? as usize;
+        let cycles = read_u32(input)?;
+        let mut memory = Vec::with_capacity(MEMORY_SIZE);
+        input.read_to_end(&mut memory)?;
+        if memory.len() != MEMORY_SIZE {
+            let message = format!("检查点中的内存为 {} 字节（应为{}）", memory.len(), MEMORY_SIZE);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        // x0恒为0
+        regs[0] = 0;
+        Ok(CpuState { regs, pc, cycles, memory, last_access: None, regions: Vec::new() })
+    }
+
+    // 写入x0没有效果
+    fn set_reg(&mut self, rd: u8, value: i32) {
+        if rd != 0 {
+            self.regs[rd as usize] = value;
+        }
+    }
+
+    fn reg(&self, r: u8) -> i32 {
+        self.regs[r as usize]
+    }
+
+    // 取出pc处的指令字
+    pub fn fetch(&self) -> Result<u32, SimError> {
+        match u32::try_from(self.pc) {
+            Ok(pc) if self.pc.is_multiple_of(4) => self.load_word(pc).map_err(|_| SimError::PcOutOfRange { pc: self.pc }),
+            _ => Err(SimError::PcOutOfRange { pc: self.pc }),
+        }
+    }
+
+    // 从address开始的len个字节在内存中的范围，超出内存时报告当前pc处的访存错误
+    fn range(&self, address: u32, len: usize, access_type: AccessType) -> Result<Range<usize>, SimError> {
+        let start = address as usize;
+        if start + len > self.memory.len() {
+            return Err(SimError::MemoryFault { pc: self.pc, address, access_type });
+        }
+        Ok(start..start + len)
+    }
+
+    // 按小端字节序读取len（1、2或4）个字节，零扩展为32位
+    fn load(&self, address: u32, len: usize) -> Result<u32, SimError> {
+        let range = self.range(address, len, AccessType::Read)?;
+        Ok(self.memory[range].iter().rev().fold(0, |value, &byte| value << 8 | byte as u32))
+    }
+
+    // 按小端字节序读取一个字，不要求对齐
+    pub fn load_word(&self, address: u32) -> Result<u32, SimError> {
+        self.load(address, 4)
+    }
+
+    // 按小端字节序写入value的低len（1、2或4）个字节，返回原来的值
+    fn store(&mut self, address: u32, len: usize, value: u32) -> Result<u32, SimError> {
+        let range = self.range(address, len, AccessType::Write)?;
+        let old = self.load(address, len)?;
+        self.memory[range].copy_from_slice(&value.to_le_bytes()[..len]);
+        Ok(old)
+    }
+
+    // 按小端字节序写入一个字，返回原来的值
+    pub fn store_word(&mut self, address: u32, value: u32) -> Result<u32, SimError> {
+        self.store(address, 4, value)
+    }
+
+    // 访问的每个字节都要在所在区域的权限之内，不在任何区域内的字节不受限制
+    fn check_protection(&self, address: u32, len: usize, access_type: AccessType) -> Result<(), SimError> {
+        for byte in (0..len as u32).map(|i| address.wrapping_add(i)) {
+            let region = self.regions.iter().rev().find(|region| (region.start..region.end).contains(&byte));
+            if let Some(&MemoryRegion { permission, .. }) = region.filter(|region| !region.permission.allows(access_type)) {
+                return Err(SimError::ProtectionFault { pc: self.pc, address, access_type, permission });
+            }
+        }
+        Ok(())
+    }
+
+    // 访存指令的读取，记录到last_access
+    fn access_load(&mut self, address: u32, len: usize) -> Result<u32, SimError> {
+        self.check_protection(address, len, AccessType::Read)?;
+        let value = self.load(address, len)?;
+        self.last_access = Some(MemoryAccess { address, len, access_type: AccessType::Read, value });
+        Ok(value)
+    }
+
+    // 访存指令的写入，记录到last_access的是实际写入的低len个字节
+    fn access_store(&mut self, address: u32, len: usize, value: u32) -> Result<(), SimError> {
+        self.check_protection(address, len, AccessType::Write)?;
+        self.store(address, len, value)?;
+        let value = value & (u64::MAX >> (64 - 8 * len)) as u32;
+        self.last_access = Some(MemoryAccess { address, len, access_type: AccessType::Write, value });
+        Ok(())
+    }
+
+    // x[rd] = sext(imm) << 16：imm先符号扩展到32位再左移，负数的高16位为1
+    fn simulate_lui(&mut self, rd: u8, imm: i16) {
+        self.set_reg(rd, (imm as i32) << 16);
+    }
+
+    // rs1加符号扩展的偏移得到访存地址
+    fn address(&self, rs1: u8, offset: i16) -> u32 {
+        self.reg(rs1).wrapping_add(offset as i32) as u32
+    }
+
+    // 执行一条指令，执行了halt时返回false（pc停在halt上）
+    pub fn step(&mut self) -> Result<bool, SimError> {
+        let pc = self.pc;
+        let word = self.fetch()?;
+        let instruction = Instruction::decode(word).ok_or(SimError::IllegalInstruction { pc, word })?;
+        self.cycles = self.cycles.wrapping_add(1);
+        self.last_access = None;
+        let mut next_pc = (pc as u32).wrapping_add(4) as usize;
+        // 分支和跳转的偏移相对于当前指令的地址，地址按32位回绕
+        let target = |offset: i32| (pc as u32).wrapping_add(offset as u32) as usize;
+
+        match instruction {
+            Instruction::Halt => return Ok(false),
+            Instruction::Add { rd, rs1, rs2 } => self.set_reg(rd, self.reg(rs1).wrapping_add(self.reg(rs2))),
+            Instruction::Mul { rd, rs1, rs2 } => self.set_reg(rd, self.reg(rs1).wrapping_mul(self.reg(rs2))),
+            Instruction::Sub { rd, rs1, rs2 } => self.set_reg(rd, self.reg(rs1).wrapping_sub(self.reg(rs2))),
+            Instruction::Or { rd, rs1, rs2 } => self.set_reg(rd, self.reg(rs1) | self.reg(rs2)),
+            Instruction::And { rd, rs1, rs2 } => self.set_reg(rd, self.reg(rs1) & self.reg(rs2)),
+            Instruction::Xor { rd, rs1, rs2 } => self.set_reg(rd, self.reg(rs1) ^ self.reg(rs2)),
+            Instruction::Nor { rd, rs1, rs2 } => self.set_reg(rd, !(self.reg(rs1) | self.reg(rs2))),
+            Instruction::Srl { rd, rs1, rs2 } => self.set_reg(rd, ((self.reg(rs1) as u32) >> (self.reg(rs2) & 31)) as i32),
+            Instruction::Sra { rd, rs1, rs2 } => self.set_reg(rd, self.reg(rs1) >> (self.reg(rs2) & 31)),
+            Instruction::Slt { rd, rs1, rs2 } => self.set_reg(rd, (self.reg(rs1) < self.reg(rs2)) as i32),
+            Instruction::Sltu { rd, rs1, rs2 } => self.set_reg(rd, ((self.reg(rs1) as u32) < (self.reg(rs2) as u32)) as i32),
+            Instruction::Ctz { rd, rs1 } => self.set_reg(rd, self.reg(rs1).trailing_zeros() as i32),
+            Instruction::Cpop { rd, rs1 } => self.set_reg(rd, self.reg(rs1).count_ones() as i32),
+            Instruction::Clz { rd, rs1 } => self.set_reg(rd, self.reg(rs1).leading_zeros() as i32),
+            Instruction::Rev8 { rd, rs1 } => self.set_reg(rd, self.reg(rs1).swap_bytes()),
+            Instruction::Brev8 { rd, rs1 } => self.set_reg(rd, self.reg(rs1).swap_bytes().reverse_bits()),
+            Instruction::Addi { rd, rs1, imm } => self.set_reg(rd, self.reg(rs1).wrapping_add(imm as i32)),
+            Instruction::Andi { rd, rs1, imm } => self.set_reg(rd, self.reg(rs1) & imm as i32),
+            Instruction::Ori { rd, rs1, imm } => self.set_reg(rd, self.reg(rs1) | imm as i32),
+            Instruction::Xori { rd, rs1, imm } => self.set_reg(rd, self.reg(rs1) ^ imm as i32),
+            Instruction::Slti { rd, rs1, imm } => self.set_reg(rd, (self.reg(rs1) < imm as i32) as i32),
+            Instruction::Lui { rd, imm } => self.simulate_lui(rd, imm),
+            Instruction::Slli { rd, rs1, imm } => self.set_reg(rd, self.reg(rs1) << (imm & 31)),
+            Instruction::Srli { rd, rs1, imm } => self.set_reg(rd, ((self.reg(rs1) as u32) >> (imm & 31)) as i32),
+            Instruction::Srai { rd, rs1, imm } => self.set_reg(rd, self.reg(rs1) >> (imm & 31)),
+            Instruction::Lw { rd, rs1, offset } => {
+                let value = self.access_load(self.address(rs1, offset), 4)?;
+                self.set_reg(rd, value as i32);
+            }
+            Instruction::Lb { rd, rs1, offset } => {
+                let value = self.access_load(self.address(rs1, offset), 1)?;
+                self.set_reg(rd, value as i8 as i32);
+            }
+            Instruction::Lh { rd, rs1, offset } => {
+                let value = self.access_load(self.address(rs1, offset), 2)?;
+                self.set_reg(rd, value as i16 as i32);
+            }
+            Instruction::Lbu { rd, rs1, offset } => {
+                let value = self.access_load(self.address(rs1, offset), 1)?;
+                self.set_reg(rd, value as i32);
+            }
+            Instruction::Lhu { rd, rs1, offset } => {
+                let value = self.access_load(self.address(rs1, offset), 2)?;
+                self.set_reg(rd, value as i32);
+            }
+            Instruction::Sw { rs1, rs2, offset } => self.access_store(self.address(rs1, offset), 4, self.reg(rs2) as u32)?,
+            Instruction::Sb { rs1, rs2, offset } => self.access_store(self.address(rs1, offset), 1, self.reg(rs2) as u32)?,
+            Instruction::Sh { rs1, rs2, offset } => self.access_store(self.address(rs1, offset), 2, self.reg(rs2) as u32)?,
+            Instruction::Bne { .. } | Instruction::Beq { .. } | Instruction::Blt { .. }
+            | Instruction::Bge { .. } | Instruction::Bgeu { .. } => {
+                if let Some((true, branch_to)) = branch_outcome(instruction, &self.regs, pc as u32) {
+                    next_pc = branch_to as usize;
+                }
+            }
+            Instruction::Jal { rd, offset } => {
+                self.set_reg(rd, next_pc as i32);
+                next_pc = target(offset);
+            }
+            // 先算跳转目标再写rd，rd与rs1相同时也正确
+            Instruction::Jalr { rd, rs1, imm } => {
+                let jump_to = self.address(rs1, imm) as usize;
+                self.set_reg(rd, next_pc as i32);
+                next_pc = jump_to;
+            }
+        }
+        self.pc = next_pc;
+        Ok(true)
+    }
+
+    // 执行到halt，返回执行的指令条数（含halt）
+    pub fn run(&mut self) -> Result<usize, SimError> {
+        let mut count = 1;
+        while self.step()? {
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    // 同run，每条指令执行后通知tracer
+    pub fn run_traced(&mut self, tracer: &mut dyn Tracer) -> Result<usize, SimError> {
+        let mut count = 0;
+        loop {
+            let (pc, word) = (self.pc, self.fetch()?);
+            let running = self.step()?;
+            count += 1;
+            tracer.on_execute(pc as u32, word, self);
+            if !running {
+                return Ok(count);
+            }
+        }
+    }
+
+    // 每行4个寄存器: x1 (ra) = 十进制 (0x十六进制)
+    pub fn format_registers(&self) -> String {
+        let mut output = String::new();
+        for (i, value) in self.regs.iter().enumerate() {
+            let name = format!("x{} ({})", i, ABI_NAMES[i]);
+            output.push_str(&format!("{:<10} = {:>11} (0x{:08X})", name, value, value));
+            output.push_str(if i % 4 == 3 { "\n" } else { "  " });
+        }
+        output
+    }
+}
+
+// 条件分支在regs下是否跳转，以及跳转目标；不是条件分支时返回None
+pub fn branch_outcome(instruction: Instruction, regs: &[i32; 32], pc: u32) -> Option<(bool, u32)> {
+    let reg = |r: u8| regs[r as usize];
+    let (taken, offset) = match instruction {
+        Instruction::Bne { rs1, rs2, offset } => (reg(rs1) != reg(rs2), offset),
+        Instruction::Beq { rs1, rs2, offset } => (reg(rs1) == reg(rs2), offset),
+        Instruction::Blt { rs1, rs2, offset } => (reg(rs1) < reg(rs2), offset),
+        Instruction::Bge { rs1, rs2, offset } => (reg(rs1) >= reg(rs2), offset),
+        Instruction::Bgeu { rs1, rs2, offset } => (reg(rs1) as u32 >= reg(rs2) as u32, offset),
+        _ => return None,
+    };
+    Some((taken, pc.wrapping_add(offset as i32 as u32)))
+}
+
+// 两个程序状态的一处差异，values[0]和values[1]分别来自两个程序
+#[derive(Debug, Clone, PartialEq)]
+pub enum StateDifference {
+    Register { index: usize, values: [i32; 2] },
+    Memory { address: u32, values: [u8; 2] },
+}
+
+impl fmt::Display for StateDifference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateDifference::Register { index, values: [a, b] } => {
+                write!(f, "x{} ({}): {} (0x{:08X}) != {} (0x{:08X})", index, ABI_NAMES[*index], a, a, b, b)
+            }
+            StateDifference::Memory { address, values: [a, b] } => {
+                write!(f, "内存 0x{:04X}: 0x{:02X} != 0x{:02X}", address, a, b)
+            }
+        }
+    }
+}
+
+// 同步执行时第一处差异出现的位置
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub cycle: usize,      // 第几步之后出现差异（从1开始）
+    pub pcs: [usize; 2],   // 这一步两个程序各自执行的指令的pc，已经停机的程序为停机时的pc
+    pub difference: StateDifference,
+}
+
+// 两个程序每步各执行一条指令，先执行到halt的程序停在原地等另一个。
+// 每步之后比较全部寄存器，以及这一步中任一程序写入的内存字节；
+// 两个程序的代码本身不同，没有被写过的内存不参与比较。
+// 都执行到halt且没有差异时返回None
+pub fn run_lockstep(a: &mut CpuState, b: &mut CpuState) -> Result<Option<Divergence>, SimError> {
+    let mut running = [true, true];
+    let mut cycle = 0;
+    while running[0] || running[1] {
+        cycle += 1;
+        let mut pcs = [a.pc, b.pc];
+        let mut written = Vec::new();
+        for (i, cpu) in [&mut *a, &mut *b].into_iter().enumerate() {
+            if !running[i] {
+                continue;
+            }
+            pcs[i] = cpu.pc;
+            running[i] = cpu.step()?;
+            if let Some(MemoryAccess { address, len, access_type: AccessType::Write, .. }) = cpu.last_access {
+                written.extend(address..address + len as u32);
+            }
+        }
+
+        let register = (0..32).find(|&i| a.regs[i] != b.regs[i])
+            .map(|index| StateDifference::Register { index, values: [a.regs[index], b.regs[index]] });
+        let memory = || written.iter().find(|&&address| a.memory[address as usize] != b.memory[address as usize])
+            .map(|&address| StateDifference::Memory { address, values: [a.memory[address as usize], b.memory[address as usize]] });
+        if let Some(difference) = register.or_else(memory) {
+            return Ok(Some(Divergence { cycle, pcs, difference }));
+        }
+    }
+    Ok(None)
+}
+
+// 执行跟踪：每条指令执行后调用，pc和word是刚执行的指令，state是执行后的状态
+pub trait Tracer {
+    fn on_execute(&mut self, pc: u32, word: u32, state: &CpuState);
+}
+
+// 每条指令输出一行: PC=0x0004  00010042  addi x1, x0, 10   x1=10
+// 写入的寄存器（x0除外）显示执行后的值；有源码映射时在行尾附上源码行号
+pub struct PrintTracer<W: Write> {
+    out: W,
+    error: Option<io::Error>,
+    source_map: HashMap<usize, usize>, // 字下标到源码行号
+}
+
+impl PrintTracer<io::Stdout> {
+    pub fn stdout() -> Self {
+        PrintTracer::new(io::stdout())
+    }
+}
+
+impl<W: Write> PrintTracer<W> {
+    pub fn new(out: W) -> Self {
+        PrintTracer { out, error: None, source_map: HashMap::new() }
+    }
+
+    pub fn with_source_map(mut self, source_map: HashMap<usize, usize>) -> Self {
+        self.source_map = source_map;
+        self
+    }
+
+    // 刷新输出并返回写入器，跟踪过程中的第一个写入错误在这里返回
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+impl<W: Write> Tracer for PrintTracer<W> {
+    fn on_execute(&mut self, pc: u32, word: u32, state: &CpuState) {
+        if self.error.is_some() {
+            return;
+        }
+        // 执行过的字一定能解码；用Instruction的规范写法，sw/blt的操作数顺序与源码一致
+        let text = Instruction::decode(word).map_or_else(String::new, |instruction| instruction.to_string());
+        let mut line = format!("PC=0x{:04X}  {:08X}  {}", pc, word, text);
+        if let (Some(rd), _) = register_operands(word) {
+            if rd != 0 {
+                line.push_str(&format!("   x{}={}", rd, state.regs[rd as usize]));
+            }
+        }
+        if let Some(source_line) = self.source_map.get(&(pc as usize / 4)) {
+            line.push_str(&format!("   # 第{}行", source_line));
+        }
+        if let Err(error) = writeln!(self.out, "{}", line) {
+            self.error = Some(error);
+        }
+    }
+}
+
+// 读取PrintTracer的输出，按pc收集每次执行写入的寄存器和值（按执行顺序）。
+// 不以PC=开头的行（例如sim的其他输出）被忽略，不写寄存器的指令不出现在结果中
+pub fn parse_trace(text: &str) -> Result<HashMap<u32, (u8, Vec<i32>)>, String> {
+    let mut values: HashMap<u32, (u8, Vec<i32>)> = HashMap::new();
+    let mut records = 0;
+    for (number, line) in text.lines().enumerate() {
+        let Some(rest) = line.strip_prefix("PC=0x") else { continue };
+        let invalid = || format!("第{}行: 无效的跟踪记录: {}", number + 1, line);
+        let pc_text = rest.split_whitespace().next().ok_or_else(invalid)?;
+        let pc = u32::from_str_radix(pc_text, 16).map_err(|_| invalid())?;
+        records += 1;
+        // 各字段之间是三个空格，写入的寄存器形如x1=10
+        let Some(write) = line.split("   ").skip(1).find(|field| field.starts_with('x')) else { continue };
+        let (reg, value) = write.split_once('=').ok_or_else(invalid)?;
+        let reg: u8 = reg[1..].parse().ok().filter(|&reg| reg < 32).ok_or_else(invalid)?;
+        let value: i32 = value.trim().parse().map_err(|_| invalid())?;
+        values.entry(pc).or_insert_with(|| (reg, Vec::new())).1.push(value);
+    }
+    if records == 0 {
+        return Err("跟踪文件中没有指令记录".to_string());
+    }
+    Ok(values)
+}
+
+// 只记录跳转了的条件分支，每条一行: PC=0x0010  BNE x1,x2  taken  target=0x0004
+// 打开show_cycle时在行尾附上这条指令是第几个周期
+pub struct BranchTracer<W: Write> {
+    out: W,
+    error: Option<io::Error>,
+    show_cycle: bool,
+}
+
+impl<W: Write> BranchTracer<W> {
+    pub fn new(out: W) -> Self {
+        BranchTracer { out, error: None, show_cycle: false }
+    }
+
+    pub fn with_cycles(mut self) -> Self {
+        self.show_cycle = true;
+        self
+    }
+
+    // 刷新输出并返回写入器，跟踪过程中的第一个写入错误在这里返回
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+impl<W: Write> Tracer for BranchTracer<W> {
+    fn on_execute(&mut self, pc: u32, word: u32, state: &CpuState) {
+        if self.error.is_some() {
+            return;
+        }
+        // 分支不写寄存器，执行后的寄存器与执行前相同
+        let Some(instruction) = Instruction::decode(word) else { return };
+        let Some((true, target)) = branch_outcome(instruction, &state.regs, pc) else { return };
+        let text = instruction.to_string();
+        let (mnemonic, operands) = text.split_once(' ').unwrap_or((&text, ""));
+        // 去掉偏移，只保留两个寄存器
+        let registers: Vec<&str> = operands.split(", ").take(2).collect();
+        let mut line = format!("PC=0x{:04X}  {} {}  taken  target=0x{:04X}", pc, mnemonic.to_uppercase(), registers.join(","), target);
+        if self.show_cycle {
+            line.push_str(&format!("  cycle={}", state.cycles));
+        }
+        if let Err(error) = writeln!(self.out, "{}", line) {
+            self.error = Some(error);
+        }
+    }
+}
+
+// 执行统计：指令条数、各助记符的执行次数和条件分支的跳转情况
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Statistics {
+    pub instructions: usize,
+    pub histogram: BTreeMap<&'static str, usize>,
+    pub branches_taken: usize,
+    pub branches_not_taken: usize,
+}
+
+impl Statistics {
+    // 每行一个字段的JSON，寄存器按有符号十进制，内存校验和是整个内存的CRC-32
+    pub fn to_json(&self, state: &CpuState, elapsed: Duration) -> String {
+        let registers: Vec<String> = state.regs.iter().map(|value| value.to_string()).collect();
+        let histogram: Vec<String> = self.histogram.iter().map(|(mnemonic, count)| format!("\"{}\": {}", mnemonic, count)).collect();
+        format!("{{\n  \"instructions\": {},\n  \"cycles\": {},\n  \"registers\": [{}],\n  \"memory_crc32\": {},\n  \
+                 \"histogram\": {{{}}},\n  \"branches\": {{\"taken\": {}, \"not_taken\": {}}},\n  \"elapsed_seconds\": {:.6}\n}}\n",
+            self.instructions, state.cycles, registers.join(", "), crc32(&state.memory),
+            histogram.join(", "), self.branches_taken, self.branches_not_taken, elapsed.as_secs_f64())
+    }
+}
+
+impl Tracer for Statistics {
+    fn on_execute(&mut self, pc: u32, word: u32, state: &CpuState) {
+        self.instructions += 1;
+        if let Some(entry) = lookup_opcode(word & 0x3F) {
+            *self.histogram.entry(entry.mnemonic).or_insert(0) += 1;
+        }
+        let outcome = Instruction::decode(word).and_then(|instruction| branch_outcome(instruction, &state.regs, pc));
+        match outcome {
+            Some((true, _)) => self.branches_taken += 1,
+            Some((false, _)) => self.branches_not_taken += 1,
+            None => {}
+        }
+    }
+}
+
+// 按访存指令给出的地址统计读写次数
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryProfile {
+    pub counts: HashMap<u32, (usize, usize)>, // 地址到(读取次数, 写入次数)
+}
+
+impl MemoryProfile {
+    // 按访问次数从多到少排列，次数相同时按地址排列
+    pub fn sorted(&self) -> Vec<(u32, usize, usize)> {
+        let mut entries: Vec<_> = self.counts.iter().map(|(&address, &(reads, writes))| (address, reads, writes)).collect();
+        entries.sort_by_key(|&(address, reads, writes)| (std::cmp::Reverse(reads + writes), address));
+        entries
+    }
+
+    // 每个地址一行: addr=0x0010  reads=150  writes=3
+    // chart_width不为None时在行尾附上按访问次数缩放的条形图，最长的一条占chart_width个字符
+    pub fn format_report(&self, chart_width: Option<usize>) -> String {
+        let entries = self.sorted();
+        let reads_width = entries.iter().map(|entry| entry.1.to_string().len()).max().unwrap_or(0);
+        let writes_width = entries.iter().map(|entry| entry.2.to_string().len()).max().unwrap_or(0);
+        let max_total = entries.first().map_or(0, |entry| entry.1 + entry.2);
+        let mut output = String::new();
+        for (address, reads, writes) in entries {
+            match chart_width {
+                Some(width) => output.push_str(&format!("addr=0x{:04X}  reads={:<rw$}  writes={:<ww$}  {}\n",
+                    address, reads, writes, bar((reads + writes) * width * 8 / max_total), rw = reads_width, ww = writes_width)),
+                None => output.push_str(&format!("addr=0x{:04X}  reads={}  writes={}\n", address, reads, writes)),
+            }
+        }
+        output
+    }
+}
+
+// 长度为eighths/8个字符的条，不足一格的部分用▏到▉表示
+fn bar(eighths: usize) -> String {
+    const PARTIAL: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+    let mut bar = "█".repeat(eighths / 8);
+    if !eighths.is_multiple_of(8) {
+        bar.push(PARTIAL[eighths % 8]);
+    }
+    bar
+}
+
+impl Tracer for MemoryProfile {
+    fn on_execute(&mut self, _pc: u32, _word: u32, state: &CpuState) {
+        if let Some(access) = state.last_access {
+            let counts = self.counts.entry(access.address).or_insert((0, 0));
+            match access.access_type {
+                AccessType::Read => counts.0 += 1,
+                AccessType::Write => counts.1 += 1,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assemble;
+
+    fn run(source: &str) -> CpuState {
+        let mut cpu = CpuState::new(&assemble(source).unwrap()).unwrap();
+        cpu.run().unwrap();
+        cpu
+    }
+
+    #[test]
+    fn test_sim_programs() {
+        // asm/sum.asm: 1+2+...+10
+        let cpu = run(include_str!("../asm/sum.asm"));
+        assert_eq!(cpu.regs[1], 55);
+        // asm/factorial.asm: 10!
+        let cpu = run(include_str!("../asm/factorial.asm"));
+        assert_eq!(cpu.regs[1], 3628800);
+
+        // 函数调用、访存和li展开
+        let cpu = run("li sp, 0x8000\nli a0, 0x12345678\njal ra, store\nlw a1, 4(sp)\nhalt\nstore: sw a0, 4(sp)\nret");
+        assert_eq!(cpu.regs[11], 0x12345678);
+        assert_eq!(cpu.pc, 24);
+    }
+
+    #[test]
+    fn test_sim_alu() {
+        let cpu = run("addi x1, x0, -8\nsrai x2, x1, 1\nsrli x3, x1, 28\nslt x4, x1, x0\nsltu x5, x1, x0\n\
+                       lui x6, 0x1234\nrev8 x7, x6\nclz x8, x6\ncpop x9, x1\nhalt");
+        assert_eq!(&cpu.regs[1..10], &[-8, -4, 15, 1, 0, 0x12340000, 0x3412, 3, 29]);
+
+        // clz覆盖每个2的幂，全0时为32
+        for k in 0..32 {
+            let cpu = run(&format!("li x1, {:#X}\nclz x2, x1\nhalt", 1u32 << k));
+            assert_eq!(cpu.regs[2], 31 - k, "clz(1 << {})", k);
+        }
+        assert_eq!(run("clz x1, x0\nhalt").regs[1], 32);
+
+        // ctz和cpop的边界值
+        for (value, ctz, cpop) in [(0u32, 32, 0), (1, 0, 1), (0x80000000, 31, 1), (0xFFFFFFFF, 0, 32)] {
+            let cpu = run(&format!("li x1, {:#X}\nctz x2, x1\ncpop x3, x1\nhalt", value));
+            assert_eq!((cpu.regs[2], cpu.regs[3]), (ctz, cpop), "{:#X}", value);
+        }
+
+        // rev8两次还原，brev8只反转字节内部的位
+        for value in [0u32, 1, 0x12345678, 0x80000001, 0xDEADBEEF, 0xFFFFFFFF] {
+            let cpu = run(&format!("li x1, {:#X}\nrev8 x2, x1\nrev8 x3, x2\nhalt", value));
+            assert_eq!(cpu.regs[3] as u32, value);
+        }
+        let cpu = run("li x1, 0x80000001\nbrev8 x2, x1\nhalt");
+        assert_eq!(cpu.regs[2] as u32, 0x01000080);
+    }
+
+    #[test]
+    fn test_sim_popcount() {
+        let cpu = run("li x1, 0xFFFFFFFF\nli x2, 0xAAAAAAAA\npopcount x3, x0\npopcount x4, x1\npopcount x5, x2\nhalt");
+        assert_eq!(&cpu.regs[3..6], &[0, 32, 16]);
+        // rd与rs相同，以及不依赖最低字节的进位
+        let cpu = run("li x1, 0x80000001\npopcount x1, x1\nli x2, 0x7FFFFFFF\npopcount x3, x2\nhalt");
+        assert_eq!((cpu.regs[1], cpu.regs[2], cpu.regs[3]), (2, 0x7FFFFFFF, 31));
+    }
+
+    #[test]
+    fn test_sim_x0_and_errors() {
+        // 写入x0没有效果
+        let cpu = run("addi x0, x0, 5\nadd x0, x0, x0\nhalt");
+        assert_eq!(cpu.regs[0], 0);
+
+        // 跳出内存范围
+        let mut cpu = CpuState::new(&assemble("jalr x0, x0, -4").unwrap()).unwrap();
+        assert_eq!(cpu.run(), Err(SimError::PcOutOfRange { pc: 0xFFFFFFFC }));
+        // 数据字不是合法指令
+        let mut cpu = CpuState::new(&assemble("nop\n.word 0xFFFFFFFF").unwrap()).unwrap();
+        assert_eq!(cpu.run(), Err(SimError::IllegalInstruction { pc: 4, word: 0xFFFFFFFF }));
+        assert!(CpuState::new(&[0; MEMORY_SIZE / 4 + 1]).is_err());
+    }
+
+    #[test]
+    fn test_print_tracer() {
+        let program = assemble("addi x1, x0, 10\naddi x2, x0, -3\nadd x3, x1, x2\nsw x3, 0(x0)\nhalt").unwrap();
+        let mut cpu = CpuState::new(&program).unwrap();
+        let mut tracer = PrintTracer::new(Vec::new());
+        assert_eq!(cpu.run_traced(&mut tracer), Ok(5));
+        let trace = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        assert_eq!(trace, "\
+PC=0x0000  000A0042  addi x1, x0, 10   x1=10
+PC=0x0004  FFFD0082  addi x2, x0, -3   x2=-3
+PC=0x0008  000208C1  add x3, x1, x2   x3=7
+PC=0x000C  00030007  sw x3, 0(x0)
+PC=0x0010  00000000  halt
+");
+    }
+
+    #[test]
+    fn test_branch_tracer() {
+        let source = "li x1, 2\nloop: addi x1, x1, -1\nbne x1, x0, loop\nbge x1, x0, 8\nhalt\nblt x0, x1, -4\nhalt";
+        let mut cpu = CpuState::new(&assemble(source).unwrap()).unwrap();
+        let mut tracer = BranchTracer::new(Vec::new()).with_cycles();
+        cpu.run_traced(&mut tracer).unwrap();
+        // 最后一次bne和blt没有跳转
+        assert_eq!(String::from_utf8(tracer.finish().unwrap()).unwrap(), "\
+PC=0x0008  BNE x1,x0  taken  target=0x0004  cycle=3
+PC=0x000C  BGE x1,x0  taken  target=0x0014  cycle=6
+");
+        assert_eq!(cpu.pc, 0x18);
+
+        let regs = [0; 32];
+        assert_eq!(branch_outcome(Instruction::Bgeu { rs1: 0, rs2: 0, offset: -8 }, &regs, 4), Some((true, 0xFFFFFFFC)));
+        assert_eq!(branch_outcome(Instruction::Jal { rd: 0, offset: 8 }, &regs, 4), None);
+    }
+
+    #[test]
+    fn test_statistics() {
+        let source = "li x1, 3\nloop: addi x1, x1, -1\nbnez x1, loop\nsw x1, 0x100(x0)\nhalt";
+        let mut cpu = CpuState::new(&assemble(source).unwrap()).unwrap();
+        let mut statistics = Statistics::default();
+        assert_eq!(cpu.run_traced(&mut statistics), Ok(9));
+        assert_eq!(statistics.instructions, 9);
+        assert_eq!(statistics.histogram, BTreeMap::from([("addi", 4), ("bne", 3), ("halt", 1), ("sw", 1)]));
+        assert_eq!((statistics.branches_taken, statistics.branches_not_taken), (2, 1));
+
+        let json = statistics.to_json(&cpu, Duration::from_millis(1500));
+        let zeros = vec!["0"; 32].join(", ");
+        assert_eq!(json, format!("{{
+  \"instructions\": 9,
+  \"cycles\": 9,
+  \"registers\": [{}],
+  \"memory_crc32\": {},
+  \"histogram\": {{\"addi\": 4, \"bne\": 3, \"halt\": 1, \"sw\": 1}},
+  \"branches\": {{\"taken\": 2, \"not_taken\": 1}},
+  \"elapsed_seconds\": 1.500000
+}}
+", zeros, crc32(&cpu.memory)));
+    }
+
+    #[test]
+    fn test_memory_profile() {
+        let source = "li x1, 4\nloop: lw x2, 0x100(x0)\nsw x2, 0x104(x0)\nlw x3, 0x104(x0)\n\
+                      addi x1, x1, -1\nbnez x1, loop\nsb x1, 0x10(x0)\nhalt";
+        let mut cpu = CpuState::new(&assemble(source).unwrap()).unwrap();
+        let mut profile = MemoryProfile::default();
+        cpu.run_traced(&mut profile).unwrap();
+        assert_eq!(profile.sorted(), [(0x104, 4, 4), (0x100, 4, 0), (0x10, 0, 1)]);
+        assert_eq!(profile.format_report(None), "\
+addr=0x0104  reads=4  writes=4
+addr=0x0100  reads=4  writes=0
+addr=0x0010  reads=0  writes=1
+");
+        // 8次访问占满4格，4次是2格，1次是半格
+        assert_eq!(profile.format_report(Some(4)), "\
+addr=0x0104  reads=4  writes=4  ████
+addr=0x0100  reads=4  writes=0  ██
+addr=0x0010  reads=0  writes=1  ▌
+");
+        assert_eq!(MemoryProfile::default().format_report(Some(10)), "");
+    }
+
+    #[test]
+    fn test_parse_trace() {
+        let source = "li x1, 3\nloop: addi x1, x1, -1\nbnez x1, loop\nsw x1, 0(x0)\nhalt";
+        let mut cpu = CpuState::new(&assemble(source).unwrap()).unwrap();
+        let mut tracer = PrintTracer::new(Vec::new()).with_source_map(crate::generate_source_map(source));
+        cpu.run_traced(&mut tracer).unwrap();
+        let trace = format!("读取二进制文件: a.o\n{}", String::from_utf8(tracer.finish().unwrap()).unwrap());
+        let values = parse_trace(&trace).unwrap();
+        assert_eq!(values, HashMap::from([(0, (1, vec![3])), (4, (1, vec![2, 1, 0]))]));
+
+        assert_eq!(parse_trace("PC=0x00G0  00000000  halt").unwrap_err(), "第1行: 无效的跟踪记录: PC=0x00G0  00000000  halt");
+        assert_eq!(parse_trace("执行完成").unwrap_err(), "跟踪文件中没有指令记录");
+    }
+
+    #[test]
+    fn test_trace_source_lines() {
+        let source = "# 计数\naddi x1, x0, 2\n\nloop: addi x1, x1, -1\n  bne x1, x0, loop\nhalt";
+        let mut cpu = CpuState::new(&assemble(source).unwrap()).unwrap();
+        let mut tracer = PrintTracer::new(Vec::new()).with_source_map(crate::generate_source_map(source));
+        cpu.run_traced(&mut tracer).unwrap();
+        let trace = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        let lines: Vec<&str> = trace.lines().map(|line| line.rsplit("   ").next().unwrap()).collect();
+        assert_eq!(lines, ["# 第2行", "# 第4行", "# 第5行", "# 第4行", "# 第5行", "# 第6行"]);
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        // 执行到一半保存，恢复后继续执行，结果与一次执行到底相同
+        let program = assemble(include_str!("../asm/factorial.asm")).unwrap();
+        let mut expected = CpuState::new(&program).unwrap();
+        let count = expected.run().unwrap();
+        assert_eq!(expected.cycles as usize, count);
+
+        let mut cpu = CpuState::new(&program).unwrap();
+        for _ in 0..10 {
+            cpu.step().unwrap();
+        }
+        cpu.store_word(0x8000, 0xCAFEBABE).unwrap();
+        let mut checkpoint = Vec::new();
+        cpu.save_state(&mut checkpoint).unwrap();
+        assert_eq!(checkpoint.len(), 4 + 32 * 4 + 4 + 4 + MEMORY_SIZE);
+        assert_eq!(&checkpoint[..4], b"RVCK");
+
+        let mut restored = CpuState::load_state(&mut checkpoint.as_slice()).unwrap();
+        assert_eq!((restored.regs, restored.pc, restored.cycles), (cpu.regs, cpu.pc, 10));
+        assert_eq!(restored.memory, cpu.memory);
+        restored.run().unwrap();
+        assert_eq!((restored.regs, restored.pc, restored.cycles), (expected.regs, expected.pc, expected.cycles));
+        assert_eq!(restored.load_word(0x8000), Ok(0xCAFEBABE));
+
+        // 魔数错误和内存不完整
+        checkpoint[0] = 0;
+        let error = CpuState::load_state(&mut checkpoint.as_slice()).unwrap_err();
+        assert_eq!(error.to_string(), "检查点魔数错误: 0x4B435600（应为0x4B435652）");
+        checkpoint[0] = b'R';
+        let error = CpuState::load_state(&mut &checkpoint[..checkpoint.len() - 1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(CpuState::load_state(&mut &checkpoint[..100]).is_err());
+    }
+
+    #[test]
+    fn test_lockstep() {
+        let new = |source: &str| CpuState::new(&assemble(source).unwrap()).unwrap();
+
+        // 写法不同但每步的寄存器和写入的内存都相同
+        let mut a = new("addi x1, x0, 5\nsw x1, 0x100(x0)\nhalt");
+        let mut b = new("ori x1, x0, 5\nsw x1, 0x100(x0)\nhalt\n.word 0x12345678");
+        assert_eq!(run_lockstep(&mut a, &mut b), Ok(None));
+
+        // 寄存器不同
+        let mut a = new("addi x1, x0, 1\naddi x2, x0, 2\nhalt");
+        let mut b = new("addi x1, x0, 1\naddi x2, x0, 3\nhalt");
+        let divergence = run_lockstep(&mut a, &mut b).unwrap().unwrap();
+        assert_eq!(divergence, Divergence { cycle: 2, pcs: [4, 4], difference: StateDifference::Register { index: 2, values: [2, 3] } });
+        assert_eq!(divergence.difference.to_string(), "x2 (sp): 2 (0x00000002) != 3 (0x00000003)");
+
+        // 只有写入的字节不同
+        let mut a = new("addi x1, x0, 0x1FF\nsb x1, 0x100(x0)\nhalt");
+        let mut b = new("addi x1, x0, 0x1FF\nsh x1, 0x100(x0)\nhalt");
+        let divergence = run_lockstep(&mut a, &mut b).unwrap().unwrap();
+        assert_eq!(divergence.difference, StateDifference::Memory { address: 0x101, values: [0, 1] });
+        assert_eq!(divergence.difference.to_string(), "内存 0x0101: 0x00 != 0x01");
+
+        // 一个先停机，另一个之后的写入仍然比较
+        let mut a = new("halt");
+        let mut b = new("nop\naddi x3, x0, 1\nhalt");
+        let divergence = run_lockstep(&mut a, &mut b).unwrap().unwrap();
+        assert_eq!((divergence.cycle, divergence.pcs), (2, [0, 4]));
+    }
+
+    #[test]
+    fn test_memory_access() {
+        let mut cpu = CpuState::new(&assemble("li x1, -1\nsh x1, 0x100(x0)\nlbu x2, 0x101(x0)\nnop\nhalt").unwrap()).unwrap();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.last_access, Some(MemoryAccess { address: 0x100, len: 2, access_type: AccessType::Write, value: 0xFFFF }));
+        cpu.step().unwrap();
+        assert_eq!(cpu.last_access, Some(MemoryAccess { address: 0x101, len: 1, access_type: AccessType::Read, value: 0xFF }));
+        cpu.step().unwrap();
+        assert_eq!(cpu.last_access, None);
+    }
+
+    #[test]
+    fn test_memory_fault() {
+        let mut cpu = CpuState::new(&assemble("li x1, 0xDEADBEEF\nlw x2, 0(x1)\nhalt").unwrap()).unwrap();
+        assert_eq!(cpu.run(), Err(SimError::MemoryFault { pc: 8, address: 0xDEADBEEF, access_type: AccessType::Read }));
+        let mut cpu = CpuState::new(&assemble("li x1, 0xDEADBEEF\nsw x0, 0(x1)\nhalt").unwrap()).unwrap();
+        let error = cpu.run().unwrap_err();
+        assert_eq!(error, SimError::MemoryFault { pc: 8, address: 0xDEADBEEF, access_type: AccessType::Write });
+        assert_eq!(error.to_string(), "pc=0x00000008: 写入地址 0xDEADBEEF 超出内存范围");
+
+        // 最后一个完整的字可以访问，跨过内存末尾的字不行
+        let mut cpu = CpuState::new(&[]).unwrap();
+        let last = (MEMORY_SIZE - 4) as u32;
+        assert_eq!(cpu.store_word(last, 0x12345678), Ok(0));
+        assert_eq!(cpu.load_word(last), Ok(0x12345678));
+        assert!(matches!(cpu.load_word(last + 1), Err(SimError::MemoryFault { access_type: AccessType::Read, .. })));
+        assert!(matches!(cpu.load_word(0xDEADBEEF), Err(SimError::MemoryFault { address: 0xDEADBEEF, .. })));
+    }
+
+    #[test]
+    fn test_memory_protection() {
+        let new = |source: &str, regions: &[(u32, u32, Permission)]| {
+            let mut cpu = CpuState::new(&assemble(source).unwrap()).unwrap();
+            cpu.regions = regions.iter().map(|&(start, end, permission)| MemoryRegion { start, end, permission }).collect();
+            cpu
+        };
+        let fault = |pc, address, access_type, permission| Err(SimError::ProtectionFault { pc, address, access_type, permission });
+
+        // 只读区域可以读不能写；跨越区域边界的访问只要有一个字节不允许就出错
+        let regions = [(0x100, 0x110, Permission::ReadOnly)];
+        assert!(new("lw x1, 0x10C(x0)\nsw x1, 0x110(x0)\nhalt", &regions).run().is_ok());
+        assert_eq!(new("sw x1, 0x10C(x0)\nhalt", &regions).run(), fault(0, 0x10C, AccessType::Write, Permission::ReadOnly));
+        assert_eq!(new("sh x1, 0xFF(x0)\nhalt", &regions).run(), fault(0, 0xFF, AccessType::Write, Permission::ReadOnly));
+
+        // 只写区域不能读，禁止访问的区域读写都不行；后面的区域覆盖前面的
+        let regions = [(0x100, 0x200, Permission::WriteOnly), (0x180, 0x184, Permission::NoAccess)];
+        assert!(new("sb x1, 0x17F(x0)\nsw x1, 0x184(x0)\nhalt", &regions).run().is_ok());
+        assert_eq!(new("nop\nlbu x1, 0x100(x0)\nhalt", &regions).run(), fault(4, 0x100, AccessType::Read, Permission::WriteOnly));
+        assert_eq!(new("sb x1, 0x183(x0)\nhalt", &regions).run(), fault(0, 0x183, AccessType::Write, Permission::NoAccess));
+        let error = new("lw x1, 0x180(x0)\nhalt", &regions).run().unwrap_err();
+        assert_eq!(error.to_string(), "pc=0x00000000: 读取地址 0x00000180 违反内存保护（NA）");
+
+        // 保护只针对访存指令，取指不受限制
+        assert!(new("nop\nhalt", &[(0, 8, Permission::NoAccess)]).run().is_ok());
+        assert_eq!(Permission::from_name("WO"), Some(Permission::WriteOnly));
+        assert_eq!(Permission::from_name("rw"), None);
+    }
+
+    #[test]
+    fn test_narrow_loads() {
+        // sw按小端存放0x80FF1234：地址0x100起依次是34 12 FF 80
+        let source = "li x1, 0x80FF1234\nsw x1, 0x100(x0)\n\
+                      lbu x2, 0x100(x0)\nlbu x3, 0x103(x0)\nlb x4, 0x103(x0)\n\
+                      lh x5, 0x102(x0)\nlhu x6, 0x102(x0)\nlhu x7, 0x100(x0)\nhalt";
+        let mut cpu = CpuState::new(&assemble(source).unwrap()).unwrap();
+        cpu.run().unwrap();
+        assert_eq!(cpu.regs[2], 0x34);
+        assert_eq!(cpu.regs[3], 0x80);
+        assert_eq!(cpu.regs[4], -128);
+        assert_eq!(cpu.regs[5], 0x80FF_u16 as i16 as i32);
+        assert_eq!(cpu.regs[6], 0x80FF);
+        assert_eq!(cpu.regs[7], 0x1234);
+
+        // 最后一个字节可以读，再往后就越界
+        let mut cpu = CpuState::new(&assemble("li x1, 0xFFFF\nlbu x2, 0(x1)\nlh x3, 0(x1)\nhalt").unwrap()).unwrap();
+        assert_eq!(cpu.run(), Err(SimError::MemoryFault { pc: 12, address: 0xFFFF, access_type: AccessType::Read }));
+    }
+
+    #[test]
+    fn test_narrow_stores() {
+        // sb/sh只改写1/2个字节，周围的字节保持不变
+        let source = "li x1, 0x11223344\nsw x1, 0x100(x0)\n\
+                      li x2, 0x7FAB\nsb x2, 0x101(x0)\nlbu x3, 0x101(x0)\n\
+                      sh x2, 0x106(x0)\nlhu x4, 0x106(x0)\nlw x5, 0x100(x0)\nlw x6, 0x104(x0)\nhalt";
+        let mut cpu = CpuState::new(&assemble(source).unwrap()).unwrap();
+        cpu.run().unwrap();
+        assert_eq!(cpu.regs[3], 0xAB);
+        assert_eq!(cpu.regs[4], 0x7FAB);
+        assert_eq!(cpu.regs[5], 0x1122AB44);
+        assert_eq!(cpu.regs[6], 0x7FAB0000);
+
+        let mut cpu = CpuState::new(&assemble("li x1, 0xFFFF\nsb x0, 0(x1)\nsh x0, 0(x1)\nhalt").unwrap()).unwrap();
+        assert_eq!(cpu.run(), Err(SimError::MemoryFault { pc: 12, address: 0xFFFF, access_type: AccessType::Write }));
+    }
+
+    #[test]
+    fn test_srl_sra() {
+        let source = "addi x2, x0, -1\naddi x3, x0, 4\nsra x1, x2, x3\nsrl x4, x2, x3\n\
+                      addi x5, x0, 36\nsra x6, x2, x5\nsrl x7, x3, x5\nhalt";
+        let mut cpu = CpuState::new(&assemble(source).unwrap()).unwrap();
+        cpu.run().unwrap();
+        assert_eq!(cpu.regs[1], -1);
+        assert_eq!(cpu.regs[4], 0x0FFF_FFFF);
+        // 移位量只取低5位，36即4
+        assert_eq!(cpu.regs[6], -1);
+        assert_eq!(cpu.regs[7], 0);
+    }
+
+    #[test]
+    fn test_nor() {
+        let source = "li x1, 0x0F0F00FF\nli x2, 0x00FF0F0F\nnor x3, x1, x2\nnot x4, x1\nnot x5, x0\nhalt";
+        let mut cpu = CpuState::new(&assemble(source).unwrap()).unwrap();
+        cpu.run().unwrap();
+        assert_eq!(cpu.regs[3] as u32, 0xF000F000);
+        assert_eq!(cpu.regs[4] as u32, 0xF0F0FF00);
+        assert_eq!(cpu.regs[5], -1);
+    }
+
+    #[test]
+    fn test_lui_sign_extension() {
+        let mut cpu = CpuState::new(&[]).unwrap();
+        cpu.simulate_lui(1, -1);
+        assert_eq!(cpu.regs[1] as u32, 0xFFFF_0000);
+        cpu.simulate_lui(0, -1);
+        assert_eq!(cpu.regs[0], 0);
+
+        // 经过编码、解码后结果相同；0x8000的高位同样被扩展
+        let source = "lui x1, -1\nlui x2, -32768\nlui x3, 0x7FFF\nhalt";
+        let mut cpu = CpuState::new(&assemble(source).unwrap()).unwrap();
+        cpu.run().unwrap();
+        assert_eq!(cpu.regs[1] as u32, 0xFFFF_0000);
+        assert_eq!(cpu.regs[2] as u32, 0x8000_0000);
+        assert_eq!(cpu.regs[3] as u32, 0x7FFF_0000);
+    }
+}
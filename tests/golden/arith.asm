@@ -0,0 +1,10 @@
+# 算术与移位，比较后分支
+addi x1, x0, -5
+addi x2, x0, 3
+sub x3, x1, x2
+mul x4, x3, x2
+slli x5, x2, 4
+zero_reg x6
+blt x1, x2, 8
+add x6, x6, x2
+halt
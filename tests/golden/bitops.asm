@@ -0,0 +1,9 @@
+# 位操作指令
+lui x1, 0x1234
+addi x1, x1, 0x5678
+clz x2, x1
+ctz x3, x1
+cpop x4, x1
+rev8 x5, x1
+brev8 x6, x1
+halt
@@ -0,0 +1,16 @@
+# 斐波那契数列: 前12项依次写入0x100起的内存，x1为最后一项(89)
+.equ BASE, 0x100
+.equ COUNT, 12
+    li x1, 0            # F(n-1)
+    li x2, 1            # F(n)
+    li x3, BASE         # 写入地址
+    li x4, BASE+COUNT*4
+loop:
+    sw x1, 0(x3)
+    add x5, x1, x2
+    mov x1, x2
+    mov x2, x5
+    addi x3, x3, 4
+    bne x3, x4, loop
+    lw x1, -4(x3)
+    halt
@@ -0,0 +1,8 @@
+# 将x1写入内存后读回x2
+addi x1, x0, 42
+addi x3, x0, 16
+sw x1, 0(x3)
+sw x1, 4(x3)
+lw x2, 4(x3)
+lw x4, -4(x3)
+halt
@@ -0,0 +1,36 @@
+# 冒泡排序: 把0x100起的5个字按有符号数升序排列
+.equ BASE, 0x100
+.equ N, 5
+    li x1, BASE
+    li x2, 7
+    sw x2, 0(x1)
+    li x2, -3
+    sw x2, 4(x1)
+    li x2, 42
+    sw x2, 8(x1)
+    li x2, 0
+    sw x2, 12(x1)
+    li x2, 5
+    sw x2, 16(x1)
+
+    li x10, BASE+(N-1)*4         # 本轮比较的最后一个地址
+outer:
+    li x11, BASE
+    li x12, 0                    # 本轮是否发生交换
+inner:
+    lw x2, 0(x11)
+    lw x3, 4(x11)
+    bge x3, x2, next             # 已经有序
+    sw x3, 0(x11)
+    sw x2, 4(x11)
+    li x12, 1
+next:
+    addi x11, x11, 4
+    bne x11, x10, inner
+    addi x10, x10, -4
+    beqz x12, done               # 没有交换说明已经有序
+    bne x10, x1, outer
+done:
+    lw x4, 0(x1)                 # 最小值 -3
+    lw x5, 16(x1)                # 最大值 42
+    halt
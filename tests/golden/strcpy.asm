@@ -0,0 +1,26 @@
+# 字符串复制: 先在0x100写入"RISC\0"，再逐字节复制到0x200，x6为复制的字符数
+.equ SRC, 0x100
+.equ DST, 0x200
+    li x1, SRC
+    li x2, 'R'
+    sb x2, 0(x1)
+    li x2, 'I'
+    sb x2, 1(x1)
+    li x2, 'S'
+    sb x2, 2(x1)
+    li x2, 'C'
+    sb x2, 3(x1)
+    sb x0, 4(x1)
+
+    li x3, DST
+    li x6, 0
+copy:
+    lbu x2, 0(x1)
+    sb x2, 0(x3)
+    beqz x2, done
+    addi x1, x1, 1
+    addi x3, x3, 1
+    addi x6, x6, 1
+    j copy
+done:
+    halt
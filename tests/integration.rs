@@ -0,0 +1,143 @@
+// 黄金文件测试: tests/golden/NAME.asm 汇编后的字节（小端序，与.o输出相同）应与 NAME.bin 逐字节一致。
+// 修改指令编码后运行 make update-golden（cargo run -- --update-golden）重新生成，
+// 并检查生成的差异是否符合预期
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use riscv_tools::sim::CpuState;
+use riscv_tools::{assemble, decode_instruction, disassemble_listing, words_from_bytes, DisasmConfig};
+
+// 至少要覆盖的程序：累加循环、排序、字符串复制、斐波那契数列等
+const REQUIRED_PROGRAMS: &[&str] = &["sum", "factorial", "sort", "strcpy", "fib", "memory", "arith", "bitops"];
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn golden_sources() -> Vec<PathBuf> {
+    let mut sources: Vec<_> = fs::read_dir(golden_dir()).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "asm"))
+        .collect();
+    sources.sort();
+    sources
+}
+
+// 逐字比较，不一致时返回按地址列出的差异，缺少或多出的字显示为 --------
+fn hex_diff(expected: &[u32], actual: &[u32]) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+    let mut diff = format!("期望 {} 个字，实际 {} 个字\n地址    期望      实际\n", expected.len(), actual.len());
+    for i in 0..expected.len().max(actual.len()) {
+        let (want, got) = (expected.get(i), actual.get(i));
+        if want == got {
+            continue;
+        }
+        let show = |word: Option<&u32>| word.map_or("--------".to_string(), |word| format!("{:08X}", word));
+        let decoded = |word: Option<&u32>| word.map_or(String::new(), |&word| decode_instruction(word));
+        diff.push_str(&format!("{:04X}:   {}  {}  {} => {}\n", i * 4, show(want), show(got), decoded(want), decoded(got)));
+    }
+    Some(diff)
+}
+
+fn image_bytes(img: &[u32]) -> Vec<u8> {
+    img.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+#[test]
+fn test_golden_files() {
+    let sources = golden_sources();
+    let mut failures = Vec::new();
+    for path in &sources {
+        let source = fs::read_to_string(path).unwrap();
+        let bin_path = path.with_extension("bin");
+        let expected = fs::read(&bin_path)
+            .unwrap_or_else(|_| panic!("缺少黄金文件: {}", bin_path.display()));
+        let actual = assemble(&source)
+            .unwrap_or_else(|errors| panic!("{} 汇编失败: {}", path.display(), errors[0]));
+        if expected == image_bytes(&actual) {
+            continue;
+        }
+        // 按字显示差异，黄金文件长度不是4的倍数时末尾的字节单独说明
+        let mut failure = format!("{}\n", path.display());
+        if expected.len() % 4 != 0 {
+            failure.push_str(&format!("黄金文件有 {} 个字节，不是4的倍数\n", expected.len()));
+        }
+        failure.push_str(&hex_diff(&words_from_bytes(&expected, false), &actual).unwrap_or_default());
+        failures.push(failure);
+    }
+    // 全部比较完后一起报告，一次看到所有受影响的程序
+    assert!(failures.is_empty(), "{} 个程序与黄金文件不一致:\n{}", failures.len(), failures.join("\n"));
+
+    for name in REQUIRED_PROGRAMS {
+        assert!(sources.iter().any(|path| path.file_stem().unwrap() == *name), "缺少黄金测试程序: {}.asm", name);
+    }
+}
+
+#[test]
+fn test_hex_diff() {
+    assert_eq!(hex_diff(&[0x42, 0], &[0x42, 0]), None);
+    let diff = hex_diff(&[0x00010082, 0], &[0x00020082]).unwrap();
+    assert_eq!(diff, "期望 2 个字，实际 1 个字\n地址    期望      实际\n\
+        0000:   00010082  00020082  addi x2, x0, 1 => addi x2, x0, 2\n\
+        0004:   00000000  --------  halt => \n");
+}
+
+#[test]
+fn test_disassembly_reassembles() {
+    // 每个黄金程序反汇编后的输出都是源码，重新汇编得到完全相同的字
+    for path in golden_sources() {
+        let img = assemble(&fs::read_to_string(&path).unwrap()).unwrap();
+        let listing = disassemble_listing(&img, &DisasmConfig::default());
+        let reassembled = assemble(&listing).unwrap_or_else(|errors| panic!("{} 的反汇编无法重新汇编: {}", path.display(), errors[0]));
+        if let Some(diff) = hex_diff(&img, &reassembled) {
+            panic!("{} 的反汇编重新汇编后不一致:\n{}", path.display(), diff);
+        }
+    }
+}
+
+// asm/sha256.asm 从0x5000读入消息长度和内容，把32字节摘要写到0x4200
+fn run_sha256(message: &[u8]) -> String {
+    let source = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("asm/sha256.asm")).unwrap();
+    let img = assemble(&source).unwrap_or_else(|errors| panic!("asm/sha256.asm 汇编失败: {}", errors[0]));
+    let mut cpu = CpuState::new(&img).unwrap();
+    cpu.memory[0x5000..0x5004].copy_from_slice(&(message.len() as u32).to_le_bytes());
+    cpu.memory[0x5004..0x5004 + message.len()].copy_from_slice(message);
+    cpu.run().unwrap();
+    cpu.memory[0x4200..0x4220].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[test]
+fn test_sha256_program() {
+    assert_eq!(run_sha256(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    assert_eq!(run_sha256(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    // 56字节的消息放不下长度，需要填充出第二个分组
+    assert_eq!(
+        run_sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+    );
+}
+
+#[test]
+fn test_argument_errors_exit_nonzero() {
+    // 参数错误时错误信息写到stderr，退出状态为2
+    let cases: &[&[&str]] = &[
+        &["asm", "--max-imm-bits", "17", "sum"],
+        &["asm", "--unknown-option", "sum"],
+        &["asm", "--format", "mif", "--mif-depth", "3", "sum"],
+        &["disasm", "--data-range", "x", "a.o", "a.asm"],
+        &["disasm", "a.o"],
+        &["sim", "--inject-fault", "0x4", "a.o"],
+        &["explain-imm"],
+        &["no-such-command"],
+    ];
+    for args in cases {
+        let output = Command::new(env!("CARGO_BIN_EXE_riscv-tools")).args(*args).output().unwrap();
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.starts_with("错误: "), "{:?}: {}", args, stderr);
+    }
+}